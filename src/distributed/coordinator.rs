@@ -0,0 +1,215 @@
+
+// Import requirements
+use crate::fundamentals::geometry::*;
+use crate::raytracing::rendering::*;
+use crate::raytracing::stats::*;
+use crate::raytracing::cancel::*;
+use super::protocol::*;
+use std::collections::VecDeque;
+use std::io::BufReader;
+use std::net::{TcpListener, TcpStream, Shutdown};
+use std::sync::{Arc, Condvar, Mutex};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Parameters of the coordinator
+#[derive(Copy, Clone)]
+pub struct CoordinatorSettings {
+	// A worker that has not returned its tile after this time is considered dead, the tile is given to another one
+	pub worker_timeout: Duration,
+	// Time a new connection has to introduce itself as a worker
+	pub handshake_timeout: Duration
+}
+
+impl CoordinatorSettings {
+
+	pub fn new() -> CoordinatorSettings {
+		return CoordinatorSettings { worker_timeout: Duration::from_secs(600), handshake_timeout: Duration::from_secs(10) };
+	}
+}
+
+impl Default for CoordinatorSettings {
+	fn default() -> CoordinatorSettings {
+		return CoordinatorSettings::new();
+	}
+}
+
+// Which tiles still have to be rendered, shared by the threads serving the workers
+struct Schedule {
+	// Tiles nobody is working on, including the ones of workers that have died
+	pending: VecDeque<TileRequest>,
+	// Set when all tiles are finished or the rendering has been cancelled
+	finished: bool,
+	// Connections of the workers, shut down when the rendering is cancelled
+	connections: Vec<TcpStream>
+}
+
+struct Scheduler {
+	schedule: Mutex<Schedule>,
+	changed: Condvar
+}
+
+impl Scheduler {
+
+	// Returns the next tile for a worker, waiting while all remaining tiles are being rendered by other workers, since
+	// one of them might die. Returns None when there is nothing left to do.
+	fn take(&self) -> Option<TileRequest> {
+		let mut schedule = self.schedule.lock().unwrap();
+
+		loop {
+			if schedule.finished {
+				return None;
+			}
+
+			if let Some(tile) = schedule.pending.pop_front() {
+				return Some(tile);
+			}
+
+			schedule = self.changed.wait(schedule).unwrap();
+		}
+	}
+
+	// Gives the tile of a failed worker back to the pending ones
+	fn give_back(&self, tile: TileRequest) {
+		let mut schedule = self.schedule.lock().unwrap();
+		schedule.pending.push_front(tile);
+		self.changed.notify_all();
+	}
+
+	// Lets all waiting threads return
+	fn finish(&self) {
+		let mut schedule = self.schedule.lock().unwrap();
+		schedule.finished = true;
+		self.changed.notify_all();
+	}
+}
+
+// Renders the image on the workers connecting to the listener. The image is split into tiles of settings.tile_height
+// rows, which are handed out to the workers one at a time. If a worker disconnects or does not answer within the
+// timeout, its tile is given to the next worker asking for one, so the rendering completes as long as there is at least
+// one working worker. Workers may connect at any time during the rendering. Every tile is rendered with the same
+// seeds as locally, so the image is exactly the same as the one of render.
+pub fn render_distributed<P>(listener: TcpListener, space: &Space, camera: &CameraZ, settings: &RenderSettings, coordinator: &CoordinatorSettings, cancel: &CancellationToken, mut progress: P) -> RenderResult
+	where P: FnMut(&RenderProgress) {
+
+	let start_time = Instant::now();
+	let encoded_scene = Arc::new(encode_scene(space, camera, settings).expect("Could not encode the scene"));
+
+	let tiles = settings.tiles().into_iter().map(|(y_start, y_count)| TileRequest { y_start, y_count, first_sample: 0 });
+	let tile_count = tiles.len();
+
+	let scheduler = Arc::new(Scheduler {
+		schedule: Mutex::new(Schedule { pending: tiles.collect(), finished: false, connections: Vec::new() }),
+		changed: Condvar::new()
+	});
+
+	let (tx, rx) = mpsc::channel::<FrameBuffer>();
+	let t_scheduler = Arc::clone(&scheduler);
+	let t_settings = *settings;
+	let t_coordinator = *coordinator;
+
+	// Accepts the workers until the rendering is finished, every worker being served by a thread of its own
+	let accept_handle = thread::spawn(move || {
+		let mut join_handles: Vec<thread::JoinHandle<()>> = Vec::new();
+		listener.set_nonblocking(true).expect("Could not configure the listener");
+
+		while !t_scheduler.schedule.lock().unwrap().finished {
+			match listener.accept() {
+				Ok((stream, _)) => {
+					let w_scheduler = Arc::clone(&t_scheduler);
+					let w_scene = Arc::clone(&encoded_scene);
+					let w_tx = tx.clone();
+
+					join_handles.push(thread::spawn(move || {
+						// A failed worker only loses its tile, which is given to another one
+						let _ = serve_worker(stream, &w_scene, &w_scheduler, &t_settings, &t_coordinator, &w_tx);
+					}));
+				}
+				// No worker waiting, or one that has given up before being accepted
+				Err(_) => thread::sleep(Duration::from_millis(10))
+			}
+		}
+
+		for join_handle in join_handles {
+			join_handle.join().unwrap();
+		}
+	});
+
+	let mut frame = FrameBuffer::for_region(settings);
+	let mut tiles_completed: u32 = 0;
+
+	// The tiles are added in the order they are finished, which does not change the result since they do not overlap
+	while tiles_completed < tile_count as u32 && !cancel.is_cancelled() {
+		let t_frame = match rx.recv_timeout(Duration::from_millis(100)) {
+			Ok(t_frame) => t_frame,
+			Err(_) => continue
+		};
+
+		frame.add(&t_frame);
+		tiles_completed += 1;
+
+		let elapsed = start_time.elapsed();
+
+		progress(&RenderProgress {
+			pass: 0,
+			tiles_completed,
+			tile_count: tile_count as u32,
+			elapsed,
+			eta: elapsed.mul_f64(((tile_count as u32 - tiles_completed) as f64) / (tiles_completed as f64)),
+			rays_per_second: (frame.stats.counters.total_rays() as f64) / elapsed.as_secs_f64().max(1e-9),
+			counters: frame.stats.counters
+		});
+	}
+
+	// Stop the workers: the waiting ones are told that the rendering is done, the ones still rendering a tile of a
+	// cancelled rendering are disconnected
+	scheduler.finish();
+
+	if cancel.is_cancelled() {
+		for connection in &scheduler.schedule.lock().unwrap().connections {
+			let _ = connection.shutdown(Shutdown::Both);
+		}
+	}
+
+	accept_handle.join().unwrap();
+
+	// The processor time is the sum of the times the workers spent on the tiles
+	frame.stats.wall_time = start_time.elapsed();
+
+	frame.complete = tiles_completed == tile_count as u32;
+	return frame.resolve();
+}
+
+// Sends the scene to a worker and then one tile after the other, until there are no more tiles
+fn serve_worker(stream: TcpStream, encoded_scene: &[u8], scheduler: &Scheduler, settings: &RenderSettings, coordinator: &CoordinatorSettings, tx: &mpsc::Sender<FrameBuffer>) -> std::io::Result<()> {
+	stream.set_nonblocking(false)?;
+	stream.set_nodelay(true)?;
+	stream.set_read_timeout(Some(coordinator.handshake_timeout))?;
+
+	let mut writer = stream.try_clone()?;
+	let mut reader = BufReader::new(stream.try_clone()?);
+
+	read_hello(&mut reader)?;
+	write_scene(&mut writer, encoded_scene)?;
+	stream.set_read_timeout(Some(coordinator.worker_timeout))?;
+	scheduler.schedule.lock().unwrap().connections.push(stream);
+
+	while let Some(tile) = scheduler.take() {
+		let t_frame = write_tile(&mut writer, &tile).and_then(|_| read_tile_result(&mut reader, settings, &tile));
+
+		match t_frame {
+			Ok(t_frame) => {
+				let _ = tx.send(t_frame);
+			}
+			Err(e) => {
+				// Disconnect the worker, so that a slow one does not keep on rendering a tile nobody waits for
+				let _ = writer.shutdown(Shutdown::Both);
+				scheduler.give_back(tile);
+				return Err(e);
+			}
+		}
+	}
+
+	return write_done(&mut writer);
+}
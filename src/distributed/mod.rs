@@ -0,0 +1,3 @@
+pub mod coordinator;
+pub mod protocol;
+pub mod worker;
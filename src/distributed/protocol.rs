@@ -0,0 +1,737 @@
+
+// Import requirements
+use crate::fundamentals::vec3::*;
+use crate::fundamentals::binary::*;
+use crate::fundamentals::light::*;
+use crate::fundamentals::geometry::*;
+use crate::fundamentals::texture::*;
+use crate::fundamentals::bump::*;
+use crate::fundamentals::instance::*;
+use crate::fundamentals::primitive::*;
+use crate::fundamentals::csg::*;
+use crate::fundamentals::sdf::*;
+use crate::fundamentals::heightfield::*;
+use crate::fundamentals::math::matrix::*;
+use crate::fundamentals::math::transform::*;
+use crate::raytracing::rendering::*;
+use crate::raytracing::sampling::*;
+use crate::raytracing::adaptive::*;
+use crate::raytracing::filter::*;
+use crate::raytracing::checkpoint::*;
+use std::io::{Error, ErrorKind, Read, Write};
+use std::sync::Arc;
+use std::time::Duration;
+
+// The messages between the coordinator and the workers. Every message is its type (one byte), the length of its
+// content (u32) and the content. All numbers are little endian, floating point numbers are sent with all their bits,
+// so a tile rendered by a worker is exactly the same as one rendered locally.
+//
+//   worker       coordinator
+//   Hello   -->
+//           <--  Scene
+//           <--  Tile
+//   Result  -->
+//           ...
+//           <--  Done
+
+const MAGIC: &[u8; 8] = b"PHOTONDR";
+const VERSION: u32 = 8;
+
+const HELLO: u8 = 1;
+const SCENE: u8 = 2;
+const TILE: u8 = 3;
+const RESULT: u8 = 4;
+const DONE: u8 = 5;
+
+// Longest accepted message, so that a broken connection can not make the receiver allocate arbitrary amounts of memory
+const MAX_MESSAGE_LENGTH: u32 = 1 << 30;
+
+// Rows of the image and range of samples a worker has to render
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct TileRequest {
+	pub y_start: i32,
+	pub y_count: i32,
+	pub first_sample: u32
+}
+
+// Sent by a worker after connecting
+pub fn write_hello<W: Write>(out: &mut W) -> std::io::Result<()> {
+	let mut content = Vec::new();
+	content.write_all(MAGIC)?;
+	content.write_all(&VERSION.to_le_bytes())?;
+	return write_message(out, HELLO, &content);
+}
+
+// Fails with InvalidData if the other side is not a worker of this version
+pub fn read_hello<R: Read>(input: &mut R) -> std::io::Result<()> {
+	let content = read_message(input, &[HELLO])?.1;
+	let mut reader = content.as_slice();
+	let mut magic = [0u8; 8];
+	reader.read_exact(&mut magic)?;
+
+	if &magic != MAGIC || read_u32(&mut reader)? != VERSION {
+		return Err(Error::new(ErrorKind::InvalidData, "Not a worker of this version"));
+	}
+
+	return Ok(());
+}
+
+// Encodes the scene, the camera and the settings that change the rendered samples. The thread count and the tile
+// height are left to the worker and the coordinator. The coordinator encodes the scene once and sends it to every
+// worker with write_scene.
+pub fn encode_scene(space: &Space, camera: &CameraZ, settings: &RenderSettings) -> std::io::Result<Vec<u8>> {
+	let mut content = Vec::new();
+	let out = &mut content;
+
+	out.write_all(&(space.spheres.len() as u32).to_le_bytes())?;
+
+	for sphere in &space.spheres {
+		write_sphere(out, sphere)?;
+	}
+
+	out.write_all(&(space.planes.len() as u32).to_le_bytes())?;
+
+	for plane in &space.planes {
+		write_vector(out, &plane.center)?;
+		write_vector(out, &plane.normal)?;
+		write_texture(out, &plane.material_color)?;
+		write_surface_detail(out, &plane.surface_detail)?;
+		write_visibility(out, &plane.visibility)?;
+	}
+
+	out.write_all(&(space.directional_lights.len() as u32).to_le_bytes())?;
+
+	for light in &space.directional_lights {
+		write_vector(out, &light.direction)?;
+		write_color(out, &light.color)?;
+	}
+
+	out.write_all(&(space.point_lights.len() as u32).to_le_bytes())?;
+
+	for light in &space.point_lights {
+		write_vector(out, &light.position)?;
+		write_color(out, &light.color)?;
+	}
+
+	out.write_all(&(space.primitives.primitives.len() as u32).to_le_bytes())?;
+
+	for primitive in &space.primitives.primitives {
+		write_primitive(out, primitive)?;
+	}
+
+	// Every shape is sent once, the instances refer to it by its index
+	let mut shapes: Vec<&Arc<Shape>> = Vec::new();
+
+	for instance in &space.instances.instances {
+		if !shapes.iter().any(|s| Arc::ptr_eq(s, &instance.shape)) {
+			shapes.push(&instance.shape);
+		}
+	}
+
+	out.write_all(&(shapes.len() as u32).to_le_bytes())?;
+
+	for shape in &shapes {
+		out.write_all(&(shape.spheres.len() as u32).to_le_bytes())?;
+
+		for sphere in &shape.spheres {
+			write_sphere(out, sphere)?;
+		}
+	}
+
+	out.write_all(&(space.instances.instances.len() as u32).to_le_bytes())?;
+
+	for instance in &space.instances.instances {
+		let shape_index = shapes.iter().position(|s| Arc::ptr_eq(s, &instance.shape)).unwrap_or(0);
+		out.write_all(&(shape_index as u32).to_le_bytes())?;
+		write_transform(out, &instance.transform)?;
+		out.write_all(&[u8::from(instance.material_override.is_some())])?;
+
+		if let Some(texture) = &instance.material_override {
+			write_texture(out, texture)?;
+		}
+
+		write_visibility(out, &instance.visibility)?;
+	}
+
+	write_vector(out, &camera.location)?;
+	write_f64(out, camera.distance_to_image_plane)?;
+	write_f64(out, camera.image_plane_width)?;
+
+	out.write_all(&settings.width.to_le_bytes())?;
+	out.write_all(&settings.height.to_le_bytes())?;
+	out.write_all(&settings.seed.to_le_bytes())?;
+	out.write_all(&settings.samples_per_pixel.to_le_bytes())?;
+	write_name(out, settings.sampler.name())?;
+	write_name(out, settings.filter.filter_type.name())?;
+	write_f64(out, settings.filter.radius)?;
+	out.write_all(&[u8::from(settings.aovs), u8::from(settings.adaptive.is_some()), u8::from(settings.region.is_some())])?;
+
+	if let Some(a) = settings.adaptive {
+		out.write_all(&a.min_samples.to_le_bytes())?;
+		out.write_all(&a.max_samples.to_le_bytes())?;
+		write_f64(out, a.error_threshold)?;
+	}
+
+	if let Some(r) = settings.region {
+		for v in [r.x, r.y, r.width, r.height] {
+			out.write_all(&v.to_le_bytes())?;
+		}
+	}
+
+	return Ok(content);
+}
+
+// Sends a scene encoded by encode_scene
+pub fn write_scene<W: Write>(out: &mut W, encoded_scene: &[u8]) -> std::io::Result<()> {
+	return write_message(out, SCENE, encoded_scene);
+}
+
+// Receives the scene, the camera and the settings. The settings not sent keep their defaults.
+pub fn read_scene<R: Read>(input: &mut R) -> std::io::Result<(Space, CameraZ, RenderSettings)> {
+	let content = read_message(input, &[SCENE])?.1;
+	let reader = &mut content.as_slice();
+
+	let mut space = Space { spheres: Vec::new(), planes: Vec::new(), directional_lights: Vec::new(), point_lights: Vec::new(), primitives: PrimitiveSet::empty(), instances: InstanceSet::empty() };
+
+	for _ in 0..read_u32(reader)? {
+		space.spheres.push(read_sphere(reader)?);
+	}
+
+	for _ in 0..read_u32(reader)? {
+		space.planes.push(Plane { center: read_vector(reader)?, normal: read_vector(reader)?, material_color: read_texture(reader)?, surface_detail: read_surface_detail(reader)?, visibility: read_visibility(reader)? });
+	}
+
+	for _ in 0..read_u32(reader)? {
+		space.directional_lights.push(DirectionalLight { direction: read_vector(reader)?, color: read_color(reader)? });
+	}
+
+	for _ in 0..read_u32(reader)? {
+		space.point_lights.push(PointLight { position: read_vector(reader)?, color: read_color(reader)? });
+	}
+
+	let mut primitives = Vec::new();
+
+	for _ in 0..read_u32(reader)? {
+		primitives.push(read_primitive(reader)?);
+	}
+
+	space.primitives = PrimitiveSet::new(primitives);
+
+	let mut shapes: Vec<Arc<Shape>> = Vec::new();
+
+	for _ in 0..read_u32(reader)? {
+		let mut spheres = Vec::new();
+
+		for _ in 0..read_u32(reader)? {
+			spheres.push(read_sphere(reader)?);
+		}
+
+		shapes.push(Arc::new(Shape::new(spheres)));
+	}
+
+	let mut instances = Vec::new();
+
+	for _ in 0..read_u32(reader)? {
+		let shape = shapes.get(read_u32(reader)? as usize).ok_or_else(|| invalid_data("Unknown shape"))?.clone();
+		let transform = read_transform(reader)?;
+		let mut flag = [0u8; 1];
+		reader.read_exact(&mut flag)?;
+		let material_override = if flag[0] != 0 { Some(read_texture(reader)?) } else { None };
+		instances.push(Instance { shape, transform, material_override, visibility: read_visibility(reader)? });
+	}
+
+	space.instances = InstanceSet::new(instances);
+
+	let camera = CameraZ { location: read_vector(reader)?, distance_to_image_plane: read_f64(reader)?, image_plane_width: read_f64(reader)? };
+
+	let width = read_u32(reader)? as i32;
+	let height = read_u32(reader)? as i32;
+	let mut settings = RenderSettings::new(width, height);
+	settings.seed = read_u64(reader)?;
+	settings.samples_per_pixel = read_u32(reader)?;
+	settings.sampler = SamplerType::from_name(&read_name(reader)?).ok_or_else(|| invalid_data("Unknown sampler"))?;
+	settings.filter = PixelFilter::new(FilterType::from_name(&read_name(reader)?).ok_or_else(|| invalid_data("Unknown filter"))?);
+	settings.filter.radius = read_f64(reader)?;
+
+	let mut flags = [0u8; 3];
+	reader.read_exact(&mut flags)?;
+	settings.aovs = flags[0] != 0;
+
+	if flags[1] != 0 {
+		settings.adaptive = Some(AdaptiveSampling { min_samples: read_u32(reader)?, max_samples: read_u32(reader)?, error_threshold: read_f64(reader)? });
+	}
+
+	if flags[2] != 0 {
+		settings.region = Some(PixelRegion { x: read_u32(reader)? as i32, y: read_u32(reader)? as i32, width: read_u32(reader)? as i32, height: read_u32(reader)? as i32 });
+	}
+
+	return Ok((space, camera, settings));
+}
+
+// Asks a worker to render a tile
+pub fn write_tile<W: Write>(out: &mut W, tile: &TileRequest) -> std::io::Result<()> {
+	let mut content = Vec::new();
+	content.write_all(&tile.y_start.to_le_bytes())?;
+	content.write_all(&tile.y_count.to_le_bytes())?;
+	content.write_all(&tile.first_sample.to_le_bytes())?;
+	return write_message(out, TILE, &content);
+}
+
+// Tells a worker that there are no more tiles
+pub fn write_done<W: Write>(out: &mut W) -> std::io::Result<()> {
+	return write_message(out, DONE, &[]);
+}
+
+// Receives the next tile to render, or None when the rendering is done
+pub fn read_tile<R: Read>(input: &mut R) -> std::io::Result<Option<TileRequest>> {
+	let (message_type, content) = read_message(input, &[TILE, DONE])?;
+
+	if message_type == DONE {
+		return Ok(None);
+	}
+
+	let reader = &mut content.as_slice();
+	return Ok(Some(TileRequest { y_start: read_u32(reader)? as i32, y_count: read_u32(reader)? as i32, first_sample: read_u32(reader)? }));
+}
+
+// Sends the rendered tile back, together with the time the worker needed for it
+pub fn write_tile_result<W: Write>(out: &mut W, frame: &FrameBuffer) -> std::io::Result<()> {
+	let mut content = Vec::new();
+	content.write_all(&(frame.stats.cpu_time.as_nanos() as u64).to_le_bytes())?;
+	write_frame_buffer(&mut content, frame)?;
+	return write_message(out, RESULT, &content);
+}
+
+// Receives the rendered tile that has been requested from the worker
+pub fn read_tile_result<R: Read>(input: &mut R, settings: &RenderSettings, tile: &TileRequest) -> std::io::Result<FrameBuffer> {
+	let content = read_message(input, &[RESULT])?.1;
+	let reader = &mut content.as_slice();
+
+	let mut frame = FrameBuffer::new(settings, tile.y_start, tile.y_count);
+	let cpu_time = Duration::from_nanos(read_u64(reader)?);
+	read_frame_buffer(reader, &mut frame)?;
+	frame.stats.cpu_time = cpu_time;
+
+	if !reader.is_empty() {
+		return Err(invalid_data("The tile does not have the requested size"));
+	}
+
+	return Ok(frame);
+}
+
+// Sends one message in a single write, so that it is not split up into several packets unnecessarily
+fn write_message<W: Write>(out: &mut W, message_type: u8, content: &[u8]) -> std::io::Result<()> {
+	let mut message = Vec::with_capacity(content.len() + 5);
+	message.push(message_type);
+	message.extend_from_slice(&(content.len() as u32).to_le_bytes());
+	message.extend_from_slice(content);
+	out.write_all(&message)?;
+	return out.flush();
+}
+
+// Receives one message, which has to be of one of the expected types
+fn read_message<R: Read>(input: &mut R, expected_types: &[u8]) -> std::io::Result<(u8, Vec<u8>)> {
+	let mut message_type = [0u8; 1];
+	input.read_exact(&mut message_type)?;
+	let length = read_u32(input)?;
+
+	if !expected_types.contains(&message_type[0]) || length > MAX_MESSAGE_LENGTH {
+		return Err(invalid_data("Unexpected message"));
+	}
+
+	let mut content = vec![0u8; length as usize];
+	input.read_exact(&mut content)?;
+	return Ok((message_type[0], content));
+}
+
+fn invalid_data(message: &str) -> Error {
+	return Error::new(ErrorKind::InvalidData, message);
+}
+
+fn write_vector<W: Write>(out: &mut W, v: &Vector3) -> std::io::Result<()> {
+	write_f64(out, v.x)?;
+	write_f64(out, v.y)?;
+	return write_f64(out, v.z);
+}
+
+fn write_color<W: Write>(out: &mut W, c: &LightColor) -> std::io::Result<()> {
+	write_f64(out, c.r)?;
+	write_f64(out, c.g)?;
+	return write_f64(out, c.b);
+}
+
+fn write_name<W: Write>(out: &mut W, name: &str) -> std::io::Result<()> {
+	out.write_all(&[name.len() as u8])?;
+	return out.write_all(name.as_bytes());
+}
+
+// Textures are sent as their index in the Texture enum followed by their parameters, images with all their pixels
+fn write_texture<W: Write>(out: &mut W, texture: &Texture) -> std::io::Result<()> {
+	let (index, pattern) = match texture {
+		Texture::Constant(c) => {
+			out.write_all(&[0])?;
+			return write_color(out, c);
+		}
+		Texture::Image(image) => {
+			out.write_all(&[1])?;
+			out.write_all(&image.width.to_le_bytes())?;
+			out.write_all(&image.height.to_le_bytes())?;
+			write_name(out, image.wrap.name())?;
+			write_f64(out, image.scale)?;
+
+			for p in image.pixels.iter() {
+				write_color(out, p)?;
+			}
+
+			return Ok(());
+		}
+		Texture::Checker(p) => (2, p),
+		Texture::Grid(p) => (3, p),
+		Texture::Perlin(p) => (4, p),
+		Texture::Worley(p) => (5, p),
+		Texture::Marble(p) => (6, p),
+		Texture::Wood(p) => (7, p)
+	};
+
+	out.write_all(&[index])?;
+	write_color(out, &pattern.color_a)?;
+	write_color(out, &pattern.color_b)?;
+	return write_f64(out, pattern.scale);
+}
+
+fn read_texture<R: Read>(input: &mut R) -> std::io::Result<Texture> {
+	let mut index = [0u8; 1];
+	input.read_exact(&mut index)?;
+
+	match index[0] {
+		0 => return Ok(Texture::Constant(read_color(input)?)),
+		1 => {
+			let width = read_u32(input)? as i32;
+			let height = read_u32(input)? as i32;
+			let wrap = WrapMode::from_name(&read_name(input)?).ok_or_else(|| invalid_data("Unknown wrap mode"))?;
+			let scale = read_f64(input)?;
+
+			if width <= 0 || height <= 0 || (width as u64) * (height as u64) > (MAX_MESSAGE_LENGTH as u64) / 24 {
+				return Err(invalid_data("Invalid image size"));
+			}
+
+			let mut pixels = Vec::with_capacity((width * height) as usize);
+
+			for _ in 0..width * height {
+				pixels.push(read_color(input)?);
+			}
+
+			return Ok(Texture::Image(ImageTexture { width, height, pixels: Arc::new(pixels), wrap, scale }));
+		}
+		_ => {}
+	}
+
+	let pattern = PatternTexture { color_a: read_color(input)?, color_b: read_color(input)?, scale: read_f64(input)? };
+
+	return match index[0] {
+		2 => Ok(Texture::Checker(pattern)),
+		3 => Ok(Texture::Grid(pattern)),
+		4 => Ok(Texture::Perlin(pattern)),
+		5 => Ok(Texture::Worley(pattern)),
+		6 => Ok(Texture::Marble(pattern)),
+		7 => Ok(Texture::Wood(pattern)),
+		_ => Err(invalid_data("Unknown texture"))
+	};
+}
+
+fn write_sphere<W: Write>(out: &mut W, sphere: &Sphere) -> std::io::Result<()> {
+	write_vector(out, &sphere.center)?;
+	write_f64(out, sphere.radius)?;
+	write_texture(out, &sphere.material_color)?;
+	write_surface_detail(out, &sphere.surface_detail)?;
+	return write_visibility(out, &sphere.visibility);
+}
+
+fn read_sphere<R: Read>(input: &mut R) -> std::io::Result<Sphere> {
+	return Ok(Sphere { center: read_vector(input)?, radius: read_f64(input)?, material_color: read_texture(input)?, surface_detail: read_surface_detail(input)?, visibility: read_visibility(input)? });
+}
+
+// Primitives are sent as their kind followed by their transform and material
+fn write_primitive<W: Write>(out: &mut W, primitive: &Primitive) -> std::io::Result<()> {
+	write_primitive_kind(out, &primitive.kind)?;
+	write_transform(out, &primitive.transform)?;
+	write_texture(out, &primitive.material_color)?;
+	write_surface_detail(out, &primitive.surface_detail)?;
+	write_color(out, &primitive.emission)?;
+	return write_visibility(out, &primitive.visibility);
+}
+
+fn read_primitive<R: Read>(input: &mut R) -> std::io::Result<Primitive> {
+	return Ok(Primitive {
+		kind: read_primitive_kind(input)?,
+		transform: read_transform(input)?,
+		material_color: read_texture(input)?,
+		surface_detail: read_surface_detail(input)?,
+		emission: read_color(input)?,
+		visibility: read_visibility(input)?
+	});
+}
+
+// Kinds of primitives are sent as their name followed by their parameters
+fn write_primitive_kind<W: Write>(out: &mut W, kind: &PrimitiveKind) -> std::io::Result<()> {
+	write_name(out, kind.name())?;
+
+	return match kind {
+		PrimitiveKind::Sphere { radius } | PrimitiveKind::Disk { radius } => write_f64(out, *radius),
+		PrimitiveKind::Box { half_size } => write_vector(out, half_size),
+		PrimitiveKind::Quad { edge_u, edge_v } => {
+			write_vector(out, edge_u)?;
+			write_vector(out, edge_v)
+		}
+		PrimitiveKind::Cylinder { radius, height } | PrimitiveKind::Cone { radius, height } => {
+			write_f64(out, *radius)?;
+			write_f64(out, *height)
+		}
+		PrimitiveKind::Torus { major_radius, minor_radius } => {
+			write_f64(out, *major_radius)?;
+			write_f64(out, *minor_radius)
+		}
+		PrimitiveKind::Csg(node) => write_csg_node(out, node),
+		PrimitiveKind::Sdf(sdf) => {
+			write_f64(out, sdf.epsilon)?;
+			out.write_all(&sdf.max_steps.to_le_bytes())?;
+			write_sdf_node(out, &sdf.root)
+		}
+		PrimitiveKind::Heightfield(field) => {
+			out.write_all(&(field.width as u32).to_le_bytes())?;
+			out.write_all(&(field.depth as u32).to_le_bytes())?;
+
+			for h in field.heights.iter() {
+				write_f64(out, *h)?;
+			}
+
+			Ok(())
+		}
+	};
+}
+
+fn read_primitive_kind<R: Read>(input: &mut R) -> std::io::Result<PrimitiveKind> {
+	return Ok(match read_name(input)?.as_str() {
+		"sphere" => PrimitiveKind::Sphere { radius: read_f64(input)? },
+		"box" => PrimitiveKind::Box { half_size: read_vector(input)? },
+		"disk" => PrimitiveKind::Disk { radius: read_f64(input)? },
+		"quad" => PrimitiveKind::Quad { edge_u: read_vector(input)?, edge_v: read_vector(input)? },
+		"cylinder" => PrimitiveKind::Cylinder { radius: read_f64(input)?, height: read_f64(input)? },
+		"cone" => PrimitiveKind::Cone { radius: read_f64(input)?, height: read_f64(input)? },
+		"torus" => PrimitiveKind::Torus { major_radius: read_f64(input)?, minor_radius: read_f64(input)? },
+		"csg" => PrimitiveKind::Csg(Box::new(read_csg_node(input)?)),
+		"sdf" => PrimitiveKind::Sdf(Box::new(Sdf { epsilon: read_f64(input)?, max_steps: read_u32(input)?, root: read_sdf_node(input)? })),
+		"heightfield" => {
+			let (width, depth) = (read_u32(input)? as usize, read_u32(input)? as usize);
+
+			if width < 2 || depth < 2 {
+				return Err(invalid_data("Heightfields need at least 2 x 2 samples"));
+			}
+
+			let heights = (0..width * depth).map(|_| read_f64(input)).collect::<std::io::Result<Vec<f64>>>()?;
+			PrimitiveKind::Heightfield(Heightfield::new(width, depth, heights))
+		}
+		_ => return Err(invalid_data("Unknown primitive"))
+	});
+}
+
+// CSG trees are sent depth first, each node as its name followed by its children or, for solids, its kind and transform
+fn write_csg_node<W: Write>(out: &mut W, node: &CsgNode) -> std::io::Result<()> {
+	let (name, children) = match node {
+		CsgNode::Solid(solid) => {
+			write_name(out, "solid")?;
+			write_primitive_kind(out, &solid.kind)?;
+			return write_transform(out, &solid.transform);
+		}
+		CsgNode::Union(a, b) => ("union", (a, b)),
+		CsgNode::Intersection(a, b) => ("intersection", (a, b)),
+		CsgNode::Difference(a, b) => ("difference", (a, b))
+	};
+
+	write_name(out, name)?;
+	write_csg_node(out, children.0)?;
+	return write_csg_node(out, children.1);
+}
+
+fn read_csg_node<R: Read>(input: &mut R) -> std::io::Result<CsgNode> {
+	let name = read_name(input)?;
+
+	if name == "solid" {
+		let kind = read_primitive_kind(input)?;
+
+		if !kind.is_solid() {
+			return Err(invalid_data("CSG leaves need to be closed primitives"));
+		}
+
+		return Ok(CsgNode::solid(kind, read_transform(input)?));
+	}
+
+	let (a, b) = (read_csg_node(input)?, read_csg_node(input)?);
+
+	return match name.as_str() {
+		"union" => Ok(CsgNode::union(a, b)),
+		"intersection" => Ok(CsgNode::intersection(a, b)),
+		"difference" => Ok(CsgNode::difference(a, b)),
+		_ => Err(invalid_data("Unknown CSG node"))
+	};
+}
+
+// SDF trees are sent depth first, each node as its name followed by its parameters and children
+fn write_sdf_node<W: Write>(out: &mut W, node: &SdfNode) -> std::io::Result<()> {
+	write_name(out, node.name())?;
+
+	return match node {
+		SdfNode::Sphere { radius } => write_f64(out, *radius),
+		SdfNode::Box { half_size } => write_vector(out, half_size),
+		SdfNode::RoundBox { half_size, radius } => {
+			write_vector(out, half_size)?;
+			write_f64(out, *radius)
+		}
+		SdfNode::Capsule { a, b, radius } => {
+			write_vector(out, a)?;
+			write_vector(out, b)?;
+			write_f64(out, *radius)
+		}
+		SdfNode::SmoothUnion { a, b, smoothness } => {
+			write_f64(out, *smoothness)?;
+			write_sdf_node(out, a)?;
+			write_sdf_node(out, b)
+		}
+		SdfNode::Twist { node, rate } => {
+			write_f64(out, *rate)?;
+			write_sdf_node(out, node)
+		}
+		SdfNode::Repeat { node, spacing, count } => {
+			write_vector(out, spacing)?;
+			write_vector(out, count)?;
+			write_sdf_node(out, node)
+		}
+		SdfNode::Translate { node, offset } => {
+			write_vector(out, offset)?;
+			write_sdf_node(out, node)
+		}
+	};
+}
+
+fn read_sdf_node<R: Read>(input: &mut R) -> std::io::Result<SdfNode> {
+	return Ok(match read_name(input)?.as_str() {
+		"sphere" => SdfNode::Sphere { radius: read_f64(input)? },
+		"box" => SdfNode::Box { half_size: read_vector(input)? },
+		"round-box" => SdfNode::RoundBox { half_size: read_vector(input)?, radius: read_f64(input)? },
+		"capsule" => SdfNode::Capsule { a: read_vector(input)?, b: read_vector(input)?, radius: read_f64(input)? },
+		"smooth-union" => {
+			let smoothness = read_f64(input)?;
+			SdfNode::smooth_union(read_sdf_node(input)?, read_sdf_node(input)?, smoothness)
+		}
+		"twist" => {
+			let rate = read_f64(input)?;
+			SdfNode::twist(read_sdf_node(input)?, rate)
+		}
+		"repeat" => {
+			let (spacing, count) = (read_vector(input)?, read_vector(input)?);
+
+			if !SdfNode::is_repeat_count(&count) {
+				return Err(invalid_data("SDF repetitions need finite counts of at least 0"));
+			}
+
+			SdfNode::repeat(read_sdf_node(input)?, spacing, count)
+		}
+		"translate" => {
+			let offset = read_vector(input)?;
+			SdfNode::translate(read_sdf_node(input)?, offset)
+		}
+		_ => return Err(invalid_data("Unknown SDF node"))
+	});
+}
+
+// Visibilities are sent as one flag per kind of ray
+fn write_visibility<W: Write>(out: &mut W, visibility: &Visibility) -> std::io::Result<()> {
+	return out.write_all(&[u8::from(visibility.camera), u8::from(visibility.shadow), u8::from(visibility.indirect)]);
+}
+
+fn read_visibility<R: Read>(input: &mut R) -> std::io::Result<Visibility> {
+	let mut flags = [0u8; 3];
+	input.read_exact(&mut flags)?;
+	return Ok(Visibility { camera: flags[0] != 0, shadow: flags[1] != 0, indirect: flags[2] != 0 });
+}
+
+// Transforms are sent with their inverse, so workers use exactly the same one
+fn write_transform<W: Write>(out: &mut W, transform: &Transform) -> std::io::Result<()> {
+	write_matrix(out, &transform.matrix)?;
+	return write_matrix(out, &transform.inverse);
+}
+
+fn read_transform<R: Read>(input: &mut R) -> std::io::Result<Transform> {
+	return Ok(Transform { matrix: read_matrix(input)?, inverse: read_matrix(input)? });
+}
+
+// Matrices are sent row by row
+fn write_matrix<W: Write>(out: &mut W, matrix: &Matrix4) -> std::io::Result<()> {
+	for v in matrix.m.iter().flatten() {
+		write_f64(out, *v)?;
+	}
+
+	return Ok(());
+}
+
+fn read_matrix<R: Read>(input: &mut R) -> std::io::Result<Matrix4> {
+	let mut m = [[0.0; 4]; 4];
+
+	for v in m.iter_mut().flatten() {
+		*v = read_f64(input)?;
+	}
+
+	return Ok(Matrix4::new(m));
+}
+
+// Surface details are sent as their index in the SurfaceDetail enum followed by their strength and texture
+fn write_surface_detail<W: Write>(out: &mut W, detail: &SurfaceDetail) -> std::io::Result<()> {
+	match detail {
+		SurfaceDetail::Smooth => return out.write_all(&[0]),
+		SurfaceDetail::NormalMap(map) => {
+			out.write_all(&[1])?;
+			write_f64(out, map.strength)?;
+			return write_texture(out, &Texture::Image(map.image.clone()));
+		}
+		SurfaceDetail::Bump(map) => {
+			out.write_all(&[2])?;
+			write_f64(out, map.strength)?;
+			return write_texture(out, &map.height);
+		}
+	}
+}
+
+fn read_surface_detail<R: Read>(input: &mut R) -> std::io::Result<SurfaceDetail> {
+	let mut index = [0u8; 1];
+	input.read_exact(&mut index)?;
+
+	return match index[0] {
+		0 => Ok(SurfaceDetail::Smooth),
+		1 => {
+			let strength = read_f64(input)?;
+
+			match read_texture(input)? {
+				Texture::Image(image) => Ok(SurfaceDetail::NormalMap(NormalMap { image, strength })),
+				_ => Err(invalid_data("Normal maps must be images"))
+			}
+		}
+		2 => Ok(SurfaceDetail::Bump(BumpMap { strength: read_f64(input)?, height: read_texture(input)? })),
+		_ => Err(invalid_data("Unknown surface detail"))
+	};
+}
+
+fn read_vector<R: Read>(input: &mut R) -> std::io::Result<Vector3> {
+	return Ok(Vector3 { x: read_f64(input)?, y: read_f64(input)?, z: read_f64(input)? });
+}
+
+fn read_color<R: Read>(input: &mut R) -> std::io::Result<LightColor> {
+	return Ok(LightColor { r: read_f64(input)?, g: read_f64(input)?, b: read_f64(input)? });
+}
+
+fn read_name<R: Read>(input: &mut R) -> std::io::Result<String> {
+	let mut length = [0u8; 1];
+	input.read_exact(&mut length)?;
+	let mut bytes = vec![0u8; length[0] as usize];
+	input.read_exact(&mut bytes)?;
+	return String::from_utf8(bytes).map_err(|_| invalid_data("Invalid name"));
+}
@@ -0,0 +1,73 @@
+
+// Import requirements
+use crate::raytracing::rendering::*;
+use crate::raytracing::cancel::*;
+use super::protocol::*;
+use std::io::{BufReader, BufWriter};
+use std::net::TcpStream;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// How long a worker keeps trying to reach a coordinator that has not been started yet
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Renders tiles for the coordinator at the address until it has no more. Every connection renders one tile at a time
+// on a thread of its own, so a worker uses as many connections as it should use processors. Returns the number of
+// tiles rendered, or the first error of a connection.
+pub fn run_worker(address: &str, connection_count: i32) -> std::io::Result<u32> {
+	let mut join_handles: Vec<thread::JoinHandle<std::io::Result<u32>>> = Vec::new();
+
+	for _ in 0..connection_count.max(1) {
+		let t_address = String::from(address);
+		join_handles.push(thread::spawn(move || serve_coordinator(&t_address)));
+	}
+
+	let mut tile_count = 0;
+	let mut error: Option<std::io::Error> = None;
+
+	for join_handle in join_handles {
+		match join_handle.join().unwrap() {
+			Ok(count) => tile_count += count,
+			Err(e) => error = error.or(Some(e))
+		}
+	}
+
+	return match error {
+		Some(e) => Err(e),
+		None => Ok(tile_count)
+	};
+}
+
+// Connects to the coordinator, receives the scene and renders the tiles it asks for
+fn serve_coordinator(address: &str) -> std::io::Result<u32> {
+	let stream = connect(address)?;
+	stream.set_nodelay(true)?;
+
+	let mut writer = BufWriter::new(stream.try_clone()?);
+	let mut reader = BufReader::new(stream);
+
+	write_hello(&mut writer)?;
+	let (space, camera, settings) = read_scene(&mut reader)?;
+	let mut tile_count = 0;
+
+	while let Some(tile) = read_tile(&mut reader)? {
+		let frame = render_tile(&space, &camera, &settings, tile.y_start, tile.y_count, SampleRange { first: tile.first_sample, ..SampleRange::all(&settings) }, &CancellationToken::new());
+		write_tile_result(&mut writer, &frame)?;
+		tile_count += 1;
+	}
+
+	return Ok(tile_count);
+}
+
+// Workers may be started before the coordinator, so a refused connection is tried again for a while
+fn connect(address: &str) -> std::io::Result<TcpStream> {
+	let start_time = Instant::now();
+
+	loop {
+		match TcpStream::connect(address) {
+			Ok(stream) => return Ok(stream),
+			Err(e) if start_time.elapsed() >= CONNECT_TIMEOUT => return Err(e),
+			Err(_) => thread::sleep(Duration::from_millis(100))
+		}
+	}
+}
@@ -0,0 +1,26 @@
+
+// Import requirements
+use std::io::{Read, Write};
+
+// Little endian numbers of the binary formats (checkpoints and the messages of distributed renderings). Floating point
+// numbers are written with all their bits, so they are read back exactly.
+
+pub fn read_u32<R: Read>(input: &mut R) -> std::io::Result<u32> {
+	let mut bytes = [0u8; 4];
+	input.read_exact(&mut bytes)?;
+	return Ok(u32::from_le_bytes(bytes));
+}
+
+pub fn read_u64<R: Read>(input: &mut R) -> std::io::Result<u64> {
+	let mut bytes = [0u8; 8];
+	input.read_exact(&mut bytes)?;
+	return Ok(u64::from_le_bytes(bytes));
+}
+
+pub fn read_f64<R: Read>(input: &mut R) -> std::io::Result<f64> {
+	return Ok(f64::from_bits(read_u64(input)?));
+}
+
+pub fn write_f64<W: Write>(out: &mut W, v: f64) -> std::io::Result<()> {
+	return out.write_all(&v.to_le_bytes());
+}
@@ -0,0 +1,105 @@
+
+// Import requirements
+use super::vec3::*;
+use super::texture::*;
+
+// Distance in units of space over which the slope of a bump map is measured
+const BUMP_DELTA: f64 = 0.001;
+
+// Derivatives of the surface position by the texture coordinates, together with the geometric normal. Both
+// derivatives are perpendicular to the normal, and to each other for all primitives but parallelograms, on which
+// bump maps are therefore only approximated.
+pub struct TangentFrame {
+	pub dp_du: Vector3,
+	pub dp_dv: Vector3,
+	pub normal: Vector3
+}
+
+impl TangentFrame {
+
+	// Unit vector along increasing u. Where u degenerates (at the poles of a sphere) it is derived from v instead.
+	pub fn tangent(&self) -> Vector3 {
+		if v3_len(&self.dp_du) > 1e-12 {
+			return v3_normalize(&self.dp_du);
+		}
+
+		if v3_len(&self.dp_dv) > 1e-12 {
+			return v3_normalize(&v3_cross_product(&self.normal, &self.dp_dv));
+		}
+
+		return v3_orthonormal_basis(&self.normal).b1;
+	}
+
+	// Unit vector completing the tangent and the normal to a right handed frame, pointing against v (up in images)
+	pub fn bitangent(&self) -> Vector3 {
+		return v3_cross_product(&self.normal, &self.tangent());
+	}
+}
+
+// Perturbation of the shading normal, which gives surfaces the look of small bumps and dents without changing their
+// geometry
+#[derive(Clone, Debug)]
+pub enum SurfaceDetail {
+	// The geometric normal is used for shading
+	Smooth,
+	NormalMap(NormalMap),
+	Bump(BumpMap)
+}
+
+// Normals in tangent space from an image, encoded like in common normal maps: the channels hold (n + 1) / 2 without
+// any transfer function, red pointing along the tangent, green along the bitangent and blue along the normal
+#[derive(Clone, Debug)]
+pub struct NormalMap {
+	pub image: ImageTexture,
+	// Scales the tilt of the normals, 1 keeps them as they are in the image
+	pub strength: f64
+}
+
+// Heights from the average of the channels of a texture, the normal following the slope of the heights
+#[derive(Clone, Debug)]
+pub struct BumpMap {
+	pub height: Texture,
+	// Height of a texture value of 1 in units of space
+	pub strength: f64
+}
+
+impl SurfaceDetail {
+
+	// Returns the shading normal at the texture coordinates (u, v) and the point relative to the center of the primitive
+	pub fn shading_normal(&self, frame: &TangentFrame, u: f64, v: f64, point: &Vector3) -> Vector3 {
+		return match self {
+			SurfaceDetail::Smooth => frame.normal,
+			SurfaceDetail::NormalMap(map) => {
+				let c = map.image.evaluate(u, v);
+				let (x, y, z) = ((2.0 * c.r - 1.0) * map.strength, (2.0 * c.g - 1.0) * map.strength, 2.0 * c.b - 1.0);
+				let n = v3_sum(&v3_sum(&v3_scale(&frame.tangent(), x), &v3_scale(&frame.bitangent(), y)), &v3_scale(&frame.normal, z.max(0.0)));
+				if v3_len(&n) > 0.0 { v3_normalize(&n) } else { frame.normal }
+			}
+			SurfaceDetail::Bump(map) => {
+				let height = |u: f64, v: f64, point: &Vector3| {
+					let c = map.height.evaluate(u, v, point);
+					return map.strength * (c.r + c.g + c.b) / 3.0;
+				};
+
+				let h = height(u, v, point);
+
+				// The slope along both derivatives, measured by stepping BUMP_DELTA along the surface
+				let slope = |dp: &Vector3, du: f64, dv: f64| -> Vector3 {
+					let len = v3_len(dp);
+
+					if len < 1e-12 {
+						return Vector3 { x: 0.0, y: 0.0, z: 0.0 };
+					}
+
+					let step = BUMP_DELTA / len;
+					let moved = v3_sum(point, &v3_scale(dp, step));
+					let gradient = (height(u + du * step, v + dv * step, &moved) - h) / BUMP_DELTA;
+					return v3_scale(dp, gradient / len);
+				};
+
+				let gradient = v3_sum(&slope(&frame.dp_du, 1.0, 0.0), &slope(&frame.dp_dv, 0.0, 1.0));
+				v3_normalize(&v3_delta(&frame.normal, &gradient))
+			}
+		};
+	}
+}
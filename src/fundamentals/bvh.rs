@@ -0,0 +1,200 @@
+
+// Import requirements
+use super::math::vector::*;
+use super::math::transform::*;
+
+// Most items in a leaf of the hierarchy
+const MAX_LEAF_SIZE: usize = 2;
+
+// Axis aligned bounding box
+#[derive(Copy, Clone, Debug)]
+pub struct Aabb {
+	pub min: Vector3,
+	pub max: Vector3
+}
+
+impl Aabb {
+
+	// The box containing nothing, the neutral element of union
+	pub fn empty() -> Aabb {
+		return Aabb {
+			min: Vector3 { x: f64::MAX, y: f64::MAX, z: f64::MAX },
+			max: Vector3 { x: f64::MIN, y: f64::MIN, z: f64::MIN }
+		};
+	}
+
+	pub fn union(&self, other: &Aabb) -> Aabb {
+		return Aabb {
+			min: Vector3 { x: self.min.x.min(other.min.x), y: self.min.y.min(other.min.y), z: self.min.z.min(other.min.z) },
+			max: Vector3 { x: self.max.x.max(other.max.x), y: self.max.y.max(other.max.y), z: self.max.z.max(other.max.z) }
+		};
+	}
+
+	pub fn add_point(&self, p: &Vector3) -> Aabb {
+		return self.union(&Aabb { min: *p, max: *p });
+	}
+
+	pub fn centroid(&self) -> Vector3 {
+		return (self.min + self.max) * 0.5;
+	}
+
+	// Returns the box around the eight transformed corners of this box
+	pub fn transform(&self, transform: &Transform) -> Aabb {
+		let mut result = Aabb::empty();
+
+		for i in 0..8 {
+			let corner = Point3 {
+				x: if i & 1 == 0 { self.min.x } else { self.max.x },
+				y: if i & 2 == 0 { self.min.y } else { self.max.y },
+				z: if i & 4 == 0 { self.min.z } else { self.max.z }
+			};
+
+			result = result.add_point(&transform.apply_point(corner).to_vector());
+		}
+
+		return result;
+	}
+
+	// Returns the distance at which the ray enters the box, if it does so before max_distance. inverse_direction is
+	// 1 / direction per component (slab test).
+	pub fn intersect(&self, origin: &Vector3, inverse_direction: &Vector3, max_distance: f64) -> Option<f64> {
+		let mut near = 0.0f64;
+		let mut far = max_distance;
+
+		for axis in 0..3 {
+			let t0 = (self.min[axis] - origin[axis]) * inverse_direction[axis];
+			let t1 = (self.max[axis] - origin[axis]) * inverse_direction[axis];
+
+			// NaN (0 * infinity for rays within a slab plane) is ignored by min and max
+			near = near.max(t0.min(t1));
+			far = far.min(t0.max(t1));
+
+			if near > far {
+				return None;
+			}
+		}
+
+		return Some(near);
+	}
+}
+
+// Bounding volume hierarchy over items given by their bounding boxes. Only the indices of the items are stored, the
+// items themselves stay with the owner of the hierarchy.
+#[derive(Debug)]
+pub struct Bvh {
+	nodes: Vec<BvhNode>,
+	indices: Vec<usize>
+}
+
+// Inner nodes have two children, the second one stored at second_child, the first one right after the node. Leaves
+// hold count indices starting at first.
+#[derive(Debug)]
+struct BvhNode {
+	bounds: Aabb,
+	first: usize,
+	count: usize,
+	second_child: usize
+}
+
+impl Bvh {
+
+	// Builds the hierarchy by splitting the items at the median of their centers along the longest axis
+	pub fn new(bounds: &[Aabb]) -> Bvh {
+		let mut bvh = Bvh { nodes: Vec::new(), indices: (0..bounds.len()).collect() };
+
+		if !bounds.is_empty() {
+			bvh.build(bounds, 0, bounds.len());
+		}
+
+		return bvh;
+	}
+
+	pub fn len(&self) -> usize {
+		return self.indices.len();
+	}
+
+	pub fn is_empty(&self) -> bool {
+		return self.indices.is_empty();
+	}
+
+	// Returns the bounds of all items
+	pub fn bounds(&self) -> Aabb {
+		return self.nodes.first().map_or(Aabb::empty(), |n| n.bounds);
+	}
+
+	fn build(&mut self, bounds: &[Aabb], first: usize, count: usize) -> usize {
+		let node_index = self.nodes.len();
+		let items = &mut self.indices[first..first + count];
+		let node_bounds = items.iter().fold(Aabb::empty(), |b, &i| b.union(&bounds[i]));
+		self.nodes.push(BvhNode { bounds: node_bounds, first, count, second_child: 0 });
+
+		if count <= MAX_LEAF_SIZE {
+			return node_index;
+		}
+
+		let centers = items.iter().fold(Aabb::empty(), |b, &i| b.add_point(&bounds[i].centroid()));
+		let extent = centers.max - centers.min;
+		let axis = if extent.x >= extent.y && extent.x >= extent.z { 0 } else if extent.y >= extent.z { 1 } else { 2 };
+
+		// Ties are broken by the index, so the hierarchy only depends on the items
+		let half = count / 2;
+		items.select_nth_unstable_by(half, |&a, &b| bounds[a].centroid()[axis].total_cmp(&bounds[b].centroid()[axis]).then(a.cmp(&b)));
+
+		self.nodes[node_index].count = 0;
+		self.build(bounds, first, half);
+		let second_child = self.build(bounds, first + half, count - half);
+		self.nodes[node_index].second_child = second_child;
+		return node_index;
+	}
+
+	// Calls hit for the items whose bounds the ray enters before max_distance, nearer subtrees first. hit returns the
+	// distance of an intersection with the item, if there is one, and subtrees further away than the nearest of them
+	// are skipped.
+	pub fn traverse<F: FnMut(usize) -> Option<f64>>(&self, origin: &Vector3, direction: &Vector3, max_distance: f64, mut hit: F) {
+		if self.nodes.is_empty() {
+			return;
+		}
+
+		let inverse_direction = Vector3 { x: 1.0 / direction.x, y: 1.0 / direction.y, z: 1.0 / direction.z };
+		let mut nearest = max_distance;
+
+		// The tree is balanced, so its depth stays far below the size of the stack
+		let mut stack = [0usize; 64];
+		let mut stack_size = 1;
+
+		while stack_size > 0 {
+			stack_size -= 1;
+			let node_index = stack[stack_size];
+			let node = &self.nodes[node_index];
+
+			if node.bounds.intersect(origin, &inverse_direction, nearest).is_none() {
+				continue;
+			}
+
+			if node.count > 0 {
+				for &item in &self.indices[node.first..node.first + node.count] {
+					if let Some(distance) = hit(item) {
+						nearest = nearest.min(distance);
+					}
+				}
+				continue;
+			}
+
+			// The child entered first is visited first, so it can prune the other one
+			let (a, b) = (node_index + 1, node.second_child);
+			let distance_a = self.nodes[a].bounds.intersect(origin, &inverse_direction, nearest).unwrap_or(f64::MAX);
+			let distance_b = self.nodes[b].bounds.intersect(origin, &inverse_direction, nearest).unwrap_or(f64::MAX);
+
+			let (first, second) = if distance_a <= distance_b { (a, b) } else { (b, a) };
+			stack[stack_size] = second;
+			stack[stack_size + 1] = first;
+			stack_size += 2;
+		}
+	}
+}
+
+// Returns the bounding box of a sphere
+pub fn sphere_bounds(center: &Vector3, radius: f64) -> Aabb {
+	let r = Vector3 { x: radius, y: radius, z: radius };
+	return Aabb { min: *center - r, max: *center + r };
+}
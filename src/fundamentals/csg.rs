@@ -0,0 +1,78 @@
+
+// Import requirements
+use super::primitive::*;
+use super::bvh::*;
+use super::math::transform::*;
+
+// Tree of constructive solid geometry. The leaves are closed primitives (spheres, boxes, cylinders, cones, tori and
+// other CSG solids) placed by a transform, the inner nodes combine the solids of their children. Disks, quads and
+// heightfields enclose nothing and SDFs are only found by sphere tracing, so they can't be leaves. Neither can the
+// spheres and instances of the space, which are no primitive kinds.
+#[derive(Clone, PartialEq, Debug)]
+pub enum CsgNode {
+	// Boxed, as solids are much larger than the other nodes
+	Solid(Box<CsgSolid>),
+	Union(Box<CsgNode>, Box<CsgNode>),
+	Intersection(Box<CsgNode>, Box<CsgNode>),
+	// The first solid without the second one
+	Difference(Box<CsgNode>, Box<CsgNode>)
+}
+
+// A closed primitive placed in the space of the CSG tree
+#[derive(Clone, PartialEq, Debug)]
+pub struct CsgSolid {
+	pub kind: PrimitiveKind,
+	pub transform: Transform
+}
+
+impl CsgNode {
+
+	// Returns the leaf of the closed primitive, see PrimitiveKind::is_solid
+	pub fn solid(kind: PrimitiveKind, transform: Transform) -> CsgNode {
+		assert!(kind.is_solid(), "CSG leaves need to be closed primitives");
+		return CsgNode::Solid(Box::new(CsgSolid { kind, transform }));
+	}
+
+	pub fn union(a: CsgNode, b: CsgNode) -> CsgNode {
+		return CsgNode::Union(Box::new(a), Box::new(b));
+	}
+
+	pub fn intersection(a: CsgNode, b: CsgNode) -> CsgNode {
+		return CsgNode::Intersection(Box::new(a), Box::new(b));
+	}
+
+	pub fn difference(a: CsgNode, b: CsgNode) -> CsgNode {
+		return CsgNode::Difference(Box::new(a), Box::new(b));
+	}
+
+	// Returns bounds containing the solid. Intersections and differences lie within their first solid.
+	pub fn bounds(&self) -> Aabb {
+		return match self {
+			CsgNode::Solid(solid) => solid.kind.bounds().transform(&solid.transform),
+			CsgNode::Union(a, b) => a.bounds().union(&b.bounds()),
+			CsgNode::Intersection(a, _) | CsgNode::Difference(a, _) => a.bounds()
+		};
+	}
+
+	// Returns the leaf with the index and its transform to the space of the tree, the leaves being numbered depth first
+	// from the left. The leaves of nested CSG solids are numbered in place of them.
+	pub fn leaf(&self, index: usize) -> Option<(&PrimitiveKind, Transform)> {
+		return match self {
+			CsgNode::Solid(solid) => match &solid.kind {
+				PrimitiveKind::Csg(node) => node.leaf(index).map(|(kind, transform)| (kind, solid.transform * transform)),
+				kind => if index == 0 { Some((kind, solid.transform)) } else { None }
+			},
+			CsgNode::Union(a, b) | CsgNode::Intersection(a, b) | CsgNode::Difference(a, b) => {
+				let count = a.leaf_count();
+				if index < count { a.leaf(index) } else { b.leaf(index - count) }
+			}
+		};
+	}
+
+	pub fn leaf_count(&self) -> usize {
+		return match self {
+			CsgNode::Solid(solid) => if let PrimitiveKind::Csg(node) = &solid.kind { node.leaf_count() } else { 1 },
+			CsgNode::Union(a, b) | CsgNode::Intersection(a, b) | CsgNode::Difference(a, b) => a.leaf_count() + b.leaf_count()
+		};
+	}
+}
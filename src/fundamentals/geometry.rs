@@ -2,25 +2,67 @@
 // Import requirements
 use super::vec3::*;
 use super::light::*;
+use super::texture::*;
+use super::bump::*;
+use super::instance::*;
+use super::primitive::*;
+
+// Which kinds of rays see a primitive. A primitive hidden from some rays still takes part in the rest of the light
+// transport, e.g. one invisible to the camera still casts shadows and is seen by global illumination rays.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Visibility {
+	pub camera: bool,
+	// Shadow casting
+	pub shadow: bool,
+	// Seen by global illumination rays
+	pub indirect: bool
+}
+
+impl Visibility {
+
+	pub fn all() -> Visibility {
+		return Visibility { camera: true, shadow: true, indirect: true };
+	}
+
+	pub fn none() -> Visibility {
+		return Visibility { camera: false, shadow: false, indirect: false };
+	}
+
+	// Returns the visibility to the rays both visibilities are visible to, which is how nested nodes combine
+	pub fn and(&self, other: &Visibility) -> Visibility {
+		return Visibility { camera: self.camera && other.camera, shadow: self.shadow && other.shadow, indirect: self.indirect && other.indirect };
+	}
+}
 
 // Ideal spheres
+#[derive(Clone, Debug)]
 pub struct Sphere {
 	pub center: Vector3,
 	pub radius: f64,
-	pub material_color: LightColor
+	pub material_color: Texture,
+	pub surface_detail: SurfaceDetail,
+	pub visibility: Visibility
 }
 
 // Ideal planes
+#[derive(Clone, Debug)]
 pub struct Plane {
 	pub center: Vector3,
 	pub normal: Vector3,
-	pub material_color: LightColor
+	pub material_color: Texture,
+	pub surface_detail: SurfaceDetail,
+	pub visibility: Visibility
 }
 
+#[derive(Debug)]
 pub struct Space {
 	pub spheres: Vec<Sphere>,
 	pub planes: Vec<Plane>,
 	pub directional_lights: Vec<DirectionalLight>,
-	pub point_lights: Vec<PointLight>
+	pub point_lights: Vec<PointLight>,
+	// Boxes, disks, quads, cylinders, cones, tori, transformed spheres, CSG solids, SDFs and heightfields
+	pub primitives: PrimitiveSet,
+	// Shared shapes placed with their own transforms
+	pub instances: InstanceSet
 }
 
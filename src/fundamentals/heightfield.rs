@@ -0,0 +1,64 @@
+
+// Import requirements
+use super::bvh::*;
+use super::math::vector::*;
+use super::random::*;
+use std::sync::Arc;
+
+// Grid of heights over the unit square, samples i along x and j along z at (i / (width - 1), j / (depth - 1)). Each
+// cell between four samples is the bilinear patch through them. The heights are shared, so large fields can be
+// cloned cheaply.
+#[derive(Clone, PartialEq)]
+pub struct Heightfield {
+	pub width: usize,
+	pub depth: usize,
+	// Row by row along z, width samples each
+	pub heights: Arc<Vec<f64>>,
+	pub min_height: f64,
+	pub max_height: f64
+}
+
+impl Heightfield {
+
+	// Returns the field of the heights, which need at least two samples along each axis
+	pub fn new(width: usize, depth: usize, heights: Vec<f64>) -> Heightfield {
+		assert!(width >= 2 && depth >= 2, "Heightfields need at least 2 x 2 samples");
+		assert_eq!(heights.len(), width * depth, "Heightfields need width * depth heights");
+
+		let min_height = heights.iter().copied().fold(f64::MAX, f64::min);
+		let max_height = heights.iter().copied().fold(f64::MIN, f64::max);
+		return Heightfield { width, depth, heights: Arc::new(heights), min_height, max_height };
+	}
+
+	// Number of cells along x and z
+	pub fn cells(&self) -> (usize, usize) {
+		return (self.width - 1, self.depth - 1);
+	}
+
+	pub fn height(&self, i: usize, j: usize) -> f64 {
+		return self.heights[j * self.width + i];
+	}
+
+	// Returns the normal of the surface at a sample, from the differences to its neighbors (one sided at the border).
+	// The normals of the cells are interpolated from these, so the field looks smooth.
+	pub fn normal(&self, i: usize, j: usize) -> Vector3 {
+		let (cells_x, cells_z) = self.cells();
+		let (i0, i1) = (i.saturating_sub(1), (i + 1).min(cells_x));
+		let (j0, j1) = (j.saturating_sub(1), (j + 1).min(cells_z));
+		let slope_x = (self.height(i1, j) - self.height(i0, j)) * cells_x as f64 / (i1 - i0) as f64;
+		let slope_z = (self.height(i, j1) - self.height(i, j0)) * cells_z as f64 / (j1 - j0) as f64;
+		return Vector3::new(-slope_x, 1.0, -slope_z).normalize();
+	}
+
+	pub fn bounds(&self) -> Aabb {
+		return Aabb { min: Vector3::new(0.0, self.min_height, 0.0), max: Vector3::new(1.0, self.max_height, 1.0) };
+	}
+}
+
+// The heights are summarized by a hash like the pixels of images
+impl std::fmt::Debug for Heightfield {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		let hash = fnv1a(self.heights.iter().map(|h| h.to_bits()));
+		return write!(f, "Heightfield {{ width: {}, depth: {}, heights: {:016x} }}", self.width, self.depth, hash);
+	}
+}
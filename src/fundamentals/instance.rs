@@ -0,0 +1,55 @@
+
+// Import requirements
+use super::geometry::*;
+use super::texture::*;
+use super::bvh::*;
+use super::math::transform::*;
+use std::sync::Arc;
+
+// Geometry in its own object space, shared by all the instances placed in the space. Holds the bottom level of the
+// two level hierarchy, built once however many instances there are. Planes are unbounded, so shapes consist of
+// bounded primitives only.
+#[derive(Debug)]
+pub struct Shape {
+	pub spheres: Vec<Sphere>,
+	pub bvh: Bvh
+}
+
+impl Shape {
+
+	pub fn new(spheres: Vec<Sphere>) -> Shape {
+		let bounds: Vec<Aabb> = spheres.iter().map(|s| sphere_bounds(&s.center, s.radius)).collect();
+		return Shape { bvh: Bvh::new(&bounds), spheres };
+	}
+}
+
+// A shape placed in the space by an affine transform from object to world space. Costs a reference and the
+// transform, the shape itself is shared.
+#[derive(Clone, Debug)]
+pub struct Instance {
+	pub shape: Arc<Shape>,
+	pub transform: Transform,
+	// Replaces the textures of all primitives of the shape, evaluated in object space
+	pub material_override: Option<Texture>,
+	// Combined with the visibility of the primitives of the shape
+	pub visibility: Visibility
+}
+
+// The instances of the space together with the top level of the hierarchy over their world space bounds
+#[derive(Debug)]
+pub struct InstanceSet {
+	pub instances: Vec<Instance>,
+	pub bvh: Bvh
+}
+
+impl InstanceSet {
+
+	pub fn new(instances: Vec<Instance>) -> InstanceSet {
+		let bounds: Vec<Aabb> = instances.iter().map(|i| i.shape.bvh.bounds().transform(&i.transform)).collect();
+		return InstanceSet { bvh: Bvh::new(&bounds), instances };
+	}
+
+	pub fn empty() -> InstanceSet {
+		return InstanceSet::new(Vec::new());
+	}
+}
@@ -4,7 +4,7 @@
 use super::vec3::*;
 
 // Light color has this structure
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug)]
 pub struct LightColor {
 	pub r: f64,
 	pub g: f64, 
@@ -12,12 +12,14 @@ pub struct LightColor {
 }
 
 // Point light sources
+#[derive(Clone, Debug)]
 pub struct PointLight {
 	pub position: Vector3, 
 	pub color: LightColor
 }
 
 // Directional lights (like the sun) with parallel light rays
+#[derive(Clone, Debug)]
 pub struct DirectionalLight {
 	pub direction: Vector3,
 	pub color: LightColor
@@ -30,6 +32,7 @@ pub struct WeightedLightColorSummand {
 }
 
 // Returns the weighted sum of the specified summands
+#[allow(clippy::redundant_field_names)]
 pub fn compute_weighted_light_color(summands: &Vec<WeightedLightColorSummand>) -> LightColor {
 
 	let mut r: f64 = 0.0;
@@ -43,4 +46,9 @@ pub fn compute_weighted_light_color(summands: &Vec<WeightedLightColorSummand>) -
 	}
 
 	return LightColor { r: r, g: g, b: b};
-}
\ No newline at end of file
+}
+
+// Returns the luminance (perceived brightness) of the light color, using the Rec. 709 weights
+pub fn luminance(c: &LightColor) -> f64 {
+	return 0.2126 * c.r + 0.7152 * c.g + 0.0722 * c.b;
+}
@@ -0,0 +1,130 @@
+
+// Import requirements
+use super::real::*;
+use super::vector::*;
+use std::ops::Mul;
+
+// 4x4 matrix in row major order, applied to column vectors: m[row][column]. Points have the homogeneous coordinate
+// 1, vectors 0.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Matrix4<T = f64> {
+	pub m: [[T; 4]; 4]
+}
+
+impl<T: Real> Matrix4<T> {
+
+	pub fn new(m: [[T; 4]; 4]) -> Matrix4<T> {
+		return Matrix4 { m };
+	}
+
+	pub fn identity() -> Matrix4<T> {
+		let mut m = [[T::zero(); 4]; 4];
+
+		for (i, row) in m.iter_mut().enumerate() {
+			row[i] = T::one();
+		}
+
+		return Matrix4 { m };
+	}
+
+	pub fn transpose(&self) -> Matrix4<T> {
+		let mut m = [[T::zero(); 4]; 4];
+
+		for (i, row) in m.iter_mut().enumerate() {
+			for (j, value) in row.iter_mut().enumerate() {
+				*value = self.m[j][i];
+			}
+		}
+
+		return Matrix4 { m };
+	}
+
+	// Returns the inverse by Gauss-Jordan elimination with partial pivoting, or None if the matrix is singular
+	pub fn inverse(&self) -> Option<Matrix4<T>> {
+		let mut a = self.m;
+		let mut inverse = Matrix4::identity().m;
+
+		for column in 0..4 {
+
+			// The row with the largest value in this column keeps the elimination stable
+			let mut pivot = column;
+
+			for row in column + 1..4 {
+				if a[row][column].abs() > a[pivot][column].abs() {
+					pivot = row;
+				}
+			}
+
+			if a[pivot][column] == T::zero() {
+				return None;
+			}
+
+			a.swap(pivot, column);
+			inverse.swap(pivot, column);
+
+			let scale = T::one() / a[column][column];
+
+			for j in 0..4 {
+				a[column][j] *= scale;
+				inverse[column][j] *= scale;
+			}
+
+			for row in 0..4 {
+				if row != column {
+					let factor = a[row][column];
+
+					for j in 0..4 {
+						a[row][j] -= factor * a[column][j];
+						inverse[row][j] -= factor * inverse[column][j];
+					}
+				}
+			}
+		}
+
+		return Some(Matrix4 { m: inverse });
+	}
+
+	// Applies the matrix to a point, including the division by the homogeneous coordinate of projections
+	pub fn transform_point(&self, p: Point3<T>) -> Point3<T> {
+		let m = &self.m;
+		let x = m[0][0]*p.x + m[0][1]*p.y + m[0][2]*p.z + m[0][3];
+		let y = m[1][0]*p.x + m[1][1]*p.y + m[1][2]*p.z + m[1][3];
+		let z = m[2][0]*p.x + m[2][1]*p.y + m[2][2]*p.z + m[2][3];
+		let w = m[3][0]*p.x + m[3][1]*p.y + m[3][2]*p.z + m[3][3];
+
+		if w == T::one() {
+			return Point3 { x, y, z };
+		}
+
+		return Point3 { x: x / w, y: y / w, z: z / w };
+	}
+
+	// Applies the upper 3x3 part of the matrix, vectors are not moved by translations
+	pub fn transform_vector(&self, v: Vector3<T>) -> Vector3<T> {
+		let m = &self.m;
+
+		return Vector3 {
+			x: m[0][0]*v.x + m[0][1]*v.y + m[0][2]*v.z,
+			y: m[1][0]*v.x + m[1][1]*v.y + m[1][2]*v.z,
+			z: m[2][0]*v.x + m[2][1]*v.y + m[2][2]*v.z
+		};
+	}
+}
+
+impl<T: Real> Mul for Matrix4<T> {
+	type Output = Matrix4<T>;
+
+	fn mul(self, other: Matrix4<T>) -> Matrix4<T> {
+		let mut m = [[T::zero(); 4]; 4];
+
+		for (i, row) in m.iter_mut().enumerate() {
+			for (j, value) in row.iter_mut().enumerate() {
+				for k in 0..4 {
+					*value += self.m[i][k] * other.m[k][j];
+				}
+			}
+		}
+
+		return Matrix4 { m };
+	}
+}
@@ -0,0 +1,5 @@
+pub mod real;
+pub mod vector;
+pub mod matrix;
+pub mod transform;
+pub mod quaternion;
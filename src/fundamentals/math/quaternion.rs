@@ -0,0 +1,104 @@
+
+// Import requirements
+use super::real::*;
+use super::vector::*;
+use super::matrix::*;
+use std::ops::Mul;
+
+// Rotation as a unit quaternion w + xi + yj + zk. Unlike matrices they interpolate smoothly (slerp), which is what
+// animated rotations need.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Quaternion<T = f64> {
+	pub w: T,
+	pub x: T,
+	pub y: T,
+	pub z: T
+}
+
+impl<T: Real> Quaternion<T> {
+
+	pub fn identity() -> Quaternion<T> {
+		return Quaternion { w: T::one(), x: T::zero(), y: T::zero(), z: T::zero() };
+	}
+
+	// Rotation by the angle (in radians, counterclockwise when looking against the axis) around the axis
+	pub fn from_axis_angle(axis: Vector3<T>, angle: T) -> Quaternion<T> {
+		let half = angle / T::from_f64(2.0);
+		let a = axis.normalize() * half.sin();
+		return Quaternion { w: half.cos(), x: a.x, y: a.y, z: a.z };
+	}
+
+	pub fn conjugate(self) -> Quaternion<T> {
+		return Quaternion { w: self.w, x: -self.x, y: -self.y, z: -self.z };
+	}
+
+	pub fn dot(self, other: Quaternion<T>) -> T {
+		return self.w*other.w + self.x*other.x + self.y*other.y + self.z*other.z;
+	}
+
+	pub fn normalize(self) -> Quaternion<T> {
+		let len = self.dot(self).sqrt();
+		return Quaternion { w: self.w/len, x: self.x/len, y: self.y/len, z: self.z/len };
+	}
+
+	// Rotates the vector, the quaternion must be normalized
+	pub fn rotate(self, v: Vector3<T>) -> Vector3<T> {
+
+		// v' = v + 2w (q x v) + 2 q x (q x v) with q the vector part, cheaper than q v q*
+		let q = Vector3 { x: self.x, y: self.y, z: self.z };
+		let two = T::from_f64(2.0);
+		let t = q.cross(v) * two;
+		return v + t * self.w + q.cross(t);
+	}
+
+	// Spherical linear interpolation from a (t = 0) to b (t = 1) along the shorter arc, at constant angular speed
+	pub fn slerp(a: Quaternion<T>, b: Quaternion<T>, t: T) -> Quaternion<T> {
+		let mut b = b;
+		let mut cos_angle = a.dot(b);
+
+		// q and -q are the same rotation, the one nearer to a takes the shorter way
+		if cos_angle < T::zero() {
+			b = Quaternion { w: -b.w, x: -b.x, y: -b.y, z: -b.z };
+			cos_angle = -cos_angle;
+		}
+
+		// Nearly the same rotation, linear interpolation avoids the division by sin(angle) of almost 0
+		let (wa, wb) = if cos_angle > T::from_f64(0.9995) {
+			(T::one() - t, t)
+		} else {
+			let angle = cos_angle.min(T::one()).acos();
+			let sin_angle = angle.sin();
+			(((T::one() - t) * angle).sin() / sin_angle, (t * angle).sin() / sin_angle)
+		};
+
+		let q = Quaternion { w: a.w*wa + b.w*wb, x: a.x*wa + b.x*wb, y: a.y*wa + b.y*wb, z: a.z*wa + b.z*wb };
+		return q.normalize();
+	}
+
+	// Returns the rotation matrix of the normalized quaternion
+	pub fn to_matrix(self) -> Matrix4<T> {
+		let (w, x, y, z) = (self.w, self.x, self.y, self.z);
+		let (one, two, zero) = (T::one(), T::from_f64(2.0), T::zero());
+
+		return Matrix4::new([
+			[one - two*(y*y + z*z), two*(x*y - w*z), two*(x*z + w*y), zero],
+			[two*(x*y + w*z), one - two*(x*x + z*z), two*(y*z - w*x), zero],
+			[two*(x*z - w*y), two*(y*z + w*x), one - two*(x*x + y*y), zero],
+			[zero, zero, zero, one]
+		]);
+	}
+}
+
+// The product applies the right rotation first, then the left one
+impl<T: Real> Mul for Quaternion<T> {
+	type Output = Quaternion<T>;
+
+	fn mul(self, o: Quaternion<T>) -> Quaternion<T> {
+		return Quaternion {
+			w: self.w*o.w - self.x*o.x - self.y*o.y - self.z*o.z,
+			x: self.w*o.x + self.x*o.w + self.y*o.z - self.z*o.y,
+			y: self.w*o.y - self.x*o.z + self.y*o.w + self.z*o.x,
+			z: self.w*o.z + self.x*o.y - self.y*o.x + self.z*o.w
+		};
+	}
+}
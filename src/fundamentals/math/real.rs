@@ -0,0 +1,45 @@
+
+// Import requirements
+use std::ops::{Add, Div, Mul, Neg, Sub, AddAssign, SubAssign, MulAssign, DivAssign};
+
+// The floating point types the math types are generic over, f32 and f64. The renderer itself works with f64, f32 is
+// there for compact storage, e.g. of large meshes.
+pub trait Real: Copy + PartialEq + PartialOrd + std::fmt::Debug
+	+ Add<Output = Self> + Sub<Output = Self> + Mul<Output = Self> + Div<Output = Self> + Neg<Output = Self>
+	+ AddAssign + SubAssign + MulAssign + DivAssign {
+
+	fn zero() -> Self;
+	fn one() -> Self;
+	fn from_f64(v: f64) -> Self;
+	fn to_f64(self) -> f64;
+	fn sqrt(self) -> Self;
+	fn abs(self) -> Self;
+	fn sin(self) -> Self;
+	fn cos(self) -> Self;
+	fn acos(self) -> Self;
+	fn min(self, other: Self) -> Self;
+	fn max(self, other: Self) -> Self;
+	fn copysign(self, sign: Self) -> Self;
+}
+
+macro_rules! impl_real {
+	($t:ty) => {
+		impl Real for $t {
+			fn zero() -> Self { return 0.0; }
+			fn one() -> Self { return 1.0; }
+			fn from_f64(v: f64) -> Self { return v as $t; }
+			fn to_f64(self) -> f64 { return self as f64; }
+			fn sqrt(self) -> Self { return <$t>::sqrt(self); }
+			fn abs(self) -> Self { return <$t>::abs(self); }
+			fn sin(self) -> Self { return <$t>::sin(self); }
+			fn cos(self) -> Self { return <$t>::cos(self); }
+			fn acos(self) -> Self { return <$t>::acos(self); }
+			fn min(self, other: Self) -> Self { return <$t>::min(self, other); }
+			fn max(self, other: Self) -> Self { return <$t>::max(self, other); }
+			fn copysign(self, sign: Self) -> Self { return <$t>::copysign(self, sign); }
+		}
+	};
+}
+
+impl_real!(f32);
+impl_real!(f64);
@@ -0,0 +1,84 @@
+
+// Import requirements
+use super::real::*;
+use super::vector::*;
+use super::matrix::*;
+use super::quaternion::*;
+use std::ops::Mul;
+
+// Affine (or projective) transform together with its inverse, which is needed for the normals and for taking rays
+// into the space of an object
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Transform<T = f64> {
+	pub matrix: Matrix4<T>,
+	pub inverse: Matrix4<T>
+}
+
+impl<T: Real> Transform<T> {
+
+	pub fn identity() -> Transform<T> {
+		return Transform { matrix: Matrix4::identity(), inverse: Matrix4::identity() };
+	}
+
+	// Returns the transform of the matrix, or None if the matrix can't be inverted
+	pub fn new(matrix: Matrix4<T>) -> Option<Transform<T>> {
+		return Some(Transform { matrix, inverse: matrix.inverse()? });
+	}
+
+	pub fn translate(v: Vector3<T>) -> Transform<T> {
+		let (one, zero) = (T::one(), T::zero());
+		let m = |d: Vector3<T>| Matrix4::new([[one, zero, zero, d.x], [zero, one, zero, d.y], [zero, zero, one, d.z], [zero, zero, zero, one]]);
+		return Transform { matrix: m(v), inverse: m(-v) };
+	}
+
+	// Scales along the axes, none of the factors may be 0
+	pub fn scale(x: T, y: T, z: T) -> Transform<T> {
+		let (one, zero) = (T::one(), T::zero());
+		let m = |x: T, y: T, z: T| Matrix4::new([[x, zero, zero, zero], [zero, y, zero, zero], [zero, zero, z, zero], [zero, zero, zero, one]]);
+		return Transform { matrix: m(x, y, z), inverse: m(one / x, one / y, one / z) };
+	}
+
+	// Rotation by the angle in radians around the axis through the origin
+	pub fn rotate(axis: Vector3<T>, angle: T) -> Transform<T> {
+		return Transform::from_quaternion(Quaternion::from_axis_angle(axis, angle));
+	}
+
+	// Rotation of a normalized quaternion, whose inverse is the transposed matrix
+	pub fn from_quaternion(q: Quaternion<T>) -> Transform<T> {
+		let matrix = q.to_matrix();
+		return Transform { matrix, inverse: matrix.transpose() };
+	}
+
+	pub fn inverse(&self) -> Transform<T> {
+		return Transform { matrix: self.inverse, inverse: self.matrix };
+	}
+
+	pub fn apply_point(&self, p: Point3<T>) -> Point3<T> {
+		return self.matrix.transform_point(p);
+	}
+
+	pub fn apply_vector(&self, v: Vector3<T>) -> Vector3<T> {
+		return self.matrix.transform_vector(v);
+	}
+
+	// Normals are transformed with the inverse transpose, which keeps them perpendicular to the transformed tangents
+	// also under non-uniform scaling. The result is not normalized.
+	pub fn apply_normal(&self, n: Normal3<T>) -> Normal3<T> {
+		let m = &self.inverse.m;
+
+		return Normal3 {
+			x: m[0][0]*n.x + m[1][0]*n.y + m[2][0]*n.z,
+			y: m[0][1]*n.x + m[1][1]*n.y + m[2][1]*n.z,
+			z: m[0][2]*n.x + m[1][2]*n.y + m[2][2]*n.z
+		};
+	}
+}
+
+// a * b is the transform applying b first, then a
+impl<T: Real> Mul for Transform<T> {
+	type Output = Transform<T>;
+
+	fn mul(self, other: Transform<T>) -> Transform<T> {
+		return Transform { matrix: self.matrix * other.matrix, inverse: other.inverse * self.inverse };
+	}
+}
@@ -0,0 +1,308 @@
+
+// Import requirements
+use super::real::*;
+use std::ops::{Add, Sub, Mul, Div, Neg, Index, IndexMut, AddAssign, SubAssign, MulAssign, DivAssign};
+
+// Directions and offsets in space. Positions are mostly Vector3 as well, Point3 and Normal3 are there where the
+// distinction matters, i.e. for transforms, which move points but not directions and treat normals differently.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Vector3<T = f64> {
+	pub x: T,
+	pub y: T,
+	pub z: T,
+}
+
+// A position in space
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Point3<T = f64> {
+	pub x: T,
+	pub y: T,
+	pub z: T,
+}
+
+// A surface normal. Not necessarily normalized, but transformed with the inverse transpose so that it stays
+// perpendicular to the surface.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Normal3<T = f64> {
+	pub x: T,
+	pub y: T,
+	pub z: T,
+}
+
+impl<T: Real> Vector3<T> {
+
+	pub fn new(x: T, y: T, z: T) -> Vector3<T> {
+		return Vector3 { x, y, z };
+	}
+
+	pub fn zero() -> Vector3<T> {
+		return Vector3 { x: T::zero(), y: T::zero(), z: T::zero() };
+	}
+
+	pub fn dot(self, other: Vector3<T>) -> T {
+		return self.x*other.x + self.y*other.y + self.z*other.z;
+	}
+
+	pub fn cross(self, other: Vector3<T>) -> Vector3<T> {
+		return Vector3 {
+			x: self.y*other.z - self.z*other.y,
+			y: self.z*other.x - self.x*other.z,
+			z: self.x*other.y - self.y*other.x
+		};
+	}
+
+	pub fn length_squared(self) -> T {
+		return self.x*self.x + self.y*self.y + self.z*self.z;
+	}
+
+	pub fn length(self) -> T {
+		return self.length_squared().sqrt();
+	}
+
+	// Returns the vector divided by its length. The zero vector has no direction and gives NaNs.
+	pub fn normalize(self) -> Vector3<T> {
+		let len = self.length();
+		return Vector3 { x: self.x/len, y: self.y/len, z: self.z/len };
+	}
+
+	// Multiplies component by component
+	pub fn mul_elements(self, other: Vector3<T>) -> Vector3<T> {
+		return Vector3 { x: self.x*other.x, y: self.y*other.y, z: self.z*other.z };
+	}
+}
+
+impl<T: Real> Point3<T> {
+
+	pub fn new(x: T, y: T, z: T) -> Point3<T> {
+		return Point3 { x, y, z };
+	}
+
+	pub fn origin() -> Point3<T> {
+		return Point3 { x: T::zero(), y: T::zero(), z: T::zero() };
+	}
+
+	// Returns the offset of the point from the origin
+	pub fn to_vector(self) -> Vector3<T> {
+		return Vector3 { x: self.x, y: self.y, z: self.z };
+	}
+
+	pub fn distance(self, other: Point3<T>) -> T {
+		return (self - other).length();
+	}
+}
+
+impl<T: Real> Normal3<T> {
+
+	pub fn new(x: T, y: T, z: T) -> Normal3<T> {
+		return Normal3 { x, y, z };
+	}
+
+	pub fn to_vector(self) -> Vector3<T> {
+		return Vector3 { x: self.x, y: self.y, z: self.z };
+	}
+
+	pub fn dot(self, v: Vector3<T>) -> T {
+		return self.to_vector().dot(v);
+	}
+
+	pub fn normalize(self) -> Normal3<T> {
+		return Normal3::from(self.to_vector().normalize());
+	}
+
+	// Returns the normal flipped if necessary so that it lies in the same hemisphere as v
+	pub fn face_forward(self, v: Vector3<T>) -> Normal3<T> {
+		return if self.dot(v) < T::zero() { -self } else { self };
+	}
+}
+
+impl<T: Real> From<Point3<T>> for Vector3<T> {
+	fn from(p: Point3<T>) -> Vector3<T> {
+		return p.to_vector();
+	}
+}
+
+impl<T: Real> From<Normal3<T>> for Vector3<T> {
+	fn from(n: Normal3<T>) -> Vector3<T> {
+		return n.to_vector();
+	}
+}
+
+impl<T: Real> From<Vector3<T>> for Point3<T> {
+	fn from(v: Vector3<T>) -> Point3<T> {
+		return Point3 { x: v.x, y: v.y, z: v.z };
+	}
+}
+
+impl<T: Real> From<Vector3<T>> for Normal3<T> {
+	fn from(v: Vector3<T>) -> Normal3<T> {
+		return Normal3 { x: v.x, y: v.y, z: v.z };
+	}
+}
+
+impl<T: Real> Add for Vector3<T> {
+	type Output = Vector3<T>;
+
+	fn add(self, other: Vector3<T>) -> Vector3<T> {
+		return Vector3 { x: self.x+other.x, y: self.y+other.y, z: self.z+other.z };
+	}
+}
+
+impl<T: Real> Sub for Vector3<T> {
+	type Output = Vector3<T>;
+
+	fn sub(self, other: Vector3<T>) -> Vector3<T> {
+		return Vector3 { x: self.x-other.x, y: self.y-other.y, z: self.z-other.z };
+	}
+}
+
+impl<T: Real> Mul<T> for Vector3<T> {
+	type Output = Vector3<T>;
+
+	fn mul(self, s: T) -> Vector3<T> {
+		return Vector3 { x: self.x*s, y: self.y*s, z: self.z*s };
+	}
+}
+
+impl<T: Real> Div<T> for Vector3<T> {
+	type Output = Vector3<T>;
+
+	fn div(self, s: T) -> Vector3<T> {
+		return Vector3 { x: self.x/s, y: self.y/s, z: self.z/s };
+	}
+}
+
+impl<T: Real> Neg for Vector3<T> {
+	type Output = Vector3<T>;
+
+	fn neg(self) -> Vector3<T> {
+		return Vector3 { x: -self.x, y: -self.y, z: -self.z };
+	}
+}
+
+impl<T: Real> AddAssign for Vector3<T> {
+	fn add_assign(&mut self, other: Vector3<T>) {
+		*self = *self + other;
+	}
+}
+
+impl<T: Real> SubAssign for Vector3<T> {
+	fn sub_assign(&mut self, other: Vector3<T>) {
+		*self = *self - other;
+	}
+}
+
+impl<T: Real> MulAssign<T> for Vector3<T> {
+	fn mul_assign(&mut self, s: T) {
+		*self = *self * s;
+	}
+}
+
+impl<T: Real> DivAssign<T> for Vector3<T> {
+	fn div_assign(&mut self, s: T) {
+		*self = *self / s;
+	}
+}
+
+// The scalar may stand on either side of the multiplication
+macro_rules! impl_scalar_mul {
+	($t:ty) => {
+		impl Mul<Vector3<$t>> for $t {
+			type Output = Vector3<$t>;
+
+			fn mul(self, v: Vector3<$t>) -> Vector3<$t> {
+				return v * self;
+			}
+		}
+
+		impl Mul<Normal3<$t>> for $t {
+			type Output = Normal3<$t>;
+
+			fn mul(self, n: Normal3<$t>) -> Normal3<$t> {
+				return Normal3 { x: n.x * self, y: n.y * self, z: n.z * self };
+			}
+		}
+	};
+}
+
+impl_scalar_mul!(f32);
+impl_scalar_mul!(f64);
+
+// Point - point is the offset between them, points move by vectors
+impl<T: Real> Sub for Point3<T> {
+	type Output = Vector3<T>;
+
+	fn sub(self, other: Point3<T>) -> Vector3<T> {
+		return Vector3 { x: self.x-other.x, y: self.y-other.y, z: self.z-other.z };
+	}
+}
+
+impl<T: Real> Add<Vector3<T>> for Point3<T> {
+	type Output = Point3<T>;
+
+	fn add(self, v: Vector3<T>) -> Point3<T> {
+		return Point3 { x: self.x+v.x, y: self.y+v.y, z: self.z+v.z };
+	}
+}
+
+impl<T: Real> Sub<Vector3<T>> for Point3<T> {
+	type Output = Point3<T>;
+
+	fn sub(self, v: Vector3<T>) -> Point3<T> {
+		return Point3 { x: self.x-v.x, y: self.y-v.y, z: self.z-v.z };
+	}
+}
+
+impl<T: Real> AddAssign<Vector3<T>> for Point3<T> {
+	fn add_assign(&mut self, v: Vector3<T>) {
+		*self = *self + v;
+	}
+}
+
+impl<T: Real> Neg for Normal3<T> {
+	type Output = Normal3<T>;
+
+	fn neg(self) -> Normal3<T> {
+		return Normal3 { x: -self.x, y: -self.y, z: -self.z };
+	}
+}
+
+impl<T: Real> Mul<T> for Normal3<T> {
+	type Output = Normal3<T>;
+
+	fn mul(self, s: T) -> Normal3<T> {
+		return Normal3 { x: self.x*s, y: self.y*s, z: self.z*s };
+	}
+}
+
+// The components are indexed 0 (x), 1 (y) and 2 (z)
+macro_rules! impl_index {
+	($name:ident) => {
+		impl<T> Index<usize> for $name<T> {
+			type Output = T;
+
+			fn index(&self, i: usize) -> &T {
+				return match i {
+					0 => &self.x,
+					1 => &self.y,
+					2 => &self.z,
+					_ => panic!("Index {} out of range for a three dimensional {}", i, stringify!($name))
+				};
+			}
+		}
+
+		impl<T> IndexMut<usize> for $name<T> {
+			fn index_mut(&mut self, i: usize) -> &mut T {
+				return match i {
+					0 => &mut self.x,
+					1 => &mut self.y,
+					2 => &mut self.z,
+					_ => panic!("Index {} out of range for a three dimensional {}", i, stringify!($name))
+				};
+			}
+		}
+	};
+}
+
+impl_index!(Vector3);
+impl_index!(Point3);
+impl_index!(Normal3);
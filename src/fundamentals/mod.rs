@@ -1,3 +1,16 @@
+pub mod math;
 pub mod vec3;
+pub mod binary;
 pub mod light;
 pub mod geometry;
+pub mod heightfield;
+pub mod random;
+pub mod noise;
+pub mod texture;
+pub mod bump;
+pub mod bvh;
+pub mod instance;
+pub mod scene;
+pub mod sdf;
+pub mod primitive;
+pub mod csg;
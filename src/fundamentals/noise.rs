@@ -0,0 +1,92 @@
+
+// Import requirements
+use super::vec3::*;
+use super::random::*;
+
+// Deterministic noise functions for procedural textures. The lattice points get their random values from a hash of
+// their coordinates instead of a permutation table, so the noise is the same everywhere and needs no state.
+
+// Gradients of Perlin's improved noise, the directions to the edges of a cube
+const GRADIENTS: [[f64; 3]; 12] = [
+	[1.0, 1.0, 0.0], [-1.0, 1.0, 0.0], [1.0, -1.0, 0.0], [-1.0, -1.0, 0.0],
+	[1.0, 0.0, 1.0], [-1.0, 0.0, 1.0], [1.0, 0.0, -1.0], [-1.0, 0.0, -1.0],
+	[0.0, 1.0, 1.0], [0.0, -1.0, 1.0], [0.0, 1.0, -1.0], [0.0, -1.0, -1.0]
+];
+
+// Perlin's improved gradient noise, roughly in [-1, 1] with features about one unit apart
+pub fn perlin_noise(p: &Vector3) -> f64 {
+	let (x0, y0, z0) = (p.x.floor(), p.y.floor(), p.z.floor());
+	let (fx, fy, fz) = (p.x - x0, p.y - y0, p.z - z0);
+	let (ix, iy, iz) = (x0 as i64, y0 as i64, z0 as i64);
+
+	// Contribution of the gradient at a corner of the cell, dotted with the offset from the corner
+	let corner = |dx: i64, dy: i64, dz: i64| -> f64 {
+		let g = GRADIENTS[(hash_lattice(ix + dx, iy + dy, iz + dz) % 12) as usize];
+		return g[0] * (fx - dx as f64) + g[1] * (fy - dy as f64) + g[2] * (fz - dz as f64);
+	};
+
+	let (u, v, w) = (fade(fx), fade(fy), fade(fz));
+
+	let x00 = lerp(corner(0, 0, 0), corner(1, 0, 0), u);
+	let x10 = lerp(corner(0, 1, 0), corner(1, 1, 0), u);
+	let x01 = lerp(corner(0, 0, 1), corner(1, 0, 1), u);
+	let x11 = lerp(corner(0, 1, 1), corner(1, 1, 1), u);
+
+	return lerp(lerp(x00, x10, v), lerp(x01, x11, v), w);
+}
+
+// Sum of the absolute noise of several octaves, each of double frequency and half amplitude. Gives the veins of
+// marble and the irregular rings of wood.
+pub fn turbulence(p: &Vector3, octaves: u32) -> f64 {
+	let mut sum = 0.0;
+	let mut frequency = 1.0;
+	let mut amplitude = 1.0;
+
+	for _ in 0..octaves {
+		sum += amplitude * perlin_noise(&v3_scale(p, frequency)).abs();
+		frequency *= 2.0;
+		amplitude *= 0.5;
+	}
+
+	return sum;
+}
+
+// Worley (cellular) noise: the distance to the nearest of randomly placed feature points, one in every unit cell.
+// 0 at the feature points, rarely above 1.
+pub fn worley_noise(p: &Vector3) -> f64 {
+	let (ix, iy, iz) = (p.x.floor() as i64, p.y.floor() as i64, p.z.floor() as i64);
+	let mut nearest = f64::MAX;
+
+	for dz in -1..=1 {
+		for dy in -1..=1 {
+			for dx in -1..=1 {
+				let (cx, cy, cz) = (ix + dx, iy + dy, iz + dz);
+				let hash = hash_lattice(cx, cy, cz);
+
+				// The feature point of the cell, from three 21 bit parts of the hash
+				let feature = Vector3 {
+					x: cx as f64 + ((hash & 0x1fffff) as f64) / 2097152.0,
+					y: cy as f64 + (((hash >> 21) & 0x1fffff) as f64) / 2097152.0,
+					z: cz as f64 + (((hash >> 42) & 0x1fffff) as f64) / 2097152.0
+				};
+
+				nearest = nearest.min(v3_len(&v3_delta(&feature, p)));
+			}
+		}
+	}
+
+	return nearest;
+}
+
+fn hash_lattice(x: i64, y: i64, z: i64) -> u64 {
+	return mix_bits(mix_bits(mix_bits(x as u64) ^ (y as u64)) ^ (z as u64));
+}
+
+// Smooth step with vanishing first and second derivative at 0 and 1
+fn fade(t: f64) -> f64 {
+	return t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+}
+
+fn lerp(a: f64, b: f64, t: f64) -> f64 {
+	return a + (b - a) * t;
+}
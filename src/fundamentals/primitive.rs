@@ -0,0 +1,154 @@
+
+// Import requirements
+use super::light::*;
+use super::geometry::*;
+use super::texture::*;
+use super::bump::*;
+use super::bvh::*;
+use super::csg::*;
+use super::sdf::*;
+use super::heightfield::*;
+use super::math::vector::*;
+use super::math::transform::*;
+
+// The bounded primitives, each given in its own object space. Solids are closed, disks, quads and heightfields are
+// thin surfaces seen from both sides.
+#[derive(Clone, PartialEq, Debug)]
+pub enum PrimitiveKind {
+	// Centered at the origin. Unlike the spheres of the space it can be placed by any transform and combined by CSG.
+	Sphere { radius: f64 },
+	// Centered at the origin, reaching half_size along each axis
+	Box { half_size: Vector3 },
+	// In the plane y = 0, centered at the origin, facing plus y
+	Disk { radius: f64 },
+	// Parallelogram with a corner at the origin, spanned by the two edges
+	Quad { edge_u: Vector3, edge_v: Vector3 },
+	// Around the y axis from y = 0 to y = height, closed by caps at both ends
+	Cylinder { radius: f64, height: f64 },
+	// Around the y axis with the base (closed by a cap) at y = 0 and the apex at y = height
+	Cone { radius: f64, height: f64 },
+	// Around the y axis in the plane y = 0, the tube of minor_radius going around the circle of major_radius
+	Torus { major_radius: f64, minor_radius: f64 },
+	// Solids combined by constructive solid geometry
+	Csg(Box<CsgNode>),
+	// Solid given by a signed distance function
+	Sdf(Box<Sdf>),
+	// Terrain over the unit square of the plane y = 0, see Heightfield
+	Heightfield(Heightfield)
+}
+
+impl PrimitiveKind {
+
+	pub fn name(&self) -> &'static str {
+		return match self {
+			PrimitiveKind::Sphere { .. } => "sphere",
+			PrimitiveKind::Box { .. } => "box",
+			PrimitiveKind::Disk { .. } => "disk",
+			PrimitiveKind::Quad { .. } => "quad",
+			PrimitiveKind::Cylinder { .. } => "cylinder",
+			PrimitiveKind::Cone { .. } => "cone",
+			PrimitiveKind::Torus { .. } => "torus",
+			PrimitiveKind::Csg(_) => "csg",
+			PrimitiveKind::Sdf(_) => "sdf",
+			PrimitiveKind::Heightfield(_) => "heightfield"
+		};
+	}
+
+	// Returns whether the primitive encloses an inside, which it has to as a leaf of a CSG tree
+	pub fn is_solid(&self) -> bool {
+		return match self {
+			PrimitiveKind::Sphere { .. } | PrimitiveKind::Box { .. } | PrimitiveKind::Cylinder { .. } | PrimitiveKind::Cone { .. } | PrimitiveKind::Torus { .. } | PrimitiveKind::Csg(_) => true,
+			PrimitiveKind::Disk { .. } | PrimitiveKind::Quad { .. } | PrimitiveKind::Sdf(_) | PrimitiveKind::Heightfield(_) => false
+		};
+	}
+
+	// Returns whether points can be picked uniformly on the surface, which emitters need to be sampled as lights
+	pub fn can_sample_surface(&self) -> bool {
+		return !matches!(self, PrimitiveKind::Csg(_) | PrimitiveKind::Sdf(_) | PrimitiveKind::Heightfield(_));
+	}
+
+	// Returns the bounds in object space
+	pub fn bounds(&self) -> Aabb {
+		return match self {
+			PrimitiveKind::Sphere { radius } => sphere_bounds(&Vector3::zero(), *radius),
+			PrimitiveKind::Box { half_size } => Aabb { min: -*half_size, max: *half_size },
+			PrimitiveKind::Disk { radius } => Aabb { min: Vector3::new(-radius, 0.0, -radius), max: Vector3::new(*radius, 0.0, *radius) },
+			PrimitiveKind::Quad { edge_u, edge_v } => Aabb::empty().add_point(&Vector3::zero()).add_point(edge_u).add_point(edge_v).add_point(&(*edge_u + *edge_v)),
+			PrimitiveKind::Cylinder { radius, height } | PrimitiveKind::Cone { radius, height } => Aabb { min: Vector3::new(-radius, 0.0, -radius), max: Vector3::new(*radius, *height, *radius) },
+			PrimitiveKind::Torus { major_radius, minor_radius } => {
+				let r = major_radius + minor_radius;
+				Aabb { min: Vector3::new(-r, -minor_radius, -r), max: Vector3::new(r, *minor_radius, r) }
+			}
+			PrimitiveKind::Csg(node) => node.bounds(),
+			PrimitiveKind::Sdf(sdf) => sdf.root.bounds(),
+			PrimitiveKind::Heightfield(field) => field.bounds()
+		};
+	}
+}
+
+// A primitive placed in the space by the transform from its object space to world space. Primitives emitting light
+// are seen glowing by the camera and light the space like area lights, see PrimitiveSet. CSG solids, SDFs and
+// heightfields can't be sampled that way and light the space through the global illumination rays hitting them.
+#[derive(Clone, Debug)]
+pub struct Primitive {
+	pub kind: PrimitiveKind,
+	pub transform: Transform,
+	pub material_color: Texture,
+	pub surface_detail: SurfaceDetail,
+	pub emission: LightColor,
+	pub visibility: Visibility
+}
+
+impl Primitive {
+
+	// Returns the primitive placed by the transform, white, smooth, not emitting and visible to all rays
+	pub fn new(kind: PrimitiveKind, transform: Transform) -> Primitive {
+		return Primitive {
+			kind,
+			transform,
+			material_color: Texture::Constant(LightColor { r: 1.0, g: 1.0, b: 1.0 }),
+			surface_detail: SurfaceDetail::Smooth,
+			emission: LightColor { r: 0.0, g: 0.0, b: 0.0 },
+			visibility: Visibility::all()
+		};
+	}
+
+	// Returns the box between the two corners, aligned to the axes of the space
+	pub fn axis_aligned_box(min: Vector3, max: Vector3) -> Primitive {
+		let kind = PrimitiveKind::Box { half_size: (max - min) * 0.5 };
+		return Primitive::new(kind, Transform::translate((min + max) * 0.5));
+	}
+
+	pub fn is_emitter(&self) -> bool {
+		return self.emission.r > 0.0 || self.emission.g > 0.0 || self.emission.b > 0.0;
+	}
+}
+
+// The primitives of the space together with a hierarchy over their world space bounds and the emitters that are
+// sampled like lights
+#[derive(Debug)]
+pub struct PrimitiveSet {
+	pub primitives: Vec<Primitive>,
+	pub bvh: Bvh,
+	// Indices of the emitting primitives whose surface can be sampled
+	pub emitters: Vec<usize>
+}
+
+impl PrimitiveSet {
+
+	pub fn new(primitives: Vec<Primitive>) -> PrimitiveSet {
+		let bounds: Vec<Aabb> = primitives.iter().map(|p| p.kind.bounds().transform(&p.transform)).collect();
+		let emitters = (0..primitives.len()).filter(|&i| primitives[i].is_emitter() && primitives[i].kind.can_sample_surface()).collect();
+		return PrimitiveSet { bvh: Bvh::new(&bounds), primitives, emitters };
+	}
+
+	// Whether the primitive with the index is sampled like a light, so that its emission is not counted again when a
+	// global illumination ray hits it
+	pub fn is_sampled_emitter(&self, index: usize) -> bool {
+		return self.emitters.contains(&index);
+	}
+
+	pub fn empty() -> PrimitiveSet {
+		return PrimitiveSet::new(Vec::new());
+	}
+}
@@ -0,0 +1,86 @@
+
+// Import requirements
+use rand::RngCore;
+
+// Small, fast and fully deterministic random generator (PCG-XSH-RR 64/32). Unlike thread_rng() its complete
+// state is two integers, so it can be seeded per pixel and per sample and reproduces the same numbers everywhere.
+#[derive(Copy, Clone)]
+pub struct Pcg32 {
+	pub state: u64,
+	pub increment: u64
+}
+
+const PCG_MULTIPLIER: u64 = 6364136223846793005;
+
+impl Pcg32 {
+
+	// Creates a generator for the given seed. Different streams produce independent sequences for the same seed.
+	pub fn new(seed: u64, stream: u64) -> Pcg32 {
+		let mut rng = Pcg32 { state: 0, increment: (stream << 1) | 1 };
+		rng.step();
+		rng.state = rng.state.wrapping_add(seed);
+		rng.step();
+		return rng;
+	}
+
+	fn step(&mut self) {
+		self.state = self.state.wrapping_mul(PCG_MULTIPLIER).wrapping_add(self.increment);
+	}
+}
+
+impl RngCore for Pcg32 {
+
+	fn next_u32(&mut self) -> u32 {
+		let old_state = self.state;
+		self.step();
+		let xor_shifted = (((old_state >> 18) ^ old_state) >> 27) as u32;
+		let rotation = (old_state >> 59) as u32;
+		return xor_shifted.rotate_right(rotation);
+	}
+
+	fn next_u64(&mut self) -> u64 {
+		let high = self.next_u32() as u64;
+		let low = self.next_u32() as u64;
+		return (high << 32) | low;
+	}
+
+	fn fill_bytes(&mut self, dest: &mut [u8]) {
+		for chunk in dest.chunks_mut(4) {
+			let bytes = self.next_u32().to_le_bytes();
+			chunk.copy_from_slice(&bytes[..chunk.len()]);
+		}
+	}
+
+	fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+		self.fill_bytes(dest);
+		return Ok(());
+	}
+}
+
+// SplitMix64 finalizer, scrambles all bits of the input so that neighbouring indices give unrelated seeds
+pub fn mix_bits(v: u64) -> u64 {
+	let mut z = v.wrapping_add(0x9E3779B97F4A7C15);
+	z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+	z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+	return z ^ (z >> 31);
+}
+
+// FNV-1a over the values, each one mixed in whole. Hashes bytes as in the standard, and larger values (the bits of
+// floating point numbers) without splitting them up.
+pub fn fnv1a<I: IntoIterator<Item = u64>>(values: I) -> u64 {
+	let mut hash: u64 = 0xcbf29ce484222325;
+
+	for v in values {
+		hash ^= v;
+		hash = hash.wrapping_mul(0x100000001b3);
+	}
+
+	return hash;
+}
+
+// Returns the generator for one sample of one pixel. It only depends on the global seed, the pixel index and the
+// sample index, so the result does not depend on which thread renders the pixel or in which order.
+pub fn rng_for_pixel_sample(seed: u64, pixel_index: u64, sample_index: u64) -> Pcg32 {
+	let pixel_seed = mix_bits(seed ^ mix_bits(pixel_index));
+	return Pcg32::new(mix_bits(pixel_seed ^ sample_index), seed);
+}
@@ -0,0 +1,223 @@
+
+// Import requirements
+use super::light::*;
+use super::geometry::*;
+use super::texture::*;
+use super::instance::*;
+use super::primitive::*;
+use super::math::vector::*;
+use super::math::transform::*;
+use std::sync::Arc;
+
+// Relative tolerance for recognizing transforms that keep spheres spheres
+const SIMILARITY_TOLERANCE: f64 = 1e-9;
+
+// What a node of the scene graph holds, given in the space of the node
+#[derive(Clone, Debug)]
+pub enum NodeContent {
+	Group(Vec<SceneNode>),
+	Sphere(Sphere),
+	Plane(Plane),
+	// Boxed, as primitives are much larger than the other contents
+	Primitive(Box<Primitive>),
+	// A shared shape, optionally with the textures of its primitives replaced
+	Shape(Arc<Shape>, Option<Texture>),
+	PointLight(PointLight),
+	DirectionalLight(DirectionalLight)
+}
+
+// A named node of the scene graph. The transform takes the node into the space of its parent, so transforms nest
+// from the root down. The visibility applies to the node and everything below it, lights ignore it.
+#[derive(Clone, Debug)]
+pub struct SceneNode {
+	pub name: String,
+	pub transform: Transform,
+	pub visibility: Visibility,
+	pub content: NodeContent
+}
+
+impl SceneNode {
+
+	// Returns the node with the identity transform, visible to all rays
+	pub fn new(name: &str, content: NodeContent) -> SceneNode {
+		return SceneNode { name: String::from(name), transform: Transform::identity(), visibility: Visibility::all(), content };
+	}
+
+	pub fn group(name: &str, children: Vec<SceneNode>) -> SceneNode {
+		return SceneNode::new(name, NodeContent::Group(children));
+	}
+
+	// Returns the first node with the name, searching this node and then its children depth first
+	pub fn find(&self, name: &str) -> Option<&SceneNode> {
+		if self.name == name {
+			return Some(self);
+		}
+
+		if let NodeContent::Group(children) = &self.content {
+			return children.iter().find_map(|child| child.find(name));
+		}
+
+		return None;
+	}
+
+	pub fn find_mut(&mut self, name: &str) -> Option<&mut SceneNode> {
+		if self.name == name {
+			return Some(self);
+		}
+
+		if let NodeContent::Group(children) = &mut self.content {
+			return children.iter_mut().find_map(|child| child.find_mut(name));
+		}
+
+		return None;
+	}
+
+	// Returns the transform from the space of the named node to the space of this node
+	fn find_transform(&self, name: &str) -> Option<Transform> {
+		if self.name == name {
+			return Some(self.transform);
+		}
+
+		if let NodeContent::Group(children) = &self.content {
+			return children.iter().find_map(|child| child.find_transform(name)).map(|t| self.transform * t);
+		}
+
+		return None;
+	}
+}
+
+// Hierarchy of named nodes describing a scene. Scripts and animations change the nodes, found by their names, and
+// build the space to render from the graph again.
+#[derive(Clone, Debug)]
+pub struct SceneGraph {
+	pub root: SceneNode
+}
+
+impl SceneGraph {
+
+	pub fn new(root: SceneNode) -> SceneGraph {
+		return SceneGraph { root };
+	}
+
+	pub fn find(&self, name: &str) -> Option<&SceneNode> {
+		return self.root.find(name);
+	}
+
+	pub fn find_mut(&mut self, name: &str) -> Option<&mut SceneNode> {
+		return self.root.find_mut(name);
+	}
+
+	// Returns the transform from the space of the named node to world space
+	pub fn world_transform(&self, name: &str) -> Option<Transform> {
+		return self.root.find_transform(name);
+	}
+
+	// Flattens the graph into the space rendered. Transforms and visibilities are combined along the paths from the
+	// root. Planes, lights and spheres under transforms keeping them spheres end up in world space, other spheres
+	// become instances of a shape of their own. The other primitives keep the transform combined with their own.
+	pub fn build(&self) -> Space {
+		let mut space = Space { spheres: Vec::new(), planes: Vec::new(), directional_lights: Vec::new(), point_lights: Vec::new(), primitives: PrimitiveSet::empty(), instances: InstanceSet::empty() };
+		let mut primitives: Vec<Primitive> = Vec::new();
+		let mut instances: Vec<Instance> = Vec::new();
+
+		flatten_node(&self.root, &Transform::identity(), &Visibility::all(), &mut space, &mut primitives, &mut instances);
+
+		space.primitives = PrimitiveSet::new(primitives);
+		space.instances = InstanceSet::new(instances);
+		return space;
+	}
+}
+
+fn flatten_node(node: &SceneNode, parent_transform: &Transform, parent_visibility: &Visibility, space: &mut Space, primitives: &mut Vec<Primitive>, instances: &mut Vec<Instance>) {
+	let transform = *parent_transform * node.transform;
+	let visibility = parent_visibility.and(&node.visibility);
+
+	// Nodes without transforms are taken as they are, so no rounding creeps in
+	let is_identity = transform == Transform::identity();
+
+	match &node.content {
+		NodeContent::Group(children) => {
+			for child in children {
+				flatten_node(child, &transform, &visibility, space, primitives, instances);
+			}
+		}
+		NodeContent::Sphere(sphere) => {
+			let mut sphere = sphere.clone();
+			sphere.visibility = sphere.visibility.and(&visibility);
+
+			if is_identity {
+				space.spheres.push(sphere);
+			} else if let Some(scale) = get_similarity_scale(&transform) {
+				sphere.center = transform.apply_point(Point3::from(sphere.center)).to_vector();
+				sphere.radius *= scale;
+				space.spheres.push(sphere);
+			} else {
+				instances.push(Instance { shape: Arc::new(Shape::new(vec![sphere])), transform, material_override: None, visibility: Visibility::all() });
+			}
+		}
+		NodeContent::Plane(plane) => {
+			let mut plane = plane.clone();
+			plane.visibility = plane.visibility.and(&visibility);
+
+			if !is_identity {
+				plane.center = transform.apply_point(Point3::from(plane.center)).to_vector();
+				plane.normal = transform.apply_normal(Normal3::from(plane.normal)).to_vector().normalize();
+			}
+
+			space.planes.push(plane);
+		}
+		NodeContent::Primitive(primitive) => {
+			let mut primitive = primitive.as_ref().clone();
+			primitive.transform = transform * primitive.transform;
+			primitive.visibility = primitive.visibility.and(&visibility);
+			primitives.push(primitive);
+		}
+		NodeContent::Shape(shape, material_override) => {
+			instances.push(Instance { shape: shape.clone(), transform, material_override: material_override.clone(), visibility });
+		}
+		NodeContent::PointLight(light) => {
+			let mut light = light.clone();
+
+			if !is_identity {
+				light.position = transform.apply_point(Point3::from(light.position)).to_vector();
+			}
+
+			space.point_lights.push(light);
+		}
+		NodeContent::DirectionalLight(light) => {
+			let mut light = light.clone();
+
+			if !is_identity {
+				light.direction = transform.apply_vector(light.direction);
+			}
+
+			space.directional_lights.push(light);
+		}
+	}
+}
+
+// Returns the scale factor of a transform made of rotations, translations and uniform scalings only, which take
+// spheres to spheres, or None for other transforms
+fn get_similarity_scale(transform: &Transform) -> Option<f64> {
+	let m = &transform.matrix.m;
+
+	if m[3] != [0.0, 0.0, 0.0, 1.0] {
+		return None;
+	}
+
+	let columns: Vec<Vector3> = (0..3).map(|j| Vector3 { x: m[0][j], y: m[1][j], z: m[2][j] }).collect();
+	let scale = columns[0].length();
+	let tolerance = SIMILARITY_TOLERANCE * scale * scale;
+
+	for (i, a) in columns.iter().enumerate() {
+		if (a.length_squared() - scale * scale).abs() > tolerance {
+			return None;
+		}
+
+		if columns[i + 1..].iter().any(|b| a.dot(*b).abs() > tolerance) {
+			return None;
+		}
+	}
+
+	return Some(scale);
+}
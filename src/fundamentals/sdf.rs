@@ -0,0 +1,186 @@
+
+// Import requirements
+use super::bvh::*;
+use super::math::vector::*;
+
+// Default precision of the sphere tracing, the distance to the surface counted as a hit
+pub const DEFAULT_SDF_EPSILON: f64 = 1e-4;
+
+// Default number of steps along a ray before the sphere tracing gives up
+pub const DEFAULT_SDF_MAX_STEPS: u32 = 256;
+
+// Node of a signed distance function, negative within the solid and positive outside. The shapes are centered at the
+// origin, the other nodes change the distance functions of their children.
+#[derive(Clone, PartialEq, Debug)]
+pub enum SdfNode {
+	Sphere { radius: f64 },
+	// Reaching half_size along each axis
+	Box { half_size: Vector3 },
+	// Reaching half_size along each axis, with the edges rounded by radius
+	RoundBox { half_size: Vector3, radius: f64 },
+	// The points within radius of the segment from a to b
+	Capsule { a: Vector3, b: Vector3, radius: f64 },
+	// Union blending the two solids where they come closer than smoothness
+	SmoothUnion { a: Box<SdfNode>, b: Box<SdfNode>, smoothness: f64 },
+	// Twists the solid around the y axis by rate radians per unit of height
+	Twist { node: Box<SdfNode>, rate: f64 },
+	// Repeats the solid count times to each side along each axis, spacing apart. Axes with a count of 0 are not
+	// repeated. The solid should fit within the spacing.
+	Repeat { node: Box<SdfNode>, spacing: Vector3, count: Vector3 },
+	Translate { node: Box<SdfNode>, offset: Vector3 }
+}
+
+impl SdfNode {
+
+	pub fn smooth_union(a: SdfNode, b: SdfNode, smoothness: f64) -> SdfNode {
+		return SdfNode::SmoothUnion { a: Box::new(a), b: Box::new(b), smoothness };
+	}
+
+	pub fn twist(node: SdfNode, rate: f64) -> SdfNode {
+		return SdfNode::Twist { node: Box::new(node), rate };
+	}
+
+	// Returns the repetition of the node, the counts need to be finite and not negative
+	pub fn repeat(node: SdfNode, spacing: Vector3, count: Vector3) -> SdfNode {
+		assert!(SdfNode::is_repeat_count(&count), "SDF repetitions need finite counts of at least 0");
+		return SdfNode::Repeat { node: Box::new(node), spacing, count };
+	}
+
+	pub fn translate(node: SdfNode, offset: Vector3) -> SdfNode {
+		return SdfNode::Translate { node: Box::new(node), offset };
+	}
+
+	// Returns whether the counts of a repetition can bound its cells, which negative or NaN ones can't
+	pub fn is_repeat_count(count: &Vector3) -> bool {
+		return (0..3).all(|axis| count[axis] >= 0.0 && count[axis].is_finite());
+	}
+
+	pub fn name(&self) -> &'static str {
+		return match self {
+			SdfNode::Sphere { .. } => "sphere",
+			SdfNode::Box { .. } => "box",
+			SdfNode::RoundBox { .. } => "round-box",
+			SdfNode::Capsule { .. } => "capsule",
+			SdfNode::SmoothUnion { .. } => "smooth-union",
+			SdfNode::Twist { .. } => "twist",
+			SdfNode::Repeat { .. } => "repeat",
+			SdfNode::Translate { .. } => "translate"
+		};
+	}
+
+	// Returns the signed distance of the point to the surface. Twists and smooth unions only bound the distance, see
+	// lipschitz_bound.
+	pub fn distance(&self, p: &Vector3) -> f64 {
+		return match self {
+			SdfNode::Sphere { radius } => p.length() - radius,
+			SdfNode::Box { half_size } => get_box_distance(p, half_size),
+			SdfNode::RoundBox { half_size, radius } => get_box_distance(p, &(*half_size - Vector3::new(*radius, *radius, *radius))) - radius,
+			SdfNode::Capsule { a, b, radius } => {
+				let (pa, ba) = (*p - *a, *b - *a);
+				let h = (pa.dot(ba) / ba.dot(ba)).clamp(0.0, 1.0);
+				(pa - ba * h).length() - radius
+			}
+			SdfNode::SmoothUnion { a, b, smoothness } => {
+				let (d1, d2) = (a.distance(p), b.distance(p));
+
+				// Polynomial smooth minimum, which digs at most smoothness / 4 below the plain one
+				if *smoothness <= 0.0 {
+					return d1.min(d2);
+				}
+
+				let h = (0.5 + 0.5 * (d2 - d1) / smoothness).clamp(0.0, 1.0);
+				d2 + (d1 - d2) * h - smoothness * h * (1.0 - h)
+			}
+			SdfNode::Twist { node, rate } => {
+				let (sin, cos) = (rate * p.y).sin_cos();
+				node.distance(&Vector3::new(cos * p.x - sin * p.z, p.y, sin * p.x + cos * p.z))
+			}
+			SdfNode::Repeat { node, spacing, count } => {
+				let mut q = *p;
+
+				for axis in 0..3 {
+					if spacing[axis] > 0.0 {
+						let cell = (p[axis] / spacing[axis]).round().clamp(-count[axis], count[axis]);
+						q[axis] -= spacing[axis] * cell;
+					}
+				}
+
+				node.distance(&q)
+			}
+			SdfNode::Translate { node, offset } => node.distance(&(*p - *offset))
+		};
+	}
+
+	// Returns bounds containing the solid
+	pub fn bounds(&self) -> Aabb {
+		return match self {
+			SdfNode::Sphere { radius } => sphere_bounds(&Vector3::zero(), *radius),
+			SdfNode::Box { half_size } | SdfNode::RoundBox { half_size, .. } => Aabb { min: -*half_size, max: *half_size },
+			SdfNode::Capsule { a, b, radius } => sphere_bounds(a, *radius).union(&sphere_bounds(b, *radius)),
+			SdfNode::SmoothUnion { a, b, smoothness } => {
+				let bounds = a.bounds().union(&b.bounds());
+				let margin = Vector3::new(1.0, 1.0, 1.0) * (smoothness.max(0.0) * 0.25);
+				Aabb { min: bounds.min - margin, max: bounds.max + margin }
+			}
+			SdfNode::Twist { node, .. } => {
+				let bounds = node.bounds();
+				let r = get_radius_around_y(&bounds);
+				Aabb { min: Vector3::new(-r, bounds.min.y, -r), max: Vector3::new(r, bounds.max.y, r) }
+			}
+			SdfNode::Repeat { node, spacing, count } => {
+				let bounds = node.bounds();
+				let reach = spacing.mul_elements(*count);
+				Aabb { min: bounds.min - reach, max: bounds.max + reach }
+			}
+			SdfNode::Translate { node, offset } => {
+				let bounds = node.bounds();
+				Aabb { min: bounds.min + *offset, max: bounds.max + *offset }
+			}
+		};
+	}
+
+	// Returns how much faster than the distance to the surface the distance function may change. Twisting stretches
+	// the space, most at the largest radius, so the distances are divided by this for safe steps.
+	pub fn lipschitz_bound(&self) -> f64 {
+		return match self {
+			SdfNode::Sphere { .. } | SdfNode::Box { .. } | SdfNode::RoundBox { .. } | SdfNode::Capsule { .. } => 1.0,
+			SdfNode::SmoothUnion { a, b, .. } => a.lipschitz_bound().max(b.lipschitz_bound()),
+			SdfNode::Twist { node, rate } => {
+				let stretch = rate * get_radius_around_y(&node.bounds());
+				node.lipschitz_bound() * (1.0 + stretch * stretch).sqrt()
+			}
+			SdfNode::Repeat { node, .. } | SdfNode::Translate { node, .. } => node.lipschitz_bound()
+		};
+	}
+}
+
+// A solid given by a signed distance function, found along rays by sphere tracing. epsilon is the distance to the
+// surface counted as a hit and also the step of the central differences giving the normals, max_steps the number of
+// steps after which a ray counts as missing.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Sdf {
+	pub root: SdfNode,
+	pub epsilon: f64,
+	pub max_steps: u32
+}
+
+impl Sdf {
+
+	pub fn new(root: SdfNode) -> Sdf {
+		return Sdf { root, epsilon: DEFAULT_SDF_EPSILON, max_steps: DEFAULT_SDF_MAX_STEPS };
+	}
+}
+
+// Exact signed distance to the box reaching half_size along each axis
+fn get_box_distance(p: &Vector3, half_size: &Vector3) -> f64 {
+	let q = Vector3::new(p.x.abs() - half_size.x, p.y.abs() - half_size.y, p.z.abs() - half_size.z);
+	let outside = Vector3::new(q.x.max(0.0), q.y.max(0.0), q.z.max(0.0)).length();
+	return outside + q.x.max(q.y).max(q.z).min(0.0);
+}
+
+// Largest distance from the y axis within the bounds
+fn get_radius_around_y(bounds: &Aabb) -> f64 {
+	let x = bounds.min.x.abs().max(bounds.max.x.abs());
+	let z = bounds.min.z.abs().max(bounds.max.z.abs());
+	return (x * x + z * z).sqrt();
+}
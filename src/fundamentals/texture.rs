@@ -0,0 +1,171 @@
+
+// Import requirements
+use super::vec3::*;
+use super::light::*;
+use super::noise::*;
+use super::random::*;
+use std::sync::Arc;
+
+// Width of the lines of the grid pattern, relative to the cell size
+const GRID_LINE_WIDTH: f64 = 0.05;
+
+// Octaves of the turbulence of marble and wood
+const TURBULENCE_OCTAVES: u32 = 6;
+
+// How strongly the turbulence distorts the stripes of marble and the rings of wood
+const MARBLE_TURBULENCE: f64 = 5.0;
+const WOOD_TURBULENCE: f64 = 0.3;
+
+// Color of a surface, varying over the surface. Images, checkerboard and grid are mapped with the texture coordinates
+// (u, v) of the surface, the noise based textures are solid textures evaluated at the point in the space of the
+// primitive (relative to its center).
+#[derive(Clone, Debug)]
+pub enum Texture {
+	// The same color everywhere
+	Constant(LightColor),
+	Image(ImageTexture),
+	// Squares of color_a and color_b alternating in u and v
+	Checker(PatternTexture),
+	// Lines of color_b along the cell borders on a background of color_a
+	Grid(PatternTexture),
+	// Smooth random blend between both colors
+	Perlin(PatternTexture),
+	// Cells around random points, color_a at the points and color_b at the borders of the cells
+	Worley(PatternTexture),
+	// Turbulent stripes along x
+	Marble(PatternTexture),
+	// Irregular rings around the y axis
+	Wood(PatternTexture)
+}
+
+// Parameters of the patterns and procedural textures
+#[derive(Copy, Clone, Debug)]
+pub struct PatternTexture {
+	pub color_a: LightColor,
+	pub color_b: LightColor,
+	// Cells per unit of the texture coordinates (checker and grid) or frequency per unit of space (noise)
+	pub scale: f64
+}
+
+// How texture coordinates outside of [0, 1) are mapped into the image
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum WrapMode {
+	// The image is tiled
+	Repeat,
+	// The border pixels are extended
+	Clamp,
+	// The image is tiled, every other copy being mirrored so that there are no seams
+	Mirror
+}
+
+impl WrapMode {
+
+	// Returns the wrap mode for the name used on the command line
+	pub fn from_name(name: &str) -> Option<WrapMode> {
+		return match name {
+			"repeat" => Some(WrapMode::Repeat),
+			"clamp" => Some(WrapMode::Clamp),
+			"mirror" => Some(WrapMode::Mirror),
+			_ => None
+		};
+	}
+
+	pub fn name(&self) -> &'static str {
+		return match self {
+			WrapMode::Repeat => "repeat",
+			WrapMode::Clamp => "clamp",
+			WrapMode::Mirror => "mirror"
+		};
+	}
+
+	// Maps a pixel index into 0..size
+	fn apply(&self, i: i64, size: i64) -> i64 {
+		return match self {
+			WrapMode::Repeat => i.rem_euclid(size),
+			WrapMode::Clamp => i.clamp(0, size - 1),
+			WrapMode::Mirror => {
+				let m = i.rem_euclid(2 * size);
+				if m < size { m } else { 2 * size - 1 - m }
+			}
+		};
+	}
+}
+
+// Image in linear light, bilinearly filtered. (0, 0) is the top left corner of the image, v pointing down. The pixels
+// are shared, so textures of the same image can be cloned cheaply.
+#[derive(Clone)]
+pub struct ImageTexture {
+	pub width: i32,
+	pub height: i32,
+	pub pixels: Arc<Vec<LightColor>>,
+	pub wrap: WrapMode,
+	// Repetitions of the image per unit of the texture coordinates
+	pub scale: f64
+}
+
+impl ImageTexture {
+
+	// Returns the bilinear interpolation of the four pixels around (u, v)
+	pub fn evaluate(&self, u: f64, v: f64) -> LightColor {
+		let x = u * self.scale * (self.width as f64) - 0.5;
+		let y = v * self.scale * (self.height as f64) - 0.5;
+		let (x0, y0) = (x.floor(), y.floor());
+		let (fx, fy) = (x - x0, y - y0);
+
+		let pixel = |dx: i64, dy: i64| -> &LightColor {
+			let px = self.wrap.apply(x0 as i64 + dx, self.width as i64);
+			let py = self.wrap.apply(y0 as i64 + dy, self.height as i64);
+			return &self.pixels[(py * (self.width as i64) + px) as usize];
+		};
+
+		let top = mix(pixel(0, 0), pixel(1, 0), fx);
+		let bottom = mix(pixel(0, 1), pixel(1, 1), fx);
+		return mix(&top, &bottom, fy);
+	}
+}
+
+// The pixels are summarized by a hash, which is enough for the scene hash and keeps the output short
+impl std::fmt::Debug for ImageTexture {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		let hash = fnv1a(self.pixels.iter().flat_map(|p| [p.r.to_bits(), p.g.to_bits(), p.b.to_bits()]));
+		return write!(f, "ImageTexture {{ width: {}, height: {}, pixels: {:016x}, wrap: {:?}, scale: {:?} }}", self.width, self.height, hash, self.wrap, self.scale);
+	}
+}
+
+impl Texture {
+
+	// Returns the color at the texture coordinates (u, v) and the point relative to the center of the primitive
+	pub fn evaluate(&self, u: f64, v: f64, point: &Vector3) -> LightColor {
+		return match self {
+			Texture::Constant(c) => *c,
+			Texture::Image(image) => image.evaluate(u, v),
+			Texture::Checker(p) => {
+				let cell = (u * p.scale).floor() as i64 + (v * p.scale).floor() as i64;
+				if cell.rem_euclid(2) == 0 { p.color_a } else { p.color_b }
+			}
+			Texture::Grid(p) => {
+				let (fu, fv) = ((u * p.scale).rem_euclid(1.0), (v * p.scale).rem_euclid(1.0));
+				let half = 0.5 * GRID_LINE_WIDTH;
+				let is_line = fu < half || fu >= 1.0 - half || fv < half || fv >= 1.0 - half;
+				if is_line { p.color_b } else { p.color_a }
+			}
+			Texture::Perlin(p) => mix(&p.color_a, &p.color_b, 0.5 + 0.5 * perlin_noise(&v3_scale(point, p.scale))),
+			Texture::Worley(p) => mix(&p.color_a, &p.color_b, worley_noise(&v3_scale(point, p.scale)).min(1.0)),
+			Texture::Marble(p) => {
+				let q = v3_scale(point, p.scale);
+				mix(&p.color_a, &p.color_b, 0.5 + 0.5 * (q.x + MARBLE_TURBULENCE * turbulence(&q, TURBULENCE_OCTAVES)).sin())
+			}
+			Texture::Wood(p) => {
+				let q = v3_scale(point, p.scale);
+				let rings = (q.x * q.x + q.z * q.z).sqrt() + WOOD_TURBULENCE * turbulence(&q, TURBULENCE_OCTAVES);
+
+				// Sharp transition at the end of every ring, like late wood
+				mix(&p.color_a, &p.color_b, rings.rem_euclid(1.0).powi(3))
+			}
+		};
+	}
+}
+
+fn mix(a: &LightColor, b: &LightColor, t: f64) -> LightColor {
+	return LightColor { r: a.r + (b.r - a.r) * t, g: a.g + (b.g - a.g) * t, b: a.b + (b.b - a.b) * t };
+}
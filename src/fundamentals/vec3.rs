@@ -2,13 +2,9 @@
 // Import random generator 
 use rand::prelude::*;
 
-// Positions and directions in space are using this structure
-#[derive(Copy, Clone)]
-pub struct Vector3 {
-	pub x: f64,
-	pub y: f64,
-	pub z: f64,
-}
+// Positions and directions in space are using this structure. It lives in the math module, together with its
+// operators; the functions of this module are kept as shorthands for the f64 vectors.
+pub use super::math::vector::Vector3;
 
 // Basis in three dimensional vector space
 pub struct Basis3 {
@@ -19,76 +15,67 @@ pub struct Basis3 {
 
 // Computes dot product of two vectors
 pub fn v3_dot_product(a: &Vector3, b: &Vector3) -> f64 {
-	return a.x*b.x + a.y*b.y + a.z*b.z; 
+	return a.dot(*b); 
 }
 
 // Computes length of vector
 pub fn v3_len(a: &Vector3) -> f64 {
-	return (a.x*a.x+a.y*a.y+a.z*a.z).sqrt();
+	return a.length();
 }
 
 // Normalizes vector 
 pub fn v3_normalize(a: &Vector3) -> Vector3 {
-	let len = v3_len(a);
-	return Vector3 {x: a.x/len, y: a.y/len, z: a.z/len};
+	return a.normalize();
 }
 
 // Computes a-b
 pub fn v3_delta(a: &Vector3, b: &Vector3) -> Vector3 {
-	Vector3 {x: a.x-b.x, y: a.y-b.y, z: a.z-b.z}
+	return *a - *b;
 }
 
 // Computes a+b
 pub fn v3_sum(a: &Vector3, b: &Vector3) -> Vector3 {
-	Vector3 {x: a.x+b.x, y: a.y+b.y, z: a.z+b.z}
+	return *a + *b;
 }
 
 // Computes a*s
 pub fn v3_scale(a: &Vector3, s: f64) -> Vector3 {
-	Vector3 {x: a.x*s, y: a.y*s, z: a.z*s}
+	return *a * s;
 }
 
 // Computes cross product of two vectors
 pub fn v3_cross_product(a: &Vector3, b: &Vector3) -> Vector3 {
-	return Vector3 {
-		x: a.y*b.z - a.z*b.y,
-		y: a.z*b.x - a.x*b.z,
-		z: a.x*b.y - a.y*b.x
-	};
+	return a.cross(*b);
 }
 
-// Returns random vector (non-normalized)
-pub fn v3_random() -> Vector3 {
-	let mut rng = rand::thread_rng();	
+// Returns random vector (non-normalized) drawn from the specified generator
+pub fn v3_random<R: Rng>(rng: &mut R) -> Vector3 {
 	return Vector3 { x: rng.gen::<f64>(), y: rng.gen::<f64>(), z: rng.gen::<f64>()};
 }
 
 // Returns random normalized vector 
-pub fn v3_random_normal() -> Vector3 {	
-	return v3_normalize(&v3_random());
+pub fn v3_random_normal<R: Rng>(rng: &mut R) -> Vector3 {	
+	return v3_normalize(&v3_random(rng));
 }
 
-// Computes normalized basis in which the first axis vector is equal to n
-pub fn v3_compute_basis_for_normal(n: &Vector3) -> Basis3 {
-
-	// Add random vector to the normal 
-	let some_random_point = v3_sum(&n, &v3_random());
-
-	// Project random position onto the normal to get the distance to the plane the normal is defining
-	let projection_of_point_onto_n: f64 = v3_dot_product(&n, &some_random_point);
-
-	// Get point on plane by subtracting the negatively scaled normal
-	let point_on_plane = v3_sum(&some_random_point, &v3_scale(&n, -projection_of_point_onto_n));
-
-	// Get second axis vector as normalized vector to that point on the plane 
-	let b1 = v3_normalize(&point_on_plane);
-
-	// The third axis vector we get by computing the cross product of the normal and b1
-	let b2 = v3_normalize(&v3_cross_product(&n, &b1));
+// Computes the orthonormal basis in which the first axis vector is equal to the normalized vector n, with
+// b1 x b2 = n. Always returns the same basis for the same n and has no singularity (Duff et al., "Building an
+// Orthonormal Basis, Revisited", 2017, which fixes the precision problems of Frisvad's construction near -z).
+pub fn v3_orthonormal_basis(n: &Vector3) -> Basis3 {
+	let sign = 1.0f64.copysign(n.z);
+	let a = -1.0 / (sign + n.z);
+	let b = n.x * n.y * a;
 
 	return Basis3 {
-		b0: n.clone(),
-		b1: b1,
-		b2: b2
-	}
+		b0: *n,
+		b1: Vector3 { x: 1.0 + sign * n.x * n.x * a, y: sign * b, z: -sign * n.x },
+		b2: Vector3 { x: b, y: sign + n.y * n.y * a, z: -n.y }
+	};
+}
+
+// Computes normalized basis in which the first axis vector is equal to n. The generator is not used anymore, the
+// basis is the one of v3_orthonormal_basis.
+#[deprecated(note = "use v3_orthonormal_basis, which needs no random generator")]
+pub fn v3_compute_basis_for_normal<R: Rng>(n: &Vector3, _rng: &mut R) -> Basis3 {
+	return v3_orthonormal_basis(n);
 }
@@ -0,0 +1,255 @@
+// Minimal zlib (RFC 1950) / deflate (RFC 1951) decompressor for reading PNG images without external crates. It
+// supports all block types and decodes the Huffman codes bit by bit, which is slow but simple.
+
+// Import requirements
+use crate::output::deflate::adler32;
+use std::io::{Error, ErrorKind};
+
+const LENGTH_BASES: [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+const LENGTH_EXTRA_BITS: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DISTANCE_BASES: [u16; 30] = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577];
+const DISTANCE_EXTRA_BITS: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+
+// Order in which the code lengths of the code length alphabet are stored in a dynamic block
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+const MAX_BITS: usize = 15;
+
+// Reads bits least significant first, as deflate stores them
+struct BitReader<'a> {
+	bytes: &'a [u8],
+	position: usize,
+	bit_buffer: u32,
+	bit_count: u32
+}
+
+impl<'a> BitReader<'a> {
+
+	fn read_bits(&mut self, count: u32) -> std::io::Result<u32> {
+		while self.bit_count < count {
+			let byte = *self.bytes.get(self.position).ok_or_else(|| invalid_data("Unexpected end of the compressed data"))?;
+			self.position += 1;
+			self.bit_buffer |= (byte as u32) << self.bit_count;
+			self.bit_count += 8;
+		}
+
+		let value = self.bit_buffer & ((1u32 << count) - 1);
+		self.bit_buffer >>= count;
+		self.bit_count -= count;
+		return Ok(value);
+	}
+
+	// Skips the rest of the current byte
+	fn align(&mut self) {
+		self.bit_buffer = 0;
+		self.bit_count = 0;
+	}
+}
+
+// Canonical Huffman code, given by the number of codes of each length and the symbols ordered by their codes
+struct Huffman {
+	counts: [u16; MAX_BITS + 1],
+	symbols: Vec<u16>
+}
+
+impl Huffman {
+
+	// Builds the code from the code length of every symbol, 0 meaning that the symbol does not occur
+	fn new(lengths: &[u8]) -> Huffman {
+		let mut counts = [0u16; MAX_BITS + 1];
+
+		for &length in lengths {
+			counts[length as usize] += 1;
+		}
+
+		counts[0] = 0;
+
+		let mut offsets = [0u16; MAX_BITS + 2];
+
+		for length in 1..=MAX_BITS {
+			offsets[length + 1] = offsets[length] + counts[length];
+		}
+
+		let mut symbols = vec![0u16; lengths.len()];
+
+		for (symbol, &length) in lengths.iter().enumerate() {
+			if length != 0 {
+				symbols[offsets[length as usize] as usize] = symbol as u16;
+				offsets[length as usize] += 1;
+			}
+		}
+
+		return Huffman { counts, symbols };
+	}
+
+	// Reads one symbol. Huffman codes are stored most significant bit first, so they are built up bit by bit.
+	fn decode(&self, reader: &mut BitReader) -> std::io::Result<u16> {
+		let mut code: i32 = 0;
+		let mut first: i32 = 0;
+		let mut index: i32 = 0;
+
+		for length in 1..=MAX_BITS {
+			code |= reader.read_bits(1)? as i32;
+			let count = self.counts[length] as i32;
+
+			if code - first < count {
+				return Ok(self.symbols[(index + code - first) as usize]);
+			}
+
+			index += count;
+			first = (first + count) << 1;
+			code <<= 1;
+		}
+
+		return Err(invalid_data("Invalid Huffman code"));
+	}
+}
+
+// Decompresses a zlib stream (header, deflate data and checksum)
+pub fn zlib_decompress(data: &[u8]) -> std::io::Result<Vec<u8>> {
+	if data.len() < 6 || (data[0] & 0x0f) != 8 || !(((data[0] as u16) << 8) | data[1] as u16).is_multiple_of(31) || (data[1] & 0x20) != 0 {
+		return Err(invalid_data("Not a zlib stream"));
+	}
+
+	let (output, length) = inflate(&data[2..])?;
+	let checksum = data.get(2 + length..2 + length + 4).ok_or_else(|| invalid_data("Missing zlib checksum"))?;
+
+	if u32::from_be_bytes([checksum[0], checksum[1], checksum[2], checksum[3]]) != adler32(&output) {
+		return Err(invalid_data("Wrong zlib checksum"));
+	}
+
+	return Ok(output);
+}
+
+// Decompresses deflate data, returning the data and the number of compressed bytes read
+pub fn inflate(data: &[u8]) -> std::io::Result<(Vec<u8>, usize)> {
+	let mut reader = BitReader { bytes: data, position: 0, bit_buffer: 0, bit_count: 0 };
+	let mut output: Vec<u8> = Vec::new();
+
+	loop {
+		let is_last_block = reader.read_bits(1)? == 1;
+
+		match reader.read_bits(2)? {
+			0 => {
+				reader.align();
+				let header = data.get(reader.position..reader.position + 4).ok_or_else(|| invalid_data("Unexpected end of the compressed data"))?;
+				let length = u16::from_le_bytes([header[0], header[1]]) as usize;
+
+				if length != !u16::from_le_bytes([header[2], header[3]]) as usize {
+					return Err(invalid_data("Invalid stored block"));
+				}
+
+				let start = reader.position + 4;
+				output.extend_from_slice(data.get(start..start + length).ok_or_else(|| invalid_data("Unexpected end of the compressed data"))?);
+				reader.position = start + length;
+			}
+			1 => {
+				let (literal_lengths, distances) = fixed_codes();
+				inflate_block(&mut reader, &mut output, &literal_lengths, &distances)?;
+			}
+			2 => {
+				let (literal_lengths, distances) = read_dynamic_codes(&mut reader)?;
+				inflate_block(&mut reader, &mut output, &literal_lengths, &distances)?;
+			}
+			_ => return Err(invalid_data("Invalid block type"))
+		}
+
+		if is_last_block {
+			return Ok((output, reader.position));
+		}
+	}
+}
+
+// Decodes the symbols of a compressed block until its end symbol
+fn inflate_block(reader: &mut BitReader, output: &mut Vec<u8>, literal_lengths: &Huffman, distances: &Huffman) -> std::io::Result<()> {
+	loop {
+		let symbol = literal_lengths.decode(reader)? as usize;
+
+		if symbol < 256 {
+			output.push(symbol as u8);
+			continue;
+		}
+
+		if symbol == 256 {
+			return Ok(());
+		}
+
+		let length_index = symbol - 257;
+
+		if length_index >= LENGTH_BASES.len() {
+			return Err(invalid_data("Invalid length symbol"));
+		}
+
+		let length = LENGTH_BASES[length_index] as usize + reader.read_bits(LENGTH_EXTRA_BITS[length_index] as u32)? as usize;
+		let distance_index = distances.decode(reader)? as usize;
+
+		if distance_index >= DISTANCE_BASES.len() {
+			return Err(invalid_data("Invalid distance symbol"));
+		}
+
+		let distance = DISTANCE_BASES[distance_index] as usize + reader.read_bits(DISTANCE_EXTRA_BITS[distance_index] as u32)? as usize;
+
+		if distance > output.len() {
+			return Err(invalid_data("Distance too far back"));
+		}
+
+		// The repetition may overlap the bytes it creates, so it is copied byte by byte
+		let start = output.len() - distance;
+
+		for i in 0..length {
+			output.push(output[start + i]);
+		}
+	}
+}
+
+// The codes of the blocks compressed with fixed Huffman codes
+fn fixed_codes() -> (Huffman, Huffman) {
+	let mut lengths = [0u8; 288];
+	lengths[0..144].fill(8);
+	lengths[144..256].fill(9);
+	lengths[256..280].fill(7);
+	lengths[280..288].fill(8);
+	return (Huffman::new(&lengths), Huffman::new(&[5u8; 30]));
+}
+
+// Reads the codes at the start of a block compressed with dynamic Huffman codes. The code lengths are themselves
+// Huffman coded, with run lengths for repeated lengths.
+fn read_dynamic_codes(reader: &mut BitReader) -> std::io::Result<(Huffman, Huffman)> {
+	let literal_length_count = reader.read_bits(5)? as usize + 257;
+	let distance_count = reader.read_bits(5)? as usize + 1;
+	let code_length_count = reader.read_bits(4)? as usize + 4;
+
+	let mut code_length_lengths = [0u8; 19];
+
+	for &index in CODE_LENGTH_ORDER.iter().take(code_length_count) {
+		code_length_lengths[index] = reader.read_bits(3)? as u8;
+	}
+
+	let code_lengths = Huffman::new(&code_length_lengths);
+	let mut lengths: Vec<u8> = Vec::with_capacity(literal_length_count + distance_count);
+
+	while lengths.len() < literal_length_count + distance_count {
+		let symbol = code_lengths.decode(reader)?;
+
+		let (value, repeat) = match symbol {
+			0..=15 => (symbol as u8, 1),
+			16 => (*lengths.last().ok_or_else(|| invalid_data("Repeated code length without a previous one"))?, 3 + reader.read_bits(2)?),
+			17 => (0, 3 + reader.read_bits(3)?),
+			_ => (0, 11 + reader.read_bits(7)?)
+		};
+
+		for _ in 0..repeat {
+			lengths.push(value);
+		}
+	}
+
+	if lengths.len() != literal_length_count + distance_count {
+		return Err(invalid_data("Too many code lengths"));
+	}
+
+	return Ok((Huffman::new(&lengths[..literal_length_count]), Huffman::new(&lengths[literal_length_count..])));
+}
+
+fn invalid_data(message: &str) -> Error {
+	return Error::new(ErrorKind::InvalidData, message);
+}
@@ -0,0 +1,48 @@
+pub mod inflate;
+pub mod png;
+pub mod ppm;
+
+// Import requirements
+use crate::fundamentals::light::*;
+use std::fs::File;
+use std::io::{BufReader, Error, ErrorKind, Read};
+use png::*;
+use ppm::*;
+
+// An image read from a file, converted to linear light
+pub struct InputImage {
+	pub width: i32,
+	pub height: i32,
+	pub pixels: Vec<LightColor>
+}
+
+// Reads a PNG or PPM image, the format being recognized by the first bytes of the file. Both formats are assumed to
+// be sRGB encoded.
+pub fn read_image_file(path: &str) -> std::io::Result<InputImage> {
+	let mut input = BufReader::new(File::open(path)?);
+	let mut data: Vec<u8> = Vec::new();
+	input.read_to_end(&mut data)?;
+
+	if data.starts_with(&[137, 80, 78, 71]) {
+		return read_png(&mut data.as_slice());
+	}
+
+	if data.starts_with(b"P3") || data.starts_with(b"P6") {
+		return read_ppm(&mut data.as_slice());
+	}
+
+	return Err(invalid_data("Unknown image format, expected PNG or PPM"));
+}
+
+// sRGB transfer function from the display encoding to linear light, the inverse of srgb_oetf
+pub fn srgb_eotf(v: f64) -> f64 {
+	if v <= 0.04045 {
+		return v / 12.92;
+	}
+
+	return ((v + 0.055) / 1.055).powf(2.4);
+}
+
+fn invalid_data(message: &str) -> Error {
+	return Error::new(ErrorKind::InvalidData, message);
+}
@@ -0,0 +1,160 @@
+
+// Import requirements
+use super::*;
+use super::inflate::*;
+use crate::output::deflate::crc32;
+
+const PNG_SIGNATURE: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+
+// Reads a PNG image of any color type with 1 to 16 bits per channel. Alpha is ignored, interlaced images are not
+// supported.
+pub fn read_png<R: Read>(input: &mut R) -> std::io::Result<InputImage> {
+	let mut data: Vec<u8> = Vec::new();
+	input.read_to_end(&mut data)?;
+
+	if data.len() < 8 || data[0..8] != PNG_SIGNATURE {
+		return Err(invalid_data("Not a PNG image"));
+	}
+
+	let mut header: Option<Vec<u8>> = None;
+	let mut palette: Vec<u8> = Vec::new();
+	let mut compressed: Vec<u8> = Vec::new();
+	let mut position = 8;
+
+	// Chunks: length, type, data and the checksum of type and data
+	loop {
+		let length_bytes = data.get(position..position + 8).ok_or_else(|| invalid_data("Unexpected end of the PNG image"))?;
+		let length = u32::from_be_bytes([length_bytes[0], length_bytes[1], length_bytes[2], length_bytes[3]]) as usize;
+		let chunk = data.get(position + 4..position + 8 + length + 4).ok_or_else(|| invalid_data("Unexpected end of the PNG image"))?;
+		let (typed_data, checksum) = chunk.split_at(4 + length);
+
+		if crc32(typed_data) != u32::from_be_bytes([checksum[0], checksum[1], checksum[2], checksum[3]]) {
+			return Err(invalid_data("Wrong PNG chunk checksum"));
+		}
+
+		let chunk_data = &typed_data[4..];
+
+		match &typed_data[0..4] {
+			b"IHDR" => header = Some(chunk_data.to_vec()),
+			b"PLTE" => palette = chunk_data.to_vec(),
+			b"IDAT" => compressed.extend_from_slice(chunk_data),
+			b"IEND" => break,
+			_ => {}
+		}
+
+		position += 12 + length;
+	}
+
+	let header = header.filter(|h| h.len() == 13).ok_or_else(|| invalid_data("Missing PNG header"))?;
+	let width = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+	let height = u32::from_be_bytes([header[4], header[5], header[6], header[7]]) as usize;
+	let bit_depth = header[8] as usize;
+	let color_type = header[9];
+
+	if header[12] != 0 {
+		return Err(Error::new(ErrorKind::Unsupported, "Interlaced PNG images are not supported"));
+	}
+
+	let channels: usize = match color_type {
+		0 | 3 => 1,
+		2 => 3,
+		4 => 2,
+		6 => 4,
+		_ => return Err(invalid_data("Invalid PNG color type"))
+	};
+
+	if ![1, 2, 4, 8, 16].contains(&bit_depth) || (bit_depth < 8 && channels != 1) || (color_type == 3 && bit_depth == 16) {
+		return Err(invalid_data("Invalid PNG bit depth"));
+	}
+
+	let bits_per_pixel = channels * bit_depth;
+	let bytes_per_pixel = bits_per_pixel.div_ceil(8);
+	let row_size = (width * bits_per_pixel).div_ceil(8);
+	let filtered = zlib_decompress(&compressed)?;
+
+	if width == 0 || height == 0 || filtered.len() < (row_size + 1) * height {
+		return Err(invalid_data("The PNG image data is too short"));
+	}
+
+	let max_value = ((1u32 << bit_depth) - 1) as f64;
+	let mut pixels: Vec<LightColor> = Vec::with_capacity(width * height);
+	let mut previous_row: Vec<u8> = vec![0; row_size];
+	let mut row: Vec<u8> = vec![0; row_size];
+
+	for y in 0..height {
+		let filter = filtered[y * (row_size + 1)];
+		unfilter_row(filter, &filtered[y * (row_size + 1) + 1..(y + 1) * (row_size + 1)], &previous_row, bytes_per_pixel, &mut row)?;
+
+		// Channel values in the order they are stored
+		let sample = |index: usize| -> u32 {
+			return match bit_depth {
+				16 => u16::from_be_bytes([row[2 * index], row[2 * index + 1]]) as u32,
+				8 => row[index] as u32,
+				_ => {
+					let bit = index * bit_depth;
+					((row[bit / 8] >> (8 - bit_depth - bit % 8)) as u32) & ((1 << bit_depth) - 1)
+				}
+			};
+		};
+
+		for x in 0..width {
+			let pixel = match color_type {
+				3 => {
+					let entry = sample(x) as usize * 3;
+					let rgb = palette.get(entry..entry + 3).ok_or_else(|| invalid_data("PNG palette index out of range"))?;
+					[rgb[0] as f64 / 255.0, rgb[1] as f64 / 255.0, rgb[2] as f64 / 255.0]
+				}
+				0 | 4 => {
+					let v = sample(x * channels) as f64 / max_value;
+					[v, v, v]
+				}
+				_ => [sample(x * channels) as f64 / max_value, sample(x * channels + 1) as f64 / max_value, sample(x * channels + 2) as f64 / max_value]
+			};
+
+			pixels.push(LightColor { r: srgb_eotf(pixel[0]), g: srgb_eotf(pixel[1]), b: srgb_eotf(pixel[2]) });
+		}
+
+		std::mem::swap(&mut row, &mut previous_row);
+	}
+
+	return Ok(InputImage { width: width as i32, height: height as i32, pixels });
+}
+
+// Reverses the filter of one row, the counterpart of the filters chosen by the PNG writer
+fn unfilter_row(filter: u8, filtered: &[u8], previous_row: &[u8], bytes_per_pixel: usize, row: &mut [u8]) -> std::io::Result<()> {
+	for i in 0..row.len() {
+		let a = if i >= bytes_per_pixel { row[i - bytes_per_pixel] } else { 0 };
+		let b = previous_row[i];
+		let c = if i >= bytes_per_pixel { previous_row[i - bytes_per_pixel] } else { 0 };
+
+		let predicted = match filter {
+			0 => 0,
+			1 => a,
+			2 => b,
+			3 => (((a as u16) + (b as u16)) / 2) as u8,
+			4 => paeth_predictor(a, b, c),
+			_ => return Err(invalid_data("Invalid PNG filter type"))
+		};
+
+		row[i] = filtered[i].wrapping_add(predicted);
+	}
+
+	return Ok(());
+}
+
+fn paeth_predictor(a: u8, b: u8, c: u8) -> u8 {
+	let p = (a as i16) + (b as i16) - (c as i16);
+	let pa = (p - a as i16).abs();
+	let pb = (p - b as i16).abs();
+	let pc = (p - c as i16).abs();
+
+	if pa <= pb && pa <= pc {
+		return a;
+	}
+
+	if pb <= pc {
+		return b;
+	}
+
+	return c;
+}
@@ -0,0 +1,82 @@
+
+// Import requirements
+use super::*;
+
+// Reads a text (P3) or binary (P6) PPM image with up to 16 bits per channel
+pub fn read_ppm<R: Read>(input: &mut R) -> std::io::Result<InputImage> {
+	let mut data: Vec<u8> = Vec::new();
+	input.read_to_end(&mut data)?;
+
+	let mut position = 0;
+	let magic = next_token(&data, &mut position);
+
+	if magic != b"P3" && magic != b"P6" {
+		return Err(invalid_data("Not a P3 or P6 PPM image"));
+	}
+
+	let width = parse_number(next_token(&data, &mut position))?;
+	let height = parse_number(next_token(&data, &mut position))?;
+	let max_value = parse_number(next_token(&data, &mut position))?;
+
+	if width == 0 || height == 0 || max_value == 0 || max_value > 65535 {
+		return Err(invalid_data("Invalid PPM header"));
+	}
+
+	// Every value takes at least one byte, so larger images can not fit into the data
+	let value_count = (width as usize).checked_mul(height as usize).and_then(|n| n.checked_mul(3))
+		.filter(|&n| n <= data.len())
+		.ok_or_else(|| invalid_data("The PPM image is larger than its data"))?;
+	let mut values: Vec<u32> = Vec::with_capacity(value_count);
+
+	if magic == b"P3" {
+		for _ in 0..value_count {
+			values.push(parse_number(next_token(&data, &mut position))?);
+		}
+	}
+	else {
+		// A single whitespace character separates the header from the binary data, values above 255 take two bytes
+		let bytes_per_value = if max_value > 255 { 2 } else { 1 };
+		let binary = data.get(position + 1..position + 1 + value_count * bytes_per_value).ok_or_else(|| invalid_data("The PPM image data is too short"))?;
+
+		for chunk in binary.chunks(bytes_per_value) {
+			values.push(if bytes_per_value == 2 { u16::from_be_bytes([chunk[0], chunk[1]]) as u32 } else { chunk[0] as u32 });
+		}
+	}
+
+	let scale = 1.0 / (max_value as f64);
+	let pixels = values.chunks(3).map(|v| LightColor {
+		r: srgb_eotf((v[0].min(max_value) as f64) * scale),
+		g: srgb_eotf((v[1].min(max_value) as f64) * scale),
+		b: srgb_eotf((v[2].min(max_value) as f64) * scale)
+	}).collect();
+
+	return Ok(InputImage { width: width as i32, height: height as i32, pixels });
+}
+
+// Returns the next whitespace separated token of the header or of a text image, skipping comments
+fn next_token<'a>(data: &'a [u8], position: &mut usize) -> &'a [u8] {
+	loop {
+		while *position < data.len() && data[*position].is_ascii_whitespace() {
+			*position += 1;
+		}
+
+		if *position < data.len() && data[*position] == b'#' {
+			while *position < data.len() && data[*position] != b'\n' {
+				*position += 1;
+			}
+			continue;
+		}
+
+		let start = *position;
+
+		while *position < data.len() && !data[*position].is_ascii_whitespace() {
+			*position += 1;
+		}
+
+		return &data[start..*position];
+	}
+}
+
+fn parse_number(token: &[u8]) -> std::io::Result<u32> {
+	return std::str::from_utf8(token).ok().and_then(|t| t.parse::<u32>().ok()).ok_or_else(|| invalid_data("Invalid number in PPM image"));
+}
@@ -0,0 +1,9 @@
+// The code base deliberately writes explicit return statements at the end of functions
+#![allow(clippy::needless_return)]
+
+// The renderer as a library, so that it can be embedded into other programs. The command line program is in main.rs.
+pub mod fundamentals;
+pub mod raytracing;
+pub mod output;
+pub mod input;
+pub mod distributed;
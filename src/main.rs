@@ -1,22 +1,48 @@
+// The code base deliberately writes explicit return statements at the end of functions
+#![allow(clippy::needless_return)]
 
-// We are declaring the modules here
-pub mod fundamentals;
-pub mod raytracing;
+// The modules are declared in the library (lib.rs)
+use photon::{fundamentals, raytracing, output};
 
 // Import all the things from all the modules
 use fundamentals::vec3::*;
 use fundamentals::light::*;
 use fundamentals::geometry::*;
+use fundamentals::texture::*;
+use fundamentals::bump::*;
+use fundamentals::instance::*;
+use fundamentals::primitive::*;
+use fundamentals::csg::*;
+use fundamentals::sdf::*;
+use fundamentals::heightfield::*;
+use fundamentals::scene::*;
+use fundamentals::random::*;
+use fundamentals::math::transform::*;
 use raytracing::rendering::*;
+use raytracing::sampling::*;
+use raytracing::adaptive::*;
+use raytracing::filter::*;
+use output::*;
+use output::exr::*;
+use output::display::*;
+use raytracing::aov::*;
+use raytracing::denoise::*;
+use raytracing::stats::*;
+use raytracing::cancel::*;
+use raytracing::checkpoint::*;
+use photon::input::*;
+use photon::distributed::coordinator::*;
+use photon::distributed::worker::*;
+use std::io::{BufWriter, Write};
+use std::time::Duration;
 use std::sync::Arc;
-use std::thread;
-use std::sync::mpsc;
-use std::time::Instant;
 
-// Program entry point
-fn main() { 
-	const IMAGE_WIDTH: i32 = 1024;
-	const IMAGE_HEIGHT: i32 = 1024;
+const IMAGE_WIDTH: i32 = 1024;
+const IMAGE_HEIGHT: i32 = 1024;
+
+// Builds the demo scene and the camera looking at it. The primitives are named, so they can be changed
+// from the command line.
+fn create_scene_graph() -> (SceneGraph, CameraZ) {
 
 	// Let's set the camera
 	let camera = CameraZ {
@@ -25,88 +51,692 @@ fn main() {
 		image_plane_width: 10.0
 	};
 
-	let mut spheres: Vec::<Sphere> = Vec::new();
-
-	spheres.push(Sphere {
-		center: Vector3 { x: 4.4, y: 3.4, z: -10.0},
-		radius: 2.0, 
-		material_color: LightColor {r: 1.0, g: 1.0, b: 0.0}
-	});
-
-	spheres.push(Sphere {
-		center: Vector3 { x: -3.0, y: -3.0, z: -10.0},
-		radius: 3.0, 
-		material_color: LightColor {r: 0.0, g: 1.0, b: 0.0}
-	});
-
-	spheres.push(Sphere {
-		center: Vector3 { x: 4.0, y: -4.0, z: -22.0},
-		radius: 3.0, 
-		material_color: LightColor {r: 1.0, g: 0.0, b: 0.0}
-	});
-
-	let mut directional_lights: Vec<DirectionalLight> = Vec::new(); 
-
-	let mut point_lights: Vec<PointLight> = Vec::new(); 
-
-	point_lights.push(PointLight {
-		position: Vector3 {x: 0.0, y: 0.0, z: -10.0},
-		color: LightColor {r: 0.5, g: 0.5, b: 0.5}
-	});
-
-	let mut planes: Vec<Plane> = Vec::new(); 
-
-	planes.push(Plane {
-		center: Vector3 { x: -10.0, y:0.0, z: 0.0 },
-		normal: Vector3 { x: 1.0, y: 0.0, z: 0.0 },
-		material_color: LightColor { r: 1.0, g: 1.0, b: 1.0}
-	});
-
-	planes.push(Plane {
-		center: Vector3 { x: 10.0, y:0.0, z: 0.0 },
-		normal: Vector3 { x: -1.0, y: 0.0, z: 0.0 },
-		material_color: LightColor { r: 1.0, g: 1.0, b: 1.0}
-	});
-
-	planes.push(Plane {
-		center: Vector3 { x: 0.0, y:10.0, z: 0.0 },
-		normal: Vector3 { x: 0.0, y: -1.0, z: 0.0 },
-		material_color: LightColor { r: 1.0, g: 1.0, b: 1.0}
-	});
-
-	planes.push(Plane {
-		center: Vector3 { x: 0.0, y:-10.0, z: 0.0 },
-		normal: Vector3 { x: 0.0, y: 1.0, z: 0.0 },
-		material_color: LightColor { r: 1.0, g: 1.0, b: 1.0}
-	});
-
-	planes.push(Plane {
-		center: Vector3 { x: 0.0, y: 0.0, z: -36.0 },
-		normal: Vector3 { x: 0.0, y: 0.0, z: 1.0 },
-		material_color: LightColor { r: 1.0, g: 1.0, b: 1.0}
-	});
-
-	// Define space to visualize 
-	let space = Space { spheres: spheres, planes: planes, directional_lights: directional_lights, point_lights: point_lights };	
-
-	// Compute visualization
-	let pixel_lights = render(space, camera, IMAGE_WIDTH, IMAGE_HEIGHT, 8);
-	 
-	// Write the PPM header line 
-	println!("P3\n{} {}\n255", IMAGE_WIDTH, IMAGE_HEIGHT);
-
-	// Write the PPM pixels
-    for &pixel_light in &pixel_lights {
-        let ir = (255.99 * pixel_light.r) as u8;
-		let ig = (255.99 * pixel_light.g) as u8;
-		let ib = (255.99 * pixel_light.b) as u8;			
-		println!("{} {} {}", ir, ig, ib);
-    }
+	let sphere = |name: &str, center: Vector3, radius: f64, color: LightColor| SceneNode::new(name, NodeContent::Sphere(Sphere {
+		center,
+		radius,
+		material_color: Texture::Constant(color),
+		surface_detail: SurfaceDetail::Smooth,
+		visibility: Visibility::all()
+	}));
+
+	let wall = |name: &str, center: Vector3, normal: Vector3| SceneNode::new(name, NodeContent::Plane(Plane {
+		center,
+		normal,
+		material_color: Texture::Constant(LightColor { r: 1.0, g: 1.0, b: 1.0 }),
+		surface_detail: SurfaceDetail::Smooth,
+		visibility: Visibility::all()
+	}));
+
+	let spheres = SceneNode::group("spheres", vec![
+		sphere("yellow-sphere", Vector3 { x: 4.4, y: 3.4, z: -10.0 }, 2.0, LightColor { r: 1.0, g: 1.0, b: 0.0 }),
+		sphere("green-sphere", Vector3 { x: -3.0, y: -3.0, z: -10.0 }, 3.0, LightColor { r: 0.0, g: 1.0, b: 0.0 }),
+		sphere("red-sphere", Vector3 { x: 4.0, y: -4.0, z: -22.0 }, 3.0, LightColor { r: 1.0, g: 0.0, b: 0.0 })
+	]);
+
+	let room = SceneNode::group("room", vec![
+		wall("left-wall", Vector3 { x: -10.0, y: 0.0, z: 0.0 }, Vector3 { x: 1.0, y: 0.0, z: 0.0 }),
+		wall("right-wall", Vector3 { x: 10.0, y: 0.0, z: 0.0 }, Vector3 { x: -1.0, y: 0.0, z: 0.0 }),
+		wall("ceiling", Vector3 { x: 0.0, y: 10.0, z: 0.0 }, Vector3 { x: 0.0, y: -1.0, z: 0.0 }),
+		wall("floor", Vector3 { x: 0.0, y: -10.0, z: 0.0 }, Vector3 { x: 0.0, y: 1.0, z: 0.0 }),
+		wall("back-wall", Vector3 { x: 0.0, y: 0.0, z: -36.0 }, Vector3 { x: 0.0, y: 0.0, z: 1.0 })
+	]);
+
+	let light = SceneNode::new("light", NodeContent::PointLight(PointLight {
+		position: Vector3 { x: 0.0, y: 0.0, z: -10.0 },
+		color: LightColor { r: 0.5, g: 0.5, b: 0.5 }
+	}));
+
+	return (SceneGraph::new(SceneNode::group("scene", vec![spheres, room, light])), camera);
+}
+
+// Program entry point
+fn main() { 
+
+	let mut settings = RenderSettings::new(IMAGE_WIDTH, IMAGE_HEIGHT);
+	let mut adaptive = AdaptiveSampling { min_samples: 16, max_samples: 256, error_threshold: 0.0 };
+	let mut sample_counts_path: Option<String> = None;
+	let mut filter_radius: Option<f64> = None;
+	let mut output_path: Option<String> = None;
+	let mut output_format: Option<ImageFormat> = None;
+	let mut output_options = OutputOptions::new();
+	let mut aov_outputs: Vec<(AovType, String)> = Vec::new();
+	let mut aov_layers_path: Option<String> = None;
+	let mut denoise_settings: Option<DenoiseSettings> = None;
+	let mut denoise_iterations: Option<u32> = None;
+	let mut denoise_strength: Option<f64> = None;
+	let mut denoise_radius: Option<i32> = None;
+	let mut samples_given = false;
+	let mut progressive: Option<ProgressiveSettings> = None;
+	let mut time_budget: Option<Duration> = None;
+	let mut snapshot_interval: Option<Duration> = None;
+	let mut show_progress = true;
+	let mut checkpoint_path: Option<String> = None;
+	let mut coordinator_address: Option<String> = None;
+	let mut coordinator_settings = CoordinatorSettings::new();
+	let mut worker_address: Option<String> = None;
+	let mut crop_output = true;
+	let mut primitive_options: Vec<(String, String, String)> = Vec::new();
+	let mut visibility_options: Vec<(String, Visibility)> = Vec::new();
+	let mut instance_count = 0;
+	let mut heightfield: Option<String> = None;
+	let mut add_primitives = false;
+	let mut emission_options: Vec<(String, LightColor)> = Vec::new();
+	let mut sdf_epsilon: Option<f64> = None;
+	let mut sdf_max_steps: Option<u32> = None;
+
+	// Read the command line arguments, every option is followed by its value
+	let args: Vec<String> = std::env::args().collect();
+	let mut i = 1;
+
+	while i < args.len() {
+		let value = args.get(i + 1).unwrap_or_else(|| panic!("{} expects a value", args[i]));
+
+		match args[i].as_str() {
+			"--seed" => settings.seed = value.parse::<u64>().expect("--seed expects an unsigned integer"),
+			"--samples" => {
+				settings.samples_per_pixel = value.parse::<u32>().expect("--samples expects an unsigned integer");
+				samples_given = true;
+			}
+			"--sampler" => settings.sampler = SamplerType::from_name(value).expect("--sampler expects independent, stratified, halton or sobol"),
+			"--adaptive" => adaptive.error_threshold = value.parse::<f64>().expect("--adaptive expects the accepted relative error"),
+			"--min-samples" => adaptive.min_samples = value.parse::<u32>().expect("--min-samples expects an unsigned integer"),
+			"--max-samples" => adaptive.max_samples = value.parse::<u32>().expect("--max-samples expects an unsigned integer"),
+			"--sample-counts" => sample_counts_path = Some(value.clone()),
+			"--filter" => settings.filter = PixelFilter::new(FilterType::from_name(value).expect("--filter expects box, tent, gaussian, mitchell or lanczos")),
+			"--filter-radius" => filter_radius = Some(value.parse::<f64>().expect("--filter-radius expects a number")),
+			"--output" => output_path = Some(value.clone()),
+			"--format" => output_format = Some(ImageFormat::from_name(value).expect("--format expects ppm, png, pfm or exr")),
+			"--bit-depth" => output_options.bit_depth = value.parse::<u8>().ok().filter(|&d| d == 8 || d == 16 || d == 32).expect("--bit-depth expects 8, 16 or 32"),
+			"--exposure" => output_options.display.exposure = value.parse::<f64>().expect("--exposure expects a number of stops"),
+			"--tonemap" => output_options.display.tone_mapping = ToneMapping::from_name(value).expect("--tonemap expects none, reinhard, aces or agx"),
+			"--srgb" => output_options.display.srgb = parse_switch(value),
+			"--dither" => output_options.display.dither = parse_switch(value),
+			"--aov" => aov_outputs.push(parse_aov_output(value)),
+			"--aov-layers" => aov_layers_path = Some(value.clone()),
+			"--denoise" => denoise_settings = if value == "off" { None } else { Some(DenoiseSettings::new(DenoiseMethod::from_name(value).expect("--denoise expects off, atrous or nlm"))) },
+			"--denoise-iterations" => denoise_iterations = Some(value.parse::<u32>().expect("--denoise-iterations expects an unsigned integer")),
+			"--denoise-strength" => denoise_strength = Some(value.parse::<f64>().expect("--denoise-strength expects a number")),
+			"--denoise-radius" => denoise_radius = Some(value.parse::<i32>().expect("--denoise-radius expects an integer")),
+			"--progressive" => progressive = Some(ProgressiveSettings::new(value.parse::<u32>().expect("--progressive expects the number of samples per pass"), 0)),
+			"--time-budget" => time_budget = Some(parse_seconds(value)),
+			"--snapshot-interval" => snapshot_interval = Some(parse_seconds(value)),
+			"--progress" => show_progress = parse_switch(value),
+			"--checkpoint" => checkpoint_path = Some(value.clone()),
+			"--threads" => settings.thread_count = value.parse::<i32>().ok().filter(|&n| n > 0).expect("--threads expects a positive integer"),
+			"--coordinator" => coordinator_address = Some(value.clone()),
+			"--worker" => worker_address = Some(value.clone()),
+			"--worker-timeout" => coordinator_settings.worker_timeout = parse_seconds(value),
+			"--window" => {
+				let w = parse_list::<i32>(value, 4, "--window expects x,y,width,height in pixels");
+				settings.region = Some(PixelRegion { x: w[0], y: w[1], width: w[2], height: w[3] });
+			}
+			"--crop" => {
+				let c = parse_list::<f64>(value, 4, "--crop expects x0,y0,x1,y1 as fractions of the image size");
+				settings.region = Some(PixelRegion::from_crop(c[0], c[1], c[2], c[3], settings.width, settings.height));
+			}
+			"--border" => {
+				let b = parse_list::<i32>(value, 4, "--border expects left,top,right,bottom in pixels");
+				settings.region = Some(PixelRegion::from_border(b[0], b[1], b[2], b[3], settings.width, settings.height));
+			}
+			"--texture" | "--normal-map" | "--bump" => {
+				let (target, spec) = value.split_once('=').expect("--texture, --normal-map and --bump expect <primitive id or name>=<value>");
+				primitive_options.push((args[i].clone(), String::from(target), String::from(spec)));
+			}
+			"--visibility" => {
+				let (name, rays) = value.split_once('=').expect("--visibility expects <node name>=<rays>");
+				visibility_options.push((String::from(name), parse_visibility(rays)));
+			}
+			"--heightfield" => heightfield = Some(value.clone()),
+			"--instances" => instance_count = value.parse::<usize>().expect("--instances expects an unsigned integer"),
+			"--primitives" => add_primitives = parse_switch(value),
+			"--emission" => {
+				let (name, color) = value.split_once('=').expect("--emission expects <node name>=<r>,<g>,<b>");
+				let c = parse_list::<f64>(color, 3, "--emission expects <node name>=<r>,<g>,<b>");
+				emission_options.push((String::from(name), LightColor { r: c[0], g: c[1], b: c[2] }));
+			}
+			"--sdf-epsilon" => sdf_epsilon = Some(value.parse::<f64>().ok().filter(|e| *e > 0.0 && e.is_finite()).expect("--sdf-epsilon expects a positive number")),
+			"--sdf-steps" => sdf_max_steps = Some(value.parse::<u32>().ok().filter(|&n| n > 0).expect("--sdf-steps expects a positive number of steps")),
+			"--region-output" => crop_output = match value.as_str() {
+				"cropped" => true,
+				"full" => false,
+				_ => panic!("--region-output expects cropped or full")
+			},
+			"--exr-compression" => output_options.exr_compression = ExrCompression::from_name(value).expect("--exr-compression expects none, zips or zip"),
+			other => panic!("Unknown argument {}", other)
+		}
+
+		i += 2;
+	}
+
+	if let Some(radius) = filter_radius {
+		settings.filter.radius = radius;
+	}
+
+	if adaptive.error_threshold > 0.0 {
+		settings.adaptive = Some(adaptive);
+	}
+
+	if let Some(denoise) = &mut denoise_settings {
+		denoise.thread_count = settings.thread_count;
+		denoise.iterations = denoise_iterations.unwrap_or(denoise.iterations);
+		denoise.strength = denoise_strength.unwrap_or(denoise.strength);
+		denoise.search_radius = denoise_radius.unwrap_or(denoise.search_radius);
+	}
+
+	// The denoiser is guided by the albedo, normal and depth AOVs
+	settings.aovs = !aov_outputs.is_empty() || aov_layers_path.is_some() || denoise_settings.is_some();
+
+	// Progressive rendering stops at the requested number of samples, or only at the time budget if no number is given
+	if let Some(p) = &mut progressive {
+		assert!(settings.adaptive.is_none(), "--adaptive can not be combined with --progressive");
+		p.target_samples = if samples_given || time_budget.is_none() { Some(settings.samples_per_pixel) } else { None };
+		p.time_budget = time_budget;
+		p.snapshot_interval = snapshot_interval;
+	}
+
+	assert!(checkpoint_path.is_none() || progressive.is_some(), "--checkpoint needs --progressive");
+	assert!(coordinator_address.is_none() || progressive.is_none(), "--coordinator can not be combined with --progressive");
+	assert!(settings.region.is_none_or(|r| r.is_within(settings.width, settings.height)), "The region has to be a non-empty part of the image");
+
+	// The image written is either the region only or the full image with only the region rendered
+	let expanded_region = settings.region.filter(|_| !crop_output);
+	let (output_width, output_height) = settings.region.filter(|_| crop_output).map_or((IMAGE_WIDTH, IMAGE_HEIGHT), |r| (r.width, r.height));
+	let expand = |result: RenderResult| match &expanded_region {
+		Some(region) => result.expand(IMAGE_WIDTH, IMAGE_HEIGHT, region),
+		None => result
+	};
+
+	// A worker gets the scene and the settings from the coordinator and renders with one connection per thread
+	if let Some(address) = worker_address {
+		let tile_count = run_worker(&address, settings.thread_count).expect("Worker failed");
+		eprintln!("Rendered {} tiles for {}", tile_count, address);
+		return;
+	}
+
+	let (mut graph, camera) = create_scene_graph();
+
+	if let NodeContent::Group(children) = &mut graph.root.content {
+		if add_primitives {
+			children.push(create_primitives());
+		}
+
+		if instance_count > 0 {
+			children.push(create_instances(instance_count));
+		}
+
+		if let Some(source) = &heightfield {
+			children.push(create_terrain(source));
+		}
+	}
+
+	// Named nodes are changed in the graph, primitives given by their id in the space built from it
+	for (name, visibility) in &visibility_options {
+		graph.find_mut(name).expect("--visibility expects the name of a node").visibility = *visibility;
+	}
+
+	for (option, target, value) in primitive_options.iter().filter(|(_, target, _)| target.parse::<usize>().is_err()) {
+		let node = graph.find_mut(target).expect("--texture, --normal-map and --bump expect the id or the name of a primitive");
+
+		match &mut node.content {
+			NodeContent::Sphere(s) => apply_material_option(&mut s.material_color, &mut s.surface_detail, option, value),
+			NodeContent::Plane(p) => apply_material_option(&mut p.material_color, &mut p.surface_detail, option, value),
+			NodeContent::Primitive(p) => apply_material_option(&mut p.material_color, &mut p.surface_detail, option, value),
+			_ => panic!("--texture, --normal-map and --bump expect the id or the name of a primitive")
+		}
+	}
+
+	for (name, emission) in &emission_options {
+		match &mut graph.find_mut(name).expect("--emission expects the name of a node").content {
+			NodeContent::Primitive(p) => p.emission = *emission,
+			_ => panic!("--emission expects the name of a box, disk, quad, cylinder, cone, torus, CSG solid, SDF or heightfield")
+		}
+	}
+
+	let mut space = graph.build();
+
+	for (option, target, value) in &primitive_options {
+		if let Ok(id) = target.parse::<usize>() {
+			apply_primitive_option(&mut space, option, id, value);
+		}
+	}
+
+	for primitive in space.primitives.primitives.iter_mut() {
+		if let PrimitiveKind::Sdf(sdf) = &mut primitive.kind {
+			sdf.epsilon = sdf_epsilon.unwrap_or(sdf.epsilon);
+			sdf.max_steps = sdf_max_steps.unwrap_or(sdf.max_steps);
+		}
+	}
+
+	// Progress and statistics go to the standard error stream, the image may be written to the standard output
+	let report_progress = |p: &RenderProgress| {
+		if show_progress {
+			print_progress(p);
+		}
+	};
+
+	// Compute visualization. Progressive renderings refresh the output file and the checkpoint after the passes, and
+	// continue from the checkpoint if there is one.
+	let result = match &progressive {
+		Some(p) => {
+			let mut state = ProgressiveRender::new(space, camera, &settings, p);
+
+			if let Some(path) = checkpoint_path.as_ref().filter(|path| std::path::Path::new(path).exists()) {
+				let mut input = std::io::BufReader::new(std::fs::File::open(path).expect("Could not open checkpoint"));
+				read_checkpoint(&mut input, &mut state).expect("Could not resume from checkpoint");
+				eprintln!("Resuming from checkpoint with {} samples per pixel", state.samples);
+			}
+
+			let result = continue_progressive(&mut state, &CancellationToken::new(), |snapshot| {
+				if let Some(path) = &output_path {
+					let pixels = postprocess(&expand(snapshot.frame.resolve()), output_width, output_height, &denoise_settings);
+					write_output_image(Some(path), output_format, &Image { pixels: &pixels, width: output_width, height: output_height }, &output_options);
+					eprintln!("Wrote snapshot with {} samples per pixel", snapshot.samples);
+				}
+
+				if let Some(path) = &checkpoint_path {
+					save_checkpoint(path, snapshot);
+				}
+			}, report_progress);
+
+			if let Some(path) = &checkpoint_path {
+				save_checkpoint(path, &state);
+			}
+
+			result
+		}
+		None => match &coordinator_address {
+			Some(address) => {
+				let listener = std::net::TcpListener::bind(address).expect("Could not listen for workers");
+				eprintln!("Waiting for workers on {}", listener.local_addr().unwrap());
+				render_distributed(listener, &space, &camera, &settings, &coordinator_settings, &CancellationToken::new(), report_progress)
+			}
+			None => render_with_progress(space, camera, &settings, report_progress)
+		}
+	};
+
+	if show_progress {
+		print_stats(&result.stats);
+	}
+
+	let result = expand(result);
+	let pixels = postprocess(&result, output_width, output_height, &denoise_settings);
+	let image = Image { pixels: &pixels, width: output_width, height: output_height };
+
+	// Optionally write how many rays each pixel received, brighter pixels needed more
+	if let Some(path) = sample_counts_path {
+		let max_samples = settings.sample_range().1.max(result.sample_counts.iter().copied().max().unwrap_or(1));
+		let count_pixels = sample_count_pixels(&result.sample_counts, max_samples);
+		let count_image = Image { pixels: &count_pixels, width: output_width, height: output_height };
+		let count_options = OutputOptions { display: DisplayTransform::identity(), ..OutputOptions::new() };
+		write_image_file(&path, &count_image, &count_options).expect("Could not write sample count image");
+	}
+
+	for (aov_type, path) in &aov_outputs {
+		let aov = result.aov(*aov_type).unwrap();
+		write_aov_file(path, aov, output_width, output_height, &output_options).expect("Could not write AOV image");
+	}
+
+	if let Some(path) = aov_layers_path {
+		let mut out = BufWriter::new(std::fs::File::create(&path).expect("Could not create EXR file"));
+		write_exr_layers(&mut out, &image, &result.aovs, output_options.exr_sample_type(), output_options.exr_compression).and_then(|_| out.flush()).expect("Could not write EXR layers");
+	}
+
+	write_output_image(output_path.as_deref(), output_format, &image, &output_options);
+}
+
+// Returns the final pixels of a rendering, denoised if requested
+fn postprocess(result: &RenderResult, width: i32, height: i32, denoise_settings: &Option<DenoiseSettings>) -> Vec<LightColor> {
+	return match denoise_settings {
+		Some(denoise_settings) => {
+			let features = DenoiseFeatures::from_result(result).unwrap();
+			denoise(&result.pixels, width, height, &features, denoise_settings)
+		}
+		None => result.pixels.clone()
+	};
+}
+
+// Without output file the image is written to the standard output, as PPM unless another format is requested
+fn write_output_image(output_path: Option<&str>, output_format: Option<ImageFormat>, image: &Image, output_options: &OutputOptions) {
+	match (output_path, output_format) {
+		(Some(path), None) => {
+			write_image_file(path, image, output_options).expect("Could not write image");
+		}
+		(Some(path), Some(format)) => {
+			let mut out = BufWriter::new(std::fs::File::create(path).expect("Could not create image file"));
+			write_image(&mut out, image, format, output_options).and_then(|_| out.flush()).expect("Could not write image");
+		}
+		(None, format) => {
+			let mut out = BufWriter::new(std::io::stdout().lock());
+			write_image(&mut out, image, format.unwrap_or(ImageFormat::Ppm), output_options).and_then(|_| out.flush()).expect("Could not write image");
+		}
+	}
+}
+
+// Shows the progress in one line that is overwritten by the next report, every pass ending with a line of its own
+fn print_progress(p: &RenderProgress) {
+	eprint!("\rPass {}: {}/{} tiles, {:.1} s elapsed, {:.1} s left, {:.2} M rays/s   ",
+		p.pass + 1, p.tiles_completed, p.tile_count, p.elapsed.as_secs_f64(), p.eta.as_secs_f64(), p.rays_per_second / 1e6);
+
+	if p.tiles_completed == p.tile_count {
+		eprintln!();
+	}
+}
+
+// Shows the summary of a rendering
+fn print_stats(stats: &RenderStats) {
+	let c = &stats.counters;
+	eprintln!("Wall time {:.2} s, CPU time {:.2} s", stats.wall_time.as_secs_f64(), stats.cpu_time.as_secs_f64());
+	eprintln!("Rays: {} primary, {} shadow, {} indirect, {:.2} M rays/s", c.primary_rays, c.shadow_rays, c.indirect_rays, stats.rays_per_second() / 1e6);
+	eprintln!("Intersection tests: {}", c.intersection_tests);
+}
+
+// Writes the checkpoint to a temporary file first and then replaces the previous one, so that there always is a
+// complete checkpoint even if the program is interrupted while writing
+fn save_checkpoint(path: &str, state: &ProgressiveRender) {
+	let temporary_path = format!("{}.tmp", path);
+	let mut out = BufWriter::new(std::fs::File::create(&temporary_path).expect("Could not create checkpoint"));
+	write_checkpoint(&mut out, state).and_then(|_| out.flush()).expect("Could not write checkpoint");
+	drop(out);
+	std::fs::rename(&temporary_path, path).expect("Could not replace checkpoint");
+}
+
+// Parses a duration given in (fractional) seconds
+fn parse_seconds(value: &str) -> Duration {
+	return Duration::from_secs_f64(value.parse::<f64>().ok().filter(|s| *s >= 0.0).expect("Expected a number of seconds"));
+}
+
+// Parses the value of --aov, which is the AOV name and the output path: depth=depth.exr
+fn parse_aov_output(value: &str) -> (AovType, String) {
+	let (name, path) = value.split_once('=').expect("--aov expects <name>=<path>");
+	let aov_type = AovType::from_name(name).expect("--aov expects depth, normal, albedo, position, id, direct or indirect");
+	return (aov_type, String::from(path));
+}
+
+// Covers the floor with a terrain, from the heights of a grayscale image or, for hills, a grid of rolling hills. The
+// terrain is named terrain.
+fn create_terrain(source: &str) -> SceneNode {
+	let field = if source == "hills" {
+		let size = 129;
+		let heights = (0..size * size).map(|k| {
+			let (x, z) = ((k % size) as f64 / (size - 1) as f64, (k / size) as f64 / (size - 1) as f64);
+			0.5 + 0.3 * (7.0 * x).sin() * (5.0 * z).cos() + 0.2 * (13.0 * x + 11.0 * z).sin()
+		}).collect();
+		Heightfield::new(size, size, heights)
+	} else {
+		get_image_heightfield(&read_image_file(source).expect("Could not read heightfield image"))
+	};
+
+	let transform = Transform::translate(Vector3 { x: -10.0, y: -10.0, z: -36.0 }) * Transform::scale(20.0, 1.5, 32.0);
+	let mut terrain = Primitive::new(PrimitiveKind::Heightfield(field), transform);
+	terrain.material_color = Texture::Constant(LightColor { r: 0.55, g: 0.7, b: 0.35 });
+	return SceneNode::new("terrain", NodeContent::Primitive(Box::new(terrain)));
+}
+
+// Returns the heightfield of the gray values of an image, from 0 for black to 1 for white. The rows of the image run
+// along x, the top one at z = 0.
+fn get_image_heightfield(image: &InputImage) -> Heightfield {
+
+	// Height maps are stored as they are, not sRGB encoded, so the gray values are encoded back
+	let heights = image.pixels.iter().map(|p| srgb_oetf(luminance(p))).collect();
+	return Heightfield::new(image.width as usize, image.height as usize, heights);
+}
+
+// Scatters copies of a small figure over the floor, each turned, scaled and colored differently. All copies share
+// the same shape and are grouped in the node named figures.
+fn create_instances(count: usize) -> SceneNode {
+	let sphere = |x: f64, y: f64, z: f64, radius: f64, color: LightColor| Sphere {
+		center: Vector3 { x, y, z },
+		radius,
+		material_color: Texture::Constant(color),
+		surface_detail: SurfaceDetail::Smooth,
+		visibility: Visibility::all()
+	};
+
+	// Body and head, with a nose that shows which way the figure is turned
+	let white = LightColor { r: 1.0, g: 1.0, b: 1.0 };
+	let shape = Arc::new(Shape::new(vec![
+		sphere(0.0, 0.5, 0.0, 0.5, white),
+		sphere(0.0, 1.2, 0.0, 0.3, white),
+		sphere(0.0, 1.2, 0.32, 0.08, LightColor { r: 1.0, g: 0.4, b: 0.0 })
+	]));
+
+	let palette = [LightColor { r: 0.9, g: 0.9, b: 0.9 }, LightColor { r: 0.3, g: 0.5, b: 1.0 }, LightColor { r: 1.0, g: 0.8, b: 0.2 }, LightColor { r: 0.4, g: 0.9, b: 0.4 }];
+	let columns = (count as f64).sqrt().ceil() as usize;
+
+	let figures = (0..count).map(|i| {
+		let random = |k: u64| (mix_bits(((i as u64) << 8) | k) >> 11) as f64 / ((1u64 << 53) as f64);
+		let x = -9.0 + 18.0 * ((i % columns) as f64 + 0.2 + 0.6 * random(0)) / (columns as f64);
+		let z = -35.0 + 33.0 * ((i / columns) as f64 + 0.2 + 0.6 * random(1)) / (columns as f64);
+		let size = 0.6 + 0.8 * random(2);
+
+		let transform = Transform::translate(Vector3 { x, y: -10.0, z })
+			* Transform::rotate(Vector3 { x: 0.0, y: 1.0, z: 0.0 }, std::f64::consts::TAU * random(3))
+			* Transform::scale(size, size * (0.8 + 0.4 * random(4)), size);
+
+		// Every other copy keeps the colors of the shape
+		let material_override = if i % 2 == 0 { None } else { Some(Texture::Constant(palette[(i / 2) % palette.len()])) };
+		let mut figure = SceneNode::new(&format!("figure-{}", i), NodeContent::Shape(shape.clone(), material_override));
+		figure.transform = transform;
+		return figure;
+	}).collect();
+
+	return SceneNode::group("figures", figures);
+}
+
+// Furnishes the room with one primitive of each kind: a table (a box on cylinders) with a torus and a cone on it, a
+// turned box, a painting (a quad) on the left wall and a lamp (an emitting disk) below the ceiling. They are grouped
+// in the node named primitives.
+fn create_primitives() -> SceneNode {
+	let v = |x: f64, y: f64, z: f64| Vector3 { x, y, z };
+	let colored = |name: &str, mut primitive: Primitive, color: LightColor| {
+		primitive.material_color = Texture::Constant(color);
+		return SceneNode::new(name, NodeContent::Primitive(Box::new(primitive)));
+	};
+
+	let wood = LightColor { r: 0.6, g: 0.4, b: 0.25 };
+	let mut table = vec![colored("table-top", Primitive::axis_aligned_box(v(2.0, -6.5, -18.0), v(9.0, -6.0, -13.0)), wood)];
+
+	for (i, (x, z)) in [(2.6, -17.4), (8.4, -17.4), (2.6, -13.6), (8.4, -13.6)].into_iter().enumerate() {
+		let leg = Primitive::new(PrimitiveKind::Cylinder { radius: 0.3, height: 3.5 }, Transform::translate(v(x, -10.0, z)));
+		table.push(colored(&format!("table-leg-{}", i), leg, wood));
+	}
+
+	let ring = Primitive::new(PrimitiveKind::Torus { major_radius: 1.2, minor_radius: 0.4 }, Transform::translate(v(4.0, -4.4, -15.5)) * Transform::rotate(v(1.0, 0.0, 0.0), std::f64::consts::FRAC_PI_2));
+	table.push(colored("torus", ring, LightColor { r: 0.9, g: 0.7, b: 0.1 }));
+	let cone = Primitive::new(PrimitiveKind::Cone { radius: 1.0, height: 2.5 }, Transform::translate(v(7.0, -6.0, -15.5)));
+	table.push(colored("cone", cone, LightColor { r: 0.2, g: 0.5, b: 1.0 }));
+
+	let crate_box = Primitive::new(PrimitiveKind::Box { half_size: v(1.5, 1.5, 1.5) }, Transform::translate(v(-7.5, -8.5, -16.0)) * Transform::rotate(v(0.0, 1.0, 0.0), 0.5));
+
+	// A die on the crate, the rounded cube where a box and a sphere overlap with holes drilled along all three axes
+	let quarter = std::f64::consts::FRAC_PI_2;
+	let drill = |rotation: Transform| CsgNode::solid(PrimitiveKind::Cylinder { radius: 0.5, height: 3.0 }, rotation * Transform::translate(v(0.0, -1.5, 0.0)));
+	let rounded = CsgNode::intersection(CsgNode::solid(PrimitiveKind::Box { half_size: v(1.0, 1.0, 1.0) }, Transform::identity()), CsgNode::solid(PrimitiveKind::Sphere { radius: 1.35 }, Transform::identity()));
+	let holes = CsgNode::union(CsgNode::union(drill(Transform::rotate(v(1.0, 0.0, 0.0), quarter)), drill(Transform::rotate(v(0.0, 0.0, 1.0), quarter))), drill(Transform::identity()));
+	let die = Primitive::new(PrimitiveKind::Csg(Box::new(CsgNode::difference(rounded, holes))), Transform::translate(v(-7.5, -6.0, -16.0)) * Transform::rotate(v(0.0, 1.0, 0.0), 0.5));
+
+	// A sculpture of smoothly joined distance functions: a twisted column with arms and a head, standing on feet
+	let column = SdfNode::twist(SdfNode::translate(SdfNode::RoundBox { half_size: v(0.7, 2.0, 0.7), radius: 0.2 }, v(0.0, 2.0, 0.0)), 0.5);
+	let arms = SdfNode::Capsule { a: v(-1.6, 3.0, 0.0), b: v(1.6, 3.0, 0.0), radius: 0.25 };
+	let head = SdfNode::translate(SdfNode::Sphere { radius: 0.9 }, v(0.0, 4.8, 0.0));
+	let feet = SdfNode::repeat(SdfNode::translate(SdfNode::Sphere { radius: 0.3 }, v(0.0, 0.3, 0.0)), v(0.8, 0.0, 0.8), v(1.0, 0.0, 1.0));
+	let body = SdfNode::smooth_union(SdfNode::smooth_union(column, arms, 0.4), SdfNode::smooth_union(head, feet, 0.4), 0.5);
+	let sculpture = Primitive::new(PrimitiveKind::Sdf(Box::new(Sdf::new(body))), Transform::translate(v(0.5, -10.0, -17.0)) * Transform::rotate(v(0.0, 1.0, 0.0), 1.2));
+
+	let painting = Primitive::new(PrimitiveKind::Quad { edge_u: v(0.0, 0.0, 8.0), edge_v: v(0.0, 5.0, 0.0) }, Transform::translate(v(-9.95, 1.0, -30.0)));
+
+	let mut lamp = Primitive::new(PrimitiveKind::Disk { radius: 3.0 }, Transform::translate(v(0.0, 9.9, -22.0)));
+	lamp.emission = LightColor { r: 3.0, g: 3.0, b: 3.0 };
+
+	return SceneNode::group("primitives", vec![
+		SceneNode::group("table", table),
+		colored("crate", crate_box, LightColor { r: 0.8, g: 0.3, b: 0.3 }),
+		colored("die", die, LightColor { r: 0.9, g: 0.9, b: 0.85 }),
+		colored("sculpture", sculpture, LightColor { r: 0.7, g: 0.6, b: 0.9 }),
+		colored("painting", painting, LightColor { r: 0.3, g: 0.7, b: 0.6 }),
+		SceneNode::new("lamp", NodeContent::Primitive(Box::new(lamp)))
+	]);
+}
+
+// Applies --texture, --normal-map or --bump to a primitive of the space, the primitives being numbered like in the id
+// AOV (spheres first, then planes, then the other primitives)
+fn apply_primitive_option(space: &mut Space, option: &str, id: usize, value: &str) {
+	let sphere_count = space.spheres.len();
+	let plane_count = space.planes.len();
+
+	let (texture, surface_detail) = if id < sphere_count {
+		let sphere = &mut space.spheres[id];
+		(&mut sphere.material_color, &mut sphere.surface_detail)
+	} else if id < sphere_count + plane_count {
+		let plane = &mut space.planes[id - sphere_count];
+		(&mut plane.material_color, &mut plane.surface_detail)
+	} else {
+		let primitive = space.primitives.primitives.get_mut(id - sphere_count - plane_count).expect("--texture, --normal-map and --bump expect the id of a primitive");
+		(&mut primitive.material_color, &mut primitive.surface_detail)
+	};
+
+	apply_material_option(texture, surface_detail, option, value);
+}
+
+// Replaces the texture (--texture) or the surface detail (--normal-map, --bump) of a primitive. Patterns blend the
+// color of the primitive with a darker shade of it.
+fn apply_material_option(texture: &mut Texture, surface_detail: &mut SurfaceDetail, option: &str, value: &str) {
+	if option != "--texture" {
+		*surface_detail = parse_surface_detail(option, value);
+		return;
+	}
+
+	let base = match texture {
+		Texture::Constant(c) => *c,
+		_ => LightColor { r: 1.0, g: 1.0, b: 1.0 }
+	};
+
+	*texture = parse_texture(value, &base);
+}
+
+// Parses a normal map given as <strength>:<path>[:<wrap mode>[:<scale>]] or a bump map given as <strength>:<texture>
+fn parse_surface_detail(option: &str, value: &str) -> SurfaceDetail {
+	let (strength, spec) = value.split_once(':').expect("--normal-map and --bump expect <strength>:<texture>");
+	let strength = strength.parse::<f64>().expect("--normal-map and --bump expect a number as strength");
+	let white = LightColor { r: 1.0, g: 1.0, b: 1.0 };
+
+	if option == "--bump" {
+		return SurfaceDetail::Bump(BumpMap { height: parse_texture(spec, &white), strength });
+	}
+
+	let mut image = match parse_texture(&format!("image:{}", spec), &white) {
+		Texture::Image(image) => image,
+		_ => unreachable!()
+	};
+
+	// Normal maps hold directions, not light, so the sRGB decoding of the image reader is undone
+	let encode = |c: &LightColor| LightColor { r: srgb_oetf(c.r), g: srgb_oetf(c.g), b: srgb_oetf(c.b) };
+	image.pixels = Arc::new(image.pixels.iter().map(encode).collect());
+	return SurfaceDetail::NormalMap(NormalMap { image, strength });
+}
+
+// Parses a texture given as image:<path>[:<wrap mode>[:<scale>]] or <pattern>[:<scale>]
+fn parse_texture(value: &str, base: &LightColor) -> Texture {
+	let parts: Vec<&str> = value.split(':').collect();
+	let parse_scale = |index: usize| parts.get(index).map_or(1.0, |s| s.parse::<f64>().expect("--texture expects a number as scale"));
+
+	if parts[0] == "image" {
+		let path = parts.get(1).expect("--texture expects image:<path>[:<wrap mode>[:<scale>]]");
+		let image = read_image_file(path).expect("Could not read texture image");
+		let wrap = parts.get(2).map_or(WrapMode::Repeat, |w| WrapMode::from_name(w).expect("--texture expects repeat, clamp or mirror as wrap mode"));
+		return Texture::Image(ImageTexture { width: image.width, height: image.height, pixels: Arc::new(image.pixels), wrap, scale: parse_scale(3) });
+	}
+
+	let pattern = PatternTexture { color_a: *base, color_b: LightColor { r: base.r * 0.25, g: base.g * 0.25, b: base.b * 0.25 }, scale: parse_scale(1) };
+
+	return match parts[0] {
+		"checker" => Texture::Checker(pattern),
+		"grid" => Texture::Grid(pattern),
+		"perlin" => Texture::Perlin(pattern),
+		"worley" => Texture::Worley(pattern),
+		"marble" => Texture::Marble(pattern),
+		"wood" => Texture::Wood(pattern),
+		_ => panic!("--texture expects image, checker, grid, perlin, worley, marble or wood")
+	};
+}
+
+// Parses the comma separated kinds of rays seeing a node (camera, shadow, indirect), or none
+fn parse_visibility(value: &str) -> Visibility {
+	let mut visibility = Visibility::none();
+
+	for ray in value.split(',').filter(|r| *r != "none") {
+		match ray.trim() {
+			"camera" => visibility.camera = true,
+			"shadow" => visibility.shadow = true,
+			"indirect" => visibility.indirect = true,
+			_ => panic!("--visibility expects a list of camera, shadow and indirect, or none")
+		}
+	}
+
+	return visibility;
+}
+
+// Parses a comma separated list of exactly count values
+fn parse_list<T: std::str::FromStr>(value: &str, count: usize, message: &str) -> Vec<T> {
+	let values: Vec<T> = value.split(',').map(|v| v.trim().parse::<T>().ok().expect(message)).collect();
+	assert!(values.len() == count, "{}", message);
+	return values;
+}
+
+// Returns the value of an on/off command line option
+fn parse_switch(value: &str) -> bool {
+	return match value {
+		"on" => true,
+		"off" => false,
+		_ => panic!("Expected on or off instead of {}", value)
+	};
+}
+
+// Returns the number of samples per pixel as gray values, white being max_samples
+fn sample_count_pixels(sample_counts: &[u32], max_samples: u32) -> Vec<LightColor> {
+	return sample_counts.iter().map(|&count| {
+		let v = ((count as f64) / (max_samples as f64)).min(1.0);
+		return LightColor { r: v, g: v, b: v };
+	}).collect();
 }
 
 #[cfg(test)]
 mod tests {
     use super::*; // Import everything from the outer module
+	use raytracing::rays::*;
+	use raytracing::film::*;
+	use raytracing::tracing::*;
+	use photon::distributed::protocol::*;
+	use photon::input;
+	use fundamentals::noise::*;
+	use rand::Rng;
+
+	// Builds the space of the demo scene and the camera looking at it
+	fn create_scene() -> (Space, CameraZ) {
+		let (graph, camera) = create_scene_graph();
+		return (graph.build(), camera);
+	}
+
+	// The demo scene with another node in its root group
+	fn create_scene_with(node: SceneNode) -> (Space, CameraZ) {
+		let (mut graph, camera) = create_scene_graph();
+
+		if let NodeContent::Group(children) = &mut graph.root.content {
+			children.push(node);
+		}
+
+		return (graph.build(), camera);
+	}
+
+	// Sends the scene through the protocol like to a worker, checks that it arrives unchanged and returns it
+	fn assert_reaches_workers(space: &Space, camera: &CameraZ) -> Space {
+		let settings = RenderSettings::new(4, 4);
+		let mut message: Vec<u8> = Vec::new();
+		write_scene(&mut message, &encode_scene(space, camera, &settings).unwrap()).unwrap();
+		let (received, _, _) = read_scene(&mut message.as_slice()).unwrap();
+		assert_eq!(format!("{:?}", received), format!("{:?}", space));
+		return received;
+	}
 
 	fn assert_vec3_eq(a: &Vector3, b: &Vector3) {
 		assert_eq!(a.x, b.x);
@@ -115,13 +745,22 @@ mod tests {
 	}
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn test_basis_computation() {		
 		let tolerance: f64 = 0.00000001;
 
-		// Do 10 random basis computations
-		for i in 0..10 {
-			let n = v3_random_normal();
-			let b = v3_compute_basis_for_normal(&n); 
+		let mut rng = Pcg32::new(1, 0);
+
+		// Do 10 random basis computations and some at the poles, where constructions tend to break down
+		let mut normals: Vec<Vector3> = (0..10).map(|_| v3_random_normal(&mut rng)).collect();
+		normals.push(Vector3 { x: 0.0, y: 0.0, z: 1.0 });
+		normals.push(Vector3 { x: 0.0, y: 0.0, z: -1.0 });
+		normals.push(Vector3 { x: 1.0, y: 0.0, z: -0.0 });
+		normals.push(v3_normalize(&Vector3 { x: 1e-9, y: -2e-9, z: -1.0 }));
+		normals.push(Vector3 { x: 0.0, y: 1.0, z: 0.0 });
+
+		for n in &normals {
+			let b = v3_orthonormal_basis(n); 
 
 			// Validate that length of all basis vectors is zero (close enough to it)
 			assert_eq!((v3_len(&b.b0)-1.0).abs() < tolerance, true);
@@ -137,15 +776,22 @@ mod tests {
 			assert_eq!(b.b0.x.abs() + b.b0.y.abs() + b.b0.z.abs() > 0.1, true);
 			assert_eq!(b.b1.x.abs() + b.b1.y.abs() + b.b1.z.abs() > 0.1, true);
 			assert_eq!(b.b2.x.abs() + b.b2.y.abs() + b.b2.z.abs() > 0.1, true);
+
+			// The basis is right handed and the same on every call
+			assert_vec3_eq(&b.b0, n);
+			assert_eq!(v3_len(&v3_delta(&v3_cross_product(&b.b1, &b.b2), n)) < tolerance, true);
+			let again = v3_orthonormal_basis(n);
+			assert_eq!(again.b1.x == b.b1.x && again.b1.y == b.b1.y && again.b1.z == b.b1.z && again.b2.x == b.b2.x && again.b2.y == b.b2.y && again.b2.z == b.b2.z, true);
 		}
 	}
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn test_ray_sphere_intersections() {
 
 				// Sphere 1 
 				let ray1 = Ray {origin: Vector3 {x: 0.0, y: 0.0, z: 0.0}, direction: Vector3 {x: 0.0, y: 1.0, z: 0.0}};
-				let sphere1 = Sphere {center: Vector3 {x: 0.0, y: 10.0, z: 0.0}, radius: 1.0, material_color: LightColor {r: 1.0, g: 1.0, b: 0.0}};
+				let sphere1 = Sphere {center: Vector3 {x: 0.0, y: 10.0, z: 0.0}, radius: 1.0, material_color: Texture::Constant(LightColor {r: 1.0, g: 1.0, b: 0.0}), surface_detail: SurfaceDetail::Smooth, visibility: Visibility::all()};
 				let intersections1 = get_ray_sphere_intersections(&ray1, &sphere1);
 
 				match intersections1 {
@@ -163,7 +809,7 @@ mod tests {
 
 				// Sphere 2
 				let ray2 = Ray {origin: Vector3 {x: 0.0, y: -5.0, z: 0.0}, direction: Vector3 {x: 1.0, y: 0.0, z: 0.0}};
-				let sphere2 = Sphere {center: Vector3 {x: 5.0, y: -5.0, z: 0.0}, radius: 2.5, material_color: LightColor {r: 1.0, g: 1.0, b: 0.0}};
+				let sphere2 = Sphere {center: Vector3 {x: 5.0, y: -5.0, z: 0.0}, radius: 2.5, material_color: Texture::Constant(LightColor {r: 1.0, g: 1.0, b: 0.0}), surface_detail: SurfaceDetail::Smooth, visibility: Visibility::all()};
 				
 				let intersections2 = get_ray_sphere_intersections(&ray2, &sphere2);
 
@@ -188,5 +834,1352 @@ mod tests {
 				let intersections4 = get_ray_sphere_intersections(&ray4, &sphere2);
 				assert_eq!(intersections4.is_none(), true);
     }
-}
 
+	#[test]
+	fn test_render_is_deterministic() {
+		let mut settings = RenderSettings::new(12, 10);
+		settings.samples_per_pixel = 8;
+		settings.seed = 42;
+
+		// Same seed on different thread counts (also one that does not divide the height) must give identical bits,
+		// also when the filter makes samples contribute to pixels of other threads
+		let mut images: Vec<Vec<LightColor>> = Vec::new();
+
+		for (thread_count, seed, filter_type) in [(1, 42, FilterType::Box), (3, 42, FilterType::Box), (1, 43, FilterType::Box), (1, 42, FilterType::Mitchell), (4, 42, FilterType::Mitchell)] {
+			let (space, camera) = create_scene();
+			settings.thread_count = thread_count;
+			settings.seed = seed;
+			settings.filter = PixelFilter::new(filter_type);
+			images.push(render(space, camera, &settings).pixels);
+		}
+
+		assert_eq!(images[0].len(), 120);
+		assert_eq!(images[1].len(), 120);
+
+		for (i, j) in [(0, 1), (3, 4)] {
+			for (a, b) in images[i].iter().zip(images[j].iter()) {
+				assert_eq!(a.r.to_bits(), b.r.to_bits());
+				assert_eq!(a.g.to_bits(), b.g.to_bits());
+				assert_eq!(a.b.to_bits(), b.b.to_bits());
+			}
+		}
+
+		// A different seed must give a different image
+		assert!(images[0].iter().zip(images[2].iter()).any(|(a, c)| a.r != c.r || a.g != c.g || a.b != c.b));
+	}
+
+	#[test]
+	fn test_samplers_are_stratified() {
+		const N: u32 = 16;
+
+		for sampler_type in [SamplerType::Independent, SamplerType::Stratified, SamplerType::Halton, SamplerType::Sobol] {
+			let mut sampler = create_sampler(sampler_type, 5, N);
+			let mut strata_x = [0; N as usize];
+			let mut strata_y = [0; N as usize];
+
+			for k in 0..N {
+				sampler.start_pixel_sample(17, k);
+
+				// Skip some dimensions to test one that is not the first
+				sampler.get_2d();
+				let (u, v) = sampler.get_2d();
+
+				assert!((0.0..1.0).contains(&u));
+				assert!((0.0..1.0).contains(&v));
+
+				strata_x[(u * N as f64) as usize] += 1;
+				strata_y[(v * N as f64) as usize] += 1;
+			}
+
+			// All low discrepancy samplers put exactly one sample into each of the 1D strata
+			if sampler_type == SamplerType::Sobol || sampler_type == SamplerType::Stratified {
+				assert!(strata_x.iter().all(|&c| c == 1));
+				assert!(strata_y.iter().all(|&c| c == 1));
+			}
+		}
+
+		// Halton with base 2 and 3: the first 2^k and 3^k samples are stratified in the respective dimension
+		let mut sampler = create_sampler(SamplerType::Halton, 5, 9);
+		let mut strata = [0; 9];
+
+		for k in 0..9 {
+			sampler.start_pixel_sample(3, k);
+			let (_, v) = sampler.get_2d();
+			strata[(v * 9.0) as usize] += 1;
+		}
+		assert!(strata.iter().all(|&c| c == 1));
+	}
+
+	#[test]
+	fn test_adaptive_sampling() {
+		let mut settings = RenderSettings::new(8, 8);
+		settings.thread_count = 2;
+		settings.sampler = SamplerType::Sobol;
+		settings.adaptive = Some(AdaptiveSampling { min_samples: 4, max_samples: 64, error_threshold: 0.05 });
+
+		let (space, camera) = create_scene();
+		let result = render(space, camera, &settings);
+
+		// All counts are within the bounds and a multiple of the batch size
+		assert_eq!(result.sample_counts.len(), 64);
+		assert!(result.sample_counts.iter().all(|&c| (4..=64).contains(&c) && c.is_multiple_of(4)));
+
+		// A constant signal converges after the first batch, a noisy one does not
+		let mut flat = PixelEstimate::new();
+		let mut noisy = PixelEstimate::new();
+
+		for k in 0..16u32 {
+			flat.add_sample(&LightColor { r: 0.5, g: 0.5, b: 0.5 });
+			let v = if k.is_multiple_of(2) { 0.0 } else { 1.0 };
+			noisy.add_sample(&LightColor { r: v, g: v, b: v });
+		}
+
+		assert!(flat.is_converged(0.01));
+		assert!(!noisy.is_converged(0.01));
+		assert!((noisy.variance() - 0.2666666).abs() < 0.001);
+	}
+
+	#[test]
+	fn test_pixel_filters() {
+		for filter_type in [FilterType::Box, FilterType::Tent, FilterType::Gaussian, FilterType::Mitchell, FilterType::Lanczos] {
+			let filter = PixelFilter::new(filter_type);
+
+			// Highest weight in the pixel center, nothing outside the radius
+			assert!(filter.evaluate(0.0, 0.0) > 0.0);
+			assert!(filter.evaluate(0.0, 0.0) >= filter.evaluate(0.3, 0.2));
+			assert_eq!(filter.evaluate(filter.radius + 0.01, 0.0), 0.0);
+		}
+
+		// A constant signal must stay constant, the weights are normalized per pixel
+		let mut film = Film::new(0, 4, 0, 4, PixelFilter::new(FilterType::Lanczos));
+
+		for y in 0..4 {
+			for x in 0..4 {
+				film.add_sample(x, y, 0.1 * (x as f64) - 0.2, 0.3 - 0.1 * (y as f64), &LightColor { r: 0.25, g: 0.5, b: 1.0 });
+			}
+		}
+
+		for p in film.resolve() {
+			assert!((p.r - 0.25).abs() < 1e-9 && (p.g - 0.5).abs() < 1e-9 && (p.b - 1.0).abs() < 1e-9);
+		}
+	}
+
+	#[test]
+	fn test_png_output() {
+		let pixels: Vec<LightColor> = (0..12).map(|i| LightColor { r: (i as f64) / 12.0, g: 0.5, b: 1.0 }).collect();
+		let image = Image { pixels: &pixels, width: 4, height: 3 };
+
+		let mut png8: Vec<u8> = Vec::new();
+		let mut png16: Vec<u8> = Vec::new();
+		let mut options = OutputOptions::new();
+		write_image(&mut png8, &image, ImageFormat::Png, &options).unwrap();
+		options.bit_depth = 16;
+		write_image(&mut png16, &image, ImageFormat::Png, &options).unwrap();
+
+		// Signature, then the IHDR chunk with size, bit depth and RGB color type
+		assert_eq!(&png8[0..8], &[137, 80, 78, 71, 13, 10, 26, 10]);
+		assert_eq!(&png8[12..16], b"IHDR");
+		assert_eq!(&png8[16..24], &[0, 0, 0, 4, 0, 0, 0, 3]);
+		assert_eq!(png8[24], 8);
+		assert_eq!(png16[24], 16);
+		assert_eq!(png8[25], 2);
+		assert_eq!(&png8[png8.len() - 8..png8.len() - 4], b"IEND");
+
+		// Known checksums
+		assert_eq!(output::deflate::crc32(b"IEND"), 0xae426082);
+		assert_eq!(output::deflate::adler32(b"Wikipedia"), 0x11e60398);
+
+		assert_eq!(ImageFormat::from_path("out/image.PNG"), Some(ImageFormat::Png));
+		assert_eq!(ImageFormat::from_path("image.ppm"), Some(ImageFormat::Ppm));
+		assert_eq!(ImageFormat::from_path("image"), None);
+	}
+
+	#[test]
+	fn test_float_output() {
+
+		// Values outside of [0, 1] must survive
+		let pixels = vec![LightColor { r: 13.5, g: -0.25, b: 0.001 }, LightColor { r: 1.0, g: 2.0, b: 65504.0 }];
+		let image = Image { pixels: &pixels, width: 2, height: 1 };
+
+		let mut pfm: Vec<u8> = Vec::new();
+		write_image(&mut pfm, &image, ImageFormat::Pfm, &OutputOptions::new()).unwrap();
+		assert_eq!(&pfm[0..12], b"PF\n2 1\n-1.0\n");
+		assert_eq!(f32::from_le_bytes([pfm[12], pfm[13], pfm[14], pfm[15]]), 13.5);
+		assert_eq!(pfm.len(), 12 + 2 * 3 * 4);
+
+		// Half conversion including rounding, overflow and subnormals
+		assert_eq!(f32_to_half(1.0), 0x3c00);
+		assert_eq!(f32_to_half(-2.0), 0xc000);
+		assert_eq!(f32_to_half(0.1), 0x2e66);
+		assert_eq!(f32_to_half(65504.0), 0x7bff);
+		assert_eq!(f32_to_half(1.0e6), 0x7c00);
+		assert_eq!(f32_to_half(2.0f32.powi(-24)), 0x0001);
+		assert_eq!(f32_to_half(1.0e-9), 0x0000);
+
+		// Uncompressed EXR: header, one offset per scanline, then the scanline with the channels B, G, R
+		let mut exr: Vec<u8> = Vec::new();
+		write_exr(&mut exr, &image, ExrSampleType::Float, ExrCompression::None).unwrap();
+		assert_eq!(&exr[0..4], &[0x76, 0x2f, 0x31, 0x01]);
+		let line = &exr[exr.len() - 24..];
+		assert_eq!(f32::from_le_bytes([line[0], line[1], line[2], line[3]]), 0.001);
+		assert_eq!(f32::from_le_bytes([line[4], line[5], line[6], line[7]]), 65504.0);
+		assert_eq!(f32::from_le_bytes([line[16], line[17], line[18], line[19]]), 13.5);
+	}
+
+	#[test]
+	fn test_display_transform() {
+		assert_eq!(srgb_oetf(0.0), 0.0);
+		assert!((srgb_oetf(1.0) - 1.0).abs() < 1e-12);
+		assert!((srgb_oetf(0.0031308) - 0.04045).abs() < 1e-4);
+		assert!((srgb_oetf(0.18) - 0.4614).abs() < 1e-3);
+
+		// Values above 1 must saturate instead of wrapping around
+		let mut display = DisplayTransform::new();
+		display.dither = false;
+		let bright = display.to_display(&LightColor { r: 1.3, g: 100.0, b: -1.0 });
+		assert_eq!(display.quantize(bright.r, 255, 0, 0), 255);
+		assert_eq!(display.quantize(bright.g, 255, 0, 1), 255);
+		assert_eq!(display.quantize(bright.b, 255, 0, 2), 0);
+
+		// Tone mapping operators are monotonic and stay within the display range
+		for tone_mapping in [ToneMapping::Reinhard, ToneMapping::Aces, ToneMapping::Agx] {
+			display.tone_mapping = tone_mapping;
+			let mut previous = -1.0;
+
+			for i in 0..100 {
+				let v = 0.001 * 1.2f64.powi(i);
+				let d = display.to_display(&LightColor { r: v, g: v, b: v });
+				assert!(d.g >= previous && (0.0..=1.0).contains(&d.g));
+				previous = d.g;
+			}
+		}
+
+		// Dithering keeps the average of a constant value between two quantization steps
+		display = DisplayTransform::identity();
+		display.dither = true;
+		let mean = (0..10000).map(|i| display.quantize(100.3 / 255.0, 255, i, 0) as f64).sum::<f64>() / 10000.0;
+		assert!((mean - 100.3).abs() < 0.05);
+	}
+
+	#[test]
+	fn test_aovs() {
+		let mut settings = RenderSettings::new(10, 10);
+		settings.samples_per_pixel = 4;
+		settings.thread_count = 2;
+		settings.aovs = true;
+
+		let (space, camera) = create_scene();
+		let sphere_center = space.spheres[1].center;
+		let result = render(space, camera, &settings);
+
+		assert_eq!(result.aovs.len(), ALL_AOV_TYPES.len());
+
+		let depth = result.aov(AovType::Depth).unwrap();
+		let normal = result.aov(AovType::Normal).unwrap();
+		let position = result.aov(AovType::Position).unwrap();
+		let id = result.aov(AovType::PrimitiveId).unwrap();
+		let direct = result.aov(AovType::Direct).unwrap();
+		let indirect = result.aov(AovType::Indirect).unwrap();
+
+		for i in 0..100 {
+			assert!(depth.pixels[i].r.is_finite());
+
+			// Normals are normalized, direct and indirect light add up to the image (box filter)
+			let n = &normal.pixels[i];
+			assert!(((n.r * n.r + n.g * n.g + n.b * n.b).sqrt() - 1.0).abs() < 1e-9);
+			assert!((direct.pixels[i].g + indirect.pixels[i].g - result.pixels[i].g).abs() < 1e-9);
+		}
+
+		// The green sphere (index 1) is left of and below the center. The average of the hit positions of a pixel lies
+		// slightly within the curved surface.
+		let on_sphere = 6 * 10 + 3;
+		assert_eq!(id.pixels[on_sphere].r, 1.0);
+		let p = &position.pixels[on_sphere];
+		let distance = v3_len(&v3_delta(&Vector3 { x: p.r, y: p.g, z: p.b }, &sphere_center));
+		assert!(distance > 2.5 && distance < 3.0 + 1e-9);
+
+		assert_eq!(AovType::PrimitiveId.channel_names(), vec![String::from("id")]);
+		assert_eq!(AovType::Albedo.channel_names()[2], "albedo.B");
+	}
+
+	#[test]
+	fn test_denoiser() {
+		let width = 32;
+		let height = 32;
+		let mut rng = Pcg32::new(3, 0);
+
+		// Two walls meeting in the middle of the image, the left one dark and the right one bright, both noisy
+		let mut pixels: Vec<LightColor> = Vec::new();
+		let mut normals: Vec<LightColor> = Vec::new();
+
+		for _ in 0..height {
+			for x in 0..width {
+				let base = if x < width / 2 { 0.2 } else { 0.8 };
+				let v = base * (0.5 + rng.gen::<f64>());
+				pixels.push(LightColor { r: v, g: v, b: v });
+				normals.push(if x < width / 2 { LightColor { r: 1.0, g: 0.0, b: 0.0 } } else { LightColor { r: 0.0, g: 0.0, b: 1.0 } });
+			}
+		}
+
+		let albedo = vec![LightColor { r: 1.0, g: 1.0, b: 1.0 }; pixels.len()];
+		let depth = vec![LightColor { r: 5.0, g: 5.0, b: 5.0 }; pixels.len()];
+		let features = DenoiseFeatures { albedo: &albedo, normal: &normals, depth: &depth };
+
+		let error = |image: &[LightColor], x_range: std::ops::Range<i32>, expected: f64| {
+			let values: Vec<f64> = (0..height).flat_map(|y| x_range.clone().map(move |x| (y * width + x) as usize)).map(|i| image[i].g).collect();
+			return values.iter().map(|v| (v - expected).abs()).sum::<f64>() / (values.len() as f64);
+		};
+
+		for method in [DenoiseMethod::ATrous, DenoiseMethod::NonLocalMeans] {
+			let mut denoise_settings = DenoiseSettings::new(method);
+			denoise_settings.thread_count = 3;
+			let denoised = denoise(&pixels, width, height, &features, &denoise_settings);
+
+			// The noise is reduced a lot, the walls do not bleed into each other at the edge
+			assert!(error(&denoised, 0..16, 0.2) < 0.3 * error(&pixels, 0..16, 0.2));
+			assert!(error(&denoised, 16..32, 0.8) < 0.3 * error(&pixels, 16..32, 0.8));
+			assert!(error(&denoised, 15..16, 0.2) < 0.02);
+			assert!(error(&denoised, 16..17, 0.8) < 0.08);
+
+			// The thread count does not change the result
+			denoise_settings.thread_count = 1;
+			let single_thread = denoise(&pixels, width, height, &features, &denoise_settings);
+			assert!(denoised.iter().zip(&single_thread).all(|(a, b)| a.r == b.r && a.g == b.g && a.b == b.b));
+		}
+	}
+
+	#[test]
+	fn test_progressive_rendering() {
+		let mut settings = RenderSettings::new(12, 12);
+		settings.samples_per_pixel = 5;
+		settings.thread_count = 2;
+		settings.aovs = true;
+
+		let (space, camera) = create_scene();
+		let reference = render(space, camera, &settings);
+
+		// Passes of 2, 2 and 1 samples, a snapshot after the first two
+		let mut snapshots: Vec<u32> = Vec::new();
+		let (space, camera) = create_scene();
+		let progressive = ProgressiveSettings::new(2, 5);
+		let result = render_progressive(space, camera, &settings, &progressive, &CancellationToken::new(), |snapshot| {
+			assert!(snapshot.frame.sample_counts.iter().all(|&c| c == snapshot.samples));
+			snapshots.push(snapshot.samples);
+		}, |_| {});
+
+		assert_eq!(snapshots, vec![2, 4]);
+		assert!(result.sample_counts.iter().all(|&c| c == 5));
+
+		// Same samples as in one go, only summed in another order
+		for i in 0..144 {
+			assert!((result.pixels[i].r - reference.pixels[i].r).abs() < 1e-12);
+			assert!((result.pixels[i].g - reference.pixels[i].g).abs() < 1e-12);
+			assert_eq!(result.aov(AovType::PrimitiveId).unwrap().pixels[i].r, reference.aov(AovType::PrimitiveId).unwrap().pixels[i].r);
+			assert!((result.aov(AovType::Depth).unwrap().pixels[i].r - reference.aov(AovType::Depth).unwrap().pixels[i].r).abs() < 1e-9);
+		}
+
+		// The passes share the strata of all samples, so stratified samples are the same as well
+		settings.sampler = SamplerType::Stratified;
+		let (space, camera) = create_scene();
+		let reference = render(space, camera, &settings);
+		let (space, camera) = create_scene();
+		let result = render_progressive(space, camera, &settings, &progressive, &CancellationToken::new(), |_| {}, |_| {});
+
+		for i in 0..144 {
+			assert!((result.pixels[i].r - reference.pixels[i].r).abs() < 1e-12);
+			assert!((result.pixels[i].b - reference.pixels[i].b).abs() < 1e-12);
+		}
+
+		// An exhausted time budget stops after the first pass
+		let (space, camera) = create_scene();
+		let budget = ProgressiveSettings { samples_per_pass: 1, target_samples: None, time_budget: Some(Duration::ZERO), snapshot_interval: None };
+		let result = render_progressive(space, camera, &settings, &budget, &CancellationToken::new(), |_| panic!("No snapshot expected"), |_| {});
+		assert!(result.sample_counts.iter().all(|&c| c == 1));
+
+		// Without a target or a budget the rendering would never end, so it is refused
+		let (space, camera) = create_scene();
+		let endless = ProgressiveSettings { target_samples: None, time_budget: None, ..budget };
+		assert!(std::panic::catch_unwind(|| render_progressive(space, camera, &settings, &endless, &CancellationToken::new(), |_| {}, |_| {})).is_err());
+	}
+
+	#[test]
+	fn test_progress_and_stats() {
+		let mut settings = RenderSettings::new(12, 12);
+		settings.samples_per_pixel = 3;
+		settings.thread_count = 2;
+		settings.tile_height = 5;
+
+		let (space, camera) = create_scene();
+		let primitive_count = (space.spheres.len() + space.planes.len()) as u64;
+		let mut reports: Vec<RenderProgress> = Vec::new();
+		let result = render_with_progress(space, camera, &settings, |p| reports.push(*p));
+
+		// Tiles of 5, 5 and 2 rows
+		assert_eq!(reports.len(), 3);
+		assert_eq!(reports.iter().map(|p| p.tiles_completed).collect::<Vec<u32>>(), vec![1, 2, 3]);
+		assert!(reports.iter().all(|p| p.tile_count == 3));
+		assert_eq!(reports[2].eta, Duration::ZERO);
+		assert_eq!(reports[2].counters, result.stats.counters);
+
+		// The scene is closed, so every camera ray hits a surface and casts the global illumination rays
+		let c = &result.stats.counters;
+		assert_eq!(c.primary_rays, 144 * 3);
+		assert_eq!(c.indirect_rays, 8 * c.primary_rays);
+		assert!(c.shadow_rays > 0 && c.shadow_rays <= c.primary_rays + c.indirect_rays);
+		assert_eq!(c.intersection_tests, c.total_rays() * primitive_count);
+		assert!(result.stats.wall_time > Duration::ZERO);
+
+		// The tile size does not change the image
+		settings.tile_height = 32;
+		let (space, camera) = create_scene();
+		let single_tile = render(space, camera, &settings);
+		assert!(result.pixels.iter().zip(&single_tile.pixels).all(|(a, b)| a.r == b.r && a.g == b.g && a.b == b.b));
+		assert_eq!(single_tile.stats.counters, result.stats.counters);
+	}
+
+	#[test]
+	fn test_cancellation() {
+		let mut settings = RenderSettings::new(12, 12);
+		settings.samples_per_pixel = 2;
+		settings.thread_count = 1;
+		settings.tile_height = 4;
+
+		// Cancelled after the first tile. The thread stops between two rows of whatever tile it is working on by then,
+		// rows are either complete or empty.
+		let cancel = CancellationToken::new();
+		let (space, camera) = create_scene();
+		let result = render_cancellable(space, camera, &settings, &cancel, |_| cancel.cancel());
+
+		assert_eq!(result.status, RenderStatus::Cancelled);
+		assert_eq!(result.pixels.len(), 144);
+		assert!(result.sample_counts[..48].iter().all(|&c| c == 2));
+
+		for row in result.sample_counts.chunks(12) {
+			assert!(row.iter().all(|&c| c == 2) || row.iter().all(|&c| c == 0));
+		}
+
+		for (p, &c) in result.pixels.iter().zip(&result.sample_counts) {
+			assert!(c > 0 || (p.r == 0.0 && p.g == 0.0 && p.b == 0.0));
+		}
+
+		// A token cancelled from the start renders nothing, also progressively
+		let (space, camera) = create_scene();
+		let progressive = ProgressiveSettings::new(1, 4);
+		let result = render_progressive(space, camera, &settings, &progressive, &cancel, |_| panic!("No snapshot expected"), |_| {});
+		assert_eq!(result.status, RenderStatus::Cancelled);
+		assert!(result.sample_counts.iter().all(|&c| c == 0));
+
+		let (space, camera) = create_scene();
+		assert_eq!(render(space, camera, &settings).status, RenderStatus::Completed);
+
+		// Cancelled only after the last tile, nothing has been skipped
+		let cancel = CancellationToken::new();
+		let (space, camera) = create_scene();
+		let result = render_cancellable(space, camera, &settings, &cancel, |p| if p.tiles_completed == p.tile_count { cancel.cancel() });
+		assert_eq!(result.status, RenderStatus::Completed);
+
+		let cancel = CancellationToken::new();
+		let (space, camera) = create_scene();
+		let result = render_progressive(space, camera, &settings, &ProgressiveSettings::new(2, 4), &cancel, |_| {}, |p| if p.pass == 1 && p.tiles_completed == p.tile_count { cancel.cancel() });
+		assert_eq!(result.status, RenderStatus::Completed);
+		assert!(result.sample_counts.iter().all(|&c| c == 4));
+	}
+
+	#[test]
+	fn test_checkpoint_resume() {
+		// Checkpoints are matched to the scene by its FNV-1a hash
+		assert_eq!(fnv1a("a".bytes().map(u64::from)), 0xaf63dc4c8601ec8c);
+
+		let mut settings = RenderSettings::new(10, 10);
+		settings.thread_count = 2;
+		settings.tile_height = 3;
+		settings.aovs = true;
+		settings.filter = PixelFilter::new(FilterType::Mitchell);
+
+		let (space, camera) = create_scene();
+		let uninterrupted = render_progressive(space, camera, &settings, &ProgressiveSettings::new(2, 6), &CancellationToken::new(), |_| {}, |_| {});
+
+		// Interrupted in the middle of the second pass, only the first pass is kept
+		let cancel = CancellationToken::new();
+		let (space, camera) = create_scene();
+		let mut state = ProgressiveRender::new(space, camera, &settings, &ProgressiveSettings::new(2, 6));
+		continue_progressive(&mut state, &cancel, |_| {}, |p| if p.pass == 1 { cancel.cancel() });
+		assert_eq!(state.samples, 2);
+
+		let mut checkpoint: Vec<u8> = Vec::new();
+		write_checkpoint(&mut checkpoint, &state).unwrap();
+
+		// Resumed by a new state, the result is bit-identical
+		let (space, camera) = create_scene();
+		let mut resumed = ProgressiveRender::new(space, camera, &settings, &ProgressiveSettings::new(2, 6));
+		read_checkpoint(&mut checkpoint.as_slice(), &mut resumed).unwrap();
+		assert_eq!(resumed.samples, 2);
+		let result = continue_progressive(&mut resumed, &CancellationToken::new(), |_| {}, |_| {});
+
+		assert_eq!(result.status, RenderStatus::Completed);
+		assert_eq!(result.sample_counts, uninterrupted.sample_counts);
+		assert_eq!(result.stats.counters, uninterrupted.stats.counters);
+		assert!(result.pixels.iter().zip(&uninterrupted.pixels).all(|(a, b)| a.r == b.r && a.g == b.g && a.b == b.b));
+
+		for (a, b) in result.aovs.iter().zip(&uninterrupted.aovs) {
+			assert!(a.pixels.iter().zip(&b.pixels).all(|(a, b)| a.r.to_bits() == b.r.to_bits() && a.g.to_bits() == b.g.to_bits()));
+		}
+
+		// Checkpoints of other scenes or settings are rejected
+		settings.seed = 1;
+		let (space, camera) = create_scene();
+		let mut other = ProgressiveRender::new(space, camera, &settings, &ProgressiveSettings::new(2, 6));
+		assert_eq!(read_checkpoint(&mut checkpoint.as_slice(), &mut other).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+		assert_eq!(other.samples, 0);
+	}
+
+	#[test]
+	fn test_region_rendering() {
+		let mut settings = RenderSettings::new(12, 12);
+		settings.samples_per_pixel = 2;
+		settings.tile_height = 4;
+		settings.aovs = true;
+		settings.filter = PixelFilter::new(FilterType::Mitchell);
+
+		let (space, camera) = create_scene();
+		let full = render(space, camera, &settings);
+
+		let region = PixelRegion { x: 3, y: 5, width: 5, height: 6 };
+		settings.region = Some(region);
+		let (space, camera) = create_scene();
+		let result = render(space, camera, &settings);
+
+		// The pixels of the region are exactly the ones of the full image, all others stay empty
+		assert_eq!((result.pixels.len(), result.sample_counts.len(), result.aovs[0].pixels.len()), (30, 30, 30));
+		assert_eq!(result.pixels[7].g, full.pixels[6 * 12 + 5].g);
+		let expanded = result.expand(12, 12, &region);
+		assert_eq!(expanded.pixels.len(), 144);
+
+		for i in 0..144 {
+			let (x, y) = ((i % 12) as i32, (i / 12) as i32);
+			let (a, b) = (&expanded.pixels[i], &full.pixels[i]);
+
+			if region.contains(x, y) {
+				assert!(a.r == b.r && a.g == b.g && a.b == b.b);
+				assert_eq!(expanded.sample_counts[i], 2);
+				assert_eq!(expanded.aov(AovType::Depth).unwrap().pixels[i].r, full.aov(AovType::Depth).unwrap().pixels[i].r);
+			}
+			else {
+				assert!(a.r == 0.0 && a.g == 0.0 && a.b == 0.0);
+				assert_eq!(expanded.sample_counts[i], 0);
+			}
+		}
+
+		// Only the columns and rows within the filter reach (2 pixels) around the region are traced: columns 1..10, rows
+		// 3..11 for the tile of rows 5..9 and rows 7..12 for the one of rows 9..11
+		assert_eq!(result.stats.counters.primary_rays, 9 * (8 + 5) * 2);
+
+		// Progressive renderings and their checkpoints only keep the sums of the region
+		let (space, camera) = create_scene();
+		let state = ProgressiveRender::new(space, camera, &settings, &ProgressiveSettings::new(1, 1));
+		assert_eq!((state.frame.film.pixels.len(), state.frame.sample_counts.len(), state.frame.aovs.len()), (30, 30, 30));
+
+		// Crop windows in fractions of the image and borders
+		assert_eq!(PixelRegion::from_crop(0.25, 0.5, 0.75, 1.0, 12, 12), PixelRegion { x: 3, y: 6, width: 6, height: 6 });
+		assert_eq!(PixelRegion::from_border(1, 2, 3, 4, 12, 12), PixelRegion { x: 1, y: 2, width: 8, height: 6 });
+		assert!(!PixelRegion::from_border(6, 0, 6, 0, 12, 12).is_within(12, 12));
+	}
+
+	#[test]
+	fn test_distributed_rendering() {
+		let mut settings = RenderSettings::new(10, 10);
+		settings.samples_per_pixel = 2;
+		settings.tile_height = 3;
+		settings.aovs = true;
+		settings.filter = PixelFilter::new(FilterType::Mitchell);
+
+		let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+		let address = listener.local_addr().unwrap().to_string();
+
+		// A worker dying with its first tile, after which two working ones take over
+		let t_address = address.clone();
+		let workers = std::thread::spawn(move || {
+			let stream = std::net::TcpStream::connect(&t_address).unwrap();
+			let mut reader = std::io::BufReader::new(stream.try_clone().unwrap());
+			write_hello(&mut &stream).unwrap();
+			read_scene(&mut reader).unwrap();
+			assert_eq!(read_tile(&mut reader).unwrap(), Some(TileRequest { y_start: 0, y_count: 3, first_sample: 0 }));
+			drop(reader);
+			drop(stream);
+
+			let w_address = t_address.clone();
+			let other = std::thread::spawn(move || run_worker(&w_address, 1).unwrap());
+			return run_worker(&t_address, 1).unwrap() + other.join().unwrap();
+		});
+
+		let (space, camera) = create_scene();
+		let mut reports: Vec<RenderProgress> = Vec::new();
+		let result = render_distributed(listener, &space, &camera, &settings, &CoordinatorSettings::new(), &CancellationToken::new(), |p| reports.push(*p));
+		assert_eq!(workers.join().unwrap(), 4);
+
+		// Exactly the same image as a local rendering
+		let local = render(space, camera, &settings);
+		assert_eq!(result.status, RenderStatus::Completed);
+		assert_eq!(reports.len(), 4);
+		assert_eq!(result.sample_counts, local.sample_counts);
+		assert_eq!(result.stats.counters, local.stats.counters);
+		assert!(result.pixels.iter().zip(&local.pixels).all(|(a, b)| a.r == b.r && a.g == b.g && a.b == b.b));
+
+		for (a, b) in result.aovs.iter().zip(&local.aovs) {
+			assert!(a.pixels.iter().zip(&b.pixels).all(|(a, b)| a.r.to_bits() == b.r.to_bits() && a.b.to_bits() == b.b.to_bits()));
+		}
+	}
+
+	#[test]
+	fn test_image_input() {
+		let pixels: Vec<LightColor> = (0..12).map(|i| LightColor { r: (i as f64) / 11.0, g: 0.5, b: 0.02 }).collect();
+		let image = Image { pixels: &pixels, width: 4, height: 3 };
+		let mut options = OutputOptions::new();
+		options.display.dither = false;
+
+		// PNG and PPM round trips, exact up to the quantization
+		for (format, bit_depth, tolerance) in [(ImageFormat::Png, 8, 0.01), (ImageFormat::Png, 16, 1e-4), (ImageFormat::Ppm, 8, 0.01)] {
+			let mut data: Vec<u8> = Vec::new();
+			options.bit_depth = bit_depth;
+			write_image(&mut data, &image, format, &options).unwrap();
+			let read = if format == ImageFormat::Png { input::png::read_png(&mut data.as_slice()) } else { input::ppm::read_ppm(&mut data.as_slice()) }.unwrap();
+
+			assert_eq!((read.width, read.height), (4, 3));
+			assert!(read.pixels.iter().zip(&pixels).all(|(a, b)| (a.r - b.r).abs() < tolerance && (a.g - b.g).abs() < tolerance && (a.b - b.b).abs() < tolerance));
+		}
+
+		// Text PPM with comments and a binary one with 16 bit values
+		let text = input::ppm::read_ppm(&mut b"P3\n# comment\n2 1 # width and height\n255\n255 0 0  0 0 255\n".as_slice()).unwrap();
+		assert_eq!((text.pixels[0].r, text.pixels[0].g, text.pixels[1].b), (1.0, 0.0, 1.0));
+		let binary = input::ppm::read_ppm(&mut b"P6 1 1 65535\n\xff\xff\x00\x00\x80\x00".as_slice()).unwrap();
+		assert_eq!(binary.pixels[0].r, 1.0);
+		assert!((binary.pixels[0].b - srgb_eotf(32768.0 / 65535.0)).abs() < 1e-12);
+		assert!(input::ppm::read_ppm(&mut b"P6 2 2 255\n\x00".as_slice()).is_err());
+		assert_eq!(input::ppm::read_ppm(&mut b"P6 4294967295 4294967295 255\n\x00".as_slice()).err().unwrap().kind(), std::io::ErrorKind::InvalidData);
+		assert_eq!(input::ppm::read_ppm(&mut b"P3 65536 65536 255\n0 0 0".as_slice()).err().unwrap().kind(), std::io::ErrorKind::InvalidData);
+
+		// Inflate of stored and fixed blocks written by the own compressor and of dynamic blocks written by zlib
+		let data: Vec<u8> = (0..5000).map(|i| ((i * i) % 251) as u8).collect();
+		assert_eq!(input::inflate::zlib_decompress(&output::deflate::zlib_compress(&data)).unwrap(), data);
+		let hex = "78da358d890d00210cc366cdb3ff0c57071da2a814e3a891ebaab22239b12585a357bcfa4a0ccc76ee1afe18e4dafe28945fa39bc2216515f93083194b98ce19029782cc243e1a5f97cee8254eb9a02e11e4030fe54c88";
+		let compressed: Vec<u8> = (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap()).collect();
+		let expected = "adcabdbdadabacaabccbbaaacbaaadaaaabdbbbdbabaaabaababcbdbcdbdabaadabcddaadaaaabbababaaabbaaddabdbcacaaaaaadcaabadaaabbbabbbcbdcadcabaabacacbabdbabddaabbbbdabadaababaddbababbdaabaadaacaaababaadbbabaabad";
+		assert_eq!(input::inflate::zlib_decompress(&compressed).unwrap(), expected.as_bytes());
+
+		// A wrong checksum is detected
+		let mut broken = compressed.clone();
+		let last = broken.len() - 1;
+		broken[last] ^= 1;
+		assert!(input::inflate::zlib_decompress(&broken).is_err());
+	}
+
+	#[test]
+	fn test_textures() {
+		let (black, white) = (LightColor { r: 0.0, g: 0.0, b: 0.0 }, LightColor { r: 1.0, g: 1.0, b: 1.0 });
+		let origin = Vector3 { x: 0.0, y: 0.0, z: 0.0 };
+
+		// 2x1 image: bilinear filtering between the pixel centers and the wrap modes outside
+		let mut image = ImageTexture { width: 2, height: 1, pixels: Arc::new(vec![black, white]), wrap: WrapMode::Clamp, scale: 1.0 };
+		assert_eq!(image.evaluate(0.25, 0.5).r, 0.0);
+		assert_eq!(image.evaluate(0.5, 0.5).r, 0.5);
+		assert_eq!(image.evaluate(1.5, 0.5).r, 1.0);
+		image.wrap = WrapMode::Repeat;
+		assert_eq!(image.evaluate(1.25, 0.5).r, 0.0);
+		assert_eq!(image.evaluate(1.0, 0.5).r, 0.5);
+		image.wrap = WrapMode::Mirror;
+		assert_eq!(image.evaluate(1.25, 0.5).r, 1.0);
+		assert_eq!(image.evaluate(-0.25, 0.5).r, 0.0);
+		assert_eq!(WrapMode::from_name(WrapMode::Mirror.name()), Some(WrapMode::Mirror));
+
+		let pattern = PatternTexture { color_a: white, color_b: black, scale: 2.0 };
+		assert_eq!(Texture::Checker(pattern).evaluate(0.1, 0.1, &origin).r, 1.0);
+		assert_eq!(Texture::Checker(pattern).evaluate(0.6, 0.1, &origin).r, 0.0);
+		assert_eq!(Texture::Checker(pattern).evaluate(-0.1, 0.1, &origin).r, 0.0);
+		assert_eq!(Texture::Grid(pattern).evaluate(0.25, 0.25, &origin).r, 1.0);
+		assert_eq!(Texture::Grid(pattern).evaluate(0.505, 0.25, &origin).r, 0.0);
+
+		// Noise is deterministic, continuous and stays in its range
+		assert_eq!(perlin_noise(&origin), 0.0);
+		let mut previous = perlin_noise(&Vector3 { x: -0.7, y: 0.3, z: 1.9 });
+
+		for i in 1..2000 {
+			let p = Vector3 { x: (i as f64) * 0.001 - 0.7, y: 0.3, z: 1.9 };
+			let n = perlin_noise(&p);
+			assert!((-1.0..=1.0).contains(&n) && (n - previous).abs() < 0.01 && n == perlin_noise(&p));
+			assert!((0.0..=3.0f64.sqrt()).contains(&worley_noise(&p)));
+			previous = n;
+		}
+
+		for texture in [Texture::Perlin(pattern), Texture::Worley(pattern), Texture::Marble(pattern), Texture::Wood(pattern)] {
+			let c = texture.evaluate(0.0, 0.0, &Vector3 { x: 0.3, y: -1.2, z: 2.5 });
+			assert!((-0.01..=1.01).contains(&c.r) && c.r == c.g);
+		}
+
+		// UV mapping of spheres and planes
+		let (u, v) = get_sphere_uv(&Vector3 { x: 0.0, y: 0.0, z: 1.0 });
+		assert!((u - 0.5).abs() < 1e-12 && (v - 0.5).abs() < 1e-12);
+		assert_eq!(get_sphere_uv(&Vector3 { x: 0.0, y: 1.0, z: 0.0 }).1, 0.0);
+		let floor = Plane { center: Vector3 { x: 0.0, y: -1.0, z: 0.0 }, normal: Vector3 { x: 0.0, y: 1.0, z: 0.0 }, material_color: Texture::Checker(pattern), surface_detail: SurfaceDetail::Smooth, visibility: Visibility::all() };
+		let (u, v) = get_plane_uv(&floor, &Vector3 { x: 2.0, y: -1.0, z: 3.0 });
+		assert!((u - 2.0).abs() < 1e-12 && (v - 3.0).abs() < 1e-12);
+
+		// A checkerboard floor shows both of its colors in the albedo, the pixels far away averaging several squares
+		let mut settings = RenderSettings::new(16, 16);
+		settings.aovs = true;
+		let (mut space, camera) = create_scene();
+		space.planes[3].material_color = Texture::Checker(PatternTexture { color_a: white, color_b: black, scale: 0.5 });
+		let result = render(space, camera, &settings);
+		let albedo = &result.aov(AovType::Albedo).unwrap().pixels[14 * 16..];
+		assert!(albedo.iter().any(|p| p.g < 0.25) && albedo.iter().any(|p| p.g > 0.75));
+	}
+
+	#[test]
+	fn test_normal_mapping() {
+		let white = LightColor { r: 1.0, g: 1.0, b: 1.0 };
+		let up = Vector3 { x: 0.0, y: 1.0, z: 0.0 };
+
+		// Plane tangents point right and down on walls and right and towards the camera on floors
+		let (tangent_u, tangent_v) = get_plane_tangents(&Vector3 { x: 0.0, y: 0.0, z: 1.0 });
+		assert_vec3_eq(&tangent_u, &Vector3 { x: 1.0, y: 0.0, z: 0.0 });
+		assert_vec3_eq(&tangent_v, &Vector3 { x: 0.0, y: -1.0, z: 0.0 });
+		let (tangent_u, tangent_v) = get_plane_tangents(&up);
+		assert_vec3_eq(&tangent_u, &Vector3 { x: 1.0, y: 0.0, z: 0.0 });
+		assert_vec3_eq(&tangent_v, &Vector3 { x: 0.0, y: 0.0, z: 1.0 });
+
+		// The sphere frame holds the derivatives of the spherical mapping and is the same every time
+		let sphere = Sphere { center: Vector3 { x: 1.0, y: 2.0, z: 3.0 }, radius: 2.0, material_color: Texture::Constant(white), surface_detail: SurfaceDetail::Smooth, visibility: Visibility::all() };
+		let position = |u: f64, v: f64| {
+			let (theta, phi) = (std::f64::consts::PI * v, 2.0 * std::f64::consts::PI * (u - 0.5));
+			let n = Vector3 { x: theta.sin() * phi.sin(), y: theta.cos(), z: theta.sin() * phi.cos() };
+			return v3_sum(&sphere.center, &v3_scale(&n, sphere.radius));
+		};
+
+		for (u, v) in [(0.3, 0.4), (0.8, 0.1), (0.55, 0.9)] {
+			let normal = v3_normalize(&v3_delta(&position(u, v), &sphere.center));
+			let (mu, mv) = get_sphere_uv(&normal);
+			assert!((mu - u).abs() < 1e-9 && (mv - v).abs() < 1e-9);
+
+			let frame = get_sphere_tangent_frame(&sphere, &normal);
+			let h = 1e-6;
+			let du = v3_scale(&v3_delta(&position(u + h, v), &position(u - h, v)), 0.5 / h);
+			let dv = v3_scale(&v3_delta(&position(u, v + h), &position(u, v - h)), 0.5 / h);
+			assert!(v3_len(&v3_delta(&du, &frame.dp_du)) < 1e-6 && v3_len(&v3_delta(&dv, &frame.dp_dv)) < 1e-6);
+
+			let (t, b) = (frame.tangent(), frame.bitangent());
+			assert!(v3_dot_product(&t, &b).abs() < 1e-12 && v3_dot_product(&t, &normal).abs() < 1e-12);
+			assert!((v3_len(&b) - 1.0).abs() < 1e-12 && v3_dot_product(&b, &frame.dp_dv) < 0.0);
+		}
+
+		// The poles have a frame as well
+		let frame = get_sphere_tangent_frame(&sphere, &up);
+		assert!((v3_len(&frame.tangent()) - 1.0).abs() < 1e-12 && v3_dot_product(&frame.tangent(), &up).abs() < 1e-12);
+
+		// Normal maps: the flat normal keeps the normal, a normal along red tilts it onto the tangent
+		let frame = TangentFrame { dp_du: Vector3 { x: 1.0, y: 0.0, z: 0.0 }, dp_dv: Vector3 { x: 0.0, y: 0.0, z: 1.0 }, normal: up };
+		let origin = Vector3 { x: 0.0, y: 0.0, z: 0.0 };
+		let pixels = vec![LightColor { r: 0.5, g: 0.5, b: 1.0 }, LightColor { r: 1.0, g: 0.5, b: 0.5 }];
+		let image = ImageTexture { width: 2, height: 1, pixels: Arc::new(pixels), wrap: WrapMode::Clamp, scale: 1.0 };
+		let detail = SurfaceDetail::NormalMap(NormalMap { image: image.clone(), strength: 1.0 });
+		assert_vec3_eq(&detail.shading_normal(&frame, 0.1, 0.5, &origin), &up);
+		assert_vec3_eq(&detail.shading_normal(&frame, 0.9, 0.5, &origin), &frame.dp_du);
+		assert_vec3_eq(&SurfaceDetail::Smooth.shading_normal(&frame, 0.9, 0.5, &origin), &up);
+
+		// Bump maps: the normal leans against the slope, here the ramp between the pixel centers of a black and a white
+		// pixel, rising by 0.5 over 0.5 units of u
+		let ramp = ImageTexture { pixels: Arc::new(vec![LightColor { r: 0.0, g: 0.0, b: 0.0 }, white]), ..image };
+		let detail = SurfaceDetail::Bump(BumpMap { height: Texture::Image(ramp), strength: 0.5 });
+		let n = detail.shading_normal(&frame, 0.5, 0.5, &origin);
+		assert!((n.x + 0.5f64.sqrt()).abs() < 1e-6 && (n.y - 0.5f64.sqrt()).abs() < 1e-6 && n.z.abs() < 1e-9);
+		let detail = SurfaceDetail::Bump(BumpMap { height: Texture::Constant(white), strength: 0.5 });
+		assert_vec3_eq(&detail.shading_normal(&frame, 0.5, 0.5, &origin), &up);
+
+		// The intersection keeps the geometric normal next to the shading normal
+		let floor = Plane { center: Vector3 { x: 0.0, y: -1.0, z: 0.0 }, normal: up, material_color: Texture::Constant(white), surface_detail: SurfaceDetail::Bump(BumpMap { height: Texture::Perlin(PatternTexture { color_a: white, color_b: LightColor { r: 0.0, g: 0.0, b: 0.0 }, scale: 2.0 }), strength: 0.2 }), visibility: Visibility::all() };
+		let hit = get_ray_plane_intersection(&Ray { origin: Vector3 { x: 0.3, y: 1.0, z: 0.2 }, direction: Vector3 { x: 0.0, y: -1.0, z: 0.0 } }, &floor).unwrap();
+		assert_vec3_eq(&hit.geometric_normal, &up);
+		assert!(v3_dot_product(&hit.normal, &up) < 1.0 - 1e-6 && (v3_len(&hit.normal) - 1.0).abs() < 1e-12);
+
+		// No light leaks through the surface where the shading normal leans far over: neither the lights nor the
+		// glowing sphere below the surface reach it, the ones above do
+		let glowing = Primitive { emission: white, ..Primitive::new(PrimitiveKind::Sphere { radius: 10.0 }, Transform::translate(Vector3::new(0.0, -11.0, 0.0))) };
+		let mut space = Space {
+			spheres: Vec::new(),
+			planes: Vec::new(),
+			directional_lights: vec![DirectionalLight { direction: Vector3 { x: -1.0, y: 0.3, z: 0.0 }, color: white }],
+			point_lights: vec![PointLight { position: Vector3 { x: 5.0, y: -1.0, z: 0.0 }, color: white }],
+			primitives: PrimitiveSet::new(vec![glowing]),
+			instances: InstanceSet::new(Vec::new())
+		};
+		let leaning = v3_normalize(&Vector3 { x: 1.0, y: 0.05, z: 0.0 });
+		let mut sampler = create_sampler(SamplerType::Independent, 0, 1);
+		sampler.start_pixel_sample(0, 0);
+		let light = get_split_light_at_surface_point(&origin, &leaning, &up, &space, 1, sampler.as_mut());
+		assert_eq!((light.direct.r, light.indirect.r), (0.0, 0.0));
+
+		space.directional_lights[0].direction.y = -0.3;
+		space.point_lights[0].position.y = 1.0;
+		space.primitives = PrimitiveSet::empty();
+		let lights = get_split_light_at_surface_point(&origin, &leaning, &up, &space, 1, sampler.as_mut());
+		assert!(lights.direct.r > 0.0);
+		let ceiling = PrimitiveKind::Quad { edge_u: Vector3::new(5.0, 0.0, 0.0), edge_v: Vector3::new(0.0, 0.0, 10.0) };
+		space.primitives = PrimitiveSet::new(vec![Primitive { emission: white, ..Primitive::new(ceiling, Transform::translate(Vector3::new(0.0, 3.0, -5.0))) }]);
+		let light = get_split_light_at_surface_point(&origin, &leaning, &up, &space, 1, sampler.as_mut());
+		assert!(light.direct.r > lights.direct.r);
+
+		// Surface details are sent to the workers
+		let (mut space, camera) = create_scene();
+		space.planes[0].surface_detail = floor.surface_detail.clone();
+		space.spheres[1].surface_detail = SurfaceDetail::NormalMap(NormalMap { image, strength: 0.5 });
+		assert_reaches_workers(&space, &camera);
+	}
+
+	#[test]
+	fn test_math() {
+		use fundamentals::math::vector::*;
+		use fundamentals::math::matrix::*;
+		use fundamentals::math::transform::*;
+		use fundamentals::math::quaternion::*;
+
+		let close = |a: Vector3, b: Vector3| (a - b).length() < 1e-12;
+		let a = Vector3::new(1.0, 2.0, 3.0);
+		let b = Vector3::new(-2.0, 0.5, 4.0);
+
+		// The operators compute exactly what the functions compute
+		assert_eq!(a + b * 2.0, v3_sum(&a, &v3_scale(&b, 2.0)));
+		assert_eq!(a - b, v3_delta(&a, &b));
+		assert_eq!(2.0 * a, a * 2.0);
+		assert_eq!(a.cross(b), v3_cross_product(&a, &b));
+		assert_eq!(a.dot(b), v3_dot_product(&a, &b));
+		assert_eq!(a.normalize(), v3_normalize(&a));
+		assert_eq!(-a / 2.0, Vector3::new(-0.5, -1.0, -1.5));
+		assert_eq!((a[0], a[1], a[2]), (1.0, 2.0, 3.0));
+		let mut c = a;
+		c += b;
+		c[2] = 0.0;
+		c *= 2.0;
+		assert_eq!(c, Vector3::new(-2.0, 5.0, 0.0));
+
+		// The same types in single precision
+		let f: Vector3<f32> = Vector3::new(3.0f32, 0.0, 4.0);
+		assert_eq!(f.length(), 5.0f32);
+		assert_eq!((f * 0.5f32 + Vector3::new(0.5, 0.0, 0.0)).x, 2.0f32);
+
+		// Points and normals
+		let p = Point3::new(1.0, 1.0, 1.0);
+		let q = p + b;
+		assert_eq!(q - p, b);
+		assert_eq!(Point3::origin().distance(Point3::new(0.0, 3.0, 4.0)), 5.0);
+		assert_eq!(Normal3::new(0.0, 0.0, 1.0).face_forward(Vector3::new(0.0, 0.0, -1.0)), Normal3::new(-0.0, -0.0, -1.0));
+
+		// Matrices: inverse and products
+		let m: Matrix4 = Matrix4::new([[2.0, 0.0, 1.0, 3.0], [0.0, 1.0, 0.0, -1.0], [1.0, 0.0, 1.0, 0.5], [0.0, 0.0, 0.0, 1.0]]);
+		let product = m * m.inverse().unwrap();
+
+		for i in 0..4 {
+			for j in 0..4 {
+				assert!((product.m[i][j] - if i == j { 1.0 } else { 0.0 }).abs() < 1e-12);
+			}
+		}
+
+		assert!(Matrix4::new([[1.0, 2.0, 0.0, 0.0], [2.0, 4.0, 0.0, 0.0], [0.0, 0.0, 1.0, 0.0], [0.0, 0.0, 0.0, 1.0]]).inverse().is_none());
+		assert_eq!(m.transpose().transpose(), m);
+
+		// Transforms: b is applied first, translations move points but not vectors
+		let t = Transform::translate(Vector3::new(1.0, 0.0, 0.0)) * Transform::scale(2.0, 1.0, 1.0);
+		assert_eq!(t.apply_point(Point3::new(1.0, 1.0, 1.0)), Point3::new(3.0, 1.0, 1.0));
+		assert_eq!(t.apply_vector(Vector3::new(1.0, 1.0, 1.0)), Vector3::new(2.0, 1.0, 1.0));
+		assert_eq!(t.inverse().apply_point(Point3::new(3.0, 1.0, 1.0)), Point3::new(1.0, 1.0, 1.0));
+		assert_eq!(Transform::new(t.matrix).unwrap().inverse, t.inverse);
+
+		// Normals stay perpendicular to the surface under non-uniform scaling
+		let squash = Transform::scale(1.0, 4.0, 1.0);
+		let (tangent, normal) = (Vector3::new(1.0, -1.0, 0.0), Normal3::new(1.0, 1.0, 0.0));
+		assert_eq!(squash.apply_normal(normal).dot(squash.apply_vector(tangent)), 0.0);
+
+		// Quaternions rotate like the rotation matrices, compose and interpolate
+		let axis = Vector3::new(1.0, 2.0, -0.5);
+		let rotation = Quaternion::from_axis_angle(axis, 0.7);
+		let quarter = Quaternion::from_axis_angle(Vector3::new(0.0, 0.0, 1.0), std::f64::consts::FRAC_PI_2);
+		assert!(close(quarter.rotate(Vector3::new(1.0, 0.0, 0.0)), Vector3::new(0.0, 1.0, 0.0)));
+		assert!(close(rotation.rotate(b), Transform::rotate(axis, 0.7).apply_vector(b)));
+		assert!(close((quarter * rotation).rotate(b), quarter.rotate(rotation.rotate(b))));
+		assert!(close(rotation.conjugate().rotate(rotation.rotate(b)), b));
+
+		let half = Quaternion::slerp(Quaternion::identity(), quarter, 0.5);
+		let eighth = Quaternion::from_axis_angle(Vector3::new(0.0, 0.0, 1.0), std::f64::consts::FRAC_PI_4);
+		assert!((half.dot(eighth) - 1.0).abs() < 1e-12);
+		assert!(close(Quaternion::slerp(rotation, rotation, 0.3).rotate(b), rotation.rotate(b)));
+	}
+
+	#[test]
+	fn test_instancing() {
+		use fundamentals::bvh::*;
+		use fundamentals::math::vector::*;
+
+		// The hierarchy finds the same nearest item as testing all of them
+		let mut rng = Pcg32::new(7, 0);
+		let spheres: Vec<(Vector3, f64)> = (0..100).map(|_| (v3_scale(&v3_random(&mut rng), 20.0), 0.2 + rng.gen::<f64>())).collect();
+		let bvh = Bvh::new(&spheres.iter().map(|(c, r)| sphere_bounds(c, *r)).collect::<Vec<Aabb>>());
+		let sphere = |i: usize| Sphere { center: spheres[i].0, radius: spheres[i].1, material_color: Texture::Constant(LightColor { r: 1.0, g: 1.0, b: 1.0 }), surface_detail: SurfaceDetail::Smooth, visibility: Visibility::all() };
+		let mut hits = 0;
+
+		for _ in 0..500 {
+			let ray = Ray { origin: Vector3 { x: -5.0, y: -5.0, z: -5.0 }, direction: v3_random_normal(&mut rng) };
+			let brute_force = (0..100).filter_map(|i| get_ray_sphere_distances(&ray, &sphere(i)).map(|d| d.0)).fold(f64::MAX, f64::min);
+			let mut nearest = f64::MAX;
+			bvh.traverse(&ray.origin, &ray.direction, f64::MAX, |i| {
+				let d = get_ray_sphere_distances(&ray, &sphere(i))?.0;
+				nearest = nearest.min(d);
+				return Some(d);
+			});
+			assert_eq!(nearest, brute_force);
+			hits += usize::from(nearest < f64::MAX);
+		}
+
+		assert!(hits > 50);
+
+		// An instance moved and scaled uniformly is hit like the sphere moved and scaled the same way
+		let white = LightColor { r: 1.0, g: 1.0, b: 1.0 };
+		let red = LightColor { r: 1.0, g: 0.0, b: 0.0 };
+		let shape = Arc::new(Shape::new(vec![Sphere { center: Vector3 { x: 0.0, y: 1.0, z: 0.0 }, radius: 1.0, material_color: Texture::Constant(white), surface_detail: SurfaceDetail::Smooth, visibility: Visibility::all() }]));
+		let transform = Transform::translate(Vector3 { x: 2.0, y: 0.0, z: -10.0 }) * Transform::scale(2.0, 2.0, 2.0);
+		let instance = Instance { shape: shape.clone(), transform, material_override: Some(Texture::Constant(red)), visibility: Visibility::all() };
+		let world = Sphere { center: Vector3 { x: 2.0, y: 2.0, z: -10.0 }, radius: 2.0, material_color: Texture::Constant(white), surface_detail: SurfaceDetail::Smooth, visibility: Visibility::all() };
+		let ray = Ray { origin: Vector3 { x: 0.0, y: 0.0, z: 0.0 }, direction: v3_normalize(&Vector3 { x: 0.2, y: 0.25, z: -1.0 }) };
+		let a = get_ray_instance_sphere_intersection(&ray, &instance, &shape.spheres[0]).unwrap();
+		let b = get_ray_sphere_intersections(&ray, &world).unwrap().near;
+		assert!((a.distance - b.distance).abs() < 1e-9 && (a.position - b.position).length() < 1e-9 && (a.normal - b.normal).length() < 1e-9);
+		assert_eq!((a.material_color.r, a.material_color.g), (1.0, 0.0));
+
+		// The instance is counted as a test next to the sphere of its shape
+		let alone = Space { spheres: Vec::new(), planes: Vec::new(), directional_lights: Vec::new(), point_lights: Vec::new(), primitives: PrimitiveSet::new(Vec::new()), instances: InstanceSet::new(vec![instance.clone()]) };
+		take_thread_counters();
+		assert!(get_nearest_primitive_for_ray(&ray, &alone, RayKind::Camera).is_some());
+		assert_eq!(take_thread_counters().intersection_tests, 2);
+
+		// Spheres hidden from a kind of ray aren't tested, so they aren't counted either
+		let hidden = Space { spheres: vec![Sphere { visibility: Visibility::none(), ..world.clone() }], instances: InstanceSet::new(Vec::new()), ..alone };
+		assert!(get_nearest_primitive_for_ray(&ray, &hidden, RayKind::Camera).is_none());
+		assert_eq!(take_thread_counters().intersection_tests, 0);
+
+		// Normals stay perpendicular to a squashed sphere: the gradient of the ellipsoid x^2 / 4 + y^2 + z^2 = 1
+		let ellipsoid = Instance { shape: Arc::new(Shape::new(vec![world])), transform: Transform::scale(2.0, 1.0, 1.0) * Transform::translate(Vector3 { x: -1.0, y: -1.0, z: 5.0 }) * Transform::scale(0.5, 0.5, 0.5), material_override: None, visibility: Visibility::all() };
+		let ray = Ray { origin: Vector3 { x: 5.0, y: 3.0, z: 4.0 }, direction: v3_normalize(&Vector3 { x: -5.0, y: -2.8, z: -4.2 }) };
+		let hit = get_ray_instance_sphere_intersection(&ray, &ellipsoid, &ellipsoid.shape.spheres[0]).unwrap();
+		let p = hit.position;
+		assert!((p.x * p.x / 4.0 + p.y * p.y + p.z * p.z - 1.0).abs() < 1e-9);
+		assert!((hit.normal - Vector3 { x: p.x / 4.0, y: p.y, z: p.z }.normalize()).length() < 1e-9);
+
+		// Instances get ids after the planes, share their shape and reach the workers
+		let (space, camera) = create_scene_with(create_instances(30));
+		assert_eq!(Arc::strong_count(&space.instances.instances[0].shape), 30);
+		let ray = Ray { origin: camera.location, direction: v3_normalize(&v3_delta(&space.instances.instances[3].transform.apply_point(Point3::new(0.0, 0.5, 0.0)).to_vector(), &camera.location)) };
+		let hit = get_nearest_surface_intersection_for_ray(&ray, &space, RayKind::Camera).unwrap();
+		assert!(hit.primitive_id >= 8);
+
+		let received = assert_reaches_workers(&space, &camera);
+		assert!(Arc::ptr_eq(&received.instances.instances[0].shape, &received.instances.instances[29].shape));
+	}
+
+	#[test]
+	fn test_scene_graph() {
+		use fundamentals::math::vector::*;
+
+		// The demo scene is found by name and flattens into the primitives it always had
+		let (graph, camera) = create_scene_graph();
+		assert!(matches!(graph.find("floor").unwrap().content, NodeContent::Plane(_)));
+		assert!(graph.find("nothing").is_none());
+		let space = graph.build();
+		assert_eq!((space.spheres.len(), space.planes.len(), space.point_lights.len(), space.instances.instances.len()), (3, 5, 1, 0));
+		assert_vec3_eq(&space.planes[3].center, &Vector3 { x: 0.0, y: -10.0, z: 0.0 });
+
+		// Transforms nest, and spheres under uniform scaling stay spheres
+		let white = LightColor { r: 1.0, g: 1.0, b: 1.0 };
+		let ball = SceneNode::new("ball", NodeContent::Sphere(Sphere { center: Vector3 { x: 1.0, y: 0.0, z: 0.0 }, radius: 1.0, material_color: Texture::Constant(white), surface_detail: SurfaceDetail::Smooth, visibility: Visibility::all() }));
+		let mut inner = SceneNode::group("inner", vec![ball]);
+		inner.transform = Transform::scale(2.0, 2.0, 2.0);
+		let mut outer = SceneNode::group("outer", vec![inner]);
+		outer.transform = Transform::translate(Vector3 { x: 0.0, y: 5.0, z: -20.0 });
+		let mut graph = SceneGraph::new(SceneNode::group("root", vec![outer]));
+		let space = graph.build();
+		assert_vec3_eq(&space.spheres[0].center, &Vector3 { x: 2.0, y: 5.0, z: -20.0 });
+		assert_eq!(space.spheres[0].radius, 2.0);
+		assert_vec3_eq(&graph.world_transform("ball").unwrap().apply_point(Point3::origin()).to_vector(), &Vector3 { x: 0.0, y: 5.0, z: -20.0 });
+
+		// Animating a node changes the space built next, other scaling turns the sphere into an instance
+		graph.find_mut("inner").unwrap().transform = Transform::scale(2.0, 1.0, 1.0);
+		let space = graph.build();
+		assert_eq!((space.spheres.len(), space.instances.instances.len()), (0, 1));
+		let ray = Ray { origin: Vector3 { x: 2.0, y: 5.0, z: 0.0 }, direction: Vector3 { x: 0.0, y: 0.0, z: -1.0 } };
+		let (_, distance) = get_nearest_primitive_for_ray(&ray, &space, RayKind::Camera).unwrap();
+		assert!((distance - 19.0).abs() < 1e-9);
+
+		// Rays only see what is visible to them, and nodes hide everything below them
+		let mut graph = create_scene_graph().0;
+		graph.find_mut("spheres").unwrap().visibility = Visibility { camera: false, shadow: true, indirect: true };
+		graph.find_mut("red-sphere").unwrap().visibility = Visibility { camera: true, shadow: false, indirect: true };
+		let space = graph.build();
+		let to_green = Ray { origin: camera.location, direction: v3_normalize(&Vector3 { x: -3.0, y: -3.0, z: -20.0 }) };
+		let to_red = Ray { origin: camera.location, direction: v3_normalize(&Vector3 { x: 4.0, y: -4.0, z: -32.0 }) };
+		let id = |ray: &Ray, kind: RayKind| get_nearest_primitive_for_ray(ray, &space, kind).map(|(p, _)| p.id(&space));
+		assert_eq!(id(&to_green, RayKind::Camera), Some(7));
+		assert_eq!(id(&to_green, RayKind::Shadow), Some(1));
+		assert_eq!(id(&to_green, RayKind::Indirect), Some(1));
+		assert_eq!(id(&to_red, RayKind::Camera), Some(7));
+		assert_eq!(id(&to_red, RayKind::Shadow), Some(7));
+		assert_eq!(id(&to_red, RayKind::Indirect), Some(2));
+		assert_eq!(parse_visibility("camera,indirect"), Visibility { camera: true, shadow: false, indirect: true });
+		assert_eq!(parse_visibility("none"), Visibility::none());
+
+		// The visibilities reach the workers
+		assert_reaches_workers(&space, &camera);
+	}
+
+	#[test]
+	fn test_primitives() {
+		use fundamentals::math::vector::*;
+		use raytracing::primitives::*;
+
+		let ray = |o: Vector3, d: Vector3| Ray { origin: o, direction: d.normalize() };
+		let distance = |r: &Ray, kind: &PrimitiveKind| get_ray_primitive_distance(r, kind, 0.01);
+		let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+
+		// Solids are hit from outside and left from inside
+		let cube = PrimitiveKind::Box { half_size: Vector3::new(1.0, 2.0, 3.0) };
+		assert!(close(distance(&ray(Vector3::new(0.5, 0.5, 10.0), Vector3::new(0.0, 0.0, -1.0)), &cube).unwrap(), 7.0));
+		assert!(close(distance(&ray(Vector3::zero(), Vector3::new(1.0, 0.0, 0.0)), &cube).unwrap(), 1.0));
+		assert!(distance(&ray(Vector3::new(0.0, 3.0, 10.0), Vector3::new(0.0, 0.0, -1.0)), &cube).is_none());
+		let face = get_primitive_surface(&cube, &Vector3::new(0.5, 0.5, 3.0));
+		assert_vec3_eq(&face.normal, &Vector3::new(0.0, 0.0, 1.0));
+		assert!(close(face.u, 0.75) && close(face.v, 0.375));
+
+		let disk = PrimitiveKind::Disk { radius: 1.0 };
+		assert!(close(distance(&ray(Vector3::new(0.5, 5.0, 0.5), Vector3::new(0.0, -1.0, 0.0)), &disk).unwrap(), 5.0));
+		assert!(close(distance(&ray(Vector3::new(0.5, -5.0, 0.5), Vector3::new(0.0, 1.0, 0.0)), &disk).unwrap(), 5.0));
+		assert!(distance(&ray(Vector3::new(0.8, 5.0, 0.8), Vector3::new(0.0, -1.0, 0.0)), &disk).is_none());
+
+		let quad = PrimitiveKind::Quad { edge_u: Vector3::new(2.0, 0.0, 0.0), edge_v: Vector3::new(1.0, 1.0, 0.0) };
+		assert!(close(distance(&ray(Vector3::new(2.0, 0.5, 4.0), Vector3::new(0.0, 0.0, -1.0)), &quad).unwrap(), 4.0));
+		assert!(distance(&ray(Vector3::new(0.2, 0.5, 4.0), Vector3::new(0.0, 0.0, -1.0)), &quad).is_none());
+		let surface = get_primitive_surface(&quad, &Vector3::new(2.0, 0.5, 0.0));
+		assert!(close(surface.u, 0.75) && close(surface.v, 0.5));
+
+		let cylinder = PrimitiveKind::Cylinder { radius: 1.0, height: 2.0 };
+		assert!(close(distance(&ray(Vector3::new(5.0, 1.0, 0.0), Vector3::new(-1.0, 0.0, 0.0)), &cylinder).unwrap(), 4.0));
+		assert!(close(distance(&ray(Vector3::new(0.5, 5.0, 0.0), Vector3::new(0.0, -1.0, 0.0)), &cylinder).unwrap(), 3.0));
+		assert!(distance(&ray(Vector3::new(5.0, 2.5, 0.0), Vector3::new(-1.0, 0.0, 0.0)), &cylinder).is_none());
+		assert_vec3_eq(&get_primitive_surface(&cylinder, &Vector3::new(0.0, 1.0, 1.0)).normal, &Vector3::new(0.0, 0.0, 1.0));
+		assert_vec3_eq(&get_primitive_surface(&cylinder, &Vector3::new(0.5, 2.0, 0.0)).normal, &Vector3::new(0.0, 1.0, 0.0));
+
+		let cone = PrimitiveKind::Cone { radius: 1.0, height: 2.0 };
+		assert!(close(distance(&ray(Vector3::new(5.0, 1.0, 0.0), Vector3::new(-1.0, 0.0, 0.0)), &cone).unwrap(), 4.5));
+		assert!(close(distance(&ray(Vector3::new(0.0, -3.0, 0.5), Vector3::new(0.0, 1.0, 0.0)), &cone).unwrap(), 3.0));
+		let side = get_primitive_surface(&cone, &Vector3::new(0.5, 1.0, 0.0));
+		assert!((side.normal - Vector3::new(1.0, 0.5, 0.0).normalize()).length() < 1e-9);
+		assert!(side.normal.dot(side.dp_du).abs() < 1e-9 && side.normal.dot(side.dp_dv).abs() < 1e-9);
+
+		// The torus is hit on its outside, through its hole only where the tube is, and the hits lie on its surface
+		let (major, minor) = (2.0, 0.5);
+		let torus = PrimitiveKind::Torus { major_radius: major, minor_radius: minor };
+		assert!(close(distance(&ray(Vector3::new(5.0, 0.0, 0.0), Vector3::new(-1.0, 0.0, 0.0)), &torus).unwrap(), 2.5));
+		assert!(close(distance(&ray(Vector3::new(2.0, 5.0, 0.0), Vector3::new(0.0, -1.0, 0.0)), &torus).unwrap(), 4.5));
+		assert!(distance(&ray(Vector3::new(0.0, 5.0, 0.0), Vector3::new(0.0, -1.0, 0.0)), &torus).is_none());
+		assert_vec3_eq(&get_primitive_surface(&torus, &Vector3::new(0.0, 0.0, -2.5)).normal, &Vector3::new(0.0, 0.0, -1.0));
+
+		let implicit = |p: Vector3| (p.dot(p) + major * major - minor * minor).powi(2) - 4.0 * major * major * (p.x * p.x + p.z * p.z);
+		let mut rng = Pcg32::new(11, 0);
+		let mut hits = 0;
+
+		for _ in 0..500 {
+			let origin = v3_scale(&v3_random_normal(&mut rng), 4.0);
+			let target = Vector3::new(5.0 * rng.gen::<f64>() - 2.5, rng.gen::<f64>() - 0.5, 5.0 * rng.gen::<f64>() - 2.5);
+			let r = ray(origin, target - origin);
+
+			if let Some(t) = distance(&r, &torus) {
+				assert!(implicit(r.origin + r.direction * t).abs() < 1e-6);
+				assert!((1..100).all(|i| implicit(r.origin + r.direction * (0.01 + (t - 0.02) * i as f64 / 100.0)) > 0.0));
+				hits += 1;
+			}
+		}
+
+		assert!(hits > 200);
+		let roots = get_polynomial_roots(&[24.0, -50.0, 35.0, -10.0, 1.0], 0.0, 5.0);
+		assert!(roots.len() == 4 && roots.iter().enumerate().all(|(i, r)| close(*r, i as f64 + 1.0)));
+
+		// Transformed primitives are hit in world space, with world space normals
+		let mut turned = Primitive::new(PrimitiveKind::Box { half_size: Vector3::new(1.0, 1.0, 1.0) }, Transform::translate(Vector3::new(0.0, 0.0, -10.0)) * Transform::rotate(Vector3::new(0.0, 1.0, 0.0), std::f64::consts::FRAC_PI_4) * Transform::scale(2.0, 2.0, 2.0));
+		turned.emission = LightColor { r: 2.0, g: 1.0, b: 0.0 };
+		let hit = get_ray_primitive_intersection(&ray(Vector3::zero(), Vector3::new(0.0, 0.0, -1.0)), &turned).unwrap();
+		assert!(close(hit.distance, 10.0 - 2.0 * 2.0f64.sqrt()));
+		assert!((hit.geometric_normal.z - 0.5f64.sqrt()).abs() < 1e-9 && hit.geometric_normal.y.abs() < 1e-9);
+		assert_eq!(hit.emission.r, 2.0);
+
+		// Points sampled on the surfaces lie on them with their normals, and spread over the area: the outer half of a
+		// torus is larger than the inner one
+		let mut rng = Pcg32::new(13, 0);
+		let sampled = [PrimitiveKind::Sphere { radius: 1.5 }, cube.clone(), disk.clone(), quad.clone(), cylinder.clone(), cone.clone(), torus.clone()];
+
+		for kind in sampled.iter() {
+			for _ in 0..100 {
+				let (point, normal, _) = sample_primitive_surface(kind, rng.gen::<f64>(), rng.gen::<f64>()).unwrap();
+				assert!((get_primitive_surface(kind, &point).normal - normal).length() < 1e-6);
+			}
+		}
+
+		let outer = (0..4000).filter(|_| {
+			let (point, _, _) = sample_primitive_surface(&torus, rng.gen::<f64>(), rng.gen::<f64>()).unwrap();
+			point.x * point.x + point.z * point.z > major * major
+		}).count();
+		assert!((outer as f64 / 4000.0 - (std::f64::consts::PI * major + 2.0 * minor) / (2.0 * std::f64::consts::PI * major)).abs() < 0.03);
+		assert!(sample_primitive_surface(&PrimitiveKind::Heightfield(Heightfield::new(2, 2, vec![0.0; 4])), 0.5, 0.5).is_none());
+
+		// Emitters are lights: a disk of radius 1 two units above a point lights it by its form factor of 1/5, also
+		// stretched from a smaller one, and the global illumination rays hitting it don't add it once more
+		let lit = |emitter: Primitive, recursion: i32| {
+			let space = Space { spheres: Vec::new(), planes: Vec::new(), directional_lights: Vec::new(), point_lights: Vec::new(), primitives: PrimitiveSet::new(vec![emitter]), instances: InstanceSet::new(Vec::new()) };
+			let mut sampler = create_sampler(SamplerType::Stratified, 0, 1024);
+			let up = Vector3::new(0.0, 1.0, 0.0);
+
+			let lights: Vec<SurfaceLight> = (0..1024).map(|k| {
+				sampler.start_pixel_sample(0, k);
+				get_split_light_at_surface_point(&Vector3::zero(), &up, &up, &space, recursion, sampler.as_mut())
+			}).collect();
+
+			(lights.iter().map(|l| l.direct.r).sum::<f64>() / 1024.0, lights.iter().map(|l| l.indirect.r).sum::<f64>())
+		};
+
+		let white = LightColor { r: 1.0, g: 1.0, b: 1.0 };
+		let lamp = Primitive { emission: white, ..Primitive::new(PrimitiveKind::Disk { radius: 1.0 }, Transform::translate(Vector3::new(0.0, 2.0, 0.0))) };
+		let (direct, indirect) = lit(lamp, 1);
+		assert!((direct - 0.2).abs() < 0.005 && indirect < 1e-12);
+		let stretched = Primitive { emission: white, ..Primitive::new(PrimitiveKind::Disk { radius: 0.5 }, Transform::translate(Vector3::new(0.0, 2.0, 0.0)) * Transform::scale(2.0, 1.0, 2.0)) };
+		assert!((lit(stretched, 0).0 - 0.2).abs() < 0.005);
+
+		// The furnished room numbers the primitives after the planes, the lamp emits and everything reaches the workers
+		let (space, camera) = create_scene_with(create_primitives());
+		assert_eq!(space.primitives.primitives.len(), 12);
+		let lamp = Ray { origin: camera.location, direction: v3_normalize(&Vector3 { x: 0.0, y: 9.9, z: -32.0 }) };
+		let hit = get_nearest_surface_intersection_for_ray(&lamp, &space, RayKind::Camera).unwrap();
+		assert_eq!((hit.primitive_id, hit.emission.r), (19, 3.0));
+
+		assert_reaches_workers(&space, &camera);
+	}
+
+	#[test]
+	fn test_csg() {
+		use fundamentals::math::vector::*;
+		use raytracing::primitives::*;
+		use raytracing::csg::*;
+
+		let ray = |o: Vector3, d: Vector3| Ray { origin: o, direction: d.normalize() };
+		let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+		let ball = |x: f64| CsgNode::solid(PrimitiveKind::Sphere { radius: 1.0 }, Transform::translate(Vector3::new(x, 0.0, 0.0)));
+		let along_x = ray(Vector3::new(-5.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0));
+		let bounds = |intervals: Vec<Interval>| intervals.iter().map(|i| (i.enter.distance, i.exit.distance)).collect::<Vec<(f64, f64)>>();
+
+		// Two overlapping spheres combine into intervals of entering and leaving, which end on the right leaves
+		assert_eq!(bounds(get_ray_csg_intervals(&along_x, &CsgNode::union(ball(0.0), ball(1.0)))), vec![(4.0, 7.0)]);
+		assert_eq!(bounds(get_ray_csg_intervals(&along_x, &CsgNode::intersection(ball(0.0), ball(1.0)))), vec![(5.0, 6.0)]);
+		let difference = get_ray_csg_intervals(&along_x, &CsgNode::difference(ball(0.0), ball(1.0)));
+		assert_eq!(bounds(difference.clone()), vec![(4.0, 5.0)]);
+		assert_eq!((difference[0].enter.leaf, difference[0].enter.flipped), (0, false));
+		assert_eq!((difference[0].exit.leaf, difference[0].exit.flipped), (1, true));
+		assert!(get_ray_csg_intervals(&along_x, &CsgNode::difference(ball(0.0), ball(0.0))).is_empty());
+
+		// A ray entering through the rim of a cylinder crosses side and cap at once, one touching a sphere doesn't enter
+		let cylinder = CsgNode::solid(PrimitiveKind::Cylinder { radius: 1.0, height: 2.0 }, Transform::identity());
+		for k in 0..100 {
+			let (rim, inside) = (Vector3::new((0.1 * k as f64).cos(), 2.0, (0.1 * k as f64).sin()), Vector3::new(0.3 * (0.7 * k as f64).cos(), 0.0, 0.3 * (0.7 * k as f64).sin()));
+			let through_rim = ray(rim + (rim - inside).normalize() * 3.0, inside - rim);
+			let intervals = bounds(get_ray_csg_intervals(&through_rim, &cylinder));
+			assert!(intervals.len() == 1 && close(intervals[0].0, 3.0) && close(intervals[0].1, 3.0 + (inside - rim).length()));
+		}
+
+		let touching = ray(Vector3::new(-5.0, 1.0, 0.0), Vector3::new(1.0, 0.0, 0.0));
+		assert_eq!(bounds(get_ray_csg_intervals(&touching, &CsgNode::union(ball(0.0), cylinder))), vec![(4.0, 6.0)]);
+
+		// Nested nodes, and the normals of carved surfaces point out of the remaining solid
+		let nested = PrimitiveKind::Csg(Box::new(CsgNode::difference(CsgNode::union(ball(0.0), ball(1.0)), CsgNode::intersection(ball(0.0), ball(1.0)))));
+		let (distance, surface) = get_ray_primitive_hit(&along_x, &nested, 0.01).unwrap();
+		assert!(close(distance, 4.0));
+		assert_vec3_eq(&surface.normal, &Vector3::new(-1.0, 0.0, 0.0));
+		let (distance, surface) = get_ray_primitive_hit(&along_x, &nested, 4.5).unwrap();
+		assert!(close(distance, 5.0));
+		assert_vec3_eq(&surface.normal, &Vector3::new(1.0, 0.0, 0.0));
+		assert!(close(get_ray_primitive_distance(&along_x, &nested, 5.5).unwrap(), 6.0));
+
+		// CSG solids are leaves of other trees too, placed by a transform and their leaves numbered in place of them
+		let placed = CsgNode::union(ball(-3.0), CsgNode::solid(nested, Transform::translate(Vector3::new(2.0, 0.0, 0.0)) * Transform::scale(2.0, 2.0, 2.0)));
+		assert_eq!(bounds(get_ray_csg_intervals(&along_x, &placed)), vec![(1.0, 3.0), (5.0, 7.0), (9.0, 11.0)]);
+		let crossing = get_ray_csg_crossing(&along_x, &placed, 7.5).unwrap();
+		assert_eq!((crossing.leaf, crossing.flipped), (3, true));
+		let (distance, surface) = get_ray_primitive_hit(&along_x, &PrimitiveKind::Csg(Box::new(placed)), 7.5).unwrap();
+		assert!(close(distance, 9.0));
+		assert_vec3_eq(&surface.normal, &Vector3::new(-1.0, 0.0, 0.0));
+		assert!(std::panic::catch_unwind(|| CsgNode::solid(PrimitiveKind::Disk { radius: 1.0 }, Transform::identity())).is_err());
+
+		// Rays through the holes of the die pass, the faces are hit where they are left
+		let die = create_primitives().find("die").map(|node| match &node.content {
+			NodeContent::Primitive(primitive) => primitive.kind.clone(),
+			_ => panic!("The die is a primitive")
+		}).unwrap();
+
+		assert!(get_ray_primitive_distance(&ray(Vector3::new(0.0, 0.0, 5.0), Vector3::new(0.0, 0.0, -1.0)), &die, 0.01).is_none());
+		assert!(get_ray_primitive_distance(&ray(Vector3::new(0.0, 5.0, 0.0), Vector3::new(0.0, -1.0, 0.0)), &die, 0.01).is_none());
+		let (distance, surface) = get_ray_primitive_hit(&ray(Vector3::new(5.0, 0.6, 0.6), Vector3::new(-1.0, 0.0, 0.0)), &die, 0.01).unwrap();
+		assert!(close(distance, 4.0));
+		assert_vec3_eq(&surface.normal, &Vector3::new(1.0, 0.0, 0.0));
+		let (distance, surface) = get_ray_primitive_hit(&ray(Vector3::new(5.0, 0.7, 0.0), Vector3::new(-1.0, 0.0, 0.0)), &die, 4.2).unwrap();
+		assert!(close(distance, 4.5));
+		assert_vec3_eq(&surface.normal, &Vector3::new(-1.0, 0.0, 0.0));
+
+		// Placed in the space, the die is hit in world space and reaches the workers
+		let (space, camera) = create_scene_with(create_primitives());
+		let hit = get_nearest_surface_intersection_for_ray(&ray(Vector3::new(-7.5, 5.0, -16.0), Vector3::new(0.0, -1.0, 0.0)), &space, RayKind::Camera).unwrap();
+		assert!(close(hit.distance, 12.0));
+		let hit = get_nearest_surface_intersection_for_ray(&ray(Vector3::new(-6.9, 5.0, -15.7), Vector3::new(0.0, -1.0, 0.0)), &space, RayKind::Camera).unwrap();
+		assert!(close(hit.distance, 10.0) && (hit.geometric_normal.y - 1.0).abs() < 1e-9);
+
+		assert_reaches_workers(&space, &camera);
+	}
+
+	#[test]
+	fn test_sdf() {
+		use fundamentals::math::vector::*;
+		use raytracing::primitives::*;
+		use raytracing::sdf::*;
+
+		let ray = |o: Vector3, d: Vector3| Ray { origin: o, direction: d.normalize() };
+		let close = |a: f64, b: f64, tolerance: f64| (a - b).abs() < tolerance;
+
+		// The nodes give signed distances, negative within the solids
+		let p = Vector3::new(3.0, 0.0, 0.0);
+		assert!(close(SdfNode::Sphere { radius: 1.0 }.distance(&p), 2.0, 1e-12));
+		assert!(close(SdfNode::Box { half_size: Vector3::new(1.0, 1.0, 1.0) }.distance(&Vector3::new(2.0, 2.0, 0.5)), 2.0f64.sqrt(), 1e-12));
+		assert!(close(SdfNode::Box { half_size: Vector3::new(1.0, 2.0, 3.0) }.distance(&Vector3::zero()), -1.0, 1e-12));
+		assert!(close(SdfNode::RoundBox { half_size: Vector3::new(1.0, 1.0, 1.0), radius: 0.5 }.distance(&Vector3::new(2.0, 0.0, 0.0)), 1.0, 1e-12));
+		assert!(close(SdfNode::Capsule { a: Vector3::new(0.0, -1.0, 0.0), b: Vector3::new(0.0, 1.0, 0.0), radius: 0.5 }.distance(&Vector3::new(0.0, 3.0, 0.0)), 1.5, 1e-12));
+		let pair = |smoothness: f64| SdfNode::smooth_union(SdfNode::Sphere { radius: 1.0 }, SdfNode::translate(SdfNode::Sphere { radius: 1.0 }, Vector3::new(3.0, 0.0, 0.0)), smoothness);
+		let between = Vector3::new(1.5, 0.0, 0.0);
+		assert!(close(pair(0.0).distance(&between), 0.5, 1e-12));
+		assert!(close(pair(1.0).distance(&between), 0.25, 1e-12));
+		let twisted = SdfNode::twist(SdfNode::Box { half_size: Vector3::new(2.0, 5.0, 0.5) }, std::f64::consts::FRAC_PI_2);
+		assert!(close(twisted.distance(&Vector3::new(0.0, 1.0, 1.5)), -0.5, 1e-12));
+		let row = SdfNode::repeat(SdfNode::Sphere { radius: 0.5 }, Vector3::new(2.0, 0.0, 0.0), Vector3::new(2.0, 0.0, 0.0));
+		assert!(close(row.distance(&Vector3::new(4.0, 1.0, 0.0)), 0.5, 1e-12));
+		assert!(close(row.distance(&Vector3::new(7.0, 0.0, 0.0)), 2.5, 1e-12));
+		assert!(close(row.bounds().max.x, 4.5, 1e-12));
+		assert!(!SdfNode::is_repeat_count(&Vector3::new(-1.0, 0.0, 0.0)) && !SdfNode::is_repeat_count(&Vector3::new(0.0, f64::NAN, 0.0)));
+		assert!(std::panic::catch_unwind(|| SdfNode::repeat(SdfNode::Sphere { radius: 0.5 }, Vector3::new(2.0, 0.0, 0.0), Vector3::new(-1.0, 0.0, 0.0))).is_err());
+
+		// Sphere tracing finds the surface within epsilon, also from inside, and normals come from central differences
+		let sphere = PrimitiveKind::Sdf(Box::new(Sdf::new(SdfNode::Sphere { radius: 1.0 })));
+		let (distance, surface) = get_ray_primitive_hit(&ray(Vector3::new(0.0, 0.0, 5.0), Vector3::new(0.0, 0.0, -1.0)), &sphere, 0.01).unwrap();
+		assert!(close(distance, 4.0, DEFAULT_SDF_EPSILON));
+		assert!((surface.normal - Vector3::new(0.0, 0.0, 1.0)).length() < 1e-6);
+		assert!(close(get_ray_primitive_distance(&ray(Vector3::zero(), Vector3::new(1.0, 1.0, 0.0)), &sphere, 0.01).unwrap(), 1.0, DEFAULT_SDF_EPSILON));
+		assert!(get_ray_primitive_distance(&ray(Vector3::new(0.0, 1.5, 5.0), Vector3::new(0.0, 0.0, -1.0)), &sphere, 0.01).is_none());
+
+		// Twisted solids are hit on their surface, and the tracing gives up after the step limit
+		let mut column = Sdf::new(twisted);
+		let mut rng = Pcg32::new(5, 0);
+
+		for _ in 0..200 {
+			let origin = v3_scale(&v3_random_normal(&mut rng), 10.0);
+			let target = Vector3::new(0.6 * rng.gen::<f64>() - 0.3, 8.0 * rng.gen::<f64>() - 4.0, 0.6 * rng.gen::<f64>() - 0.3);
+			let r = ray(origin, target - origin);
+			let t = get_ray_sdf_distance(&r, &column, 0.01).unwrap();
+			assert!(column.root.distance(&(r.origin + r.direction * t)).abs() < column.epsilon * column.root.lipschitz_bound());
+			assert!(get_sdf_normal(&column, &(r.origin + r.direction * t)).dot(r.direction) < 0.0);
+		}
+
+		column.max_steps = 2;
+		assert!(get_ray_sdf_distance(&ray(Vector3::new(0.3, 4.0, 10.0), Vector3::new(0.0, 0.0, -1.0)), &column, 0.01).is_none());
+
+		// Placed in the space, SDFs are found like the other primitives and reach the workers
+		let (space, camera) = create_scene_with(create_primitives());
+		let head = ray(Vector3::new(0.5, -5.2, 0.0), Vector3::new(0.0, 0.0, -1.0));
+		let hit = get_nearest_surface_intersection_for_ray(&head, &space, RayKind::Camera).unwrap();
+		assert!(close(hit.distance, 16.1, 1e-3) && hit.geometric_normal.z > 0.99);
+		assert_eq!(hit.primitive_id, 17);
+
+		assert_reaches_workers(&space, &camera);
+	}
+
+	#[test]
+	fn test_heightfield() {
+		use fundamentals::math::vector::*;
+		use raytracing::primitives::*;
+		use raytracing::heightfield::*;
+		use photon::input::ppm::*;
+
+		let ray = |o: Vector3, d: Vector3| Ray { origin: o, direction: d.normalize() };
+		let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+		let down = Vector3::new(0.0, -1.0, 0.0);
+
+		// A flat field is hit where the ray comes down, with the texture coordinates along x and z
+		let flat = PrimitiveKind::Heightfield(Heightfield::new(2, 2, vec![0.5; 4]));
+		let (distance, surface) = get_ray_primitive_hit(&ray(Vector3::new(0.3, 5.0, 0.7), down), &flat, 0.01).unwrap();
+		assert!(close(distance, 4.5) && close(surface.u, 0.3) && close(surface.v, 0.7));
+		assert_vec3_eq(&surface.normal, &Vector3::new(0.0, 1.0, 0.0));
+		assert!(get_ray_primitive_distance(&ray(Vector3::new(1.5, 5.0, 0.5), down), &flat, 0.01).is_none());
+		assert!(get_ray_primitive_distance(&ray(Vector3::new(0.5, 0.5, 0.5), Vector3::new(1.0, 1.0, 0.0)), &flat, 0.01).is_none());
+
+		// A slope has the normal of a plane, also between the samples
+		let slope = Heightfield::new(5, 3, (0..15).map(|k| (k % 5) as f64 * 0.25).collect());
+		let (distance, surface) = get_ray_primitive_hit(&ray(Vector3::new(0.3, 5.0, 0.4), down), &PrimitiveKind::Heightfield(slope.clone()), 0.01).unwrap();
+		assert!(close(distance, 4.7));
+		assert!((surface.normal - Vector3::new(-1.0, 1.0, 0.0).normalize()).length() < 1e-9);
+		assert!(surface.normal.dot(surface.dp_du).abs() < 1e-9 && surface.normal.dot(surface.dp_dv).abs() < 1e-9);
+
+		// Across a bend the normal is the one of the patch, only the shading normal is interpolated between the samples
+		let bend = Heightfield::new(3, 2, vec![0.0, 0.5, 0.0, 0.0, 0.5, 0.0]);
+		let (_, surface) = get_ray_primitive_hit(&ray(Vector3::new(0.25, 5.0, 0.5), down), &PrimitiveKind::Heightfield(bend), 0.01).unwrap();
+		assert!((surface.normal - Vector3::new(-1.0, 1.0, 0.0).normalize()).length() < 1e-9);
+		assert!((surface.shading_normal - (Vector3::new(-1.0, 1.0, 0.0).normalize() + Vector3::new(0.0, 1.0, 0.0)).normalize()).length() < 1e-9);
+
+		// Rays walking over rolling hills stop at the first point on the bilinear patches, not before
+		let size = 33;
+		let hills = Heightfield::new(size, size, (0..size * size).map(|k| 0.5 + 0.4 * ((k % size) as f64 * 0.7).sin() * ((k / size) as f64 * 0.5).cos()).collect());
+		let height = |p: &Vector3| {
+			let (x, z) = (p.x * (size - 1) as f64, p.z * (size - 1) as f64);
+			let (i, j) = ((x.floor() as usize).min(size - 2), (z.floor() as usize).min(size - 2));
+			let (u, v) = (x - i as f64, z - j as f64);
+			let h = |di: usize, dj: usize| hills.height(i + di, j + dj);
+			(h(0, 0) * (1.0 - u) + h(1, 0) * u) * (1.0 - v) + (h(0, 1) * (1.0 - u) + h(1, 1) * u) * v
+		};
+
+		let mut rng = Pcg32::new(3, 0);
+		let mut hits = 0;
+
+		for _ in 0..300 {
+			let origin = Vector3::new(rng.gen::<f64>(), 2.0, rng.gen::<f64>());
+			let target = Vector3::new(rng.gen::<f64>(), 0.0, rng.gen::<f64>());
+			let r = ray(origin, target - origin);
+
+			if let Some(t) = get_ray_heightfield_distance(&r, &hills, 0.01) {
+				let p = r.origin + r.direction * t;
+				assert!((p.y - height(&p)).abs() < 1e-9);
+				assert!((1..100).all(|k| { let q = r.origin + r.direction * (t * k as f64 / 100.0); q.y > height(&q) }));
+				hits += 1;
+			}
+		}
+
+		assert!(hits > 250);
+
+		// Heights come from the gray values of images
+		let image = read_ppm(&mut "P3 3 2 255 0 0 0 51 51 51 255 255 255 102 102 102 204 204 204 153 153 153".as_bytes()).unwrap();
+		let field = get_image_heightfield(&image);
+		assert_eq!((field.width, field.depth), (3, 2));
+		assert!(field.heights.iter().zip([0.0, 0.2, 1.0, 0.4, 0.8, 0.6]).all(|(a, b)| (a - b).abs() < 1e-9));
+
+		// Placed in the space, the terrain is seen from both sides and reaches the workers
+		let (space, camera) = create_scene_with(create_terrain("hills"));
+		let hit = get_nearest_surface_intersection_for_ray(&ray(Vector3::new(0.0, 5.0, -30.0), down), &space, RayKind::Camera).unwrap();
+		assert!(hit.distance < 15.0 && hit.distance > 13.5 && hit.geometric_normal.y > 0.0);
+		let hit = get_nearest_surface_intersection_for_ray(&ray(Vector3::new(0.0, -9.99, -30.0), Vector3::new(0.0, 1.0, 0.0)), &space, RayKind::Camera).unwrap();
+		assert!(hit.distance < 1.5 && hit.geometric_normal.y < 0.0);
+
+		assert_reaches_workers(&space, &camera);
+	}
+}
@@ -0,0 +1,198 @@
+
+// Minimal zlib (RFC 1950) / deflate (RFC 1951) compressor, enough for PNG and EXR output without external crates.
+// It finds repetitions with a hash chained LZ77 search and writes a single block with the fixed Huffman codes.
+
+// Size of the window in which previous repetitions are searched
+const WINDOW_SIZE: usize = 32768;
+
+// Matches are between 3 and 258 bytes long
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+
+// Number of previous positions with the same hash that are compared, more means better but slower compression
+const MAX_CHAIN: usize = 32;
+
+const HASH_BITS: u32 = 15;
+
+const LENGTH_BASES: [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+const LENGTH_EXTRA_BITS: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DISTANCE_BASES: [u16; 30] = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577];
+const DISTANCE_EXTRA_BITS: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+
+// Writes bits least significant first, as deflate wants it
+struct BitWriter {
+	bytes: Vec<u8>,
+	bit_buffer: u32,
+	bit_count: u32
+}
+
+impl BitWriter {
+
+	fn write_bits(&mut self, value: u32, count: u32) {
+		self.bit_buffer |= value << self.bit_count;
+		self.bit_count += count;
+
+		while self.bit_count >= 8 {
+			self.bytes.push((self.bit_buffer & 0xff) as u8);
+			self.bit_buffer >>= 8;
+			self.bit_count -= 8;
+		}
+	}
+
+	// Huffman codes are defined most significant bit first, so they are written reversed
+	fn write_code(&mut self, code: u32, length: u32) {
+		self.write_bits(code.reverse_bits() >> (32 - length), length);
+	}
+
+	fn flush(&mut self) {
+		if self.bit_count > 0 {
+			self.bytes.push((self.bit_buffer & 0xff) as u8);
+			self.bit_buffer = 0;
+			self.bit_count = 0;
+		}
+	}
+
+	// Writes a literal byte or the end-of-block symbol (256) or a length symbol (257..285) with the fixed code
+	fn write_literal_length_symbol(&mut self, symbol: u32) {
+		match symbol {
+			0..=143 => self.write_code(0x30 + symbol, 8),
+			144..=255 => self.write_code(0x190 + symbol - 144, 9),
+			256..=279 => self.write_code(symbol - 256, 7),
+			_ => self.write_code(0xc0 + symbol - 280, 8)
+		}
+	}
+
+	fn write_match(&mut self, length: usize, distance: usize) {
+		let length_index = LENGTH_BASES.iter().rposition(|&b| (b as usize) <= length).unwrap();
+		self.write_literal_length_symbol(257 + length_index as u32);
+		self.write_bits((length - LENGTH_BASES[length_index] as usize) as u32, LENGTH_EXTRA_BITS[length_index] as u32);
+
+		let distance_index = DISTANCE_BASES.iter().rposition(|&b| (b as usize) <= distance).unwrap();
+		self.write_code(distance_index as u32, 5);
+		self.write_bits((distance - DISTANCE_BASES[distance_index] as usize) as u32, DISTANCE_EXTRA_BITS[distance_index] as u32);
+	}
+}
+
+fn hash3(data: &[u8], i: usize) -> usize {
+	let v = (data[i] as u32) | ((data[i + 1] as u32) << 8) | ((data[i + 2] as u32) << 16);
+	return (v.wrapping_mul(2654435761) >> (32 - HASH_BITS)) as usize;
+}
+
+// Compresses the data into a raw deflate stream
+pub fn deflate(data: &[u8]) -> Vec<u8> {
+	let mut writer = BitWriter { bytes: Vec::with_capacity(data.len() / 2 + 64), bit_buffer: 0, bit_count: 0 };
+
+	// Single final block with fixed Huffman codes
+	writer.write_bits(1, 1);
+	writer.write_bits(1, 2);
+
+	// Most recent position per hash and the previous position with the same hash per position in the window
+	let mut head: Vec<usize> = vec![usize::MAX; 1 << HASH_BITS];
+	let mut previous: Vec<usize> = vec![usize::MAX; WINDOW_SIZE];
+
+	let mut i = 0;
+
+	while i < data.len() {
+		let mut best_length = 0;
+		let mut best_distance = 0;
+
+		if i + MIN_MATCH <= data.len() {
+			let h = hash3(data, i);
+			let mut candidate = head[h];
+			let mut chain = 0;
+			let max_length = MAX_MATCH.min(data.len() - i);
+
+			while candidate != usize::MAX && i - candidate <= WINDOW_SIZE && chain < MAX_CHAIN {
+				let mut length = 0;
+
+				while length < max_length && data[candidate + length] == data[i + length] {
+					length += 1;
+				}
+
+				if length > best_length {
+					best_length = length;
+					best_distance = i - candidate;
+
+					if length == max_length {
+						break;
+					}
+				}
+
+				let next = previous[candidate % WINDOW_SIZE];
+
+				// Positions must strictly decrease, otherwise the entry was overwritten by a newer position
+				if next == usize::MAX || next >= candidate {
+					break;
+				}
+
+				candidate = next;
+				chain += 1;
+			}
+		}
+
+		let step = if best_length >= MIN_MATCH {
+			writer.write_match(best_length, best_distance);
+			best_length
+		} else {
+			writer.write_literal_length_symbol(data[i] as u32);
+			1
+		};
+
+		// Remember all positions that have been passed
+		for p in i..(i + step) {
+			if p + MIN_MATCH <= data.len() {
+				let h = hash3(data, p);
+				previous[p % WINDOW_SIZE] = head[h];
+				head[h] = p;
+			}
+		}
+
+		i += step;
+	}
+
+	writer.write_literal_length_symbol(256);
+	writer.flush();
+
+	return writer.bytes;
+}
+
+// Adler-32 checksum of the zlib format
+pub fn adler32(data: &[u8]) -> u32 {
+	let mut a: u32 = 1;
+	let mut b: u32 = 0;
+
+	for chunk in data.chunks(5552) {
+		for &byte in chunk {
+			a += byte as u32;
+			b += a;
+		}
+		a %= 65521;
+		b %= 65521;
+	}
+
+	return (b << 16) | a;
+}
+
+// Compresses the data into a zlib stream (header, deflate data and checksum)
+pub fn zlib_compress(data: &[u8]) -> Vec<u8> {
+	let mut out: Vec<u8> = vec![0x78, 0x01];
+	out.extend(deflate(data));
+	out.extend(adler32(data).to_be_bytes());
+	return out;
+}
+
+// CRC-32 as used by PNG chunks
+pub fn crc32(data: &[u8]) -> u32 {
+	let mut crc: u32 = 0xffffffff;
+
+	for &byte in data {
+		crc ^= byte as u32;
+
+		for _ in 0..8 {
+			let mask = (!(crc & 1)).wrapping_add(1);
+			crc = (crc >> 1) ^ (0xedb88320 & mask);
+		}
+	}
+
+	return !crc;
+}
@@ -0,0 +1,182 @@
+
+// Import requirements
+use crate::fundamentals::light::*;
+use crate::fundamentals::random::*;
+
+// Operators compressing the unbounded rendered light into the displayable range [0, 1]
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ToneMapping {
+	// Values above 1 are clipped
+	None,
+	// Reinhard on the luminance, which keeps the hue
+	Reinhard,
+	// Fit of the ACES filmic reference and output transforms (Stephen Hill)
+	Aces,
+	// AgX by Troy Sobotka, desaturating very bright light towards white instead of skewing its hue
+	Agx
+}
+
+impl ToneMapping {
+
+	// Returns the tone mapping operator for the name used on the command line
+	pub fn from_name(name: &str) -> Option<ToneMapping> {
+		return match name {
+			"none" => Some(ToneMapping::None),
+			"reinhard" => Some(ToneMapping::Reinhard),
+			"aces" => Some(ToneMapping::Aces),
+			"agx" => Some(ToneMapping::Agx),
+			_ => None
+		};
+	}
+}
+
+// Conversion of the rendered (scene linear) light to display values, applied before writing integer images
+#[derive(Copy, Clone)]
+pub struct DisplayTransform {
+	// Exposure adjustment in stops, the light is multiplied by 2^exposure
+	pub exposure: f64,
+	pub tone_mapping: ToneMapping,
+	// Encode the result with the sRGB transfer function (otherwise the values are written linear)
+	pub srgb: bool,
+	// Add triangular noise of one quantization step before rounding, which avoids banding in gradients
+	pub dither: bool
+}
+
+impl DisplayTransform {
+
+	// Returns the standard transform for sRGB displays
+	pub fn new() -> DisplayTransform {
+		return DisplayTransform { exposure: 0.0, tone_mapping: ToneMapping::None, srgb: true, dither: true };
+	}
+
+	// Returns the transform that writes values as they are (only clipped), used for data like sample counts
+	pub fn identity() -> DisplayTransform {
+		return DisplayTransform { exposure: 0.0, tone_mapping: ToneMapping::None, srgb: false, dither: false };
+	}
+
+	// Converts the light to display encoded values in [0, 1]
+	pub fn to_display(&self, light: &LightColor) -> LightColor {
+		let scale = self.exposure.exp2();
+		let c = [light.r * scale, light.g * scale, light.b * scale];
+
+		let mapped = match self.tone_mapping {
+			ToneMapping::None => c,
+			ToneMapping::Reinhard => tone_map_reinhard(c),
+			ToneMapping::Aces => tone_map_aces(c),
+			ToneMapping::Agx => tone_map_agx(c)
+		};
+
+		let encode = |v: f64| {
+			let v = v.clamp(0.0, 1.0);
+			return if self.srgb { srgb_oetf(v) } else { v };
+		};
+
+		return LightColor { r: encode(mapped[0]), g: encode(mapped[1]), b: encode(mapped[2]) };
+	}
+
+	// Converts a display value in [0, 1] to an integer in 0..=max_value. The dither noise only depends on the pixel
+	// and channel, so the same image is always written the same way.
+	pub fn quantize(&self, v: f64, max_value: u16, pixel_index: usize, channel: u64) -> u16 {
+		let mut noise = 0.0;
+
+		if self.dither {
+			let h = mix_bits(((pixel_index as u64) << 2) | channel);
+			let u1 = ((h >> 40) as f64) / ((1u64 << 24) as f64);
+			let u2 = (((h >> 16) & 0xffffff) as f64) / ((1u64 << 24) as f64);
+			noise = u1 + u2 - 1.0;
+		}
+
+		let scaled = v * (max_value as f64) + 0.5 + noise;
+		return scaled.floor().clamp(0.0, max_value as f64) as u16;
+	}
+}
+
+impl Default for DisplayTransform {
+	fn default() -> Self {
+		return DisplayTransform::new();
+	}
+}
+
+// sRGB transfer function from linear light to the display encoding
+pub fn srgb_oetf(v: f64) -> f64 {
+	if v <= 0.0031308 {
+		return 12.92 * v;
+	}
+
+	return 1.055 * v.powf(1.0 / 2.4) - 0.055;
+}
+
+fn multiply(m: &[[f64; 3]; 3], c: [f64; 3]) -> [f64; 3] {
+	return [
+		m[0][0] * c[0] + m[0][1] * c[1] + m[0][2] * c[2],
+		m[1][0] * c[0] + m[1][1] * c[1] + m[1][2] * c[2],
+		m[2][0] * c[0] + m[2][1] * c[1] + m[2][2] * c[2]
+	];
+}
+
+fn tone_map_reinhard(c: [f64; 3]) -> [f64; 3] {
+	let l = luminance(&LightColor { r: c[0], g: c[1], b: c[2] });
+
+	if l <= 0.0 {
+		return [0.0, 0.0, 0.0];
+	}
+
+	let scale = (l / (1.0 + l)) / l;
+	return [c[0] * scale, c[1] * scale, c[2] * scale];
+}
+
+// sRGB to the ACES fitting space, including the exposure adjustment of the reference transform
+const ACES_INPUT: [[f64; 3]; 3] = [
+	[0.59719, 0.35458, 0.04823],
+	[0.07600, 0.90834, 0.01566],
+	[0.02840, 0.13383, 0.83777]
+];
+
+const ACES_OUTPUT: [[f64; 3]; 3] = [
+	[1.60475, -0.53108, -0.07367],
+	[-0.10208, 1.10813, -0.00605],
+	[-0.00327, -0.07276, 1.07602]
+];
+
+fn tone_map_aces(c: [f64; 3]) -> [f64; 3] {
+	let v = multiply(&ACES_INPUT, c);
+
+	// Curve of the reference rendering and output device transforms
+	let fit = |x: f64| (x * (x + 0.0245786) - 0.000090537) / (x * (0.983729 * x + 0.4329510) + 0.238081);
+
+	return multiply(&ACES_OUTPUT, [fit(v[0]), fit(v[1]), fit(v[2])]);
+}
+
+// Rotation into the AgX working space (sRGB primaries) and back
+const AGX_INSET: [[f64; 3]; 3] = [
+	[0.842479062253094, 0.0784335999999992, 0.0792237451477643],
+	[0.0423282422610123, 0.878468636469772, 0.0791661274605434],
+	[0.0423756549057051, 0.0784336, 0.879142973793104]
+];
+
+const AGX_OUTSET: [[f64; 3]; 3] = [
+	[1.19687900512017, -0.0980208811401368, -0.0990297440797205],
+	[-0.0528968517574562, 1.15190312990417, -0.0989611768448433],
+	[-0.0529716355144438, -0.0980434501171241, 1.15107367264116]
+];
+
+// Range of the log encoding in stops around middle gray
+const AGX_MIN_EV: f64 = -12.47393;
+const AGX_MAX_EV: f64 = 4.026069;
+
+fn tone_map_agx(c: [f64; 3]) -> [f64; 3] {
+	let v = multiply(&AGX_INSET, c);
+
+	// Log encoding followed by the polynomial approximation of the AgX sigmoid
+	let curve = |x: f64| {
+		let x = (x.max(1e-10).log2().clamp(AGX_MIN_EV, AGX_MAX_EV) - AGX_MIN_EV) / (AGX_MAX_EV - AGX_MIN_EV);
+		let x2 = x * x;
+		let x4 = x2 * x2;
+		return 15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x - 0.00232;
+	};
+
+	let encoded = multiply(&AGX_OUTSET, [curve(v[0]), curve(v[1]), curve(v[2])]);
+
+	// The sigmoid already produces display encoded values, they are linearized again for the sRGB encoding
+	return [encoded[0].max(0.0).powf(2.2), encoded[1].max(0.0).powf(2.2), encoded[2].max(0.0).powf(2.2)];
+}
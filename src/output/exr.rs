@@ -0,0 +1,271 @@
+
+// Import requirements
+use super::*;
+use super::deflate::*;
+use crate::raytracing::aov::*;
+
+// Magic number and version (2, single part scanline image) of OpenEXR files
+const EXR_MAGIC: [u8; 4] = [0x76, 0x2f, 0x31, 0x01];
+const EXR_VERSION: [u8; 4] = [2, 0, 0, 0];
+
+// How the channel values are stored
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ExrSampleType {
+	// 16 bit floats, enough for most compositing and half the size
+	Half,
+	// 32 bit floats
+	Float
+}
+
+// Supported subset of the EXR compression methods
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ExrCompression {
+	None,
+	// Deflate compression of single scanlines
+	Zips,
+	// Deflate compression of blocks of 16 scanlines
+	Zip
+}
+
+impl ExrCompression {
+
+	// Returns the compression for the name used on the command line
+	pub fn from_name(name: &str) -> Option<ExrCompression> {
+		return match name {
+			"none" => Some(ExrCompression::None),
+			"zips" => Some(ExrCompression::Zips),
+			"zip" => Some(ExrCompression::Zip),
+			_ => None
+		};
+	}
+
+	// Identifier of the compression in the EXR header
+	fn id(&self) -> u8 {
+		return match self {
+			ExrCompression::None => 0,
+			ExrCompression::Zips => 2,
+			ExrCompression::Zip => 3
+		};
+	}
+
+	// Number of scanlines that are stored together in one chunk
+	fn lines_per_chunk(&self) -> i32 {
+		return match self {
+			ExrCompression::None => 1,
+			ExrCompression::Zips => 1,
+			ExrCompression::Zip => 16
+		};
+	}
+}
+
+// One named channel of an EXR image, width * height values in row order
+pub struct ExrChannel {
+	pub name: String,
+	pub values: Vec<f32>
+}
+
+// Writes the image as EXR with the channels R, G and B
+pub fn write_exr<W: Write>(out: &mut W, image: &Image, sample_type: ExrSampleType, compression: ExrCompression) -> std::io::Result<()> {
+	let channels = vec![
+		ExrChannel { name: String::from("R"), values: image.pixels.iter().map(|p| p.r as f32).collect() },
+		ExrChannel { name: String::from("G"), values: image.pixels.iter().map(|p| p.g as f32).collect() },
+		ExrChannel { name: String::from("B"), values: image.pixels.iter().map(|p| p.b as f32).collect() }
+	];
+
+	return write_exr_channels(out, image.width, image.height, channels, sample_type, compression);
+}
+
+// Returns the EXR channels of an AOV, named after the conventions of the AOV type
+pub fn aov_channels(aov: &AovBuffer) -> Vec<ExrChannel> {
+	let names = aov.aov_type.channel_names();
+	let mut channels: Vec<ExrChannel> = Vec::new();
+
+	for (i, name) in names.into_iter().enumerate() {
+		let values = aov.pixels.iter().map(|p| (match i { 0 => p.r, 1 => p.g, _ => p.b }) as f32).collect();
+		channels.push(ExrChannel { name, values });
+	}
+
+	return channels;
+}
+
+// Writes the image (as R, G and B) together with all AOVs as layers of one EXR file
+pub fn write_exr_layers<W: Write>(out: &mut W, image: &Image, aovs: &[AovBuffer], sample_type: ExrSampleType, compression: ExrCompression) -> std::io::Result<()> {
+	let mut channels = vec![
+		ExrChannel { name: String::from("R"), values: image.pixels.iter().map(|p| p.r as f32).collect() },
+		ExrChannel { name: String::from("G"), values: image.pixels.iter().map(|p| p.g as f32).collect() },
+		ExrChannel { name: String::from("B"), values: image.pixels.iter().map(|p| p.b as f32).collect() }
+	];
+
+	for aov in aovs {
+		channels.extend(aov_channels(aov));
+	}
+
+	return write_exr_channels(out, image.width, image.height, channels, sample_type, compression);
+}
+
+// Writes an EXR scanline image with arbitrary channels. Channel names may contain layer prefixes like "albedo.R".
+pub fn write_exr_channels<W: Write>(out: &mut W, width: i32, height: i32, mut channels: Vec<ExrChannel>, sample_type: ExrSampleType, compression: ExrCompression) -> std::io::Result<()> {
+
+	// EXR requires the channels to be sorted by name, the values within a scanline are stored in this order
+	channels.sort_by(|a, b| a.name.cmp(&b.name));
+
+	let pixel_type: i32 = if sample_type == ExrSampleType::Half { 1 } else { 2 };
+
+	let mut channel_list: Vec<u8> = Vec::new();
+	for channel in &channels {
+		channel_list.extend(channel.name.as_bytes());
+		channel_list.push(0);
+		channel_list.extend(pixel_type.to_le_bytes());
+		// pLinear and three reserved bytes, then x and y sampling
+		channel_list.extend([0, 0, 0, 0]);
+		channel_list.extend(1i32.to_le_bytes());
+		channel_list.extend(1i32.to_le_bytes());
+	}
+	channel_list.push(0);
+
+	let mut window: Vec<u8> = Vec::new();
+	for v in [0, 0, width - 1, height - 1] {
+		window.extend(v.to_le_bytes());
+	}
+
+	let mut header: Vec<u8> = Vec::new();
+	header.extend(EXR_MAGIC);
+	header.extend(EXR_VERSION);
+	write_attribute(&mut header, "channels", "chlist", &channel_list);
+	write_attribute(&mut header, "compression", "compression", &[compression.id()]);
+	write_attribute(&mut header, "dataWindow", "box2i", &window);
+	write_attribute(&mut header, "displayWindow", "box2i", &window);
+	write_attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+	write_attribute(&mut header, "pixelAspectRatio", "float", &1.0f32.to_le_bytes());
+	write_attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+	write_attribute(&mut header, "screenWindowWidth", "float", &1.0f32.to_le_bytes());
+	header.push(0);
+
+	// Build all chunks first, the offset table in front of them needs their sizes
+	let lines_per_chunk = compression.lines_per_chunk();
+	let mut chunks: Vec<Vec<u8>> = Vec::new();
+	let mut y = 0;
+
+	while y < height {
+		let y_end = (y + lines_per_chunk).min(height);
+		let mut raw: Vec<u8> = Vec::new();
+
+		for line in y..y_end {
+			for channel in &channels {
+				let row = &channel.values[(line * width) as usize..((line + 1) * width) as usize];
+
+				for &v in row {
+					if sample_type == ExrSampleType::Half {
+						raw.extend(f32_to_half(v).to_le_bytes());
+					} else {
+						raw.extend(v.to_le_bytes());
+					}
+				}
+			}
+		}
+
+		// Compressed data is only used if it is actually smaller, readers detect this by the size
+		let data = if compression == ExrCompression::None { raw } else {
+			let compressed = zlib_compress(&exr_zip_predictor(&raw));
+			if compressed.len() < raw.len() { compressed } else { raw }
+		};
+
+		let mut chunk: Vec<u8> = Vec::with_capacity(data.len() + 8);
+		chunk.extend(y.to_le_bytes());
+		chunk.extend((data.len() as i32).to_le_bytes());
+		chunk.extend(data);
+		chunks.push(chunk);
+
+		y = y_end;
+	}
+
+	let mut offset = (header.len() + 8 * chunks.len()) as u64;
+
+	out.write_all(&header)?;
+
+	for chunk in &chunks {
+		out.write_all(&offset.to_le_bytes())?;
+		offset += chunk.len() as u64;
+	}
+
+	for chunk in &chunks {
+		out.write_all(chunk)?;
+	}
+
+	return Ok(());
+}
+
+fn write_attribute(header: &mut Vec<u8>, name: &str, attribute_type: &str, value: &[u8]) {
+	header.extend(name.as_bytes());
+	header.push(0);
+	header.extend(attribute_type.as_bytes());
+	header.push(0);
+	header.extend((value.len() as i32).to_le_bytes());
+	header.extend(value);
+}
+
+// Preprocessing of the ZIP compression: the bytes are split in even and odd ones and then delta encoded, which lets
+// deflate find much more repetitions in float data
+fn exr_zip_predictor(raw: &[u8]) -> Vec<u8> {
+	let mut reordered: Vec<u8> = Vec::with_capacity(raw.len());
+	reordered.extend(raw.iter().step_by(2));
+	reordered.extend(raw.iter().skip(1).step_by(2));
+
+	let mut previous = reordered.first().copied().unwrap_or(0);
+
+	for v in reordered.iter_mut().skip(1) {
+		let current = *v;
+		*v = current.wrapping_sub(previous).wrapping_add(128);
+		previous = current;
+	}
+
+	return reordered;
+}
+
+// Converts a 32 bit float to the 16 bit half format, rounding to the nearest even value. Values that are too large
+// become infinite, too small ones become (signed) zero.
+pub fn f32_to_half(v: f32) -> u16 {
+	let bits = v.to_bits();
+	let sign = ((bits >> 16) & 0x8000) as u16;
+	let exponent = ((bits >> 23) & 0xff) as i32;
+	let mantissa = bits & 0x7fffff;
+
+	// Infinity and NaN
+	if exponent == 255 {
+		return sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 };
+	}
+
+	let half_exponent = exponent - 127 + 15;
+
+	if half_exponent >= 31 {
+		return sign | 0x7c00;
+	}
+
+	// Subnormal half values, the implicit leading one becomes explicit
+	if half_exponent <= 0 {
+		if half_exponent < -10 {
+			return sign;
+		}
+
+		let full_mantissa = mantissa | 0x800000;
+		let shift = (14 - half_exponent) as u32;
+		return sign | round_shifted(full_mantissa, shift) as u16;
+	}
+
+	// Rounding may carry into the exponent, which is still correct (up to infinity)
+	let h = ((half_exponent as u32) << 10) + round_shifted(mantissa, 13);
+	return sign | h as u16;
+}
+
+// Shifts value right by shift bits, rounding to the nearest even result
+fn round_shifted(value: u32, shift: u32) -> u32 {
+	let shifted = value >> shift;
+	let remainder = value & ((1 << shift) - 1);
+	let halfway = 1 << (shift - 1);
+
+	if remainder > halfway || (remainder == halfway && (shifted & 1) == 1) {
+		return shifted + 1;
+	}
+
+	return shifted;
+}
@@ -0,0 +1,172 @@
+pub mod deflate;
+pub mod display;
+pub mod exr;
+pub mod pfm;
+pub mod png;
+pub mod ppm;
+
+// Import requirements
+use crate::fundamentals::light::*;
+use crate::fundamentals::random::*;
+use crate::raytracing::aov::*;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use exr::*;
+use display::*;
+
+// The image file formats that can be written
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ImageFormat {
+	Ppm,
+	Png,
+	// Floating point formats, keeping the full dynamic range of the rendered light
+	Pfm,
+	Exr
+}
+
+impl ImageFormat {
+
+	// Returns the image format for the name used on the command line
+	pub fn from_name(name: &str) -> Option<ImageFormat> {
+		return match name {
+			"ppm" => Some(ImageFormat::Ppm),
+			"png" => Some(ImageFormat::Png),
+			"pfm" => Some(ImageFormat::Pfm),
+			"exr" => Some(ImageFormat::Exr),
+			_ => None
+		};
+	}
+
+	// Returns the image format belonging to the extension of the file path
+	pub fn from_path(path: &str) -> Option<ImageFormat> {
+		let extension = path.rsplit('.').next()?.to_ascii_lowercase();
+		return ImageFormat::from_name(&extension);
+	}
+}
+
+// Parameters of the image writers
+#[derive(Copy, Clone)]
+pub struct OutputOptions {
+	// 8 or 16 bits per channel for PPM and PNG. EXR uses 32 bit floats with a bit depth of 32 and half floats otherwise.
+	pub bit_depth: u8,
+	pub exr_compression: ExrCompression,
+	// Applied to PPM and PNG, the floating point formats keep the rendered light as it is
+	pub display: DisplayTransform
+}
+
+impl OutputOptions {
+
+	// Returns the default options: 8 bit sRGB integer images and ZIP compressed EXR with half floats
+	pub fn new() -> OutputOptions {
+		return OutputOptions { bit_depth: 8, exr_compression: ExrCompression::Zip, display: DisplayTransform::new() };
+	}
+
+	// Returns how EXR channels are stored
+	pub fn exr_sample_type(&self) -> ExrSampleType {
+		return if self.bit_depth == 32 { ExrSampleType::Float } else { ExrSampleType::Half };
+	}
+}
+
+impl Default for OutputOptions {
+	fn default() -> Self {
+		return OutputOptions::new();
+	}
+}
+
+// Rendered light values together with the image dimensions
+pub struct Image<'a> {
+	pub pixels: &'a [LightColor],
+	pub width: i32,
+	pub height: i32
+}
+
+// Returns the display encoded integer channel values of the pixel with the specified index
+pub fn encode_pixel(options: &OutputOptions, image: &Image, pixel_index: usize, max_value: u16) -> [u16; 3] {
+	let display = options.display.to_display(&image.pixels[pixel_index]);
+
+	return [
+		options.display.quantize(display.r, max_value, pixel_index, 0),
+		options.display.quantize(display.g, max_value, pixel_index, 1),
+		options.display.quantize(display.b, max_value, pixel_index, 2)
+	];
+}
+
+// Writes the image in the specified format
+pub fn write_image<W: Write>(out: &mut W, image: &Image, format: ImageFormat, options: &OutputOptions) -> std::io::Result<()> {
+	return match format {
+		ImageFormat::Ppm => ppm::write_ppm(out, image, options),
+		ImageFormat::Png => png::write_png(out, image, options),
+		ImageFormat::Pfm => pfm::write_pfm(out, image),
+		ImageFormat::Exr => write_exr(out, image, options.exr_sample_type(), options.exr_compression)
+	};
+}
+
+// Writes the image to a file, the format being chosen by the file extension
+pub fn write_image_file(path: &str, image: &Image, options: &OutputOptions) -> std::io::Result<()> {
+	let format = match ImageFormat::from_path(path) {
+		Some(f) => f,
+		None => return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("Unknown image format of {}", path)))
+	};
+
+	let mut out = BufWriter::new(File::create(path)?);
+	write_image(&mut out, image, format, options)?;
+	return out.flush();
+}
+
+// Writes an AOV to a file, the format being chosen by the file extension. Floating point formats get the values as
+// they are, integer formats get a visualization: colors go through the display transform, depth and position are
+// normalized to their range, normals mapped from [-1, 1] to [0, 1] and primitive IDs shown as random colors.
+pub fn write_aov_file(path: &str, aov: &AovBuffer, width: i32, height: i32, options: &OutputOptions) -> std::io::Result<()> {
+	let is_color = matches!(aov.aov_type, AovType::Albedo | AovType::Direct | AovType::Indirect);
+
+	match ImageFormat::from_path(path) {
+		Some(ImageFormat::Exr) => {
+			let mut out = BufWriter::new(File::create(path)?);
+			write_exr_channels(&mut out, width, height, aov_channels(aov), options.exr_sample_type(), options.exr_compression)?;
+			return out.flush();
+		}
+		Some(ImageFormat::Pfm) => {
+			return write_image_file(path, &Image { pixels: &aov.pixels, width, height }, options);
+		}
+		_ if is_color => {
+			return write_image_file(path, &Image { pixels: &aov.pixels, width, height }, options);
+		}
+		_ => {
+			let data_options = OutputOptions { display: DisplayTransform::identity(), ..*options };
+			let pixels = aov_visualization(aov);
+			return write_image_file(path, &Image { pixels: &pixels, width, height }, &data_options);
+		}
+	}
+}
+
+// Maps the values of a data AOV (depth, normal, position, ID) into [0, 1] for viewing
+fn aov_visualization(aov: &AovBuffer) -> Vec<LightColor> {
+	let finite = aov.pixels.iter().filter(|p| p.r.is_finite() && p.g.is_finite() && p.b.is_finite());
+
+	return match aov.aov_type {
+		AovType::Depth => {
+			let max_depth = finite.map(|p| p.r).fold(0.0, f64::max).max(1e-9);
+			aov.pixels.iter().map(|p| {
+				let v = (p.r / max_depth).min(1.0);
+				return LightColor { r: v, g: v, b: v };
+			}).collect()
+		}
+		AovType::Position => {
+			let lower = finite.clone().fold(f64::MAX, |m, p| m.min(p.r).min(p.g).min(p.b));
+			let upper = finite.fold(f64::MIN, |m, p| m.max(p.r).max(p.g).max(p.b));
+			let scale = 1.0 / (upper - lower).max(1e-9);
+			aov.pixels.iter().map(|p| LightColor { r: (p.r - lower) * scale, g: (p.g - lower) * scale, b: (p.b - lower) * scale }).collect()
+		}
+		AovType::PrimitiveId => {
+			aov.pixels.iter().map(|p| {
+				if p.r < 0.0 {
+					return LightColor { r: 0.0, g: 0.0, b: 0.0 };
+				}
+
+				let h = mix_bits(p.r as u64);
+				return LightColor { r: (h & 0xff) as f64 / 255.0, g: ((h >> 8) & 0xff) as f64 / 255.0, b: ((h >> 16) & 0xff) as f64 / 255.0 };
+			}).collect()
+		}
+		_ => aov.pixels.iter().map(|p| LightColor { r: p.r * 0.5 + 0.5, g: p.g * 0.5 + 0.5, b: p.b * 0.5 + 0.5 }).collect()
+	};
+}
@@ -0,0 +1,22 @@
+
+// Import requirements
+use super::*;
+
+// Writes the image as color PFM (portable float map). Values are stored as little endian 32 bit floats without any
+// clamping, rows going from the bottom to the top of the image.
+pub fn write_pfm<W: Write>(out: &mut W, image: &Image) -> std::io::Result<()> {
+
+	// A negative scale marks little endian data
+	write!(out, "PF\n{} {}\n-1.0\n", image.width, image.height)?;
+
+	for y in (0..image.height).rev() {
+		for x in 0..image.width {
+			let pixel = &image.pixels[(y * image.width + x) as usize];
+			out.write_all(&(pixel.r as f32).to_le_bytes())?;
+			out.write_all(&(pixel.g as f32).to_le_bytes())?;
+			out.write_all(&(pixel.b as f32).to_le_bytes())?;
+		}
+	}
+
+	return Ok(());
+}
@@ -0,0 +1,121 @@
+
+// Import requirements
+use super::*;
+use super::deflate::*;
+
+const PNG_SIGNATURE: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+
+// PNG color type for RGB without alpha
+const COLOR_TYPE_RGB: u8 = 2;
+
+// Writes the image as RGB PNG with 8 or 16 bits per channel
+pub fn write_png<W: Write>(out: &mut W, image: &Image, options: &OutputOptions) -> std::io::Result<()> {
+	let bit_depth: u8 = if options.bit_depth == 16 { 16 } else { 8 };
+	let max_value: u16 = if bit_depth == 16 { 65535 } else { 255 };
+	let bytes_per_pixel = 3 * (bit_depth as usize) / 8;
+	let row_size = (image.width as usize) * bytes_per_pixel;
+
+	let mut header: Vec<u8> = Vec::new();
+	header.extend((image.width as u32).to_be_bytes());
+	header.extend((image.height as u32).to_be_bytes());
+	header.extend([bit_depth, COLOR_TYPE_RGB, 0, 0, 0]);
+
+	// Every row starts with the filter type, the filter is chosen per row
+	let mut filtered: Vec<u8> = Vec::with_capacity((row_size + 1) * (image.height as usize));
+	let mut previous_row: Vec<u8> = vec![0; row_size];
+	let mut row: Vec<u8> = Vec::with_capacity(row_size);
+
+	for y in 0..image.height {
+		row.clear();
+
+		for x in 0..image.width {
+			for v in encode_pixel(options, image, (y * image.width + x) as usize, max_value) {
+				if bit_depth == 16 {
+					row.extend(v.to_be_bytes());
+				} else {
+					row.push(v as u8);
+				}
+			}
+		}
+
+		filter_row(&row, &previous_row, bytes_per_pixel, &mut filtered);
+		std::mem::swap(&mut row, &mut previous_row);
+	}
+
+	out.write_all(&PNG_SIGNATURE)?;
+	write_chunk(out, b"IHDR", &header)?;
+	write_chunk(out, b"IDAT", &zlib_compress(&filtered))?;
+	write_chunk(out, b"IEND", &[])?;
+
+	return Ok(());
+}
+
+// Writes a chunk with its length and checksum
+fn write_chunk<W: Write>(out: &mut W, chunk_type: &[u8; 4], data: &[u8]) -> std::io::Result<()> {
+	let mut crc_data: Vec<u8> = Vec::with_capacity(data.len() + 4);
+	crc_data.extend(chunk_type);
+	crc_data.extend(data);
+
+	out.write_all(&(data.len() as u32).to_be_bytes())?;
+	out.write_all(&crc_data)?;
+	out.write_all(&crc32(&crc_data).to_be_bytes())?;
+
+	return Ok(());
+}
+
+// Appends the row with the filter that gives the smallest sum of absolute differences, which is the usual heuristic
+// for the filter that compresses best
+fn filter_row(row: &[u8], previous_row: &[u8], bytes_per_pixel: usize, out: &mut Vec<u8>) {
+	let mut best_filter: u8 = 0;
+	let mut best_sum: u64 = u64::MAX;
+	let mut best_row: Vec<u8> = Vec::new();
+	let mut candidate: Vec<u8> = Vec::with_capacity(row.len());
+
+	for filter in 0..5u8 {
+		candidate.clear();
+
+		for i in 0..row.len() {
+			let a = if i >= bytes_per_pixel { row[i - bytes_per_pixel] } else { 0 };
+			let b = previous_row[i];
+			let c = if i >= bytes_per_pixel { previous_row[i - bytes_per_pixel] } else { 0 };
+
+			let predicted = match filter {
+				0 => 0,
+				1 => a,
+				2 => b,
+				3 => (((a as u16) + (b as u16)) / 2) as u8,
+				_ => paeth_predictor(a, b, c)
+			};
+
+			candidate.push(row[i].wrapping_sub(predicted));
+		}
+
+		let sum: u64 = candidate.iter().map(|&v| (v as i8).unsigned_abs() as u64).sum();
+
+		if sum < best_sum {
+			best_sum = sum;
+			best_filter = filter;
+			std::mem::swap(&mut best_row, &mut candidate);
+		}
+	}
+
+	out.push(best_filter);
+	out.extend(&best_row);
+}
+
+fn paeth_predictor(a: u8, b: u8, c: u8) -> u8 {
+	let p = (a as i16) + (b as i16) - (c as i16);
+	let pa = (p - a as i16).abs();
+	let pb = (p - b as i16).abs();
+	let pc = (p - c as i16).abs();
+
+	if pa <= pb && pa <= pc {
+		return a;
+	}
+
+	if pb <= pc {
+		return b;
+	}
+
+	return c;
+}
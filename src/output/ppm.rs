@@ -0,0 +1,19 @@
+
+// Import requirements
+use super::*;
+
+// Writes the image as text PPM (P3). With a bit depth of 16 the maximum channel value is 65535.
+pub fn write_ppm<W: Write>(out: &mut W, image: &Image, options: &OutputOptions) -> std::io::Result<()> {
+	let max_value: u16 = if options.bit_depth == 16 { 65535 } else { 255 };
+
+	// Write the PPM header line 
+	writeln!(out, "P3\n{} {}\n{}", image.width, image.height, max_value)?;
+
+	// Write the PPM pixels
+	for i in 0..image.pixels.len() {
+		let [ir, ig, ib] = encode_pixel(options, image, i, max_value);
+		writeln!(out, "{} {} {}", ir, ig, ib)?;
+	}
+
+	return Ok(());
+}
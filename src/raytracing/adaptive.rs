@@ -0,0 +1,89 @@
+
+// Import requirements
+use crate::fundamentals::light::*;
+
+// 95% quantile of the normal distribution, used for the confidence interval of the pixel estimate
+const CONFIDENCE_Z: f64 = 1.96;
+
+// Below this luminance the error threshold is taken as absolute instead of relative, otherwise black pixels would
+// never be considered converged
+const MIN_REFERENCE_LUMINANCE: f64 = 0.01;
+
+// Adaptive sampling keeps casting rays into a pixel until the estimate of its value is precise enough
+#[derive(Copy, Clone)]
+pub struct AdaptiveSampling {
+	// Every pixel gets at least this many samples. They are also cast in batches of this size between the checks.
+	pub min_samples: u32,
+	// No pixel gets more samples than this
+	pub max_samples: u32,
+	// Accepted half-width of the 95% confidence interval of the pixel luminance, relative to the luminance
+	pub error_threshold: f64
+}
+
+// Running mean and variance of the samples of one pixel (Welford's algorithm)
+pub struct PixelEstimate {
+	pub sample_count: u32,
+	color_sum: LightColor,
+	luminance_mean: f64,
+	luminance_m2: f64
+}
+
+impl PixelEstimate {
+
+	pub fn new() -> PixelEstimate {
+		return PixelEstimate {
+			sample_count: 0,
+			color_sum: LightColor { r: 0.0, g: 0.0, b: 0.0 },
+			luminance_mean: 0.0,
+			luminance_m2: 0.0
+		};
+	}
+
+	// Adds the light of one more sample
+	pub fn add_sample(&mut self, light: &LightColor) {
+		self.sample_count += 1;
+		self.color_sum.r += light.r;
+		self.color_sum.g += light.g;
+		self.color_sum.b += light.b;
+
+		let l = luminance(light);
+		let delta = l - self.luminance_mean;
+		self.luminance_mean += delta / (self.sample_count as f64);
+		self.luminance_m2 += delta * (l - self.luminance_mean);
+	}
+
+	// Returns the average light of all samples
+	pub fn mean(&self) -> LightColor {
+		if self.sample_count == 0 {
+			return LightColor { r: 0.0, g: 0.0, b: 0.0 };
+		}
+
+		let w = 1.0 / (self.sample_count as f64);
+		return LightColor { r: self.color_sum.r * w, g: self.color_sum.g * w, b: self.color_sum.b * w };
+	}
+
+	// Returns the sample variance of the luminance
+	pub fn variance(&self) -> f64 {
+		if self.sample_count < 2 {
+			return 0.0;
+		}
+
+		return self.luminance_m2 / ((self.sample_count - 1) as f64);
+	}
+
+	// Returns true if the confidence interval of the mean luminance is narrower than the threshold
+	pub fn is_converged(&self, error_threshold: f64) -> bool {
+		if self.sample_count < 2 {
+			return false;
+		}
+
+		let standard_error = (self.variance() / (self.sample_count as f64)).sqrt();
+		return CONFIDENCE_Z * standard_error <= error_threshold * self.luminance_mean.max(MIN_REFERENCE_LUMINANCE);
+	}
+}
+
+impl Default for PixelEstimate {
+	fn default() -> Self {
+		return PixelEstimate::new();
+	}
+}
@@ -0,0 +1,226 @@
+
+// Import requirements
+use crate::fundamentals::vec3::*;
+use crate::fundamentals::light::*;
+use super::tracing::*;
+
+// Arbitrary output variables: auxiliary per pixel buffers describing the first surface hit by the camera rays
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum AovType {
+	// Distance from the camera along the ray, infinite for the background
+	Depth,
+	// World space surface normal
+	Normal,
+	// Material color, without any lighting
+	Albedo,
+	// World space hit position
+	Position,
+	// Index of the primitive within the space, -1 for the background
+	PrimitiveId,
+	// Light reflected towards the camera that came directly from the light sources
+	Direct,
+	// Light reflected towards the camera that came from other surfaces
+	Indirect
+}
+
+pub const ALL_AOV_TYPES: [AovType; 7] = [AovType::Depth, AovType::Normal, AovType::Albedo, AovType::Position, AovType::PrimitiveId, AovType::Direct, AovType::Indirect];
+
+impl AovType {
+
+	// Returns the AOV type for the name used on the command line
+	pub fn from_name(name: &str) -> Option<AovType> {
+		return ALL_AOV_TYPES.iter().copied().find(|t| t.name() == name);
+	}
+
+	pub fn name(&self) -> &'static str {
+		return match self {
+			AovType::Depth => "depth",
+			AovType::Normal => "normal",
+			AovType::Albedo => "albedo",
+			AovType::Position => "position",
+			AovType::PrimitiveId => "id",
+			AovType::Direct => "direct",
+			AovType::Indirect => "indirect"
+		};
+	}
+
+	// Names of the EXR channels of the AOV, following the usual conventions. Depth and ID only have one channel.
+	pub fn channel_names(&self) -> Vec<String> {
+		return match self {
+			AovType::Depth => vec![String::from("Z")],
+			AovType::PrimitiveId => vec![String::from("id")],
+			AovType::Normal => vec![String::from("N.X"), String::from("N.Y"), String::from("N.Z")],
+			AovType::Position => vec![String::from("P.X"), String::from("P.Y"), String::from("P.Z")],
+			_ => ["R", "G", "B"].iter().map(|c| format!("{}.{}", self.name(), c)).collect()
+		};
+	}
+}
+
+// One AOV of the full image. Three channel AOVs use r, g and b for x, y and z, single channel ones only r.
+pub struct AovBuffer {
+	pub aov_type: AovType,
+	pub pixels: Vec<LightColor>
+}
+
+// Number of values an AovAccumulator is stored as
+pub const AOV_ACCUMULATOR_VALUES: usize = 20;
+
+// Collects the AOV values of all samples of one pixel. They are averaged (box filtered) over the samples of the pixel,
+// except for the primitive ID which can not be averaged and is taken from the sample nearest to the pixel center.
+// Depth and position are only averaged over the samples that hit something.
+#[derive(Copy, Clone)]
+pub struct AovAccumulator {
+	sample_count: u32,
+	hit_count: u32,
+	depth_sum: f64,
+	normal_sum: Vector3,
+	albedo_sum: LightColor,
+	position_sum: Vector3,
+	direct_sum: LightColor,
+	indirect_sum: LightColor,
+	primitive_id: i32,
+	primitive_id_offset: f64
+}
+
+impl AovAccumulator {
+
+	pub fn new() -> AovAccumulator {
+		let black = LightColor { r: 0.0, g: 0.0, b: 0.0 };
+		let zero = Vector3 { x: 0.0, y: 0.0, z: 0.0 };
+
+		return AovAccumulator {
+			sample_count: 0,
+			hit_count: 0,
+			depth_sum: 0.0,
+			normal_sum: zero,
+			albedo_sum: black,
+			position_sum: zero,
+			direct_sum: black,
+			indirect_sum: black,
+			primitive_id: -1,
+			primitive_id_offset: f64::MAX
+		};
+	}
+
+	// Adds the result of one camera ray that was cast at the offset (offset_x, offset_y) from the pixel center
+	pub fn add_sample(&mut self, hit: &Option<PrimaryHit>, offset_x: f64, offset_y: f64) {
+		self.sample_count += 1;
+
+		let offset = offset_x * offset_x + offset_y * offset_y;
+
+		match hit {
+			Some(h) => {
+				let d = &h.intersection;
+				self.hit_count += 1;
+				self.depth_sum += d.distance;
+				self.normal_sum = v3_sum(&self.normal_sum, &d.normal);
+				self.position_sum = v3_sum(&self.position_sum, &d.position);
+				add_light(&mut self.albedo_sum, &d.material_color);
+				add_light(&mut self.direct_sum, &h.direct);
+				add_light(&mut self.indirect_sum, &h.indirect);
+
+				if offset < self.primitive_id_offset {
+					self.primitive_id = d.primitive_id;
+					self.primitive_id_offset = offset;
+				}
+			}
+			None => {
+				if offset < self.primitive_id_offset {
+					self.primitive_id = -1;
+					self.primitive_id_offset = offset;
+				}
+			}
+		}
+	}
+
+	// Adds the samples collected by another accumulator of the same pixel
+	pub fn merge(&mut self, other: &AovAccumulator) {
+		self.sample_count += other.sample_count;
+		self.hit_count += other.hit_count;
+		self.depth_sum += other.depth_sum;
+		self.normal_sum = v3_sum(&self.normal_sum, &other.normal_sum);
+		self.position_sum = v3_sum(&self.position_sum, &other.position_sum);
+		add_light(&mut self.albedo_sum, &other.albedo_sum);
+		add_light(&mut self.direct_sum, &other.direct_sum);
+		add_light(&mut self.indirect_sum, &other.indirect_sum);
+
+		if other.primitive_id_offset < self.primitive_id_offset {
+			self.primitive_id = other.primitive_id;
+			self.primitive_id_offset = other.primitive_id_offset;
+		}
+	}
+
+	// Returns all sums as numbers, for storing them in a checkpoint
+	pub fn to_values(&self) -> [f64; AOV_ACCUMULATOR_VALUES] {
+		let n = &self.normal_sum;
+		let p = &self.position_sum;
+		let a = &self.albedo_sum;
+		let d = &self.direct_sum;
+		let i = &self.indirect_sum;
+
+		return [
+			self.sample_count as f64, self.hit_count as f64, self.depth_sum,
+			n.x, n.y, n.z, a.r, a.g, a.b, p.x, p.y, p.z, d.r, d.g, d.b, i.r, i.g, i.b,
+			self.primitive_id as f64, self.primitive_id_offset
+		];
+	}
+
+	// Restores an accumulator from the numbers returned by to_values
+	pub fn from_values(v: &[f64; AOV_ACCUMULATOR_VALUES]) -> AovAccumulator {
+		return AovAccumulator {
+			sample_count: v[0] as u32,
+			hit_count: v[1] as u32,
+			depth_sum: v[2],
+			normal_sum: Vector3 { x: v[3], y: v[4], z: v[5] },
+			albedo_sum: LightColor { r: v[6], g: v[7], b: v[8] },
+			position_sum: Vector3 { x: v[9], y: v[10], z: v[11] },
+			direct_sum: LightColor { r: v[12], g: v[13], b: v[14] },
+			indirect_sum: LightColor { r: v[15], g: v[16], b: v[17] },
+			primitive_id: v[18] as i32,
+			primitive_id_offset: v[19]
+		};
+	}
+
+	// Returns the value of the specified AOV for this pixel
+	pub fn resolve(&self, aov_type: AovType) -> LightColor {
+		let per_sample = 1.0 / (self.sample_count.max(1) as f64);
+		let per_hit = 1.0 / (self.hit_count.max(1) as f64);
+		let scale = |c: &LightColor, s: f64| LightColor { r: c.r * s, g: c.g * s, b: c.b * s };
+		let from_vector = |v: &Vector3| LightColor { r: v.x, g: v.y, b: v.z };
+
+		return match aov_type {
+			AovType::Depth => {
+				let depth = if self.hit_count == 0 { f64::INFINITY } else { self.depth_sum * per_hit };
+				LightColor { r: depth, g: depth, b: depth }
+			}
+			AovType::Normal => {
+				// The average of the normals is normalized again, the background has no normal
+				if self.hit_count == 0 || v3_len(&self.normal_sum) == 0.0 {
+					LightColor { r: 0.0, g: 0.0, b: 0.0 }
+				} else {
+					from_vector(&v3_normalize(&self.normal_sum))
+				}
+			}
+			AovType::Albedo => scale(&self.albedo_sum, per_sample),
+			AovType::Position => from_vector(&v3_scale(&self.position_sum, per_hit)),
+			AovType::PrimitiveId => {
+				let id = self.primitive_id as f64;
+				LightColor { r: id, g: id, b: id }
+			}
+			AovType::Direct => scale(&self.direct_sum, per_sample),
+			AovType::Indirect => scale(&self.indirect_sum, per_sample)
+		};
+	}
+}
+
+impl Default for AovAccumulator {
+	fn default() -> Self {
+		return AovAccumulator::new();
+	}
+}
+
+fn add_light(sum: &mut LightColor, c: &LightColor) {
+	sum.r += c.r;
+	sum.g += c.g;
+	sum.b += c.b;
+}
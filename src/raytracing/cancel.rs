@@ -0,0 +1,27 @@
+
+// Import requirements
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Handle to stop a rendering from another thread (or from the progress function). All clones share the same state, so
+// the rendering gets one clone and the caller keeps another one to cancel it.
+#[derive(Clone, Default)]
+pub struct CancellationToken {
+	cancelled: Arc<AtomicBool>
+}
+
+impl CancellationToken {
+
+	pub fn new() -> CancellationToken {
+		return CancellationToken { cancelled: Arc::new(AtomicBool::new(false)) };
+	}
+
+	// Asks the rendering to stop. The render threads finish the row they are working on and the rendering returns.
+	pub fn cancel(&self) {
+		self.cancelled.store(true, Ordering::Relaxed);
+	}
+
+	pub fn is_cancelled(&self) -> bool {
+		return self.cancelled.load(Ordering::Relaxed);
+	}
+}
@@ -0,0 +1,129 @@
+
+// Import requirements
+use super::rendering::*;
+use super::aov::*;
+use crate::fundamentals::binary::*;
+use std::io::{Error, ErrorKind, Read, Write};
+
+// Checkpoints of progressive renderings, so that a rendering that has been interrupted can be continued. A checkpoint
+// contains the sums of all finished passes, the number of samples per pixel they contain and the statistics. The
+// random numbers are derived from the seed and the sample indices, so the seed and the number of samples are the
+// complete state of the random number generation. All numbers are stored little endian, floating point numbers with
+// all their bits, so that the resumed rendering continues with exactly the same sums.
+
+const MAGIC: &[u8; 8] = b"PHOTONCP";
+const VERSION: u32 = 2;
+
+// Writes the state of a progressive rendering between two passes
+pub fn write_checkpoint<W: Write>(out: &mut W, state: &ProgressiveRender) -> std::io::Result<()> {
+	let frame = &state.frame;
+
+	out.write_all(MAGIC)?;
+	out.write_all(&VERSION.to_le_bytes())?;
+	out.write_all(&state.scene_hash.to_le_bytes())?;
+	out.write_all(&state.settings.seed.to_le_bytes())?;
+	out.write_all(&state.settings.width.to_le_bytes())?;
+	out.write_all(&state.settings.height.to_le_bytes())?;
+	out.write_all(&state.samples.to_le_bytes())?;
+	out.write_all(&state.pass_count.to_le_bytes())?;
+	out.write_all(&[u8::from(!frame.aovs.is_empty())])?;
+	write_frame_buffer(out, frame)?;
+	return Ok(());
+}
+
+// Restores a checkpoint into the state of a new progressive rendering. The checkpoint has to belong to the same scene
+// and settings, otherwise an InvalidData error is returned and the state is left unchanged.
+pub fn read_checkpoint<R: Read>(input: &mut R, state: &mut ProgressiveRender) -> std::io::Result<()> {
+	let mut magic = [0u8; 8];
+	input.read_exact(&mut magic)?;
+
+	if &magic != MAGIC || read_u32(input)? != VERSION {
+		return Err(Error::new(ErrorKind::InvalidData, "Not a checkpoint of this version"));
+	}
+
+	let scene_hash = read_u64(input)?;
+	let seed = read_u64(input)?;
+	let width = read_u32(input)? as i32;
+	let height = read_u32(input)? as i32;
+
+	if scene_hash != state.scene_hash || seed != state.settings.seed || width != state.settings.width || height != state.settings.height {
+		return Err(Error::new(ErrorKind::InvalidData, "The checkpoint belongs to another scene or other settings"));
+	}
+
+	let samples = read_u32(input)?;
+	let pass_count = read_u32(input)?;
+	let mut has_aovs = [0u8; 1];
+	input.read_exact(&mut has_aovs)?;
+
+	if (has_aovs[0] != 0) != state.settings.aovs {
+		return Err(Error::new(ErrorKind::InvalidData, "The checkpoint has been rendered with other AOV settings"));
+	}
+
+	let mut frame = FrameBuffer::for_region(&state.settings);
+	read_frame_buffer(input, &mut frame)?;
+
+	state.frame = frame;
+	state.samples = samples;
+	state.pass_count = pass_count;
+	return Ok(());
+}
+
+// Writes the sums and the counters of a frame buffer. The size and the AOV flag are not written, the reader has to
+// create a buffer of the same rows and settings.
+pub fn write_frame_buffer<W: Write>(out: &mut W, frame: &FrameBuffer) -> std::io::Result<()> {
+	let counters = &frame.stats.counters;
+
+	for count in [counters.primary_rays, counters.shadow_rays, counters.indirect_rays, counters.intersection_tests] {
+		out.write_all(&count.to_le_bytes())?;
+	}
+
+	for p in &frame.film.pixels {
+		for v in [p.color_sum.r, p.color_sum.g, p.color_sum.b, p.weight_sum] {
+			out.write_all(&v.to_le_bytes())?;
+		}
+	}
+
+	for count in &frame.sample_counts {
+		out.write_all(&count.to_le_bytes())?;
+	}
+
+	for aov in &frame.aovs {
+		for v in aov.to_values() {
+			out.write_all(&v.to_le_bytes())?;
+		}
+	}
+
+	return Ok(());
+}
+
+// Reads what write_frame_buffer has written into a buffer of the same rows and settings
+pub fn read_frame_buffer<R: Read>(input: &mut R, frame: &mut FrameBuffer) -> std::io::Result<()> {
+	let counters = &mut frame.stats.counters;
+	counters.primary_rays = read_u64(input)?;
+	counters.shadow_rays = read_u64(input)?;
+	counters.indirect_rays = read_u64(input)?;
+	counters.intersection_tests = read_u64(input)?;
+
+	for p in &mut frame.film.pixels {
+		p.color_sum.r = read_f64(input)?;
+		p.color_sum.g = read_f64(input)?;
+		p.color_sum.b = read_f64(input)?;
+		p.weight_sum = read_f64(input)?;
+	}
+
+	for count in &mut frame.sample_counts {
+		*count = read_u32(input)?;
+	}
+
+	for aov in &mut frame.aovs {
+		let mut values = [0.0; AOV_ACCUMULATOR_VALUES];
+
+		for v in &mut values {
+			*v = read_f64(input)?;
+		}
+
+		*aov = AovAccumulator::from_values(&values);
+	}
+
+	return Ok(());
+}
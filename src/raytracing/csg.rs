@@ -0,0 +1,127 @@
+
+// Import requirements
+use crate::fundamentals::csg::*;
+use crate::fundamentals::primitive::*;
+use super::rays::*;
+use super::primitives::*;
+
+// A point where a ray crosses the surface of a CSG solid, on the surface of the leaf with the index. Flipped crossings
+// lie on the surface of a solid carved out by a difference, so the normal of the leaf points into the solid there.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Crossing {
+	pub distance: f64,
+	pub leaf: usize,
+	pub flipped: bool
+}
+
+// The part of the line of a ray between entering a solid and leaving it
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Interval {
+	pub enter: Crossing,
+	pub exit: Crossing
+}
+
+// Returns the nearest crossing of a ray with the surface of a CSG solid further away than min_distance. Ray must be
+// normalized!
+pub fn get_ray_csg_crossing(ray: &Ray, node: &CsgNode, min_distance: f64) -> Option<Crossing> {
+	return get_ray_csg_intervals(ray, node).into_iter()
+		.flat_map(|interval| [interval.enter, interval.exit])
+		.find(|crossing| crossing.distance > min_distance);
+}
+
+// Returns the ascending and disjoint intervals of the line of a ray within a CSG solid, behind the ray origin too. The
+// intervals of the leaves come from their crossings or, for nested CSG solids, their own tree, the ones of the inner
+// nodes from combining the intervals of their children. Ray must be normalized!
+pub fn get_ray_csg_intervals(ray: &Ray, node: &CsgNode) -> Vec<Interval> {
+	return get_node_intervals(ray, node, 0);
+}
+
+fn get_node_intervals(ray: &Ray, node: &CsgNode, first_leaf: usize) -> Vec<Interval> {
+	let (a, b, operation) = match node {
+		CsgNode::Solid(solid) => {
+			let (object_ray, scale) = get_object_space_ray(ray, &solid.transform);
+			let crossing = |distance: f64| Crossing { distance: distance / scale, leaf: first_leaf, flipped: false };
+
+			if let PrimitiveKind::Csg(node) = &solid.kind {
+				let nested = |c: Crossing| Crossing { distance: c.distance / scale, leaf: first_leaf + c.leaf, flipped: c.flipped };
+
+				return get_ray_csg_intervals(&object_ray, node).into_iter()
+					.map(|interval| Interval { enter: nested(interval.enter), exit: nested(interval.exit) })
+					.collect();
+			}
+
+			return get_ray_solid_intervals(&object_ray, &solid.kind).into_iter()
+				.map(|(enter, exit)| Interval { enter: crossing(enter), exit: crossing(exit) })
+				.collect();
+		}
+		CsgNode::Union(a, b) => (a, b, Operation::Union),
+		CsgNode::Intersection(a, b) => (a, b, Operation::Intersection),
+		CsgNode::Difference(a, b) => (a, b, Operation::Difference)
+	};
+
+	let first_intervals = get_node_intervals(ray, a, first_leaf);
+	let second_intervals = get_node_intervals(ray, b, first_leaf + a.leaf_count());
+	return combine_intervals(&first_intervals, &second_intervals, operation);
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Operation {
+	Union,
+	Intersection,
+	Difference
+}
+
+impl Operation {
+
+	fn contains(&self, in_first: bool, in_second: bool) -> bool {
+		return match self {
+			Operation::Union => in_first || in_second,
+			Operation::Intersection => in_first && in_second,
+			Operation::Difference => in_first && !in_second
+		};
+	}
+}
+
+// Sweeps along the crossings of both interval lists, keeping track of being within either solid, and starts or ends an
+// interval of the result wherever the combination changes. The crossings of the solid carved out by a difference are
+// flipped, as its surface bounds the result from the other side.
+fn combine_intervals(first: &[Interval], second: &[Interval], operation: Operation) -> Vec<Interval> {
+	let mut events: Vec<(Crossing, bool, bool)> = Vec::with_capacity(2 * (first.len() + second.len()));
+
+	for (intervals, is_second) in [(first, false), (second, true)] {
+		for interval in intervals {
+			events.push((interval.enter, is_second, true));
+			events.push((interval.exit, is_second, false));
+		}
+	}
+
+	events.sort_by(|a, b| a.0.distance.total_cmp(&b.0.distance));
+
+	let mut result = Vec::new();
+	let (mut in_first, mut in_second) = (false, false);
+	let mut enter: Option<Crossing> = None;
+
+	for (mut crossing, is_second, entering) in events {
+		let was_inside = operation.contains(in_first, in_second);
+
+		if is_second {
+			in_second = entering;
+		} else {
+			in_first = entering;
+		}
+
+		let is_inside = operation.contains(in_first, in_second);
+		crossing.flipped ^= is_second && operation == Operation::Difference;
+
+		if is_inside && !was_inside {
+			enter = Some(crossing);
+		} else if was_inside && !is_inside {
+			// Coincident surfaces leave empty intervals, which are dropped
+			if let Some(enter) = enter.take().filter(|enter| crossing.distance > enter.distance) {
+				result.push(Interval { enter, exit: crossing });
+			}
+		}
+	}
+
+	return result;
+}
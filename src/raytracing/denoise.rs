@@ -0,0 +1,314 @@
+
+// Import requirements
+use crate::fundamentals::light::*;
+use super::aov::*;
+use super::rendering::*;
+use std::thread;
+
+// The available denoising filters
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum DenoiseMethod {
+	// Edge-avoiding à-trous wavelet filter (Dammertz et al.), a few passes of a 5x5 kernel with growing gaps
+	ATrous,
+	// Non-local means, averaging the pixels of a search window whose neighbourhoods look alike
+	NonLocalMeans
+}
+
+impl DenoiseMethod {
+
+	// Returns the denoising method for the name used on the command line
+	pub fn from_name(name: &str) -> Option<DenoiseMethod> {
+		return match name {
+			"atrous" => Some(DenoiseMethod::ATrous),
+			"nlm" => Some(DenoiseMethod::NonLocalMeans),
+			_ => None
+		};
+	}
+}
+
+// Parameters of the denoiser. The sigmas control how fast the weight of a neighbour falls off with its difference to
+// the filtered pixel, smaller values preserve more edges but remove less noise.
+#[derive(Copy, Clone)]
+pub struct DenoiseSettings {
+	pub method: DenoiseMethod,
+	// Number of à-trous passes, the kernel covers 4 * 2^iterations + 1 pixels
+	pub iterations: u32,
+	// Accepted color difference relative to the brightness of the pixels (à-trous) or of the neighbourhood (NLM)
+	pub strength: f64,
+	// Radius in pixels of the window searched for similar pixels by non-local means
+	pub search_radius: i32,
+	// Radius in pixels of the neighbourhood compared by non-local means
+	pub patch_radius: i32,
+	pub normal_sigma: f64,
+	pub albedo_sigma: f64,
+	// Depth difference relative to the depth of the pixel, per pixel of distance
+	pub depth_sigma: f64,
+	// Number of threads the rows of the image are split up for
+	pub thread_count: i32
+}
+
+impl DenoiseSettings {
+
+	// Returns the default settings of the specified method
+	pub fn new(method: DenoiseMethod) -> DenoiseSettings {
+		return DenoiseSettings {
+			method,
+			iterations: 5,
+			strength: match method {
+				DenoiseMethod::ATrous => 2.0,
+				DenoiseMethod::NonLocalMeans => 0.5
+			},
+			search_radius: 5,
+			patch_radius: 1,
+			normal_sigma: 0.3,
+			albedo_sigma: 0.2,
+			depth_sigma: 0.1,
+			thread_count: 8
+		};
+	}
+}
+
+// The feature buffers guiding the denoiser. They are nearly noise free and show where the edges of the image are.
+pub struct DenoiseFeatures<'a> {
+	pub albedo: &'a [LightColor],
+	pub normal: &'a [LightColor],
+	pub depth: &'a [LightColor]
+}
+
+impl<'a> DenoiseFeatures<'a> {
+
+	// Returns the features from the AOVs of a rendering, if they have been rendered
+	pub fn from_result(result: &'a RenderResult) -> Option<DenoiseFeatures<'a>> {
+		return Some(DenoiseFeatures {
+			albedo: &result.aov(AovType::Albedo)?.pixels,
+			normal: &result.aov(AovType::Normal)?.pixels,
+			depth: &result.aov(AovType::Depth)?.pixels
+		});
+	}
+}
+
+// Albedo below this is not divided out, which would only amplify the noise
+const MIN_ALBEDO: f64 = 0.01;
+
+// Keeps the relative color differences of dark pixels finite
+const MIN_LUMINANCE: f64 = 0.01;
+
+// B3 spline, the 1D kernel of the à-trous filter
+const ATROUS_KERNEL: [f64; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+// Guide values of one pixel
+struct Feature {
+	albedo: [f64; 3],
+	normal: [f64; 3],
+	depth: f64
+}
+
+// Read only inputs of one filter pass
+struct Pass<'a> {
+	width: i32,
+	height: i32,
+	features: &'a [Feature],
+	settings: &'a DenoiseSettings
+}
+
+// Removes the noise from the rendered image. The material color is divided out before filtering, so only the lighting
+// is smoothed and textures stay sharp. The result only depends on the inputs, not on the thread count.
+pub fn denoise(pixels: &[LightColor], width: i32, height: i32, features: &DenoiseFeatures, settings: &DenoiseSettings) -> Vec<LightColor> {
+	let modulation: Vec<[f64; 3]> = features.albedo.iter().map(|a| [a.r.max(MIN_ALBEDO), a.g.max(MIN_ALBEDO), a.b.max(MIN_ALBEDO)]).collect();
+
+	let guides: Vec<Feature> = (0..pixels.len()).map(|i| Feature {
+		albedo: to_array(&features.albedo[i]),
+		normal: to_array(&features.normal[i]),
+		depth: features.depth[i].r
+	}).collect();
+
+	let mut irradiance: Vec<[f64; 3]> = pixels.iter().zip(&modulation).map(|(p, m)| [p.r / m[0], p.g / m[1], p.b / m[2]]).collect();
+
+	let pass = Pass { width, height, features: &guides, settings };
+
+	match settings.method {
+		DenoiseMethod::ATrous => {
+			for iteration in 0..settings.iterations {
+				irradiance = filter_rows(&pass, &irradiance, |p, input, x, y| atrous_pixel(p, input, x, y, iteration));
+			}
+		}
+		DenoiseMethod::NonLocalMeans => {
+			irradiance = filter_rows(&pass, &irradiance, nlm_pixel);
+		}
+	}
+
+	return irradiance.iter().zip(&modulation).map(|(e, m)| LightColor { r: e[0] * m[0], g: e[1] * m[1], b: e[2] * m[2] }).collect();
+}
+
+// Runs a filter over all pixels, the rows being split up into intervals computed on dedicated threads
+fn filter_rows<F>(pass: &Pass, input: &[[f64; 3]], filter: F) -> Vec<[f64; 3]>
+	where F: Fn(&Pass, &[[f64; 3]], i32, i32) -> [f64; 3] + Sync {
+
+	let width = pass.width as usize;
+	let mut output = vec![[0.0; 3]; input.len()];
+	let rows_per_thread = (pass.height as usize).div_ceil(pass.settings.thread_count.max(1) as usize).max(1);
+
+	thread::scope(|scope| {
+		for (chunk_index, chunk) in output.chunks_mut(rows_per_thread * width).enumerate() {
+			let filter = &filter;
+
+			scope.spawn(move || {
+				for (i, value) in chunk.iter_mut().enumerate() {
+					let x = (i % width) as i32;
+					let y = (chunk_index * rows_per_thread + i / width) as i32;
+					*value = filter(pass, input, x, y);
+				}
+			});
+		}
+	});
+
+	return output;
+}
+
+// Weight of a neighbour q of pixel p from the differences of their guide values
+fn feature_weight(settings: &DenoiseSettings, p: &Feature, q: &Feature, distance: f64) -> f64 {
+
+	// Background (infinite depth) is never mixed with surfaces
+	if p.depth.is_finite() != q.depth.is_finite() {
+		return 0.0;
+	}
+
+	if !p.depth.is_finite() {
+		return 1.0;
+	}
+
+	let normal_difference = squared_difference(&p.normal, &q.normal) / (settings.normal_sigma * settings.normal_sigma);
+	let albedo_difference = squared_difference(&p.albedo, &q.albedo) / (settings.albedo_sigma * settings.albedo_sigma);
+	let depth_difference = (p.depth - q.depth).abs() / (settings.depth_sigma * p.depth.max(1e-9) * distance.max(1.0));
+
+	return (-(normal_difference + albedo_difference + depth_difference)).exp();
+}
+
+// One pass of the à-trous filter for pixel (x, y), the taps being 2^iteration pixels apart. The accepted color
+// difference halves with every pass, since the noise has already been reduced by the previous ones.
+fn atrous_pixel(pass: &Pass, input: &[[f64; 3]], x: i32, y: i32, iteration: u32) -> [f64; 3] {
+	let step = 1 << iteration;
+	let p_index = (y * pass.width + x) as usize;
+	let p = &input[p_index];
+	let p_feature = &pass.features[p_index];
+
+	let sigma = pass.settings.strength * 0.5f64.powi(iteration as i32);
+	let p_luminance = luminance_of(p);
+
+	let mut sum = [0.0; 3];
+	let mut weight_sum = 0.0;
+
+	for (j, ky) in ATROUS_KERNEL.iter().enumerate() {
+		let qy = y + (j as i32 - 2) * step;
+
+		if qy < 0 || qy >= pass.height {
+			continue;
+		}
+
+		for (i, kx) in ATROUS_KERNEL.iter().enumerate() {
+			let qx = x + (i as i32 - 2) * step;
+
+			if qx < 0 || qx >= pass.width {
+				continue;
+			}
+
+			let q_index = (qy * pass.width + qx) as usize;
+			let q = &input[q_index];
+			let distance = (((qx - x) * (qx - x) + (qy - y) * (qy - y)) as f64).sqrt();
+
+			// The difference is relative to the brighter of both, so that dark outliers still take in their neighbours
+			let reference = p_luminance.max(luminance_of(q)).max(MIN_LUMINANCE);
+			let color_scale = 1.0 / (sigma * sigma * reference * reference);
+
+			let weight = kx * ky
+				* (-squared_difference(p, q) * color_scale).exp()
+				* feature_weight(pass.settings, p_feature, &pass.features[q_index], distance);
+
+			add_weighted(&mut sum, q, weight);
+			weight_sum += weight;
+		}
+	}
+
+	return normalize(&sum, weight_sum, p);
+}
+
+// Non-local means for pixel (x, y): every pixel of the search window is weighted by the similarity of the patches
+// around both pixels and by their guide values
+fn nlm_pixel(pass: &Pass, input: &[[f64; 3]], x: i32, y: i32) -> [f64; 3] {
+	let settings = pass.settings;
+	let r = settings.search_radius;
+	let f = settings.patch_radius;
+	let p_index = (y * pass.width + x) as usize;
+	let p_feature = &pass.features[p_index];
+
+	// Pixels of a patch outside of the image are taken from the nearest border pixel
+	let clamped = |cx: i32, cy: i32| &input[(cy.clamp(0, pass.height - 1) * pass.width + cx.clamp(0, pass.width - 1)) as usize];
+
+	let mut patch_luminance = 0.0;
+
+	for oy in -f..=f {
+		for ox in -f..=f {
+			patch_luminance += luminance_of(clamped(x + ox, y + oy));
+		}
+	}
+
+	let patch_size = ((2 * f + 1) * (2 * f + 1)) as f64;
+	patch_luminance = (patch_luminance / patch_size).max(MIN_LUMINANCE);
+	let color_scale = 1.0 / (3.0 * patch_size * settings.strength * settings.strength * patch_luminance * patch_luminance);
+
+	let mut sum = [0.0; 3];
+	let mut weight_sum = 0.0;
+
+	for qy in (y - r).max(0)..=(y + r).min(pass.height - 1) {
+		for qx in (x - r).max(0)..=(x + r).min(pass.width - 1) {
+			let q_index = (qy * pass.width + qx) as usize;
+			let distance = (((qx - x) * (qx - x) + (qy - y) * (qy - y)) as f64).sqrt();
+			let guide_weight = feature_weight(settings, p_feature, &pass.features[q_index], distance);
+
+			if guide_weight < 1e-6 {
+				continue;
+			}
+
+			let mut patch_difference = 0.0;
+
+			for oy in -f..=f {
+				for ox in -f..=f {
+					patch_difference += squared_difference(clamped(x + ox, y + oy), clamped(qx + ox, qy + oy));
+				}
+			}
+
+			let weight = (-patch_difference * color_scale).exp() * guide_weight;
+			add_weighted(&mut sum, &input[q_index], weight);
+			weight_sum += weight;
+		}
+	}
+
+	return normalize(&sum, weight_sum, &input[p_index]);
+}
+
+fn to_array(c: &LightColor) -> [f64; 3] {
+	return [c.r, c.g, c.b];
+}
+
+fn luminance_of(c: &[f64; 3]) -> f64 {
+	return luminance(&LightColor { r: c[0], g: c[1], b: c[2] });
+}
+
+fn squared_difference(a: &[f64; 3], b: &[f64; 3]) -> f64 {
+	return (a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2);
+}
+
+fn add_weighted(sum: &mut [f64; 3], c: &[f64; 3], weight: f64) {
+	sum[0] += c[0] * weight;
+	sum[1] += c[1] * weight;
+	sum[2] += c[2] * weight;
+}
+
+// Returns the weighted average, or the fallback if no neighbour had any weight
+fn normalize(sum: &[f64; 3], weight_sum: f64, fallback: &[f64; 3]) -> [f64; 3] {
+	if weight_sum < 1e-12 {
+		return *fallback;
+	}
+
+	return [sum[0] / weight_sum, sum[1] / weight_sum, sum[2] / weight_sum];
+}
@@ -0,0 +1,83 @@
+
+// Import requirements
+use crate::fundamentals::light::*;
+use super::filter::*;
+
+// Weighted sum of all samples that contribute to one pixel
+#[derive(Copy, Clone)]
+pub struct FilmPixel {
+	pub color_sum: LightColor,
+	pub weight_sum: f64
+}
+
+// Accumulates filtered samples for a rectangle of image pixels. Samples are splat into all pixels within the filter
+// radius, each pixel being the weighted average of the samples around it.
+#[derive(Clone)]
+pub struct Film {
+	pub x_start: i32,
+	pub x_count: i32,
+	pub y_start: i32,
+	pub y_count: i32,
+	pub filter: PixelFilter,
+	// Row by row, x_count pixels each
+	pub pixels: Vec<FilmPixel>
+}
+
+impl Film {
+
+	// Creates an empty film for x_count columns starting at column x_start and y_count rows starting at row y_start
+	pub fn new(x_start: i32, x_count: i32, y_start: i32, y_count: i32, filter: PixelFilter) -> Film {
+		let empty = FilmPixel { color_sum: LightColor { r: 0.0, g: 0.0, b: 0.0 }, weight_sum: 0.0 };
+
+		return Film {
+			x_start,
+			x_count,
+			y_start,
+			y_count,
+			filter,
+			pixels: vec![empty; (x_count * y_count) as usize]
+		};
+	}
+
+	// Adds a sample of pixel (x, y) that was taken at the offset (offset_x, offset_y) in [-0.5, 0.5) from the pixel
+	// center, measured in pixels with y pointing down. Contributions to pixels outside of the film are dropped.
+	pub fn add_sample(&mut self, x: i32, y: i32, offset_x: f64, offset_y: f64, light: &LightColor) {
+		let reach = self.filter.pixel_reach();
+
+		for ty in (y - reach)..=(y + reach) {
+			if ty < self.y_start || ty >= self.y_start + self.y_count {
+				continue;
+			}
+
+			for tx in (x - reach)..=(x + reach) {
+				if tx < self.x_start || tx >= self.x_start + self.x_count {
+					continue;
+				}
+
+				let weight = self.filter.evaluate(offset_x - ((tx - x) as f64), offset_y - ((ty - y) as f64));
+
+				if weight == 0.0 {
+					continue;
+				}
+
+				let pixel = &mut self.pixels[((ty - self.y_start) * self.x_count + tx - self.x_start) as usize];
+				pixel.color_sum.r += light.r * weight;
+				pixel.color_sum.g += light.g * weight;
+				pixel.color_sum.b += light.b * weight;
+				pixel.weight_sum += weight;
+			}
+		}
+	}
+
+	// Returns the filtered light of all pixels
+	pub fn resolve(&self) -> Vec<LightColor> {
+		return self.pixels.iter().map(|p| {
+			if p.weight_sum.abs() < 1e-12 {
+				return LightColor { r: 0.0, g: 0.0, b: 0.0 };
+			}
+
+			let w = 1.0 / p.weight_sum;
+			return LightColor { r: p.color_sum.r * w, g: p.color_sum.g * w, b: p.color_sum.b * w };
+		}).collect();
+	}
+}
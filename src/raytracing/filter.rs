@@ -0,0 +1,128 @@
+
+// Import requirements
+use std::f64::consts::PI;
+
+// The available pixel reconstruction filters
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum FilterType {
+	Box,
+	Tent,
+	Gaussian,
+	Mitchell,
+	Lanczos
+}
+
+impl FilterType {
+
+	// Returns the filter type for the name used on the command line
+	pub fn from_name(name: &str) -> Option<FilterType> {
+		return match name {
+			"box" => Some(FilterType::Box),
+			"tent" => Some(FilterType::Tent),
+			"gaussian" => Some(FilterType::Gaussian),
+			"mitchell" => Some(FilterType::Mitchell),
+			"lanczos" => Some(FilterType::Lanczos),
+			_ => None
+		};
+	}
+
+	pub fn name(&self) -> &'static str {
+		return match self {
+			FilterType::Box => "box",
+			FilterType::Tent => "tent",
+			FilterType::Gaussian => "gaussian",
+			FilterType::Mitchell => "mitchell",
+			FilterType::Lanczos => "lanczos"
+		};
+	}
+
+	// Radius (in pixels) the filter is usually used with
+	pub fn default_radius(&self) -> f64 {
+		return match self {
+			FilterType::Box => 0.5,
+			FilterType::Tent => 1.0,
+			FilterType::Gaussian => 1.5,
+			FilterType::Mitchell => 2.0,
+			FilterType::Lanczos => 3.0
+		};
+	}
+}
+
+// Falloff of the Gaussian filter
+const GAUSSIAN_ALPHA: f64 = 2.0;
+
+// Parameters of the Mitchell-Netravali filter, 1/3 each is the recommended compromise between blurring and ringing
+const MITCHELL_B: f64 = 1.0 / 3.0;
+const MITCHELL_C: f64 = 1.0 / 3.0;
+
+// Separable filter deciding how much a sample contributes to the pixels around it. Box filter with radius 0.5 means
+// that every sample only counts for its own pixel, all samples of it being averaged.
+#[derive(Copy, Clone, Debug)]
+pub struct PixelFilter {
+	pub filter_type: FilterType,
+	// Radius in pixels, samples further away than this from a pixel center do not contribute to the pixel
+	pub radius: f64
+}
+
+impl PixelFilter {
+
+	// Returns the filter of the specified type with its default radius
+	pub fn new(filter_type: FilterType) -> PixelFilter {
+		return PixelFilter { filter_type, radius: filter_type.default_radius() };
+	}
+
+	// Returns the weight of a sample at offset (dx, dy) pixels from a pixel center. Mitchell and Lanczos have
+	// negative lobes, so the weight can be negative.
+	pub fn evaluate(&self, dx: f64, dy: f64) -> f64 {
+		return self.evaluate_1d(dx) * self.evaluate_1d(dy);
+	}
+
+	// Number of neighbouring pixels in each direction a sample within a pixel can contribute to
+	pub fn pixel_reach(&self) -> i32 {
+		return ((self.radius + 0.5).ceil() as i32 - 1).max(0);
+	}
+
+	fn evaluate_1d(&self, x: f64) -> f64 {
+		let r = self.radius;
+
+		// The box is half-open, so that a sample on the border of two pixels only counts for one of them
+		if self.filter_type == FilterType::Box {
+			return if x >= -r && x < r { 1.0 } else { 0.0 };
+		}
+
+		let ax = x.abs();
+
+		if ax >= r {
+			return 0.0;
+		}
+
+		return match self.filter_type {
+			FilterType::Box => 1.0,
+			FilterType::Tent => r - ax,
+			FilterType::Gaussian => ((-GAUSSIAN_ALPHA * ax * ax).exp() - (-GAUSSIAN_ALPHA * r * r).exp()).max(0.0),
+			FilterType::Mitchell => mitchell_1d(2.0 * ax / r),
+			FilterType::Lanczos => sinc(ax) * sinc(ax / r)
+		};
+	}
+}
+
+// Mitchell-Netravali cubic for x in [0, 2]
+fn mitchell_1d(x: f64) -> f64 {
+	let b = MITCHELL_B;
+	let c = MITCHELL_C;
+
+	if x < 1.0 {
+		return ((12.0 - 9.0*b - 6.0*c) * x*x*x + (-18.0 + 12.0*b + 6.0*c) * x*x + (6.0 - 2.0*b)) / 6.0;
+	}
+
+	return ((-b - 6.0*c) * x*x*x + (6.0*b + 30.0*c) * x*x + (-12.0*b - 48.0*c) * x + (8.0*b + 24.0*c)) / 6.0;
+}
+
+// Normalized sinc function
+fn sinc(x: f64) -> f64 {
+	if x < 1e-5 {
+		return 1.0;
+	}
+
+	return (PI * x).sin() / (PI * x);
+}
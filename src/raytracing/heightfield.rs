@@ -0,0 +1,132 @@
+
+// Import requirements
+use crate::fundamentals::heightfield::*;
+use crate::fundamentals::math::vector::*;
+use super::rays::*;
+use super::primitives::*;
+
+// Slack for roots of the bilinear patches on the borders between cells
+const CELL_TOLERANCE: f64 = 1e-9;
+
+// Returns the distance of the nearest intersection of a ray with a heightfield further away than min_distance. The
+// ray walks through the cells it passes over in order (2D DDA), each cell being tested only if the ray is within the
+// heights of its corners there. Ray must be normalized!
+pub fn get_ray_heightfield_distance(ray: &Ray, field: &Heightfield, min_distance: f64) -> Option<f64> {
+	let bounds = field.bounds();
+	let (near, far) = get_ray_box_interval(ray, &bounds.min, &bounds.max)?;
+	let mut t = near.max(min_distance);
+
+	if t > far {
+		return None;
+	}
+
+	// Cell coordinates, the cells being one unit wide
+	let (cells_x, cells_z) = field.cells();
+	let (o, d) = (ray.origin, ray.direction);
+	let start = o + d * t;
+	let mut i = ((start.x * cells_x as f64).floor() as i64).clamp(0, cells_x as i64 - 1);
+	let mut j = ((start.z * cells_z as f64).floor() as i64).clamp(0, cells_z as i64 - 1);
+
+	// Distance to the next cell border and between borders along each axis
+	let axis = |origin: f64, direction: f64, cell: i64, cells: usize| -> (i64, f64, f64) {
+		if direction == 0.0 {
+			return (0, f64::MAX, f64::MAX);
+		}
+
+		let step = if direction > 0.0 { 1 } else { -1 };
+		let border = (cell + i64::from(direction > 0.0)) as f64 / cells as f64;
+		return (step, (border - origin) / direction, 1.0 / (cells as f64 * direction.abs()));
+	};
+
+	let (step_i, mut next_x, delta_x) = axis(o.x, d.x, i, cells_x);
+	let (step_j, mut next_z, delta_z) = axis(o.z, d.z, j, cells_z);
+
+	loop {
+		let exit = next_x.min(next_z).min(far);
+
+		if let Some(distance) = get_ray_cell_distance(ray, field, i as usize, j as usize, t, exit, min_distance) {
+			return Some(distance);
+		}
+
+		if exit >= far {
+			return None;
+		}
+
+		if next_x < next_z {
+			i += step_i;
+			t = next_x;
+			next_x += delta_x;
+		} else {
+			j += step_j;
+			t = next_z;
+			next_z += delta_z;
+		}
+
+		if i < 0 || j < 0 || i >= cells_x as i64 || j >= cells_z as i64 {
+			return None;
+		}
+	}
+}
+
+// Returns the surface of a heightfield at a point on it, with the normal of the bilinear patch, the normals of the
+// samples interpolated across the cell for shading and the texture coordinates running along x and z
+pub fn get_heightfield_surface(field: &Heightfield, p: &Vector3) -> PrimitiveSurface {
+	let (cells_x, cells_z) = field.cells();
+	let (i, u) = get_cell(p.x, cells_x);
+	let (j, v) = get_cell(p.z, cells_z);
+	let (h00, h10, h01, h11) = get_corners(field, i, j);
+
+	let mix = |a: Vector3, b: Vector3, t: f64| a * (1.0 - t) + b * t;
+	let bottom = mix(field.normal(i, j), field.normal(i + 1, j), u);
+	let top = mix(field.normal(i, j + 1), field.normal(i + 1, j + 1), u);
+	let twist = h00 - h10 - h01 + h11;
+	let dp_du = Vector3::new(1.0, (h10 - h00 + twist * v) * cells_x as f64, 0.0);
+	let dp_dv = Vector3::new(0.0, (h01 - h00 + twist * u) * cells_z as f64, 1.0);
+
+	return PrimitiveSurface {
+		normal: dp_dv.cross(dp_du).normalize(),
+		shading_normal: mix(bottom, top, v).normalize(),
+		u: p.x,
+		v: p.z,
+		dp_du,
+		dp_dv
+	};
+}
+
+// Nearest intersection of the ray with the bilinear patch of a cell between the distances enter and exit. Along the
+// ray the patch height is a quadratic in the distance, as are the differences to the height of the ray.
+fn get_ray_cell_distance(ray: &Ray, field: &Heightfield, i: usize, j: usize, enter: f64, exit: f64, min_distance: f64) -> Option<f64> {
+	let (h00, h10, h01, h11) = get_corners(field, i, j);
+	let (o, d) = (ray.origin, ray.direction);
+
+	let (y_enter, y_exit) = (o.y + d.y * enter, o.y + d.y * exit);
+	if y_enter.max(y_exit) < h00.min(h10).min(h01).min(h11) || y_enter.min(y_exit) > h00.max(h10).max(h01).max(h11) {
+		return None;
+	}
+
+	// Coordinates within the cell along the ray, u = a_u + b_u t and v = a_v + b_v t
+	let (cells_x, cells_z) = (field.cells().0 as f64, field.cells().1 as f64);
+	let (a_u, b_u) = (o.x * cells_x - i as f64, d.x * cells_x);
+	let (a_v, b_v) = (o.z * cells_z - j as f64, d.z * cells_z);
+	let (slope_u, slope_v, twist) = (h10 - h00, h01 - h00, h00 - h10 - h01 + h11);
+
+	let a = -twist * b_u * b_v;
+	let b = d.y - slope_u * b_u - slope_v * b_v - twist * (a_u * b_v + a_v * b_u);
+	let c = o.y - h00 - slope_u * a_u - slope_v * a_v - twist * a_u * a_v;
+
+	return get_quadratic_roots(a, b, c).into_iter()
+		.filter(|t| *t > min_distance && *t >= enter - CELL_TOLERANCE && *t <= exit + CELL_TOLERANCE)
+		.min_by(f64::total_cmp);
+}
+
+// Heights at the corners (i, j), (i + 1, j), (i, j + 1) and (i + 1, j + 1) of a cell
+fn get_corners(field: &Heightfield, i: usize, j: usize) -> (f64, f64, f64, f64) {
+	return (field.height(i, j), field.height(i + 1, j), field.height(i, j + 1), field.height(i + 1, j + 1));
+}
+
+// Cell of a coordinate in [0, 1] along an axis of cells, with the position within it
+fn get_cell(coordinate: f64, cells: usize) -> (usize, f64) {
+	let x = coordinate * cells as f64;
+	let cell = (x.floor().max(0.0) as usize).min(cells - 1);
+	return (cell, (x - cell as f64).clamp(0.0, 1.0));
+}
@@ -1,3 +1,16 @@
+pub mod adaptive;
+pub mod aov;
+pub mod cancel;
+pub mod checkpoint;
+pub mod csg;
+pub mod denoise;
+pub mod film;
+pub mod filter;
+pub mod heightfield;
+pub mod primitives;
 pub mod rays;
 pub mod rendering;
+pub mod sampling;
+pub mod sdf;
+pub mod stats;
 pub mod tracing;
@@ -0,0 +1,481 @@
+
+// Import requirements
+use crate::fundamentals::primitive::*;
+use crate::fundamentals::math::vector::*;
+use super::rays::*;
+use super::csg::*;
+use super::sdf::*;
+use super::heightfield::*;
+
+// Iterations of the bisection finding a root of a polynomial, enough to get down to the precision of f64
+const MAX_BISECTIONS: i32 = 100;
+
+// Steps of Newton's method inverting the distribution of the points on a torus, which converges within a few
+const MAX_NEWTON_STEPS: i32 = 8;
+
+// Crossings of a ray with the surface of a solid closer than this are the same crossing
+const CROSSING_TOLERANCE: f64 = 1e-9;
+
+// The surface of a primitive at a point, everything in object space. The normal is normalized and points out of
+// solids, the derivatives are the ones of the position by the texture coordinates. The shading normal is the normal
+// of the surface apart from heightfields, which are shaded smoothly across their flat patches.
+pub struct PrimitiveSurface {
+	pub normal: Vector3,
+	pub shading_normal: Vector3,
+	pub u: f64,
+	pub v: f64,
+	pub dp_du: Vector3,
+	pub dp_dv: Vector3
+}
+
+// Returns the distance of the nearest intersection of a ray with a primitive further away than min_distance, all in
+// object space. Ray must be normalized!
+pub fn get_ray_primitive_distance(ray: &Ray, kind: &PrimitiveKind, min_distance: f64) -> Option<f64> {
+	let (o, d) = (ray.origin, ray.direction);
+
+	return match kind {
+		PrimitiveKind::Disk { radius } => get_ray_disk_distance(ray, 0.0, *radius).filter(|t| *t > min_distance),
+		PrimitiveKind::Quad { edge_u, edge_v } => {
+			let n = edge_u.cross(*edge_v);
+			let t = -n.dot(o) / n.dot(d);
+			let (u, v) = get_quad_uv(edge_u, edge_v, &(o + d * t));
+			Some(t).filter(|t| t.is_finite() && *t > min_distance && (0.0..=1.0).contains(&u) && (0.0..=1.0).contains(&v))
+		}
+		PrimitiveKind::Csg(node) => get_ray_csg_crossing(ray, node, min_distance).map(|c| c.distance),
+		PrimitiveKind::Sdf(sdf) => get_ray_sdf_distance(ray, sdf, min_distance),
+		PrimitiveKind::Heightfield(field) => get_ray_heightfield_distance(ray, field, min_distance),
+
+		// The ray enters or leaves solids wherever it crosses their surface, also if it starts within them
+		_ => get_ray_solid_crossings(ray, kind).into_iter().find(|t| *t > min_distance)
+	};
+}
+
+// Returns the distance of the nearest intersection of a ray with a primitive further away than min_distance together
+// with the surface there, all in object space. The surfaces of CSG solids are the ones of their leaves, the normals
+// turned around where a difference carved them out. Ray must be normalized!
+pub fn get_ray_primitive_hit(ray: &Ray, kind: &PrimitiveKind, min_distance: f64) -> Option<(f64, PrimitiveSurface)> {
+	let PrimitiveKind::Csg(node) = kind else {
+		let distance = get_ray_primitive_distance(ray, kind, min_distance)?;
+		return Some((distance, get_primitive_surface(kind, &(ray.origin + ray.direction * distance))));
+	};
+
+	let crossing = get_ray_csg_crossing(ray, node, min_distance)?;
+	let (leaf_kind, transform) = node.leaf(crossing.leaf)?;
+	let point = ray.origin + ray.direction * crossing.distance;
+	let surface = get_primitive_surface(leaf_kind, &transform.inverse.transform_point(Point3::from(point)).to_vector());
+	let normal = transform.apply_normal(Normal3::from(surface.normal)).to_vector().normalize();
+	let normal = if crossing.flipped { -normal } else { normal };
+
+	return Some((crossing.distance, PrimitiveSurface {
+		normal,
+		shading_normal: normal,
+		u: surface.u,
+		v: surface.v,
+		dp_du: transform.apply_vector(surface.dp_du),
+		dp_dv: transform.apply_vector(surface.dp_dv)
+	}));
+}
+
+// Returns the distances of all the points where the line of the ray crosses the surface of a closed primitive, behind
+// the ray origin too, in ascending order. Crossings within CROSSING_TOLERANCE of each other are counted once, like the
+// ones of the side and the cap where a ray passes through the rim of a cylinder. The sides reach that far beyond the
+// rims so that rounding can't lose such a crossing on both of them. Disks, quads, heightfields, CSG solids and SDFs have
+// none. Ray must be normalized!
+pub fn get_ray_solid_crossings(ray: &Ray, kind: &PrimitiveKind) -> Vec<f64> {
+	let (o, d) = (ray.origin, ray.direction);
+
+	let mut crossings = match kind {
+		PrimitiveKind::Sphere { radius } => get_quadratic_roots(1.0, 2.0 * o.dot(d), o.dot(o) - radius * radius),
+		PrimitiveKind::Box { half_size } => get_ray_box_interval(ray, &-*half_size, half_size).map_or(Vec::new(), |(near, far)| vec![near, far]),
+		PrimitiveKind::Cylinder { radius, height } => {
+			let a = d.x * d.x + d.z * d.z;
+			let b = 2.0 * (o.x * d.x + o.z * d.z);
+			let c = o.x * o.x + o.z * o.z - radius * radius;
+			let on_side = |t: &f64| (-CROSSING_TOLERANCE..=height + CROSSING_TOLERANCE).contains(&(o.y + d.y * t));
+
+			get_quadratic_roots(a, b, c).into_iter().filter(on_side)
+				.chain(get_ray_disk_distance(ray, 0.0, *radius))
+				.chain(get_ray_disk_distance(ray, *height, *radius))
+				.collect()
+		}
+		PrimitiveKind::Cone { radius, height } => {
+
+			// The radius shrinks by k per unit of height: x^2 + z^2 = (radius - k y)^2
+			let k = radius / height;
+			let q = radius - k * o.y;
+			let a = d.x * d.x + d.z * d.z - k * k * d.y * d.y;
+			let b = 2.0 * (o.x * d.x + o.z * d.z + q * k * d.y);
+			let c = o.x * o.x + o.z * o.z - q * q;
+			let on_side = |t: &f64| (-CROSSING_TOLERANCE..=height + CROSSING_TOLERANCE).contains(&(o.y + d.y * t));
+
+			get_quadratic_roots(a, b, c).into_iter().filter(on_side)
+				.chain(get_ray_disk_distance(ray, 0.0, *radius))
+				.collect()
+		}
+		PrimitiveKind::Torus { major_radius, minor_radius } => get_ray_torus_crossings(ray, *major_radius, *minor_radius),
+		PrimitiveKind::Disk { .. } | PrimitiveKind::Quad { .. } | PrimitiveKind::Heightfield(_) | PrimitiveKind::Csg(_) | PrimitiveKind::Sdf(_) => Vec::new()
+	};
+
+	crossings.sort_by(f64::total_cmp);
+	crossings.dedup_by(|b, a| *b - *a < CROSSING_TOLERANCE);
+	return crossings;
+}
+
+// Returns the parts of the line of the ray within a closed primitive, as the distances where they start and end. Rays
+// grazing the surface touch it without entering (tangent to a sphere or a torus, along the rim of a cylinder), so the
+// crossings don't simply pair up and there may be an odd number of them. Instead every span between two crossings in
+// a row is within the primitive if its middle is, and spans following each other are joined. Ray must be normalized!
+pub fn get_ray_solid_intervals(ray: &Ray, kind: &PrimitiveKind) -> Vec<(f64, f64)> {
+	let mut intervals: Vec<(f64, f64)> = Vec::new();
+
+	for span in get_ray_solid_crossings(ray, kind).windows(2) {
+		if !is_inside_solid(kind, &(ray.origin + ray.direction * (0.5 * (span[0] + span[1])))) {
+			continue;
+		}
+
+		match intervals.last_mut() {
+			Some(last) if last.1 == span[0] => last.1 = span[1],
+			_ => intervals.push((span[0], span[1]))
+		}
+	}
+
+	return intervals;
+}
+
+// Returns whether a point in object space is within a closed primitive. The primitives without an inside have none.
+fn is_inside_solid(kind: &PrimitiveKind, p: &Vector3) -> bool {
+	let rho_squared = p.x * p.x + p.z * p.z;
+
+	return match kind {
+		PrimitiveKind::Sphere { radius } => p.dot(*p) <= radius * radius,
+		PrimitiveKind::Box { half_size } => p.x.abs() <= half_size.x && p.y.abs() <= half_size.y && p.z.abs() <= half_size.z,
+		PrimitiveKind::Cylinder { radius, height } => (0.0..=*height).contains(&p.y) && rho_squared <= radius * radius,
+		PrimitiveKind::Cone { radius, height } => (0.0..=*height).contains(&p.y) && rho_squared.sqrt() <= radius * (1.0 - p.y / height),
+		PrimitiveKind::Torus { major_radius, minor_radius } => {
+			let tube = rho_squared.sqrt() - major_radius;
+			tube * tube + p.y * p.y <= minor_radius * minor_radius
+		}
+		PrimitiveKind::Disk { .. } | PrimitiveKind::Quad { .. } | PrimitiveKind::Heightfield(_) | PrimitiveKind::Csg(_) | PrimitiveKind::Sdf(_) => false
+	};
+}
+
+// Returns the surface of a primitive at a point on it. CSG solids have no surface of their own, their points are taken
+// as points of a sphere.
+pub fn get_primitive_surface(kind: &PrimitiveKind, p: &Vector3) -> PrimitiveSurface {
+	let tau = std::f64::consts::TAU;
+
+	// Around the y axis u goes once around like for spheres, with the derivative of the position by it
+	let around = || (0.5 + p.x.atan2(p.z) / tau, Vector3::new(p.z, 0.0, -p.x) * tau);
+	let radial = || {
+		let rho = (p.x * p.x + p.z * p.z).sqrt();
+		if rho > 1e-12 { (rho, Vector3::new(p.x / rho, 0.0, p.z / rho)) } else { (rho, Vector3::new(0.0, 0.0, 1.0)) }
+	};
+
+	// Caps and disks are mapped like disks, [-radius, radius] along x and z to [0, 1]
+	let planar = |normal: Vector3, radius: f64| PrimitiveSurface {
+		normal,
+		shading_normal: normal,
+		u: 0.5 + p.x / (2.0 * radius),
+		v: 0.5 + p.z / (2.0 * radius),
+		dp_du: Vector3::new(2.0 * radius, 0.0, 0.0),
+		dp_dv: Vector3::new(0.0, 0.0, 2.0 * radius)
+	};
+
+	return match kind {
+		PrimitiveKind::Sphere { .. } | PrimitiveKind::Csg(_) => {
+			let normal = p.normalize();
+			let (u, v) = get_sphere_uv(&normal);
+			let (dp_du, dp_dv) = get_sphere_derivatives(p.length(), &normal);
+			PrimitiveSurface { normal, shading_normal: normal, u, v, dp_du, dp_dv }
+		}
+		PrimitiveKind::Sdf(sdf) => {
+
+			// Mapped like spheres around the origin, as the distance function gives no coordinates
+			let normal = get_sdf_normal(sdf, p);
+			let (u, v) = get_sphere_uv(&p.normalize());
+			let (dp_du, dp_dv) = get_sphere_derivatives(p.length(), &normal);
+			PrimitiveSurface { normal, shading_normal: normal, u, v, dp_du, dp_dv }
+		}
+		PrimitiveKind::Box { half_size } => {
+
+			// The face is the one the point is relatively nearest to, and the faces are mapped like planes
+			let relative = [p.x / half_size.x, p.y / half_size.y, p.z / half_size.z];
+			let axis = (0..3).max_by(|&a, &b| relative[a].abs().total_cmp(&relative[b].abs())).unwrap_or(0);
+			let mut normal = Vector3::zero();
+			normal[axis] = relative[axis].signum();
+
+			let (tangent_u, tangent_v) = get_plane_tangents(&normal);
+			let extent = |t: &Vector3| t.x.abs() * half_size.x + t.y.abs() * half_size.y + t.z.abs() * half_size.z;
+			let (extent_u, extent_v) = (extent(&tangent_u), extent(&tangent_v));
+
+			PrimitiveSurface {
+				normal,
+				shading_normal: normal,
+				u: 0.5 + p.dot(tangent_u) / (2.0 * extent_u),
+				v: 0.5 + p.dot(tangent_v) / (2.0 * extent_v),
+				dp_du: tangent_u * (2.0 * extent_u),
+				dp_dv: tangent_v * (2.0 * extent_v)
+			}
+		}
+		PrimitiveKind::Disk { radius } => planar(Vector3::new(0.0, 1.0, 0.0), *radius),
+		PrimitiveKind::Heightfield(field) => get_heightfield_surface(field, p),
+		PrimitiveKind::Quad { edge_u, edge_v } => {
+			let (u, v) = get_quad_uv(edge_u, edge_v, p);
+			let normal = edge_u.cross(*edge_v).normalize();
+			PrimitiveSurface { normal, shading_normal: normal, u, v, dp_du: *edge_u, dp_dv: *edge_v }
+		}
+		PrimitiveKind::Cylinder { radius, height } => {
+			let (rho, outward) = radial();
+
+			if (rho - radius).abs() > p.y.abs().min((p.y - height).abs()) {
+				let up = if p.y > height * 0.5 { 1.0 } else { -1.0 };
+				return planar(Vector3::new(0.0, up, 0.0), *radius);
+			}
+
+			let (u, dp_du) = around();
+			PrimitiveSurface { normal: outward, shading_normal: outward, u, v: 1.0 - p.y / height, dp_du, dp_dv: Vector3::new(0.0, -height, 0.0) }
+		}
+		PrimitiveKind::Cone { radius, height } => {
+			let (rho, outward) = radial();
+			let k = radius / height;
+
+			if (rho - (radius - k * p.y)).abs() > p.y.abs() {
+				return planar(Vector3::new(0.0, -1.0, 0.0), *radius);
+			}
+
+			// The gradient of x^2 + z^2 - (radius - k y)^2, which at the apex is taken to point up
+			let (u, dp_du) = around();
+			let normal = Vector3::new(outward.x, k, outward.z).normalize();
+			PrimitiveSurface { normal, shading_normal: normal, u, v: 1.0 - p.y / height, dp_du, dp_dv: Vector3::new(outward.x * radius, -height, outward.z * radius) }
+		}
+		PrimitiveKind::Torus { major_radius, minor_radius } => {
+
+			// v goes once around the tube, starting and ending at its inner side
+			let (_, outward) = radial();
+			let normal = (*p - outward * *major_radius).normalize();
+			let (along, up) = (normal.dot(outward), normal.y);
+			let (u, dp_du) = around();
+			let dp_dv = (Vector3::new(0.0, along, 0.0) - outward * up) * (tau * minor_radius);
+			PrimitiveSurface { normal, shading_normal: normal, u, v: 0.5 + up.atan2(along) / tau, dp_du, dp_dv }
+		}
+	};
+}
+
+// Returns a point on the surface of a primitive for the two numbers u1 and u2 in [0, 1), the points being spread
+// uniformly over the area, together with the normal there and the area of the whole surface. Surfaces made of parts
+// (the faces of a box, the side and the caps of a cylinder) pick the part by u1 in proportion to its area. CSG solids,
+// SDFs and heightfields return None, see PrimitiveKind::can_sample_surface.
+pub fn sample_primitive_surface(kind: &PrimitiveKind, u1: f64, u2: f64) -> Option<(Vector3, Vector3, f64)> {
+	let (pi, tau) = (std::f64::consts::PI, std::f64::consts::TAU);
+	let around = |u: f64| Vector3::new((tau * u).sin(), 0.0, (tau * u).cos());
+
+	// The square root spreads the points of a disk evenly from its center to its rim
+	let disk = |radius: f64, y: f64, normal_y: f64, u1: f64, u2: f64| (around(u2) * (radius * u1.sqrt()) + Vector3::new(0.0, y, 0.0), Vector3::new(0.0, normal_y, 0.0));
+
+	let ((point, normal), area) = match kind {
+		PrimitiveKind::Sphere { radius } => {
+			let y = 1.0 - 2.0 * u1;
+			let normal = around(u2) * (1.0 - y * y).max(0.0).sqrt() + Vector3::new(0.0, y, 0.0);
+			((normal * *radius, normal), 2.0 * tau * radius * radius)
+		}
+		PrimitiveKind::Disk { radius } => (disk(*radius, 0.0, 1.0, u1, u2), pi * radius * radius),
+		PrimitiveKind::Quad { edge_u, edge_v } => {
+			let cross = edge_u.cross(*edge_v);
+			((*edge_u * u1 + *edge_v * u2, cross.normalize()), cross.length())
+		}
+		PrimitiveKind::Box { half_size } => {
+			let face_area = |axis: usize| 4.0 * half_size[(axis + 1) % 3] * half_size[(axis + 2) % 3];
+			let area = 2.0 * (face_area(0) + face_area(1) + face_area(2));
+			let mut pick = u1 * area;
+			let mut face = (2, -1.0);
+
+			// Two faces along every axis, the one on the plus side first
+			for (axis, side) in [(0, 1.0), (0, -1.0), (1, 1.0), (1, -1.0), (2, 1.0), (2, -1.0)] {
+				if pick < face_area(axis) {
+					face = (axis, side);
+					break;
+				}
+
+				pick -= face_area(axis);
+			}
+
+			let (axis, side) = face;
+			let (a, b) = ((axis + 1) % 3, (axis + 2) % 3);
+			let (mut point, mut normal) = (Vector3::zero(), Vector3::zero());
+			point[axis] = side * half_size[axis];
+			point[a] = (2.0 * (pick / face_area(axis)).min(1.0) - 1.0) * half_size[a];
+			point[b] = (2.0 * u2 - 1.0) * half_size[b];
+			normal[axis] = side;
+			((point, normal), area)
+		}
+		PrimitiveKind::Cylinder { radius, height } => {
+			let (side_area, cap_area) = (tau * radius * height, pi * radius * radius);
+			let pick = u1 * (side_area + 2.0 * cap_area);
+
+			let sample = if pick < side_area {
+				let outward = around(u2);
+				(outward * *radius + Vector3::new(0.0, height * pick / side_area, 0.0), outward)
+			} else if pick < side_area + cap_area {
+				disk(*radius, 0.0, -1.0, (pick - side_area) / cap_area, u2)
+			} else {
+				disk(*radius, *height, 1.0, ((pick - side_area - cap_area) / cap_area).min(1.0), u2)
+			};
+
+			(sample, side_area + 2.0 * cap_area)
+		}
+		PrimitiveKind::Cone { radius, height } => {
+			let (side_area, base_area) = (pi * radius * (radius * radius + height * height).sqrt(), pi * radius * radius);
+			let pick = u1 * (side_area + base_area);
+
+			// Like on a disk the points get denser towards the base, and the normal is the one of get_primitive_surface
+			let sample = if pick < side_area {
+				let (outward, fraction) = (around(u2), (pick / side_area).sqrt());
+				let point = outward * (radius * fraction) + Vector3::new(0.0, height * (1.0 - fraction), 0.0);
+				(point, Vector3::new(outward.x, radius / height, outward.z).normalize())
+			} else {
+				disk(*radius, 0.0, -1.0, ((pick - side_area) / base_area).min(1.0), u2)
+			};
+
+			(sample, side_area + base_area)
+		}
+		PrimitiveKind::Torus { major_radius, minor_radius } => {
+
+			// The outer side of the tube is larger than the inner one: the angle v around the tube has the density
+			// major_radius + minor_radius cos v, whose distribution function is inverted by Newton's method
+			let target = tau * major_radius * u2;
+			let mut v = tau * u2;
+
+			for _ in 0..MAX_NEWTON_STEPS {
+				v -= (major_radius * v + minor_radius * v.sin() - target) / (major_radius + minor_radius * v.cos());
+			}
+
+			let outward = around(u1);
+			let normal = outward * v.cos() + Vector3::new(0.0, v.sin(), 0.0);
+			((outward * *major_radius + normal * *minor_radius, normal), 2.0 * tau * pi * major_radius * minor_radius)
+		}
+		PrimitiveKind::Csg(_) | PrimitiveKind::Sdf(_) | PrimitiveKind::Heightfield(_) => return None
+	};
+
+	return Some((point, normal, area));
+}
+
+// Returns the distances at which the ray enters and leaves the box between min and max, also if it starts within
+pub fn get_ray_box_interval(ray: &Ray, min: &Vector3, max: &Vector3) -> Option<(f64, f64)> {
+	let mut near = f64::MIN;
+	let mut far = f64::MAX;
+
+	for axis in 0..3 {
+		let inverse_direction = 1.0 / ray.direction[axis];
+		let t0 = (min[axis] - ray.origin[axis]) * inverse_direction;
+		let t1 = (max[axis] - ray.origin[axis]) * inverse_direction;
+
+		// NaN (0 * infinity for rays within a slab plane) is ignored by min and max
+		near = near.max(t0.min(t1));
+		far = far.min(t0.max(t1));
+	}
+
+	return if near <= far { Some((near, far)) } else { None };
+}
+
+// Distance to the disk of the radius around the y axis at the height y
+fn get_ray_disk_distance(ray: &Ray, y: f64, radius: f64) -> Option<f64> {
+	let t = (y - ray.origin.y) / ray.direction.y;
+	let p = ray.origin + ray.direction * t;
+	return Some(t).filter(|t| t.is_finite() && p.x * p.x + p.z * p.z <= radius * radius);
+}
+
+// Coordinates of a point in the plane of a parallelogram along its edges, [0, 1] within it
+fn get_quad_uv(edge_u: &Vector3, edge_v: &Vector3, p: &Vector3) -> (f64, f64) {
+	let n = edge_u.cross(*edge_v);
+	let w = n / n.dot(n);
+	return (w.dot(p.cross(*edge_v)), w.dot(edge_u.cross(*p)));
+}
+
+// Real roots of a x^2 + b x + c, also if a is 0
+pub fn get_quadratic_roots(a: f64, b: f64, c: f64) -> Vec<f64> {
+	if a.abs() < 1e-12 {
+		return if b != 0.0 { vec![-c / b] } else { Vec::new() };
+	}
+
+	let discriminant = b * b - 4.0 * a * c;
+
+	if discriminant < 0.0 {
+		return Vec::new();
+	}
+
+	// Avoids the cancellation of -b + sqrt(discriminant) for b of about the same size
+	let q = -0.5 * (b + b.signum() * discriminant.sqrt());
+
+	if q == 0.0 {
+		return vec![0.0];
+	}
+
+	return vec![q / a, c / q];
+}
+
+// The torus (x^2 + y^2 + z^2 + R^2 - r^2)^2 = 4 R^2 (x^2 + z^2) is a quartic in the distance along the ray. Its
+// roots are isolated within the part of the ray within the bounds of the torus, with the ray origin moved to where
+// that part starts, which keeps the coefficients well conditioned.
+fn get_ray_torus_crossings(ray: &Ray, major_radius: f64, minor_radius: f64) -> Vec<f64> {
+	let extent = Vector3::new(major_radius + minor_radius, minor_radius, major_radius + minor_radius);
+
+	let Some((start, end)) = get_ray_box_interval(ray, &-extent, &extent) else {
+		return Vec::new();
+	};
+
+	let (o, d) = (ray.origin + ray.direction * start, ray.direction);
+	let (r2, m2) = (major_radius * major_radius, minor_radius * minor_radius);
+	let g0 = o.dot(o) + r2 - m2;
+	let g1 = 2.0 * o.dot(d);
+	let a2 = d.x * d.x + d.z * d.z;
+	let a1 = 2.0 * (o.x * d.x + o.z * d.z);
+	let a0 = o.x * o.x + o.z * o.z;
+
+	let coefficients = [g0 * g0 - 4.0 * r2 * a0, 2.0 * g0 * g1 - 4.0 * r2 * a1, g1 * g1 + 2.0 * g0 - 4.0 * r2 * a2, 2.0 * g1, 1.0];
+	return get_polynomial_roots(&coefficients, 0.0, end - start).into_iter().map(|t| start + t).collect();
+}
+
+// Returns the roots of the polynomial with the coefficients (lowest degree first) within [from, to] in ascending
+// order. Between two roots of the derivative the polynomial is monotonic, so it has a root there only if it changes
+// its sign, which bisection then finds. Roots where the polynomial touches 0 without changing its sign are missed.
+pub fn get_polynomial_roots(coefficients: &[f64], from: f64, to: f64) -> Vec<f64> {
+	let evaluate = |x: f64| coefficients.iter().rev().fold(0.0, |sum, c| sum * x + c);
+
+	if coefficients.len() < 2 {
+		return Vec::new();
+	}
+
+	let derivative: Vec<f64> = coefficients.iter().enumerate().skip(1).map(|(i, c)| c * i as f64).collect();
+	let mut bounds = vec![from];
+	bounds.extend(get_polynomial_roots(&derivative, from, to));
+	bounds.push(to);
+
+	let mut roots = Vec::new();
+
+	for interval in bounds.windows(2) {
+		let (mut a, mut b) = (interval[0], interval[1]);
+		let negative_at_a = evaluate(a) < 0.0;
+
+		if negative_at_a == (evaluate(b) < 0.0) {
+			continue;
+		}
+
+		for _ in 0..MAX_BISECTIONS {
+			let middle = 0.5 * (a + b);
+
+			if middle <= a || middle >= b {
+				break;
+			}
+
+			if (evaluate(middle) < 0.0) == negative_at_a {
+				a = middle;
+			} else {
+				b = middle;
+			}
+		}
+
+		roots.push(0.5 * (a + b));
+	}
+
+	return roots;
+}
@@ -1,8 +1,18 @@
 
 // Import requirements
-use crate::fundamentals::vec3::*;
 use crate::fundamentals::light::*;
 use crate::fundamentals::geometry::*;
+use crate::fundamentals::bump::*;
+use crate::fundamentals::texture::*;
+use crate::fundamentals::instance::*;
+use crate::fundamentals::primitive::*;
+use crate::fundamentals::math::vector::*;
+use crate::fundamentals::math::transform::*;
+use super::stats::*;
+use super::primitives::*;
+
+// Intersections nearer than this to the ray origin are ignored, so rays leaving a surface don't hit it again
+const MIN_DISTANCE: f64 = 0.01;
 
 // Light rays, backward rays, all the rays
 pub struct Ray {
@@ -13,9 +23,20 @@ pub struct Ray {
 // Result type for ray-surface-intersection calculation
 pub struct RaySurfaceIntersection {
 	pub position: Vector3, 
+	// Shading normal, the geometric normal perturbed by the surface detail (normal or bump map) of the primitive
 	pub normal: Vector3,
+	pub geometric_normal: Vector3,
 	pub distance: f64,
-	pub material_color: LightColor
+	// Color of the surface at the intersection, evaluated from the texture of the primitive
+	pub material_color: LightColor,
+	// Light emitted by the surface
+	pub emission: LightColor,
+	// Texture coordinates of the intersection
+	pub u: f64,
+	pub v: f64,
+	// Id of the primitive within the space (see PrimitiveRef::id), set by get_nearest_surface_intersection_for_ray
+	// and -1 before
+	pub primitive_id: i32
 }
 
 // Result type for ray-surface-intersection calculation
@@ -26,10 +47,95 @@ pub struct RaySurfaceIntersections {
 	pub far: RaySurfaceIntersection
 }
 
+// What a ray is traced for, deciding which primitives it sees (see Visibility)
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum RayKind {
+	Camera,
+	Shadow,
+	Indirect
+}
+
+impl RayKind {
+
+	pub fn sees(&self, visibility: &Visibility) -> bool {
+		return match self {
+			RayKind::Camera => visibility.camera,
+			RayKind::Shadow => visibility.shadow,
+			RayKind::Indirect => visibility.indirect
+		};
+	}
+}
+
+// A primitive of the space, as found by get_nearest_primitive_for_ray
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum PrimitiveRef {
+	Sphere(usize),
+	Plane(usize),
+	Primitive(usize),
+	// The instance and the sphere within its shape
+	InstanceSphere(usize, usize)
+}
+
+impl PrimitiveRef {
+
+	// Returns the id of the primitive: the spheres first, then the planes, then the other primitives, then one id per
+	// instance
+	pub fn id(&self, space: &Space) -> usize {
+		return match self {
+			PrimitiveRef::Sphere(i) => *i,
+			PrimitiveRef::Plane(i) => space.spheres.len() + i,
+			PrimitiveRef::Primitive(i) => space.spheres.len() + space.planes.len() + i,
+			PrimitiveRef::InstanceSphere(i, _) => space.spheres.len() + space.planes.len() + space.primitives.primitives.len() + i
+		};
+	}
+}
+
 // Computes the two intersections of an ray and a sphere, if existing. Otherwise returns. Ray must be normalized!
 pub fn get_ray_sphere_intersections(ray: &Ray, sphere: &Sphere) -> Option<RaySurfaceIntersections> {
-	let ray_origin_to_sphere_center = v3_delta(&sphere.center, &ray.origin);
-	let projection_onto_normalized_ray_len = v3_dot_product(&ray.direction, &ray_origin_to_sphere_center);
+	let (near_distance_to_ray_origin, far_distance_to_ray_origin) = get_ray_sphere_distances(ray, sphere)?;
+	let near = ray.origin + ray.direction * near_distance_to_ray_origin; 
+	let far = ray.origin + ray.direction * far_distance_to_ray_origin;
+
+	return Some(
+		RaySurfaceIntersections {
+			near: get_sphere_intersection(sphere, &sphere.material_color, &near, near_distance_to_ray_origin), 
+			far: get_sphere_intersection(sphere, &sphere.material_color, &far, far_distance_to_ray_origin)
+		}
+	);
+}
+
+// Like get_ray_sphere_intersections for the near intersection only, without evaluating the texture at the far one
+pub fn get_ray_sphere_near_intersection(ray: &Ray, sphere: &Sphere) -> Option<RaySurfaceIntersection> {
+	let (near_distance_to_ray_origin, _) = get_ray_sphere_distances(ray, sphere)?;
+	let near = ray.origin + ray.direction * near_distance_to_ray_origin;
+	return Some(get_sphere_intersection(sphere, &sphere.material_color, &near, near_distance_to_ray_origin));
+}
+
+// Builds the intersection at a point on the surface of a sphere, colored by the texture
+fn get_sphere_intersection(sphere: &Sphere, texture: &Texture, position: &Vector3, distance: f64) -> RaySurfaceIntersection {
+	let normal = (*position - sphere.center).normalize();
+	let (u, v) = get_sphere_uv(&normal);
+	let point = *position - sphere.center;
+	let frame = get_sphere_tangent_frame(sphere, &normal);
+
+	return RaySurfaceIntersection {
+		position: *position,
+		normal: sphere.surface_detail.shading_normal(&frame, u, v, &point),
+		geometric_normal: normal,
+		distance,
+		material_color: texture.evaluate(u, v, &point),
+		emission: LightColor { r: 0.0, g: 0.0, b: 0.0 },
+		u,
+		v,
+		primitive_id: -1
+	};
+}
+
+// Computes the distances of the two intersections of a ray and a sphere from the ray origin, without anything else
+// of the intersections. Ray must be normalized!
+pub fn get_ray_sphere_distances(ray: &Ray, sphere: &Sphere) -> Option<(f64, f64)> {
+	let ray_origin_to_sphere_center = sphere.center - ray.origin;
+	let projection_onto_normalized_ray_len = ray.direction.dot(ray_origin_to_sphere_center);
 
 	// If the center of the sphere is behind the ray origin we handle it as non-hit for now. For later when rays might
 	// have origins within a sphere we have to get rid of this optimization.
@@ -37,8 +143,8 @@ pub fn get_ray_sphere_intersections(ray: &Ray, sphere: &Sphere) -> Option<RaySur
 		return None;
 	}
 
-	let projection_onto_normalized_ray = v3_sum(&ray.origin, &v3_scale(&ray.direction, projection_onto_normalized_ray_len)); 
-	let sphere_center_to_projection = v3_delta(&projection_onto_normalized_ray, &sphere.center);
+	let projection_onto_normalized_ray = ray.origin + ray.direction * projection_onto_normalized_ray_len; 
+	let sphere_center_to_projection = projection_onto_normalized_ray - sphere.center;
 	let sphere_radius_squared = sphere.radius * sphere.radius; 
 	let k = sphere_center_to_projection;
 	let sphere_center_to_projection_len_squared = k.x*k.x + k.y*k.y + k.z*k.z;
@@ -51,25 +157,86 @@ pub fn get_ray_sphere_intersections(ray: &Ray, sphere: &Sphere) -> Option<RaySur
 	let distance_from_projection_to_hits = (sphere_radius_squared - sphere_center_to_projection_len_squared).sqrt(); 
 	let near_distance_to_ray_origin = projection_onto_normalized_ray_len - distance_from_projection_to_hits;
 	let far_distance_to_ray_origin = projection_onto_normalized_ray_len + distance_from_projection_to_hits;
-	let near = v3_sum(&ray.origin, &v3_scale(&ray.direction, near_distance_to_ray_origin)); 
-	let far = v3_sum(&ray.origin, &v3_scale(&ray.direction, far_distance_to_ray_origin));
 
-	let near_normal = v3_normalize( &v3_delta(&near, &sphere.center));
-	let far_normal = v3_normalize( &v3_delta(&far, &sphere.center));
-		
+	return Some((near_distance_to_ray_origin, far_distance_to_ray_origin));
+}
+
+// Spherical mapping of the point of a sphere with the specified normal: u goes once around the y axis, starting
+// and ending at the minus z side, v from the top (0) to the bottom (1)
+pub fn get_sphere_uv(normal: &Vector3) -> (f64, f64) {
+	let u = 0.5 + normal.x.atan2(normal.z) / (2.0 * std::f64::consts::PI);
+	let v = normal.y.clamp(-1.0, 1.0).acos() / std::f64::consts::PI;
+	return (u, v);
+}
+
+// Derivatives of the spherical mapping of get_sphere_uv at the point of a sphere with the specified normal. At the
+// poles, where u is undefined, v is derived like for u = 0.5.
+pub fn get_sphere_tangent_frame(sphere: &Sphere, normal: &Vector3) -> TangentFrame {
+	let (dp_du, dp_dv) = get_sphere_derivatives(sphere.radius, normal);
+	return TangentFrame { dp_du, dp_dv, normal: *normal };
+}
+
+// Derivatives of the point of a sphere of the radius with the specified normal by u and v of get_sphere_uv
+pub fn get_sphere_derivatives(radius: f64, normal: &Vector3) -> (Vector3, Vector3) {
+	let pi = std::f64::consts::PI;
+	let n = normal;
+	let sin_theta = (n.x * n.x + n.z * n.z).sqrt();
+	let (sin_phi, cos_phi) = if sin_theta > 1e-12 { (n.x / sin_theta, n.z / sin_theta) } else { (0.0, 1.0) };
+
+	let dp_du = Vector3 { x: n.z, y: 0.0, z: -n.x } * (2.0 * pi * radius);
+	let dp_dv = Vector3 { x: n.y * sin_phi, y: -sin_theta, z: n.y * cos_phi } * (pi * radius);
+	return (dp_du, dp_dv);
+}
+
+// Planar projection of a point of a plane: the distances from the plane center along the two tangents of the plane,
+// in units of space
+pub fn get_plane_uv(plane: &Plane, position: &Vector3) -> (f64, f64) {
+	let (tangent_u, tangent_v) = get_plane_tangents(&plane.normal);
+	let offset = *position - plane.center;
+	return (offset.dot(tangent_u), offset.dot(tangent_v));
+}
+
+// Two tangents of a plane, perpendicular to each other and to the normal. For walls v points down and for floors and
+// ceilings v points towards plus z, u pointing to the right when looking at the front of the plane, so that images
+// appear upright and unmirrored when looked at from the camera.
+pub fn get_plane_tangents(normal: &Vector3) -> (Vector3, Vector3) {
+	let n = normal.normalize();
+	let reference = if n.y.abs() < 0.9 { Vector3 { x: 0.0, y: -1.0, z: 0.0 } } else { Vector3 { x: 0.0, y: 0.0, z: 1.0 } };
+	let tangent_u = n.cross(reference).normalize();
+	let tangent_v = tangent_u.cross(n);
+	return (tangent_u, tangent_v);
+}
+
+// Computes the intersection of an ray and a plane. 
+pub fn get_ray_plane_intersection(ray: &Ray, plane: &Plane) -> Option<RaySurfaceIntersection> {
+	let l = get_ray_plane_distance(ray, plane)?;
+	let hit_position = ray.origin + ray.direction * l;
+	let (u, v) = get_plane_uv(plane, &hit_position);
+	let point = hit_position - plane.center;
+	let (tangent_u, tangent_v) = get_plane_tangents(&plane.normal);
+	let frame = TangentFrame { dp_du: tangent_u, dp_dv: tangent_v, normal: plane.normal };
+
 	return Some(
-		RaySurfaceIntersections {
-			near: RaySurfaceIntersection {position: near, normal: near_normal, distance: near_distance_to_ray_origin, material_color: sphere.material_color}, 
-			far: RaySurfaceIntersection {position: far, normal: far_normal, distance: far_distance_to_ray_origin, material_color: sphere.material_color}
+		RaySurfaceIntersection {
+			position: hit_position, 
+			normal: plane.surface_detail.shading_normal(&frame, u, v, &point),
+			geometric_normal: plane.normal,
+			distance: l,
+			material_color: plane.material_color.evaluate(u, v, &point),
+			emission: LightColor { r: 0.0, g: 0.0, b: 0.0 },
+			u,
+			v,
+			primitive_id: -1
 		}
 	);
 }
 
-// Computes the intersection of an ray and a plane. 
-pub fn get_ray_plane_intersection(ray: &Ray, plane: &Plane) -> Option<RaySurfaceIntersection> {
-	let plane_center_to_ray_origin = v3_delta(&ray.origin, &plane.center);
-	let dot_a = v3_dot_product(&plane_center_to_ray_origin, &plane.normal);	
-	let dot_b = v3_dot_product(&ray.direction, &plane.normal);	
+// Computes the distance of the intersection of a ray and a plane from the ray origin
+#[allow(non_snake_case)]
+pub fn get_ray_plane_distance(ray: &Ray, plane: &Plane) -> Option<f64> {
+	let plane_center_to_ray_origin = ray.origin - plane.center;
+	let dot_a = plane_center_to_ray_origin.dot(plane.normal);	
+	let dot_b = ray.direction.dot(plane.normal);	
 
 	// In this case the ray is parallel to the plane and there is no hit (we ignore the special case that the ray is within the plane)
 	if dot_b == 0.0 {
@@ -83,16 +250,7 @@ pub fn get_ray_plane_intersection(ray: &Ray, plane: &Plane) -> Option<RaySurface
 		return None;
 	}
 
-	let hit_position = v3_sum(&ray.origin, &v3_scale(&ray.direction, L));
-
-	return Some(
-		RaySurfaceIntersection {
-			position: hit_position, 
-			normal: plane.normal.clone(),
-			distance: L,
-			material_color: plane.material_color
-		}
-	);
+	return Some(L);
 
 	/*
 	CALCULATION: 
@@ -115,37 +273,174 @@ pub fn get_ray_plane_intersection(ray: &Ray, plane: &Plane) -> Option<RaySurface
 	*/
 }
 
+// Takes the ray into the object space of the transform. The direction is normalized again, distances along the
+// object space ray are divided by the returned factor to get the distances along the ray.
+pub fn get_object_space_ray(ray: &Ray, transform: &Transform) -> (Ray, f64) {
+	let direction = transform.inverse.transform_vector(ray.direction);
+	let scale = direction.length();
+	let origin = transform.inverse.transform_point(Point3::from(ray.origin)).to_vector();
+	return (Ray { origin, direction: direction / scale }, scale);
+}
+
+// Computes the intersection of a ray with a sphere of the shape of an instance. The intersection is computed in
+// object space and taken back to world space, the normals with the inverse transpose of the transform.
+pub fn get_ray_instance_sphere_intersection(ray: &Ray, instance: &Instance, sphere: &Sphere) -> Option<RaySurfaceIntersection> {
+	let (object_ray, scale) = get_object_space_ray(ray, &instance.transform);
+	let (object_distance, _) = get_ray_sphere_distances(&object_ray, sphere)?;
+	let object_position = object_ray.origin + object_ray.direction * object_distance;
+	let texture = instance.material_override.as_ref().unwrap_or(&sphere.material_color);
+	let mut hit = get_sphere_intersection(sphere, texture, &object_position, object_distance);
+
+	let to_world = |n: &Vector3| instance.transform.apply_normal(Normal3::from(*n)).normalize().to_vector();
+	hit.normal = to_world(&hit.normal);
+	hit.geometric_normal = to_world(&hit.geometric_normal);
+	hit.distance = object_distance / scale;
+	hit.position = ray.origin + ray.direction * hit.distance;
+	return Some(hit);
+}
+
+// Computes the intersection of a ray with a primitive in its object space, taken back to world space like for the
+// spheres of instances. Disks, quads and heightfields face the ray, so they are seen from both sides.
+pub fn get_ray_primitive_intersection(ray: &Ray, primitive: &Primitive) -> Option<RaySurfaceIntersection> {
+	let (object_ray, scale) = get_object_space_ray(ray, &primitive.transform);
+	let (object_distance, surface) = get_ray_primitive_hit(&object_ray, &primitive.kind, MIN_DISTANCE * scale)?;
+	let point = object_ray.origin + object_ray.direction * object_distance;
+
+	let is_thin = matches!(primitive.kind, PrimitiveKind::Disk { .. } | PrimitiveKind::Quad { .. } | PrimitiveKind::Heightfield(_));
+	let side = if is_thin && surface.normal.dot(object_ray.direction) > 0.0 { -1.0 } else { 1.0 };
+	let normal = surface.normal * side;
+	let frame = TangentFrame { dp_du: surface.dp_du, dp_dv: surface.dp_dv, normal: surface.shading_normal * side };
+	let shading_normal = primitive.surface_detail.shading_normal(&frame, surface.u, surface.v, &point);
+
+	let to_world = |n: &Vector3| primitive.transform.apply_normal(Normal3::from(*n)).normalize().to_vector();
+	let distance = object_distance / scale;
+
+	return Some(RaySurfaceIntersection {
+		position: ray.origin + ray.direction * distance,
+		normal: to_world(&shading_normal),
+		geometric_normal: to_world(&normal),
+		distance,
+		material_color: primitive.material_color.evaluate(surface.u, surface.v, &point),
+		emission: primitive.emission,
+		u: surface.u,
+		v: surface.v,
+		primitive_id: -1
+	});
+}
+
 // This function returns the nearest surface intersection for a ray. If there is no intersection, None is returned.
-pub fn get_nearest_surface_intersection_for_ray(ray: &Ray, space: &Space) -> Option<RaySurfaceIntersection> {
+// The primitives are only compared by distance, the intersection (including its texture color) is computed for the
+// nearest one only. Primitives hidden from the kind of ray are skipped.
+pub fn get_nearest_surface_intersection_for_ray(ray: &Ray, space: &Space, kind: RayKind) -> Option<RaySurfaceIntersection> {
+	let (primitive, _) = get_nearest_primitive_for_ray(ray, space, kind)?;
+
+	let mut nearest_hit = match primitive {
+		PrimitiveRef::Sphere(i) => get_ray_sphere_near_intersection(ray, &space.spheres[i])?,
+		PrimitiveRef::Plane(i) => get_ray_plane_intersection(ray, &space.planes[i])?,
+		PrimitiveRef::Primitive(i) => get_ray_primitive_intersection(ray, &space.primitives.primitives[i])?,
+		PrimitiveRef::InstanceSphere(i, j) => {
+			let instance = &space.instances.instances[i];
+			get_ray_instance_sphere_intersection(ray, instance, &instance.shape.spheres[j])?
+		}
+	};
 
-	let mut nearest_hit: Option<RaySurfaceIntersection> = None;
+	nearest_hit.primitive_id = primitive.id(space) as i32;
+	return Some(nearest_hit); 
+}
+
+// Returns the nearest primitive hit by the ray and its distance. Enough for shadow rays, which don't need the
+// intersection itself. Spheres and planes are tested one by one, the other primitives through a hierarchy over their
+// world space bounds and instances through the two level hierarchy: the top level over the instances in world space,
+// the bottom level over the spheres of a shape in its object space.
+pub fn get_nearest_primitive_for_ray(ray: &Ray, space: &Space, kind: RayKind) -> Option<(PrimitiveRef, f64)> {
+	let mut nearest: Option<(PrimitiveRef, f64)> = None;
 	let mut nearest_hit_distance: f64 = f64::MAX;	
 
-	for sphere in &space.spheres {
-		match get_ray_sphere_intersections(ray, &sphere) {
-			Some(d) => {
-				if d.near.distance < nearest_hit_distance {
-					nearest_hit_distance = d.near.distance;
-					nearest_hit = Some(d.near);
-				}
+	for (i, sphere) in space.spheres.iter().enumerate().filter(|(_, s)| kind.sees(&s.visibility)) {
+		count_intersection_tests(1);
+
+		if let Some((near_distance, _)) = get_ray_sphere_distances(ray, sphere) {
+			if near_distance < nearest_hit_distance {
+				nearest_hit_distance = near_distance;
+				nearest = Some((PrimitiveRef::Sphere(i), near_distance));
 			}
-			None => {
+		}
+	}
+
+	for (i, plane) in space.planes.iter().enumerate().filter(|(_, p)| kind.sees(&p.visibility)) {
+		count_intersection_tests(1);
+
+		if let Some(distance) = get_ray_plane_distance(ray, plane) {
+			if distance < nearest_hit_distance {
+				nearest_hit_distance = distance;
+				nearest = Some((PrimitiveRef::Plane(i), distance));
 			}
 		}
 	}
 
-	for plane in &space.planes {
-		match get_ray_plane_intersection(ray, &plane) {
-			Some(d) => {
-				if d.distance < nearest_hit_distance {
-					nearest_hit_distance = d.distance;
-					nearest_hit = Some(d);
-				}
+	let primitives = &space.primitives;
+
+	primitives.bvh.traverse(&ray.origin, &ray.direction, nearest_hit_distance, |i| {
+		let primitive = &primitives.primitives[i];
+
+		if !kind.sees(&primitive.visibility) {
+			return None;
+		}
+
+		count_intersection_tests(1);
+		let (object_ray, scale) = get_object_space_ray(ray, &primitive.transform);
+		let distance = get_ray_primitive_distance(&object_ray, &primitive.kind, MIN_DISTANCE * scale)? / scale;
+
+		if distance < nearest_hit_distance {
+			nearest_hit_distance = distance;
+			nearest = Some((PrimitiveRef::Primitive(i), distance));
+		}
+
+		return Some(distance);
+	});
+
+	let instances = &space.instances;
+
+	instances.bvh.traverse(&ray.origin, &ray.direction, nearest_hit_distance, |i| {
+		let instance = &instances.instances[i];
+
+		if !kind.sees(&instance.visibility) {
+			return None;
+		}
+
+		// The instance counts as a test of its own, like a primitive, before the tests of its spheres
+		count_intersection_tests(1);
+		let shape = &instance.shape;
+		let (object_ray, scale) = get_object_space_ray(ray, &instance.transform);
+		let mut nearest_in_shape: Option<(usize, f64)> = None;
+
+		shape.bvh.traverse(&object_ray.origin, &object_ray.direction, nearest_hit_distance * scale, |j| {
+			count_intersection_tests(1);
+			let sphere = &shape.spheres[j];
+
+			if !kind.sees(&sphere.visibility) {
+				return None;
 			}
-			None => {
+
+			let (near_distance, _) = get_ray_sphere_distances(&object_ray, sphere)?;
+
+			if nearest_in_shape.is_none_or(|(_, d)| near_distance < d) {
+				nearest_in_shape = Some((j, near_distance));
 			}
+
+			return Some(near_distance);
+		});
+
+		let (j, object_distance) = nearest_in_shape?;
+		let distance = object_distance / scale;
+
+		if distance < nearest_hit_distance {
+			nearest_hit_distance = distance;
+			nearest = Some((PrimitiveRef::InstanceSphere(i, j), distance));
 		}
-	}
 
-	return nearest_hit; 
+		return Some(distance);
+	});
+
+	return nearest;
 }
@@ -3,79 +3,564 @@
 use crate::fundamentals::vec3::*;
 use crate::fundamentals::light::*;
 use crate::fundamentals::geometry::*;
+use crate::fundamentals::random::*;
 use super::rays::*;
 use super::tracing::*;
-use rand::prelude::*;
+use super::sampling::*;
+use super::adaptive::*;
+use super::filter::*;
+use super::film::*;
+use super::aov::*;
+use super::stats::*;
+use super::cancel::*;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicI32, Ordering};
 use std::thread;
 use std::sync::mpsc;
-use std::time::Instant;
-
-// Number of additional randomly chosen sub-pixel rays
-const RANDOM_SUBPIXEL_RAYS: i32 = 32;
-const fRANDOM_SUBPIXEL_RAYS: f64 = 32.0;
+use std::time::{Duration, Instant};
 
 // Perspective camera, having looking into the minus z direction, having the up vector looking into the y direction
+#[derive(Debug)]
 pub struct CameraZ {
 	pub location: Vector3, 
 	pub distance_to_image_plane: f64,
 	pub image_plane_width: f64
 }
 
-// Main rendering function. Renders the full image. The thread_count=N specifies how many threads will be used. 
-// The image plane will be split in N non-overlapping intervals, each being computed on a dedicated thread. 
-pub fn render(space: Space, camera: CameraZ, width: i32, height: i32, thread_count: i32) -> Vec<LightColor> {
+// Parameters of a rendering
+#[derive(Copy, Clone)]
+pub struct RenderSettings {
+	pub width: i32,
+	pub height: i32,
+	// Number of threads rendering the tiles of the image
+	pub thread_count: i32,
+	// Height in rows of the tiles (horizontal bands) the image is split into. Wide filters trace the rows within their
+	// reach around every tile as well, so tiles should be clearly higher than that.
+	pub tile_height: i32,
+	// All random numbers are derived from this seed
+	pub seed: u64,
+	// Number of rays per pixel (monte carlo sampling, average value is taken)
+	pub samples_per_pixel: u32,
+	// Distribution of the sub-pixel positions and global illumination directions
+	pub sampler: SamplerType,
+	// If set, the number of rays per pixel depends on the noise of the pixel and samples_per_pixel is not used
+	pub adaptive: Option<AdaptiveSampling>,
+	// Reconstruction filter weighting the samples into the pixels around them
+	pub filter: PixelFilter,
+	// Also compute the auxiliary buffers (AOVs) of the first hit of the camera rays
+	pub aovs: bool,
+	// If set, only the pixels of this region are rendered, and the results contain only them. The camera projection is
+	// the one of the full image.
+	pub region: Option<PixelRegion>
+}
 
-	// First divide the height of the image into threadCount non-overlapping intervals 
-	let interval_size = height / thread_count; 
+// Rectangle of pixels, y pointing down like the rows
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct PixelRegion {
+	pub x: i32,
+	pub y: i32,
+	pub width: i32,
+	pub height: i32
+}
 
-	let mut join_handles: Vec<thread::JoinHandle<()>> = Vec::new();
-	let mut channel_receivers: Vec<mpsc::Receiver<Vec<LightColor>>> = Vec::new();
-	let mut pixels: Vec<LightColor> = Vec::new();
+impl PixelRegion {
+
+	// Returns the part of an image of the specified size given in fractions of its width and height, from (x0, y0) to
+	// (x1, y1). Pixels are included if their center is inside.
+	pub fn from_crop(x0: f64, y0: f64, x1: f64, y1: f64, width: i32, height: i32) -> PixelRegion {
+		let column = |f: f64| ((f * (width as f64) - 0.5).ceil() as i32).clamp(0, width);
+		let row = |f: f64| ((f * (height as f64) - 0.5).ceil() as i32).clamp(0, height);
+		return PixelRegion { x: column(x0), y: row(y0), width: column(x1) - column(x0), height: row(y1) - row(y0) };
+	}
+
+	// Returns an image of the specified size without the border of the specified widths in pixels
+	pub fn from_border(left: i32, top: i32, right: i32, bottom: i32, width: i32, height: i32) -> PixelRegion {
+		return PixelRegion { x: left, y: top, width: width - left - right, height: height - top - bottom };
+	}
+
+	// Whether the region is not empty and lies within an image of the specified size
+	pub fn is_within(&self, width: i32, height: i32) -> bool {
+		return self.x >= 0 && self.y >= 0 && self.width > 0 && self.height > 0 && self.x + self.width <= width && self.y + self.height <= height;
+	}
+
+	pub fn contains(&self, x: i32, y: i32) -> bool {
+		return x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height;
+	}
+}
+
+impl RenderSettings {
+
+	// Returns the default settings for an image of the specified size
+	pub fn new(width: i32, height: i32) -> RenderSettings {
+		return RenderSettings {
+			width,
+			height,
+			thread_count: 8,
+			tile_height: 32,
+			seed: 0,
+			samples_per_pixel: 33,
+			sampler: SamplerType::Independent,
+			adaptive: None,
+			filter: PixelFilter::new(FilterType::Box),
+			aovs: false,
+			region: None
+		};
+	}
+
+	// Returns the region that is rendered, the whole image if no region is set
+	pub fn render_region(&self) -> PixelRegion {
+		return self.region.unwrap_or(PixelRegion { x: 0, y: 0, width: self.width, height: self.height });
+	}
+
+	// Returns the first row and the row count of all tiles, covering the rows of the rendered region
+	pub fn tiles(&self) -> Vec<(i32, i32)> {
+		let region = self.render_region();
+		let tile_height = self.tile_height.max(1);
+		return (region.y..region.y + region.height).step_by(tile_height as usize).map(|y| (y, tile_height.min(region.y + region.height - y))).collect();
+	}
+
+	// Returns the minimum and maximum number of samples of a pixel
+	pub fn sample_range(&self) -> (u32, u32) {
+		return match self.adaptive {
+			Some(a) => (a.min_samples.max(1), a.max_samples.max(a.min_samples).max(1)),
+			None => (self.samples_per_pixel, self.samples_per_pixel)
+		};
+	}
+}
+
+// The rendered image (the pixels of the rendered region, row by row) together with the number of rays each pixel has
+// received, the AOVs (if requested) and the statistics of the rendering
+pub struct RenderResult {
+	pub pixels: Vec<LightColor>,
+	pub sample_counts: Vec<u32>,
+	pub aovs: Vec<AovBuffer>,
+	pub stats: RenderStats,
+	pub status: RenderStatus
+}
+
+// Whether a rendering has been finished or stopped early
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum RenderStatus {
+	Completed,
+	Cancelled
+}
+
+impl RenderResult {
+
+	// Returns the buffer of the specified AOV, if it has been rendered
+	pub fn aov(&self, aov_type: AovType) -> Option<&AovBuffer> {
+		return self.aovs.iter().find(|a| a.aov_type == aov_type);
+	}
+
+	// Returns the full image of the specified size with the result of the region placed in it, the other pixels being
+	// black with a sample count of 0
+	pub fn expand(&self, width: i32, height: i32, region: &PixelRegion) -> RenderResult {
+		let index = |i: usize| {
+			let (x, y) = ((i as i32) % width, (i as i32) / width);
+			if region.contains(x, y) { Some(((y - region.y) * region.width + x - region.x) as usize) } else { None }
+		};
+
+		let black = LightColor { r: 0.0, g: 0.0, b: 0.0 };
+		let pixel_count = (width * height) as usize;
+
+		return RenderResult {
+			pixels: (0..pixel_count).map(|i| index(i).map_or(black, |j| self.pixels[j])).collect(),
+			sample_counts: (0..pixel_count).map(|i| index(i).map_or(0, |j| self.sample_counts[j])).collect(),
+			aovs: self.aovs.iter().map(|a| AovBuffer { aov_type: a.aov_type, pixels: (0..pixel_count).map(|i| index(i).map_or(black, |j| a.pixels[j])).collect() }).collect(),
+			stats: self.stats,
+			status: self.status
+		};
+	}
+}
+
+// Sums of all samples rendered so far for a range of rows of the rendered region, from which the image is resolved. The passes of a
+// progressive rendering are added to it one after the other.
+#[derive(Clone)]
+pub struct FrameBuffer {
+	pub film: Film,
+	pub sample_counts: Vec<u32>,
+	// One per pixel, empty if the AOVs are not rendered
+	pub aovs: Vec<AovAccumulator>,
+	pub stats: RenderStats,
+	// False if rows or tiles were skipped because the rendering was cancelled
+	pub complete: bool
+}
+
+impl FrameBuffer {
+
+	// Creates an empty buffer for y_count rows starting at row y_start, covering the columns of the rendered region
+	pub fn new(settings: &RenderSettings, y_start: i32, y_count: i32) -> FrameBuffer {
+		let region = settings.render_region();
+		let pixel_count = (region.width * y_count) as usize;
+
+		return FrameBuffer {
+			film: Film::new(region.x, region.width, y_start, y_count, settings.filter),
+			sample_counts: vec![0; pixel_count],
+			aovs: if settings.aovs { vec![AovAccumulator::new(); pixel_count] } else { Vec::new() },
+			stats: RenderStats::new(),
+			complete: true
+		};
+	}
+
+	// Creates an empty buffer for all rows of the rendered region
+	pub fn for_region(settings: &RenderSettings) -> FrameBuffer {
+		let region = settings.render_region();
+		return FrameBuffer::new(settings, region.y, region.height);
+	}
+
+	// Adds the samples of a buffer covering some of the rows of this one
+	pub fn add(&mut self, other: &FrameBuffer) {
+		let offset = ((other.film.y_start - self.film.y_start) * self.film.x_count) as usize;
+
+		for (i, p) in other.film.pixels.iter().enumerate() {
+			let pixel = &mut self.film.pixels[offset + i];
+			pixel.color_sum.r += p.color_sum.r;
+			pixel.color_sum.g += p.color_sum.g;
+			pixel.color_sum.b += p.color_sum.b;
+			pixel.weight_sum += p.weight_sum;
+		}
+
+		for (i, count) in other.sample_counts.iter().enumerate() {
+			self.sample_counts[offset + i] += count;
+		}
+
+		for (i, aov) in other.aovs.iter().enumerate() {
+			self.aovs[offset + i].merge(aov);
+		}
+
+		self.stats.add(&other.stats);
+		self.complete &= other.complete;
+	}
+
+	// Returns the image of the samples so far, cancelled if some of them were skipped
+	pub fn resolve(&self) -> RenderResult {
+		let aovs = if self.aovs.is_empty() { Vec::new() } else {
+			ALL_AOV_TYPES.iter().map(|&aov_type| AovBuffer { aov_type, pixels: self.aovs.iter().map(|a| a.resolve(aov_type)).collect() }).collect()
+		};
+
+		return RenderResult { pixels: self.film.resolve(), sample_counts: self.sample_counts.clone(), aovs, stats: self.stats,
+			status: if self.complete { RenderStatus::Completed } else { RenderStatus::Cancelled } };
+	}
+}
+
+// Parameters of a progressive rendering, which renders the image in passes and shows the intermediate results
+#[derive(Copy, Clone)]
+pub struct ProgressiveSettings {
+	// Number of rays per pixel added by every pass
+	pub samples_per_pass: u32,
+	// Stop when every pixel has received this many rays
+	pub target_samples: Option<u32>,
+	// Stop before the pass that would exceed this time
+	pub time_budget: Option<Duration>,
+	// Minimum time between two snapshots, without a snapshot is taken after every pass
+	pub snapshot_interval: Option<Duration>
+}
+
+impl ProgressiveSettings {
+
+	// Returns the settings for rendering target_samples rays per pixel in passes of samples_per_pass
+	pub fn new(samples_per_pass: u32, target_samples: u32) -> ProgressiveSettings {
+		return ProgressiveSettings { samples_per_pass, target_samples: Some(target_samples), time_budget: None, snapshot_interval: None };
+	}
+
+	// Number of samples per pixel the sampler spreads its strata over, all passes together. Without a target the
+	// number of passes is not known in advance, so every pass is stratified on its own.
+	pub fn sample_budget(&self) -> u32 {
+		return self.target_samples.unwrap_or(self.samples_per_pass).max(1);
+	}
+}
+
+// Samples of every pixel a pass renders, the ones from first on. The sampler spreads its strata over budget samples,
+// which is more than a pass renders when the passes of a progressive rendering share them.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct SampleRange {
+	pub first: u32,
+	pub budget: u32
+}
+
+impl SampleRange {
+
+	// Returns the samples of a rendering in one pass, without adaptive sampling all of them
+	pub fn all(settings: &RenderSettings) -> SampleRange {
+		return SampleRange { first: 0, budget: settings.sample_range().0 };
+	}
+}
+
+// Main rendering function. Renders the full image. The settings.thread_count=N specifies how many threads will be used. 
+// The image plane will be split into tiles of settings.tile_height rows, the threads taking one tile after the other.
+// All random numbers are derived from the seed per pixel and per sample, so the same seed always gives the same image,
+// independent of the thread count and the tile size.
+pub fn render(space: Space, camera: CameraZ, settings: &RenderSettings) -> RenderResult {
+	return render_with_progress(space, camera, settings, |_| {});
+}
+
+// Like render, calling the progress function after every finished tile
+pub fn render_with_progress<P>(space: Space, camera: CameraZ, settings: &RenderSettings, progress: P) -> RenderResult
+	where P: FnMut(&RenderProgress) {
+
+	return render_cancellable(space, camera, settings, &CancellationToken::new(), progress);
+}
+
+// Like render_with_progress, stopping early when the token is cancelled. The render threads check it between the rows,
+// so the rendering returns soon after. If rows were skipped, the result has the status Cancelled and contains what has
+// been rendered so far, the pixels not reached yet being black with a sample count of 0.
+pub fn render_cancellable<P>(space: Space, camera: CameraZ, settings: &RenderSettings, cancel: &CancellationToken, mut progress: P) -> RenderResult
+	where P: FnMut(&RenderProgress) {
+
+	let start_time = Instant::now();
+	let mut frame = FrameBuffer::for_region(settings);
+	let mut context = PassContext { pass: 0, start_time, cancel, progress: &mut progress };
+	render_pass(&Arc::new(space), &Arc::new(camera), settings, SampleRange::all(settings), &mut frame, &mut context);
+	finish_stats(&mut frame.stats, start_time);
+	return frame.resolve();
+}
+
+// State of a progressive rendering between two passes: the sums of all finished passes and the number of samples per
+// pixel they contain. Together with the seed this is all that is needed to continue the rendering, the random numbers
+// of the following samples being derived from their indices.
+pub struct ProgressiveRender {
+	space: Arc<Space>,
+	camera: Arc<CameraZ>,
+	pub settings: RenderSettings,
+	pub progressive: ProgressiveSettings,
+	pub frame: FrameBuffer,
+	pub samples: u32,
+	pub pass_count: u32,
+	// Hash of everything that changes the image, a checkpoint can only be resumed with the same one
+	pub scene_hash: u64
+}
+
+impl ProgressiveRender {
+
+	// Returns the state before the first pass
+	pub fn new(space: Space, camera: CameraZ, settings: &RenderSettings, progressive: &ProgressiveSettings) -> ProgressiveRender {
+		let scene_hash = compute_scene_hash(&space, &camera, settings, progressive);
+
+		return ProgressiveRender {
+			space: Arc::new(space),
+			camera: Arc::new(camera),
+			settings: *settings,
+			progressive: *progressive,
+			frame: FrameBuffer::for_region(settings),
+			samples: 0,
+			pass_count: 0,
+			scene_hash
+		};
+	}
+
+	// Whether the target number of samples per pixel has been reached
+	pub fn is_complete(&self) -> bool {
+		return self.progressive.target_samples.is_some_and(|target| self.samples >= target);
+	}
+}
+
+// Renders the image in passes of progressive.samples_per_pass rays per pixel, accumulating all of them. After a pass
+// the snapshot function gets the state of the rendering, at most once per snapshot interval. It is not called for the
+// final image, which is returned. The progress function is called after every tile of every pass. Adaptive sampling
+// is not used, every pixel receives the same number of rays. Since every sample is seeded by its index, the sum over
+// the passes contains the same samples as a single rendering with all of them.
+pub fn render_progressive<F, P>(space: Space, camera: CameraZ, settings: &RenderSettings, progressive: &ProgressiveSettings, cancel: &CancellationToken, snapshot: F, progress: P) -> RenderResult
+	where F: FnMut(&ProgressiveRender), P: FnMut(&RenderProgress) {
+
+	let mut state = ProgressiveRender::new(space, camera, settings, progressive);
+	return continue_progressive(&mut state, cancel, snapshot, progress);
+}
+
+// Continues a progressive rendering with the next passes, for example one resumed from a checkpoint. The time budget
+// counts from the call. Cancelling stops within the current pass: the result contains the finished passes and the
+// part of the current one, while the state keeps only the finished passes so that it can be continued later. The
+// settings need a target number of samples or a time budget, without either the rendering would never end.
+pub fn continue_progressive<F, P>(state: &mut ProgressiveRender, cancel: &CancellationToken, mut snapshot: F, mut progress: P) -> RenderResult
+	where F: FnMut(&ProgressiveRender), P: FnMut(&RenderProgress) {
+
+	assert!(state.progressive.target_samples.is_some() || state.progressive.time_budget.is_some(), "Progressive renderings need a target number of samples or a time budget");
+
+	let start_time = Instant::now();
+	let mut last_snapshot_time = start_time;
+	let mut session_pass_count: u32 = 0;
+	let progressive = state.progressive;
+
+	while !state.is_complete() {
+		let mut pass_samples = progressive.samples_per_pass.max(1);
+
+		if let Some(target) = progressive.target_samples {
+			pass_samples = pass_samples.min(target - state.samples);
+		}
+
+		// The passes take their samples one after the other from the same strata
+		let pass_settings = RenderSettings { samples_per_pixel: pass_samples, adaptive: None, ..state.settings };
+		let samples = SampleRange { first: state.samples, budget: progressive.sample_budget() };
+		let mut pass_frame = FrameBuffer::for_region(&pass_settings);
+		let mut context = PassContext { pass: state.pass_count, start_time, cancel, progress: &mut progress };
+		render_pass(&state.space, &state.camera, &pass_settings, samples, &mut pass_frame, &mut context);
+
+		if !pass_frame.complete {
+			let mut partial = state.frame.clone();
+			partial.add(&pass_frame);
+			finish_stats(&mut partial.stats, start_time);
+			return partial.resolve();
+		}
+
+		state.frame.add(&pass_frame);
+		state.samples += pass_samples;
+		state.pass_count += 1;
+		session_pass_count += 1;
+
+		// The next pass is assumed to take as long as the average pass so far
+		let elapsed = start_time.elapsed();
+
+		if state.is_complete() || cancel.is_cancelled() || progressive.time_budget.is_some_and(|budget| elapsed + elapsed / session_pass_count > budget) {
+			break;
+		}
+
+		if progressive.snapshot_interval.is_none_or(|interval| last_snapshot_time.elapsed() >= interval) {
+			snapshot(state);
+			last_snapshot_time = Instant::now();
+		}
+	}
 
-	let arc_space = Arc::new(space);
-	let arc_camera = Arc::new(camera);
+	finish_stats(&mut state.frame.stats, start_time);
 
-	let mut interval_start = 0; 
+	// Cancelled between two passes, the following ones are skipped
+	let mut result = state.frame.resolve();
 
-	for _ in 0..thread_count {
-		let (tx, rx) = mpsc::channel();
-		channel_receivers.push(rx);
+	if cancel.is_cancelled() && !state.is_complete() {
+		result.status = RenderStatus::Cancelled;
+	}
 
-		let t_space = Arc::clone(&arc_space);
-		let t_camera = Arc::clone(&arc_camera);
+	return result;
+}
 
+// Hash of the scene and of the settings that change the rendered samples or the order they are summed up in. The
+// scene is hashed through its debug representation, which contains every value exactly and automatically covers
+// new kinds of geometry.
+fn compute_scene_hash(space: &Space, camera: &CameraZ, settings: &RenderSettings, progressive: &ProgressiveSettings) -> u64 {
+	let description = format!("{:?} {:?} {} {} {} {:?} {:?} {} {:?} {} {}", space, camera, settings.width, settings.height, settings.seed,
+		settings.sampler, settings.filter, settings.aovs, settings.region, progressive.samples_per_pass, progressive.sample_budget());
+
+	return fnv1a(description.bytes().map(u64::from));
+}
+
+// Sets the wall time of a finished rendering, the processor time being the sum over its tiles
+fn finish_stats(stats: &mut RenderStats, start_time: Instant) {
+	stats.wall_time = start_time.elapsed();
+}
+
+// What a pass needs besides the samples to render
+struct PassContext<'a> {
+	// Index of the pass and the time the rendering started, for the progress reports
+	pass: u32,
+	start_time: Instant,
+	cancel: &'a CancellationToken,
+	progress: &'a mut dyn FnMut(&RenderProgress)
+}
+
+// Renders the samples of all pixels, as many as the settings ask for, and adds them to the frame. If the rendering is
+// cancelled, the threads stop taking tiles and only the rows rendered so far are added.
+fn render_pass(space: &Arc<Space>, camera: &Arc<CameraZ>, settings: &RenderSettings, samples: SampleRange, frame: &mut FrameBuffer, context: &mut PassContext) {
+
+	let tiles = Arc::new(settings.tiles());
+	let tile_count = tiles.len() as i32;
+
+	let pass_start_time = Instant::now();
+	let next_tile = Arc::new(AtomicI32::new(0));
+	let (tx, rx) = mpsc::channel::<FrameBuffer>();
+	let mut join_handles: Vec<thread::JoinHandle<()>> = Vec::new();
+
+	for _ in 0..settings.thread_count.min(tile_count) {
+		let t_space = Arc::clone(space);
+		let t_camera = Arc::clone(camera);
+		let t_next_tile = Arc::clone(&next_tile);
+		let t_tiles = Arc::clone(&tiles);
+		let t_settings = *settings;
+		let t_cancel = context.cancel.clone();
+		let t_tx = tx.clone();
+
+		// Every thread takes the next tile nobody has taken yet, until all are done
 		let t_join_handle = thread::spawn(move || {
-			let t_pixel_lights = render_interval(t_space, t_camera, width, height, interval_start, interval_size);
-			tx.send(t_pixel_lights).unwrap();
+			loop {
+				let tile = t_next_tile.fetch_add(1, Ordering::Relaxed);
+
+				if tile >= tile_count || t_cancel.is_cancelled() {
+					break;
+				}
+
+				let (y_start, y_count) = t_tiles[tile as usize];
+				let t_frame = render_tile(&t_space, &t_camera, &t_settings, y_start, y_count, samples, &t_cancel);
+
+				if t_tx.send(t_frame).is_err() {
+					break;
+				}
+			}
 		});
 
 		join_handles.push(t_join_handle);
-		interval_start += interval_size;
 	}
 
-	for c_receiver in &channel_receivers {
-		let c_pixel_lights = c_receiver.recv().unwrap();
-		pixels.extend(c_pixel_lights);
+	drop(tx);
+
+	// The tiles are added in the order they are finished, which does not change the result since they do not overlap
+	let mut tiles_completed: u32 = 0;
+
+	for t_frame in rx.iter() {
+		frame.add(&t_frame);
+		tiles_completed += 1;
+
+		let pass_elapsed = pass_start_time.elapsed();
+		let elapsed = context.start_time.elapsed();
+
+		(context.progress)(&RenderProgress {
+			pass: context.pass,
+			tiles_completed,
+			tile_count: tile_count as u32,
+			elapsed,
+			eta: pass_elapsed.mul_f64(((tile_count as u32 - tiles_completed) as f64) / (tiles_completed as f64)),
+			rays_per_second: (frame.stats.counters.total_rays() as f64) / pass_elapsed.as_secs_f64().max(1e-9),
+			counters: frame.stats.counters
+		});
 	}
 
 	for join_handler in join_handles {
 		join_handler.join().unwrap();	
 	}
 
-	return pixels; 
+	// Tiles not taken by any thread of a cancelled rendering
+	if tiles_completed < tile_count as u32 {
+		frame.complete = false;
+	}
 }
 
-// Renders a vertical interval of the image plane 
-fn render_interval(a_space: Arc<Space>, a_camera: Arc<CameraZ>, width: i32, height: i32, y_start_index: i32, y_count: i32) -> Vec<LightColor> {
+// Renders the samples of the rows y_start..y_start+y_count, including the counters and the time it took. The tiles of
+// an image can be rendered anywhere (on other threads or machines) and added up in any order.
+pub fn render_tile(space: &Space, camera: &CameraZ, settings: &RenderSettings, y_start: i32, y_count: i32, samples: SampleRange, cancel: &CancellationToken) -> FrameBuffer {
+	let start_time = Instant::now();
+	let start_cpu_time = thread_cpu_time();
+	take_thread_counters();
 
-	let space: &Space = a_space.as_ref();
-    let camera: &CameraZ = a_camera.as_ref();
+	let mut frame = render_interval(space, camera, settings, y_start, y_count, samples, cancel);
+	frame.stats.counters = take_thread_counters();
 
-	let DX: f64 = (width-1) as f64;
-	let DY: f64 = (height-1) as f64;
+	// Without the processor time of the thread the time it took is used, which is too high if there are more threads
+	// than processors
+	frame.stats.cpu_time = match (start_cpu_time, thread_cpu_time()) {
+		(Some(start), Some(end)) => end.saturating_sub(start),
+		_ => start_time.elapsed()
+	};
+
+	return frame;
+}
 
-	let mut pixels: Vec<LightColor> = Vec::new();
+// Renders a vertical interval of the image plane. The filter lets samples contribute to neighbouring pixels, so the
+// rows within the filter reach above and below the interval are traced as well, contributing only to the interval.
+// The same holds for the columns around a region, so the pixels of a region are exactly the ones of the full image.
+// Since samples are seeded per pixel, every pixel receives exactly the same contributions in the same order on any
+// thread count. When the rendering is cancelled, the rows that have not been started yet are left empty.
+fn render_interval(space: &Space, camera: &CameraZ, settings: &RenderSettings, y_start_index: i32, y_count: i32, samples: SampleRange, cancel: &CancellationToken) -> FrameBuffer {
+
+	let width = settings.width;
+	let height = settings.height;
+
+	let mut frame = FrameBuffer::new(settings, y_start_index, y_count);
 
 	// Compute some variables we need for iterating through the image plane pixels
 	let pixel_size = camera.image_plane_width / (width as f64);
@@ -89,51 +574,68 @@ fn render_interval(a_space: Arc<Space>, a_camera: Arc<CameraZ>, width: i32, heig
 	// These vars are being set to the pixel center during the iteration
 	let mut pixel_ray = Ray {origin: Vector3 {x: x_start, y: y_start, z: plane_z}, direction: Vector3 {x: 0.0, y: 0.0, z: 0.0}};
 	let mut ray_len: f64;
-	let mut row_y: f64 = y_start-pixel_size*(y_start_index as f64);
+	let mut row_y: f64;
 	let mut col_x: f64; 
 	let mut pixel_light: LightColor;
 
-	let mut pixel_measurements: Vec<WeightedLightColorSummand> = Vec::new(); 
-	let mut pixel_measurement_positions: Vec<Vector3> = Vec::new(); 
+	let mut offset_x: f64;
+	let mut offset_y: f64;
 
-	let mut rng = rand::thread_rng();	
-	let mut r1: f64;
-	let mut r2: f64;
-	
-	let W: f64 = 1.0 / (1.0 + fRANDOM_SUBPIXEL_RAYS);
+	// Without adaptive sampling both are the same and all samples are cast in one batch
+	let (min_samples, max_samples) = settings.sample_range();
 
-	for y in (y_start_index..(y_start_index+y_count)).rev() {
+	let mut sampler = create_sampler(settings.sampler, settings.seed, samples.budget);
 
-		col_x = x_start; 
+	let reach = settings.filter.pixel_reach();
+	let region = settings.render_region();
+	let traced_rows_start = (y_start_index - reach).max(0);
+	let traced_rows_end = (y_start_index + y_count + reach).min(height);
+	let traced_columns_start = (region.x - reach).max(0);
+	let traced_columns_end = (region.x + region.width + reach).min(width);
 
-		for x in 0..width {
+	for y in traced_rows_start..traced_rows_end {
 
-			pixel_measurements.clear();
-			pixel_measurement_positions.clear();
+		if cancel.is_cancelled() {
+			frame.complete = false;
+			break;
+		}
 
-			// This is the default ray send from the center of the pixel 
-			pixel_measurement_positions.push(Vector3 {
-				x: col_x, 
-				y: row_y, 
-				z: 0.0 
-			});
+		// Pixel centers are computed from the index and not accumulated, so they do not depend on where the interval starts
+		row_y = y_start - pixel_size*(y as f64);
 
-			// We also add random subpixel ray origins 
-			for k in 0..RANDOM_SUBPIXEL_RAYS {
-				r1 = (rng.gen::<f64>()-0.5)*2.0;
-				r2 = (rng.gen::<f64>()-0.5)*2.0;
+		for x in traced_columns_start..traced_columns_end {
 
-				pixel_measurement_positions.push(Vector3 {
-					x: col_x + pixel_half*r1, 
-					y: row_y + pixel_half*r2, 
-					z: 0.0 
-				});				
-			}
-			
-			// For each measurement origin cast a ray 
-			for pm in &pixel_measurement_positions {
-				pixel_ray.origin.x = pm.x;
-				pixel_ray.origin.y = pm.y; 
+			col_x = x_start + pixel_size*(x as f64);
+
+			let pixel_index = (y as u64) * (width as u64) + (x as u64);
+
+			let is_own_pixel = y >= y_start_index && y < y_start_index + y_count && x >= region.x && x < region.x + region.width;
+			let mut estimate = PixelEstimate::new();
+			let mut aov_accumulator = AovAccumulator::new();
+			let mut k: u32 = 0;
+
+			// Every sample gets its sub-pixel position from the first two sampler dimensions, the following dimensions
+			// are used by the global illumination
+			while k < max_samples {
+
+				// Samples are cast in batches of min_samples, after each batch it is checked if the pixel is converged
+				if k >= min_samples && k.is_multiple_of(min_samples) {
+					if let Some(a) = settings.adaptive {
+						if estimate.is_converged(a.error_threshold) {
+							break;
+						}
+					}
+				}
+
+				sampler.start_pixel_sample(pixel_index, samples.first + k);
+
+				// Offset from the pixel center in pixels, y pointing down like the rows
+				let (u1, u2) = sampler.get_2d();
+				offset_x = u1-0.5;
+				offset_y = u2-0.5;
+
+				pixel_ray.origin.x = col_x + pixel_size*offset_x;
+				pixel_ray.origin.y = row_y - pixel_size*offset_y; 
 	
 				pixel_ray.direction.x = pixel_ray.origin.x - camera.location.x;
 				pixel_ray.direction.y = pixel_ray.origin.y - camera.location.y;
@@ -144,24 +646,32 @@ fn render_interval(a_space: Arc<Space>, a_camera: Arc<CameraZ>, width: i32, heig
 				pixel_ray.direction.y /= ray_len;
 				pixel_ray.direction.z /= ray_len;
 	
-				pixel_light = get_light_for_backward_ray(&pixel_ray, &space, 1);
+				let hit = trace_camera_ray(&pixel_ray, space, 1, sampler.as_mut());
+
+				pixel_light = match &hit {
+					Some(h) => h.light(),
+					None => LightColor { r: 0.0, g: 0.0, b: 0.0 }
+				};
 
-				pixel_measurements.push(WeightedLightColorSummand {
-					light_color: pixel_light.clone(),
-					weight: W
-				});								
+				if settings.aovs && is_own_pixel {
+					aov_accumulator.add_sample(&hit, offset_x, offset_y);
+				}
+
+				estimate.add_sample(&pixel_light);
+				frame.film.add_sample(x, y, offset_x, offset_y, &pixel_light);
+				k += 1;
 			}
 
-			pixel_light = compute_weighted_light_color(&pixel_measurements);
-						
-			pixels.push(pixel_light);
+			if is_own_pixel {
+				let own_index = ((y - y_start_index) * region.width + x - region.x) as usize;
+				frame.sample_counts[own_index] = estimate.sample_count;
 
-			col_x += pixel_size;
+				if settings.aovs {
+					frame.aovs[own_index] = aov_accumulator;
+				}
+			}
 		}
-
-		row_y -= pixel_size;
 	}	
 
-	return pixels;
+	return frame;
 }
-
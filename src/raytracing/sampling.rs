@@ -0,0 +1,363 @@
+
+// Import requirements
+use crate::fundamentals::random::*;
+use rand::prelude::*;
+
+// Samplers hand out the random numbers for one sample of one pixel, one dimension after the other. Every consumer
+// (camera position, lens, time, light and global illumination directions) simply asks for the next dimension, so the
+// same dimension of all samples of a pixel belongs to the same decision and can be well distributed across them.
+pub trait Sampler {
+
+	// Prepares the sampler for the specified sample of the specified pixel and resets the dimension to 0
+	fn start_pixel_sample(&mut self, pixel_index: u64, sample_index: u32);
+
+	// Returns the next dimension as number in [0, 1)
+	fn get_1d(&mut self) -> f64;
+
+	// Returns the next two dimensions as pair of numbers in [0, 1)
+	fn get_2d(&mut self) -> (f64, f64);
+
+	// Generator for random decisions that do not benefit from well distributed numbers. It is seeded per sample as well.
+	fn rng(&mut self) -> &mut Pcg32;
+}
+
+// The available sampler implementations
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum SamplerType {
+	Independent,
+	Stratified,
+	Halton,
+	Sobol
+}
+
+impl SamplerType {
+
+	// Returns the sampler type for the name used on the command line
+	pub fn from_name(name: &str) -> Option<SamplerType> {
+		return match name {
+			"independent" => Some(SamplerType::Independent),
+			"stratified" => Some(SamplerType::Stratified),
+			"halton" => Some(SamplerType::Halton),
+			"sobol" => Some(SamplerType::Sobol),
+			_ => None
+		};
+	}
+
+	pub fn name(&self) -> &'static str {
+		return match self {
+			SamplerType::Independent => "independent",
+			SamplerType::Stratified => "stratified",
+			SamplerType::Halton => "halton",
+			SamplerType::Sobol => "sobol"
+		};
+	}
+}
+
+// Creates a sampler of the specified type. Each thread needs its own sampler.
+pub fn create_sampler(sampler_type: SamplerType, seed: u64, samples_per_pixel: u32) -> Box<dyn Sampler + Send> {
+	let state = SamplerState::new(seed, samples_per_pixel);
+
+	return match sampler_type {
+		SamplerType::Independent => Box::new(IndependentSampler { state }),
+		SamplerType::Stratified => Box::new(StratifiedSampler { state }),
+		SamplerType::Halton => Box::new(HaltonSampler { state }),
+		SamplerType::Sobol => Box::new(SobolSampler { state })
+	};
+}
+
+// Bookkeeping every sampler needs: where we are and a generator for this sample
+struct SamplerState {
+	seed: u64,
+	samples_per_pixel: u32,
+	pixel_index: u64,
+	sample_index: u32,
+	dimension: u32,
+	rng: Pcg32
+}
+
+impl SamplerState {
+
+	fn new(seed: u64, samples_per_pixel: u32) -> SamplerState {
+		return SamplerState {
+			seed,
+			samples_per_pixel: samples_per_pixel.max(1),
+			pixel_index: 0,
+			sample_index: 0,
+			dimension: 0,
+			rng: rng_for_pixel_sample(seed, 0, 0)
+		};
+	}
+
+	fn start(&mut self, pixel_index: u64, sample_index: u32) {
+		self.pixel_index = pixel_index;
+		self.sample_index = sample_index;
+		self.dimension = 0;
+		self.rng = rng_for_pixel_sample(self.seed, pixel_index, sample_index as u64);
+	}
+
+	// Returns the current dimension and advances to the next one
+	fn next_dimension(&mut self, count: u32) -> u32 {
+		let dimension = self.dimension;
+		self.dimension += count;
+		return dimension;
+	}
+
+	// Hash of seed, pixel and dimension. It is the same for all samples of the pixel.
+	fn dimension_hash(&self, dimension: u32, salt: u64) -> u32 {
+		let h = mix_bits(self.seed ^ mix_bits(self.pixel_index ^ mix_bits(((dimension as u64) << 8) | salt)));
+		return (h >> 32) as u32;
+	}
+}
+
+// Maps 32 random bits to [0, 1)
+fn u32_to_unit(v: u32) -> f64 {
+	return (v as f64) * (1.0 / 4294967296.0);
+}
+
+// Independent uniform random numbers for every dimension
+pub struct IndependentSampler {
+	state: SamplerState
+}
+
+impl Sampler for IndependentSampler {
+
+	fn start_pixel_sample(&mut self, pixel_index: u64, sample_index: u32) {
+		self.state.start(pixel_index, sample_index);
+	}
+
+	fn get_1d(&mut self) -> f64 {
+		return self.state.rng.gen::<f64>();
+	}
+
+	fn get_2d(&mut self) -> (f64, f64) {
+		return (self.state.rng.gen::<f64>(), self.state.rng.gen::<f64>());
+	}
+
+	fn rng(&mut self) -> &mut Pcg32 {
+		return &mut self.state.rng;
+	}
+}
+
+// Jittered sampling: every dimension is split into one stratum per sample and each sample gets a random position
+// within its own stratum. 2D dimensions use multi-jittering (Kensler 2013), which is stratified on the grid and
+// on both axes. Which sample gets which stratum is shuffled per pixel and dimension, so that the dimensions are not
+// correlated with each other.
+pub struct StratifiedSampler {
+	state: SamplerState
+}
+
+impl Sampler for StratifiedSampler {
+
+	fn start_pixel_sample(&mut self, pixel_index: u64, sample_index: u32) {
+		self.state.start(pixel_index, sample_index);
+	}
+
+	fn get_1d(&mut self) -> f64 {
+		let dimension = self.state.next_dimension(1);
+		let n = self.state.samples_per_pixel;
+		let round = self.state.sample_index / n;
+		let stratum = permute(self.state.sample_index % n, n, self.state.dimension_hash(dimension, round as u64));
+
+		return ((stratum as f64) + self.state.rng.gen::<f64>()) / (n as f64);
+	}
+
+	fn get_2d(&mut self) -> (f64, f64) {
+		let dimension = self.state.next_dimension(2);
+		let nx = ((self.state.samples_per_pixel as f64).sqrt().ceil()) as u32;
+		let ny = self.state.samples_per_pixel.div_ceil(nx);
+		let n = self.state.samples_per_pixel;
+		let round = self.state.sample_index / n;
+		let hash = self.state.dimension_hash(dimension, round as u64);
+		let stratum = permute(self.state.sample_index % n, nx * ny, hash);
+
+		// Position of the cell on the coarse grid and of the sample within the cell on the fine grid
+		let cell_x = stratum % nx;
+		let cell_y = stratum / nx;
+		let sub_x = permute(cell_x, nx, hash.wrapping_mul(0xa511e9b3));
+		let sub_y = permute(cell_y, ny, hash.wrapping_mul(0x63d83595));
+
+		let x = ((cell_x as f64) + ((sub_y as f64) + self.state.rng.gen::<f64>()) / (ny as f64)) / (nx as f64);
+		let y = ((cell_y as f64) + ((sub_x as f64) + self.state.rng.gen::<f64>()) / (nx as f64)) / (ny as f64);
+		return (x, y);
+	}
+
+	fn rng(&mut self) -> &mut Pcg32 {
+		return &mut self.state.rng;
+	}
+}
+
+// First primes, used as bases of the Halton dimensions
+const PRIMES: [u32; 64] = [
+	2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97, 101, 103, 107, 109,
+	113, 127, 131, 137, 139, 149, 151, 157, 163, 167, 173, 179, 181, 191, 193, 197, 199, 211, 223, 227, 229, 233, 239,
+	241, 251, 257, 263, 269, 271, 277, 281, 283, 293, 307, 311
+];
+
+// Halton sequence with one prime base per dimension. Each pixel gets its own random toroidal shift
+// (Cranley-Patterson rotation) per dimension, so neighbouring pixels do not show the same pattern. Dimensions
+// beyond the prime table fall back to independent random numbers.
+pub struct HaltonSampler {
+	state: SamplerState
+}
+
+impl HaltonSampler {
+
+	fn sample_dimension(&mut self, dimension: u32) -> f64 {
+		if dimension as usize >= PRIMES.len() {
+			return self.state.rng.gen::<f64>();
+		}
+
+		let value = radical_inverse(PRIMES[dimension as usize], self.state.sample_index as u64);
+		let shift = u32_to_unit(self.state.dimension_hash(dimension, 0));
+		return (value + shift).fract();
+	}
+}
+
+impl Sampler for HaltonSampler {
+
+	fn start_pixel_sample(&mut self, pixel_index: u64, sample_index: u32) {
+		self.state.start(pixel_index, sample_index);
+	}
+
+	fn get_1d(&mut self) -> f64 {
+		let dimension = self.state.next_dimension(1);
+		return self.sample_dimension(dimension);
+	}
+
+	fn get_2d(&mut self) -> (f64, f64) {
+		let dimension = self.state.next_dimension(2);
+		return (self.sample_dimension(dimension), self.sample_dimension(dimension + 1));
+	}
+
+	fn rng(&mut self) -> &mut Pcg32 {
+		return &mut self.state.rng;
+	}
+}
+
+// Sobol (0,2)-sequence with hash based Owen scrambling (Burley 2020). Every 2D dimension pair uses the first two Sobol
+// dimensions with its own scrambling seed, and the sample index is shuffled by a nested uniform scramble as well.
+// This decorrelates the dimension pairs and keeps the stratification of every power of two prefix of samples.
+pub struct SobolSampler {
+	state: SamplerState
+}
+
+impl SobolSampler {
+
+	fn shuffled_index(&self, dimension: u32) -> u32 {
+		return nested_uniform_scramble(self.state.sample_index, self.state.dimension_hash(dimension, 0));
+	}
+}
+
+impl Sampler for SobolSampler {
+
+	fn start_pixel_sample(&mut self, pixel_index: u64, sample_index: u32) {
+		self.state.start(pixel_index, sample_index);
+	}
+
+	fn get_1d(&mut self) -> f64 {
+		let dimension = self.state.next_dimension(1);
+		let index = self.shuffled_index(dimension);
+		return u32_to_unit(nested_uniform_scramble(sobol_dimension_0(index), self.state.dimension_hash(dimension, 1)));
+	}
+
+	fn get_2d(&mut self) -> (f64, f64) {
+		let dimension = self.state.next_dimension(2);
+		let index = self.shuffled_index(dimension);
+		let x = nested_uniform_scramble(sobol_dimension_0(index), self.state.dimension_hash(dimension, 1));
+		let y = nested_uniform_scramble(sobol_dimension_1(index), self.state.dimension_hash(dimension, 2));
+		return (u32_to_unit(x), u32_to_unit(y));
+	}
+
+	fn rng(&mut self) -> &mut Pcg32 {
+		return &mut self.state.rng;
+	}
+}
+
+// Radical inverse of index in the specified base (van der Corput sequence)
+pub fn radical_inverse(base: u32, mut index: u64) -> f64 {
+	let inverse_base = 1.0 / (base as f64);
+	let mut inverse_base_power = 1.0;
+	let mut reversed_digits: f64 = 0.0;
+
+	while index > 0 {
+		let digit = index % (base as u64);
+		index /= base as u64;
+		inverse_base_power *= inverse_base;
+		reversed_digits += (digit as f64) * inverse_base_power;
+	}
+
+	return reversed_digits.min(1.0 - f64::EPSILON);
+}
+
+// First Sobol dimension, which is the base 2 van der Corput sequence
+fn sobol_dimension_0(index: u32) -> u32 {
+	return index.reverse_bits();
+}
+
+// Second Sobol dimension. Its direction numbers follow from the primitive polynomial x + 1.
+fn sobol_dimension_1(mut index: u32) -> u32 {
+	let mut result: u32 = 0;
+	let mut direction: u32 = 1 << 31;
+
+	while index != 0 {
+		if index & 1 != 0 {
+			result ^= direction;
+		}
+		index >>= 1;
+		direction ^= direction >> 1;
+	}
+
+	return result;
+}
+
+// Hash that only lets bits influence higher bits (Laine-Karras style permutation, constants by Burley)
+fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+	x = x.wrapping_add(seed);
+	x ^= x.wrapping_mul(0x6c50b47c);
+	x ^= x.wrapping_mul(0xb82f1e52);
+	x ^= x.wrapping_mul(0xc7afe638);
+	x ^= x.wrapping_mul(0x8d22f6e6);
+	return x;
+}
+
+// Owen scrambling of a 32 bit fixed point number in [0, 1)
+pub fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+	return laine_karras_permutation(x.reverse_bits(), seed).reverse_bits();
+}
+
+// Returns the element at position index of a random permutation of 0..length chosen by seed (Kensler 2013)
+pub fn permute(mut index: u32, length: u32, seed: u32) -> u32 {
+	let mut mask = length.wrapping_sub(1);
+	mask |= mask >> 1;
+	mask |= mask >> 2;
+	mask |= mask >> 4;
+	mask |= mask >> 8;
+	mask |= mask >> 16;
+
+	loop {
+		index ^= seed;
+		index = index.wrapping_mul(0xe170893d);
+		index ^= seed >> 16;
+		index ^= (index & mask) >> 4;
+		index ^= seed >> 8;
+		index = index.wrapping_mul(0x0929eb3f);
+		index ^= seed >> 23;
+		index ^= (index & mask) >> 1;
+		index = index.wrapping_mul(1 | (seed >> 27));
+		index = index.wrapping_mul(0x6935fa69);
+		index ^= (index & mask) >> 11;
+		index = index.wrapping_mul(0x74dcb303);
+		index ^= (index & mask) >> 2;
+		index = index.wrapping_mul(0x9e501cc3);
+		index ^= (index & mask) >> 2;
+		index = index.wrapping_mul(0xc860a3df);
+		index &= mask;
+		index ^= index >> 5;
+
+		if index < length {
+			break;
+		}
+	}
+
+	return (index.wrapping_add(seed)) % length;
+}
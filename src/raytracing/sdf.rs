@@ -0,0 +1,47 @@
+
+// Import requirements
+use crate::fundamentals::sdf::*;
+use crate::fundamentals::math::vector::*;
+use super::rays::*;
+use super::primitives::*;
+
+// Returns the distance of the nearest intersection of a ray with the surface of an SDF further away than
+// min_distance, by sphere tracing: within the bounds of the solid the ray steps ahead by the distance to the surface,
+// which can't jump over it, until it comes closer than epsilon. Steps inside the solid go by the absolute distance, so
+// rays starting within it find where they leave it. Ray must be normalized!
+pub fn get_ray_sdf_distance(ray: &Ray, sdf: &Sdf, min_distance: f64) -> Option<f64> {
+	let bounds = sdf.root.bounds();
+	let (near, far) = get_ray_box_interval(ray, &bounds.min, &bounds.max)?;
+	let lipschitz = sdf.root.lipschitz_bound();
+	let mut t = near.max(min_distance);
+
+	for _ in 0..sdf.max_steps {
+		if t > far {
+			return None;
+		}
+
+		let distance = sdf.root.distance(&(ray.origin + ray.direction * t)).abs() / lipschitz;
+
+		if distance < sdf.epsilon {
+			return Some(t);
+		}
+
+		t += distance;
+	}
+
+	return None;
+}
+
+// Returns the normal of the surface of an SDF at a point near it, the gradient of the distance function by central
+// differences
+pub fn get_sdf_normal(sdf: &Sdf, p: &Vector3) -> Vector3 {
+	let mut gradient = Vector3::zero();
+
+	for axis in 0..3 {
+		let mut offset = Vector3::zero();
+		offset[axis] = sdf.epsilon;
+		gradient[axis] = sdf.root.distance(&(*p + offset)) - sdf.root.distance(&(*p - offset));
+	}
+
+	return gradient.normalize();
+}
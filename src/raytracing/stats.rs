@@ -0,0 +1,133 @@
+
+// Import requirements
+use std::cell::Cell;
+use std::time::Duration;
+
+// Number of rays and intersection tests of a rendering
+#[derive(Copy, Clone, Default, PartialEq, Debug)]
+pub struct RayCounters {
+	// Rays from the camera
+	pub primary_rays: u64,
+	// Rays from surface points towards the light sources
+	pub shadow_rays: u64,
+	// Rays from surface points into random directions (global illumination)
+	pub indirect_rays: u64,
+	// Ray against primitive tests
+	pub intersection_tests: u64
+}
+
+impl RayCounters {
+
+	pub fn new() -> RayCounters {
+		return RayCounters { primary_rays: 0, shadow_rays: 0, indirect_rays: 0, intersection_tests: 0 };
+	}
+
+	pub fn add(&mut self, other: &RayCounters) {
+		self.primary_rays += other.primary_rays;
+		self.shadow_rays += other.shadow_rays;
+		self.indirect_rays += other.indirect_rays;
+		self.intersection_tests += other.intersection_tests;
+	}
+
+	pub fn total_rays(&self) -> u64 {
+		return self.primary_rays + self.shadow_rays + self.indirect_rays;
+	}
+}
+
+// Summary of a rendering
+#[derive(Copy, Clone, Default, Debug)]
+pub struct RenderStats {
+	pub counters: RayCounters,
+	// Time from the start to the end of the rendering
+	pub wall_time: Duration,
+	// Processor time used by all threads of the rendering
+	pub cpu_time: Duration
+}
+
+impl RenderStats {
+
+	pub fn new() -> RenderStats {
+		return RenderStats { counters: RayCounters::new(), wall_time: Duration::ZERO, cpu_time: Duration::ZERO };
+	}
+
+	// Adds the counters and CPU time of a part of the rendering, the wall time is left as it is
+	pub fn add(&mut self, other: &RenderStats) {
+		self.counters.add(&other.counters);
+		self.cpu_time += other.cpu_time;
+	}
+
+	pub fn rays_per_second(&self) -> f64 {
+		return (self.counters.total_rays() as f64) / self.wall_time.as_secs_f64().max(1e-9);
+	}
+}
+
+// Returns the processor time the calling thread has used so far (user and system time). Only Linux reports it per
+// thread.
+#[cfg(target_os = "linux")]
+pub fn thread_cpu_time() -> Option<Duration> {
+	let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+
+	if unsafe { libc::getrusage(libc::RUSAGE_THREAD, &mut usage) } != 0 {
+		return None;
+	}
+
+	let to_duration = |t: libc::timeval| Duration::from_secs(t.tv_sec as u64) + Duration::from_micros(t.tv_usec as u64);
+	return Some(to_duration(usage.ru_utime) + to_duration(usage.ru_stime));
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn thread_cpu_time() -> Option<Duration> {
+	return None;
+}
+
+// Progress of a rendering, reported after every finished tile
+#[derive(Copy, Clone, Debug)]
+pub struct RenderProgress {
+	// Index of the pass of a progressive rendering, otherwise 0
+	pub pass: u32,
+	// Finished tiles of the current pass
+	pub tiles_completed: u32,
+	pub tile_count: u32,
+	// Time since the start of the rendering
+	pub elapsed: Duration,
+	// Estimated time until the current pass is finished
+	pub eta: Duration,
+	pub rays_per_second: f64,
+	// Counters of the finished tiles of the current pass
+	pub counters: RayCounters
+}
+
+// The tracing functions count into the counters of their thread, which are collected by the renderer after each tile.
+// This keeps the counting out of the signatures of all the tracing functions.
+thread_local! {
+	static THREAD_COUNTERS: Cell<RayCounters> = const { Cell::new(RayCounters { primary_rays: 0, shadow_rays: 0, indirect_rays: 0, intersection_tests: 0 }) };
+}
+
+fn update_thread_counters<F: FnOnce(&mut RayCounters)>(update: F) {
+	THREAD_COUNTERS.with(|c| {
+		let mut counters = c.get();
+		update(&mut counters);
+		c.set(counters);
+	});
+}
+
+pub fn count_primary_ray() {
+	update_thread_counters(|c| c.primary_rays += 1);
+}
+
+pub fn count_shadow_ray() {
+	update_thread_counters(|c| c.shadow_rays += 1);
+}
+
+pub fn count_indirect_ray() {
+	update_thread_counters(|c| c.indirect_rays += 1);
+}
+
+pub fn count_intersection_tests(count: u64) {
+	update_thread_counters(|c| c.intersection_tests += count);
+}
+
+// Returns the counters of the current thread and resets them
+pub fn take_thread_counters() -> RayCounters {
+	return THREAD_COUNTERS.with(|c| c.replace(RayCounters::new()));
+}
@@ -3,34 +3,73 @@
 use crate::fundamentals::vec3::*;
 use crate::fundamentals::light::*;
 use crate::fundamentals::geometry::*;
+use crate::fundamentals::primitive::*;
+use crate::fundamentals::math::vector::{Point3, Normal3};
 use super::rays::*;
-use rand::prelude::*;
+use super::primitives::*;
+use super::sampling::*;
+use super::stats::*;
 
 // The amount of rays that are being cast for global illumination per surface point
 const GLOBAL_RAYS: i32 = 8;
+#[allow(non_upper_case_globals)]
 const fGLOBAL_RAYS: f64 = 8.0;
 
-// This function returns the light being received at the specified point on a body surface
-pub fn get_light_at_surface_point(position: &Vector3, normal: &Vector3, space: &Space, recursion_counter: i32) -> LightColor {
+// Shadow rays towards a point on an emitter hit the emitter itself about there, only nearer hits are occluders
+const EMITTER_SHADOW_TOLERANCE: f64 = 1e-6;
+
+// Light received at a surface point, split into the light coming directly from the light sources and the light
+// coming from other surfaces (global illumination)
+pub struct SurfaceLight {
+	pub direct: LightColor,
+	pub indirect: LightColor
+}
+
+// The first surface a camera ray hits, together with the light it reflects towards the camera
+pub struct PrimaryHit {
+	pub intersection: RaySurfaceIntersection,
+	pub direct: LightColor,
+	pub indirect: LightColor
+}
+
+impl PrimaryHit {
+
+	// Returns the total light reflected towards the camera
+	pub fn light(&self) -> LightColor {
+		return LightColor { r: self.direct.r + self.indirect.r, g: self.direct.g + self.indirect.g, b: self.direct.b + self.indirect.b };
+	}
+}
+
+// This function returns the light being received at the specified point on a body surface. The shading normal weights
+// the light, the geometric normal tells which light can reach the surface at all: the shading normal of a bumpy
+// surface may lean towards light coming from below the surface. All random decisions are drawn from the sampler, so
+// the same sampler state always yields the same light.
+pub fn get_light_at_surface_point(position: &Vector3, normal: &Vector3, geometric_normal: &Vector3, space: &Space, recursion_counter: i32, sampler: &mut dyn Sampler) -> LightColor {
+	let light = get_split_light_at_surface_point(position, normal, geometric_normal, space, recursion_counter, sampler);
+	return LightColor { r: light.direct.r + light.indirect.r, g: light.direct.g + light.indirect.g, b: light.direct.b + light.indirect.b };
+}
+
+// Like get_light_at_surface_point, but keeps direct and indirect light apart
+pub fn get_split_light_at_surface_point(position: &Vector3, normal: &Vector3, geometric_normal: &Vector3, space: &Space, recursion_counter: i32, sampler: &mut dyn Sampler) -> SurfaceLight {
 
 	let mut ray_to_light: Vector3; 
 	let mut absorbed_light_intensity: f64; 
-	let mut result_color = LightColor {r: 0.0, g: 0.0, b: 0.0};	
 	let mut direct_light_summands: Vec<WeightedLightColorSummand> = Vec::new(); 
 	let mut global_light_summands: Vec<WeightedLightColorSummand> = Vec::new(); 
 
 	for light in &space.directional_lights {
-		ray_to_light = v3_normalize(&v3_scale(&light.direction, -1.0));
-		absorbed_light_intensity = v3_dot_product(&ray_to_light, &normal);
+		ray_to_light = (-light.direction).normalize();
+		absorbed_light_intensity = ray_to_light.dot(*normal);
 
-		// Only if dot product is positive this light is having an impact
-		if absorbed_light_intensity > 0.0 {					
+		// Only if dot product is positive this light is having an impact, and only from above the surface
+		if absorbed_light_intensity > 0.0 && ray_to_light.dot(*geometric_normal) > 0.0 {					
 
 			// Determine if the light source is visible or if something is occluding it
-			let nearest_hit: Option<RaySurfaceIntersection> = get_nearest_surface_intersection_for_ray(&Ray {origin: position.clone(), direction: ray_to_light }, &space);
+			count_shadow_ray();
+			let nearest_hit = get_nearest_primitive_for_ray(&Ray {origin: *position, direction: ray_to_light }, space, RayKind::Shadow);
 
 			match nearest_hit {
-				Some(d) => {
+				Some(_) => {
 					// Something is occluding the light from the surface point, so not light is being received
 				}
 				None => {
@@ -47,23 +86,24 @@ pub fn get_light_at_surface_point(position: &Vector3, normal: &Vector3, space: &
 	let mut distance_to_light: f64; 
 
 	for light in &space.point_lights {
-		ray_to_light = v3_delta(&light.position, &position);
-		distance_to_light = v3_len(&ray_to_light);
-		ray_to_light = v3_normalize(&ray_to_light);
+		ray_to_light = light.position - *position;
+		distance_to_light = ray_to_light.length();
+		ray_to_light = ray_to_light.normalize();
 
-		absorbed_light_intensity = v3_dot_product(&ray_to_light, &normal);
+		absorbed_light_intensity = ray_to_light.dot(*normal);
 
-		// Only if dot product is positive this light is having an impact
-		if absorbed_light_intensity > 0.0 {					
+		// Only if dot product is positive this light is having an impact, and only from above the surface
+		if absorbed_light_intensity > 0.0 && ray_to_light.dot(*geometric_normal) > 0.0 {					
 
 			// Determine if the light source is visible or if something is occluding it
-			let nearest_hit: Option<RaySurfaceIntersection> = get_nearest_surface_intersection_for_ray(&Ray {origin: position.clone(), direction: ray_to_light }, &space);
+			count_shadow_ray();
+			let nearest_hit = get_nearest_primitive_for_ray(&Ray {origin: *position, direction: ray_to_light }, space, RayKind::Shadow);
 
 			match nearest_hit {
-				Some(d) => {
+				Some((_, distance)) => {
 					
 					// If surface point is further away than the light, the light is being absorbed
-					if d.distance > distance_to_light {
+					if distance > distance_to_light {
 						direct_light_summands.push(WeightedLightColorSummand {
 							light_color: light.color,
 							weight: absorbed_light_intensity
@@ -80,9 +120,38 @@ pub fn get_light_at_surface_point(position: &Vector3, normal: &Vector3, space: &
 		}
 	}	
 	
+	// Emitting primitives are sampled like area lights, one point on each per surface point. The light of the point
+	// is weighted as if the global illumination rays had found it, whose directions around the normal have a density
+	// of about cos / pi: by the cosine at the surface over the density of the direction towards the point, which for
+	// a point picked uniformly on the area is the squared distance over the area and the cosine at the emitter.
+	for &index in &space.primitives.emitters {
+		let emitter = &space.primitives.primitives[index];
+		let (u1, u2) = sampler.get_2d();
+		let Some((point, emitter_normal, area)) = sample_emitter(emitter, u1, u2) else { continue };
+
+		ray_to_light = point - *position;
+		let distance_to_light = ray_to_light.length();
+		ray_to_light = ray_to_light.normalize();
+
+		// Disks and quads shine to both sides, solids only out of their surface
+		let emitter_cosine = if emitter.kind.is_solid() { -ray_to_light.dot(emitter_normal) } else { ray_to_light.dot(emitter_normal).abs() };
+		absorbed_light_intensity = ray_to_light.dot(*normal);
+
+		if absorbed_light_intensity > 0.0 && ray_to_light.dot(*geometric_normal) > 0.0 && emitter_cosine > 0.0 {
+			count_shadow_ray();
+			let nearest_hit = get_nearest_primitive_for_ray(&Ray {origin: *position, direction: ray_to_light }, space, RayKind::Shadow);
+
+			if nearest_hit.is_none_or(|(_, distance)| distance > distance_to_light * (1.0 - EMITTER_SHADOW_TOLERANCE)) {
+				direct_light_summands.push(WeightedLightColorSummand {
+					light_color: emitter.emission,
+					weight: absorbed_light_intensity * emitter_cosine * area / (std::f64::consts::PI * distance_to_light * distance_to_light)
+				});
+			}
+		}
+	}
+
 	if recursion_counter > 0 {
-		let basis = v3_compute_basis_for_normal(normal); 
-		let mut rng = rand::thread_rng();	
+		let basis = v3_orthonormal_basis(normal);
 
 		let mut v1: Vector3; 
 		let mut v2: Vector3; 
@@ -93,17 +162,26 @@ pub fn get_light_at_surface_point(position: &Vector3, normal: &Vector3, space: &
 		let mut absorbed_light_intensity: f64;
 		let mut ray_direction: Vector3; 		
 
-		for k in 0..GLOBAL_RAYS {
-			r1 = rng.gen::<f64>()-0.5;
-			r2 = rng.gen::<f64>()-0.5;
+		for _ in 0..GLOBAL_RAYS {
+			let (u1, u2) = sampler.get_2d();
+			r1 = u1-0.5;
+			r2 = u2-0.5;
 	
-			v1 = v3_scale(&basis.b1, r1);
-			v2 = v3_scale(&basis.b2, r2);	
+			v1 = basis.b1 * r1;
+			v2 = basis.b2 * r2;	
+
+			ray_direction = (v1 + v2 + *normal).normalize();			
 
-			ray_direction = v3_normalize(&v3_sum(&v3_sum(&v1, &v2), normal));			
-			absorbed_light_intensity = v3_dot_product(&ray_direction, normal);
+			// Directions below the surface are mirrored back above it
+			let below = ray_direction.dot(*geometric_normal);
+
+			if below < 0.0 {
+				ray_direction -= *geometric_normal * (2.0 * below);
+			}
+
+			absorbed_light_intensity = ray_direction.dot(*normal).max(0.0);
 			
-		 	let incoming_light_color = get_light_for_backward_ray(&Ray { origin: position.clone(), direction: ray_direction}, space, recursion_counter - 1);
+		 	let incoming_light_color = get_light_for_backward_ray(&Ray { origin: *position, direction: ray_direction}, space, recursion_counter - 1, sampler);
 
 			 global_light_summands.push(WeightedLightColorSummand {
 				light_color: incoming_light_color,
@@ -112,24 +190,34 @@ pub fn get_light_at_surface_point(position: &Vector3, normal: &Vector3, space: &
 		}
 	}
 
-	let mut global_sum = compute_weighted_light_color(&global_light_summands);
-	let mut direct_sum = compute_weighted_light_color(&direct_light_summands);	
+	let global_sum = compute_weighted_light_color(&global_light_summands);
+	let direct_sum = compute_weighted_light_color(&direct_light_summands);	
 
-	return LightColor { r: direct_sum.r + global_sum.r / fGLOBAL_RAYS, g: direct_sum.g + global_sum.g / fGLOBAL_RAYS, b: direct_sum.b + global_sum.b / fGLOBAL_RAYS};
+	return SurfaceLight {
+		direct: direct_sum,
+		indirect: LightColor { r: global_sum.r / fGLOBAL_RAYS, g: global_sum.g / fGLOBAL_RAYS, b: global_sum.b / fGLOBAL_RAYS }
+	};
 }
 
 // This function returns the light that is being received for the specified backward ray 
-pub fn get_light_for_backward_ray(ray: &Ray, space: &Space, recursion_counter: i32) -> LightColor {
+#[allow(clippy::single_match)]
+pub fn get_light_for_backward_ray(ray: &Ray, space: &Space, recursion_counter: i32, sampler: &mut dyn Sampler) -> LightColor {
 
-	let nearest_hit: Option<RaySurfaceIntersection> = get_nearest_surface_intersection_for_ray(&ray, &space);
+	count_indirect_ray();
+	let nearest_hit: Option<RaySurfaceIntersection> = get_nearest_surface_intersection_for_ray(ray, space, RayKind::Indirect);
 	let mut result_color = LightColor {r: 0.0, g: 0.0, b: 0.0};
 	
 	match nearest_hit {
 		Some(d) => {
-			let light_at_hit = get_light_at_surface_point(&d.position, &d.normal, &space, recursion_counter);
-			result_color.r = d.material_color.r * light_at_hit.r;
-			result_color.g = d.material_color.g * light_at_hit.g;
-			result_color.b = d.material_color.b * light_at_hit.b;
+			let light_at_hit = get_light_at_surface_point(&d.position, &d.normal, &d.geometric_normal, space, recursion_counter, sampler);
+
+			// The emission of the emitters sampled like lights is already part of the direct light
+			let primitive_index = (d.primitive_id as usize).checked_sub(space.spheres.len() + space.planes.len());
+			let emission = if primitive_index.is_some_and(|i| space.primitives.is_sampled_emitter(i)) { LightColor { r: 0.0, g: 0.0, b: 0.0 } } else { d.emission };
+
+			result_color.r = d.material_color.r * light_at_hit.r + emission.r;
+			result_color.g = d.material_color.g * light_at_hit.g + emission.g;
+			result_color.b = d.material_color.b * light_at_hit.b + emission.b;
 		}
 		None => {
 		}
@@ -137,3 +225,32 @@ pub fn get_light_for_backward_ray(ray: &Ray, space: &Space, recursion_counter: i
 
 	return result_color; 
 }
+
+// Traces a ray from the camera and returns what it hits first, or None if it does not hit anything
+pub fn trace_camera_ray(ray: &Ray, space: &Space, recursion_counter: i32, sampler: &mut dyn Sampler) -> Option<PrimaryHit> {
+
+	count_primary_ray();
+	let d = get_nearest_surface_intersection_for_ray(ray, space, RayKind::Camera)?;
+	let light_at_hit = get_split_light_at_surface_point(&d.position, &d.normal, &d.geometric_normal, space, recursion_counter, sampler);
+	let (m, e) = (d.material_color, d.emission);
+
+	// Light emitted by the surface counts as direct light
+	return Some(PrimaryHit {
+		direct: LightColor { r: m.r * light_at_hit.direct.r + e.r, g: m.g * light_at_hit.direct.g + e.g, b: m.b * light_at_hit.direct.b + e.b },
+		indirect: LightColor { r: m.r * light_at_hit.indirect.r, g: m.g * light_at_hit.indirect.g, b: m.b * light_at_hit.indirect.b },
+		intersection: d
+	});
+}
+
+// Returns a point on the surface of an emitter in world space, picked uniformly by its area in object space, with the
+// normal there and the area the point stands for: the area in object space scaled by how much the transform stretches
+// the surface around the point.
+fn sample_emitter(emitter: &Primitive, u1: f64, u2: f64) -> Option<(Vector3, Vector3, f64)> {
+	let (point, normal, area) = sample_primitive_surface(&emitter.kind, u1, u2)?;
+	let (tangent_u, tangent_v) = get_plane_tangents(&normal);
+	let stretch = emitter.transform.apply_vector(tangent_u).cross(emitter.transform.apply_vector(tangent_v)).length();
+
+	let world_point = emitter.transform.apply_point(Point3::from(point)).to_vector();
+	let world_normal = emitter.transform.apply_normal(Normal3::from(normal)).to_vector().normalize();
+	return Some((world_point, world_normal, area * stretch));
+}
@@ -0,0 +1,63 @@
+
+// Import requirements
+use std::io::{BufRead, BufReader, Read};
+use std::process::{Command, Stdio};
+use std::thread;
+
+const PHOTON: &str = env!("CARGO_BIN_EXE_photon");
+
+// A small window of the image, eight tiles of 32 rows
+const RENDER_ARGS: [&str; 8] = ["--window", "500,256,16,256", "--samples", "16", "--progress", "off", "--format", "pfm"];
+
+// Starts a coordinator and two worker processes on this machine and compares the image with a local rendering
+#[test]
+fn test_coordinator_with_worker_processes() {
+	let directory = std::env::temp_dir().join(format!("photon-distributed-{}", std::process::id()));
+	std::fs::create_dir_all(&directory).unwrap();
+	let distributed_path = directory.join("distributed.pfm");
+	let local_path = directory.join("local.pfm");
+
+	// The coordinator listens on a free port, which it reports on the standard error stream
+	let mut coordinator = Command::new(PHOTON)
+		.args(["--coordinator", "127.0.0.1:0", "--output"]).arg(&distributed_path).args(RENDER_ARGS)
+		.stderr(Stdio::piped())
+		.spawn().unwrap();
+
+	let mut coordinator_errors = BufReader::new(coordinator.stderr.take().unwrap());
+	let mut line = String::new();
+	coordinator_errors.read_line(&mut line).unwrap();
+	let address = String::from(line.trim().strip_prefix("Waiting for workers on ").expect("Coordinator address expected"));
+
+	let workers: Vec<_> = (0..2).map(|_| {
+		Command::new(PHOTON).args(["--worker", &address, "--threads", "1"]).stderr(Stdio::piped()).spawn().unwrap()
+	}).collect();
+
+	let draining = thread::spawn(move || {
+		let mut rest = String::new();
+		coordinator_errors.read_to_string(&mut rest).unwrap();
+	});
+
+	assert!(coordinator.wait().unwrap().success());
+	draining.join().unwrap();
+
+	// Every worker reports the number of tiles it has rendered, together all of them
+	let mut tile_count = 0;
+
+	for worker in workers {
+		let output = worker.wait_with_output().unwrap();
+		assert!(output.status.success());
+
+		let report = String::from_utf8(output.stderr).unwrap();
+		let count = report.trim().strip_prefix("Rendered ").and_then(|r| r.split(' ').next()).expect("Tile count expected");
+		tile_count += count.parse::<u32>().unwrap();
+	}
+
+	assert_eq!(tile_count, 8);
+
+	// The tiles are exactly the ones rendered locally
+	let status = Command::new(PHOTON).arg("--output").arg(&local_path).args(RENDER_ARGS).status().unwrap();
+	assert!(status.success());
+	assert!(std::fs::read(&distributed_path).unwrap() == std::fs::read(&local_path).unwrap());
+
+	std::fs::remove_dir_all(&directory).unwrap();
+}